@@ -28,6 +28,11 @@ fn main() {
             return;
         }
     }
+    // Unit tests against the in-memory backend run without libddsc
+    println!("cargo:rerun-if-env-changed=CYCLONEDDS_NO_LINK");
+    if env::var_os("CYCLONEDDS_NO_LINK").is_some() {
+        return;
+    }
     build::main();
 }
 
@@ -139,7 +144,6 @@ mod build {
                 .arg("-DBUILD_IDLC=OFF")
                 .arg("-DBUILD_DDSPERF=OFF")
                 .arg("-DBUILD_TESTING=OFF")
-                .arg("-DBUILD_DDSPERF=OFF")
                 .arg("-DENABLE_TYPE_DISCOVERY=YES")
                 .arg("-DENABLE_TOPIC_DISCOVERY=YES")
                 .arg(format!("-DCMAKE_INSTALL_PREFIX={}/install", outdir))
@@ -348,6 +352,11 @@ mod build {
         .whitelist_function("dds_get_datareader")
         .whitelist_function("dds_get_mask")
         .whitelist_function("dds_get_instance_handle")
+        .whitelist_function("dds_get_guid")
+        .whitelist_function("dds_create_statistics")
+        .whitelist_function("dds_refresh_statistics")
+        .whitelist_function("dds_delete_statistics")
+        .whitelist_function("dds_lookup_statistic")
         .whitelist_function("dds_read_status")
         .whitelist_function("dds_take_status")
         .whitelist_function("dds_get_status_changes")
@@ -370,10 +379,18 @@ mod build {
         .whitelist_function("dds_create_topic")
         .whitelist_function("dds_create_topic_arbitrary")
         .whitelist_function("dds_find_topic")
+        .whitelist_function("dds_get_typeinfo")
+        .whitelist_function("dds_free_typeinfo")
+        .whitelist_function("dds_create_topic_descriptor")
+        .whitelist_function("dds_delete_topic_descriptor")
         .whitelist_function("dds_get_name")
         .whitelist_function("dds_get_type_name")
         .whitelist_function("dds_set_topic_filter")
         .whitelist_function("dds_get_topic_filter")
+        .whitelist_function("dds_set_topic_filter_and_arg")
+        .whitelist_function("dds_get_topic_filter_and_arg")
+        .whitelist_function("dds_set_topic_filter_extended")
+        .whitelist_function("dds_get_topic_filter_extended")
         .whitelist_function("dds_create_subscriber")
         .whitelist_function("dds_create_publisher")
         .whitelist_function("dds_suspend")
@@ -396,7 +413,9 @@ mod build {
         .whitelist_function("dds_dispose_ih_ts")
         .whitelist_function("dds_write")
         .whitelist_function("dds_write_flush")
+        .whitelist_function("dds_write_set_batch")
         .whitelist_function("dds_writecdr")
+        .whitelist_function("dds_forwardcdr")
         .whitelist_function("dds_write_ts")
         .whitelist_function("dds_create_readcondition")
         .whitelist_function("dds_create_querycondition")
@@ -425,6 +444,8 @@ mod build {
         .whitelist_function("dds_take_mask")
         .whitelist_function("dds_take_mask_wl")
         .whitelist_function("dds_take_cdr")
+        .whitelist_function("dds_takecdr")
+        .whitelist_function("dds_readcdr")
         .whitelist_function("dds_take_instance")
         .whitelist_function("dds_take_instance_wl")
         .whitelist_function("dds_take_instance_mask")
@@ -441,10 +462,13 @@ mod build {
         .whitelist_function("dds_notify_readers")
         .whitelist_function("dds_triggered")
         .whitelist_function("dds_get_topic")
+        .whitelist_function("dds_get_entity_sertype")
         .whitelist_function("dds_get_matched_subscriptions")
         .whitelist_function("dds_get_matched_subscription_data")
         .whitelist_function("dds_get_matched_publications")
         .whitelist_function("dds_get_matched_publication_data")
+        .whitelist_function("dds_builtintopic_free_endpoint")
+        .whitelist_function("dds_builtintopic_free_participant")
         .whitelist_function("dds_assert_liveliness")   /* DDS Public Listener API Follows */
         .whitelist_function("dds_create_listener")
         .whitelist_function("dds_delete_listener")
@@ -485,7 +509,13 @@ mod build {
         .whitelist_function("dds_string_alloc")
         .whitelist_function("dds_string_dup")
         .whitelist_function("dds_string_free")
-        .whitelist_function("dds_sample_free")   /* DDS Public Status APIs follow */
+        .whitelist_function("dds_sample_free")
+        .whitelist_function("dds_time")
+        .whitelist_function("dds_sleepfor")
+        .whitelist_function("dds_sleepuntil")
+        .whitelist_function("dds_set_log_mask")
+        .whitelist_function("dds_set_log_sink")
+        .whitelist_function("dds_set_trace_sink")   /* DDS Public Status APIs follow */
         .whitelist_function("dds_get_inconsistent_topic_status")
         .whitelist_function("dds_get_publication_matched_status")
         .whitelist_function("dds_get_liveliness_lost_status")
@@ -547,7 +577,7 @@ mod build {
         .whitelist_function("dds_qget_transport_priority")
         .whitelist_function("dds_qget_destination_order")
         .whitelist_function("dds_qget_writer_data_lifecycle")
-        .whitelist_function("dds_qget_reader_data_lifecyele")
+        .whitelist_function("dds_qget_reader_data_lifecycle")
         .whitelist_function("dds_qget_durability_service")
         .whitelist_function("dds_qget_history")
         .whitelist_function("dds_qget_ignorelocal")
@@ -559,6 +589,7 @@ mod build {
         .whitelist_function("ddsi_serdata_init")
         .whitelist_function("ddsi_serdata_addref")
         .whitelist_function("ddsi_serdata_removeref")
+        .whitelist_function("ddsrs_copy_fragchain")
         .whitelist_function("ddsrt_md5_init")
         .whitelist_function("ddsrt_md5_append")
         .whitelist_function("ddsrt_md5_finish")
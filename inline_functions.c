@@ -20,4 +20,25 @@ struct ddsi_serdata *ddsi_serdata_addref (const struct ddsi_serdata *serdata_con
 void ddsi_serdata_removeref (struct ddsi_serdata *serdata) {
   if (ddsrt_atomic_dec32_ov (&serdata->refc) == 1)
     serdata->ops->free (serdata);
-}
\ No newline at end of file
+}
+// Copy the serialized payload held in a chain of received fragments. The
+// layout of nn_rdata/nn_rmsg is internal to Cyclone, so this has to be done
+// on the C side.
+#include <string.h>
+#include "dds/ddsi/q_radmin.h"
+
+void ddsrs_copy_fragchain (const struct nn_rdata *fragchain, size_t size, unsigned char *dst)
+{
+  uint32_t off = 0;
+  while (fragchain && off < size)
+  {
+    if (fragchain->maxp1 > off)
+    {
+      const unsigned char *payload = NN_RMSG_PAYLOADOFF (fragchain->rmsg, NN_RDATA_PAYLOAD_OFF (fragchain));
+      uint32_t end = fragchain->maxp1 < size ? fragchain->maxp1 : (uint32_t) size;
+      memcpy (dst + off, payload + off - fragchain->min, end - off);
+      off = end;
+    }
+    fragchain = fragchain->nextfrag;
+  }
+}
@@ -0,0 +1,179 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Acknowledgements, batching and flushing for reliable writers.
+
+use std::ffi::c_void;
+use std::fmt;
+use std::time::Instant;
+
+use crate::{
+    dds_entity_t, dds_get_matched_publications, dds_get_matched_subscriptions,
+    dds_instance_handle_t, dds_return_t, dds_wait_for_acks, dds_write, dds_write_flush,
+    dds_write_set_batch, size_t, DDSError, DdsDuration, DdsEntity,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AckError {
+    Dds(DDSError),
+    /// Not everything was acknowledged in time. Cyclone does not say which
+    /// readers are behind, only which writers are still waiting for them.
+    Timeout {
+        /// Writers with unacknowledged data
+        writers: Vec<dds_entity_t>,
+        /// Readers matched with those writers when the wait gave up
+        matched_readers: Vec<dds_instance_handle_t>,
+    },
+}
+
+impl fmt::Display for AckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AckError::Dds(e) => write!(f, "{}", e),
+            AckError::Timeout {
+                writers,
+                matched_readers,
+            } => write!(
+                f,
+                "timeout waiting for acknowledgements ({} writers waiting, {} readers matched)",
+                writers.len(),
+                matched_readers.len()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AckError {}
+
+impl From<DDSError> for AckError {
+    fn from(e: DDSError) -> Self {
+        AckError::Dds(e)
+    }
+}
+
+/// Enable or disable write batching for the whole process. While enabled,
+/// samples are queued until the writer is flushed or the queue is full.
+pub fn set_write_batch(enable: bool) {
+    unsafe { dds_write_set_batch(enable) }
+}
+
+type MatchedFn =
+    unsafe extern "C" fn(dds_entity_t, *mut dds_instance_handle_t, size_t) -> dds_return_t;
+
+fn matched(entity: dds_entity_t, get: MatchedFn) -> Result<Vec<dds_instance_handle_t>, DDSError> {
+    loop {
+        let n = DDSError::check(unsafe { get(entity, std::ptr::null_mut(), 0) })?;
+        let mut handles = vec![0; n as usize];
+        let m =
+            DDSError::check(unsafe { get(entity, handles.as_mut_ptr(), handles.len() as size_t) })?;
+        if m <= n {
+            handles.truncate(m as usize);
+            return Ok(handles);
+        }
+    }
+}
+
+impl DdsEntity {
+    /// Instance handles of the readers matched with this writer
+    pub fn matched_subscriptions(&self) -> Result<Vec<dds_instance_handle_t>, DDSError> {
+        matched(self.0, dds_get_matched_subscriptions)
+    }
+
+    /// Instance handles of the writers matched with this reader
+    pub fn matched_publications(&self) -> Result<Vec<dds_instance_handle_t>, DDSError> {
+        matched(self.0, dds_get_matched_publications)
+    }
+
+    /// Wait until all matched readers acknowledged everything written by
+    /// this writer, or by all writers of this publisher. The writers of a
+    /// publisher are waited for one after the other against a single
+    /// deadline, as Cyclone only supports waiting on writers.
+    pub fn wait_for_acks<D: Into<DdsDuration>>(&self, timeout: D) -> Result<(), AckError> {
+        let deadline = timeout
+            .into()
+            .to_std()
+            .and_then(|d| Instant::now().checked_add(d));
+        let writers = match self.matched_subscriptions() {
+            Ok(_) => vec![self.clone()],
+            Err(_) => self.children()?,
+        };
+
+        let mut waiting = Vec::new();
+        let mut matched_readers = Vec::new();
+        for writer in writers {
+            let remaining = match deadline {
+                Some(deadline) => {
+                    DdsDuration::Finite(deadline.saturating_duration_since(Instant::now()))
+                }
+                None => DdsDuration::Infinite,
+            };
+            match DDSError::check(unsafe { dds_wait_for_acks(writer.0, remaining.into()) }) {
+                Ok(_) => {}
+                Err(DDSError::Timeout) => {
+                    matched_readers.extend(writer.matched_subscriptions().unwrap_or_default());
+                    waiting.push(writer.0);
+                }
+                // deleted while waiting, nothing left to acknowledge
+                Err(DDSError::AlreadyDeleted) => {}
+                Err(e) => return Err(AckError::Dds(e)),
+            }
+        }
+
+        if waiting.is_empty() {
+            return Ok(());
+        }
+        matched_readers.sort_unstable();
+        matched_readers.dedup();
+        Err(AckError::Timeout {
+            writers: waiting,
+            matched_readers,
+        })
+    }
+
+    /// Write a sample and wait until all matched readers acknowledged it
+    ///
+    /// # Safety
+    /// The entity must be a writer and `data` a sample of its topic's type.
+    pub unsafe fn write_and_wait_for_acks<D: Into<DdsDuration>>(
+        &self,
+        data: *const c_void,
+        timeout: D,
+    ) -> Result<(), AckError> {
+        DDSError::check(dds_write(self.0, data))?;
+        self.flush();
+        self.wait_for_acks(timeout)
+    }
+
+    /// Write several samples, then flush the writer. They only go out
+    /// together while batching is enabled with [`set_write_batch`];
+    /// otherwise each is sent as it is written.
+    ///
+    /// # Safety
+    /// The entity must be a writer and `T` the in-memory sample type of its
+    /// topic.
+    pub unsafe fn write_all_and_flush<T>(&self, samples: &[T]) -> Result<(), DDSError> {
+        let result = samples.iter().try_for_each(|s| {
+            DDSError::check(dds_write(self.0, s as *const T as *const c_void)).map(|_| ())
+        });
+        self.flush();
+        result
+    }
+
+    /// Send out everything queued on this writer
+    pub fn flush(&self) {
+        unsafe { dds_write_flush(self.0) }
+    }
+}
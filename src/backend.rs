@@ -0,0 +1,262 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! The core publish/subscribe operations behind a trait.
+//!
+//! Application code written against [`Backend`] runs on Cyclone through
+//! [`Ddsc`], and in unit tests on [`memory::MemoryBackend`], which delivers
+//! samples in process without touching libddsc. Both need the `backend`
+//! feature. Setting `CYCLONEDDS_NO_LINK` in the build environment skips
+//! building and linking Cyclone, so tests against [`memory::MemoryBackend`]
+//! run on machines without libddsc; anything calling into libddsc then
+//! fails to link.
+
+use std::ffi::{c_void, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::sync::{Arc, RwLock};
+
+use crate::query_condition::loan_samples;
+use crate::status::{PublicationMatched, SampleLost, SubscriptionMatched};
+use crate::{
+    dds_create_listener, dds_create_participant, dds_create_reader, dds_create_topic,
+    dds_create_writer, dds_delete_listener, dds_domainid_t, dds_entity_t, dds_lset_data_available,
+    dds_sample_info_t, dds_set_listener, dds_topic_descriptor_t, dds_write, entity_state, DDSError,
+    DdsEntity, DdsQos,
+};
+
+pub mod memory;
+
+/// Operations shared by the Cyclone and in-memory implementations
+pub trait Backend {
+    type Entity: Clone + PartialEq;
+
+    fn create_participant(&self, domain: dds_domainid_t) -> Result<Self::Entity, DDSError>;
+
+    fn create_topic(
+        &self,
+        participant: &Self::Entity,
+        descriptor: &'static dds_topic_descriptor_t,
+        name: &str,
+        qos: Option<&DdsQos>,
+    ) -> Result<Self::Entity, DDSError>;
+
+    fn create_writer(
+        &self,
+        participant: &Self::Entity,
+        topic: &Self::Entity,
+        qos: Option<&DdsQos>,
+    ) -> Result<Self::Entity, DDSError>;
+
+    fn create_reader(
+        &self,
+        participant: &Self::Entity,
+        topic: &Self::Entity,
+        qos: Option<&DdsQos>,
+    ) -> Result<Self::Entity, DDSError>;
+
+    /// Delete an entity and its children
+    fn delete(&self, entity: Self::Entity) -> Result<(), DDSError>;
+
+    /// Publish a sample
+    ///
+    /// # Safety
+    /// `T` must be the in-memory sample type of the writer's topic.
+    unsafe fn write<T>(&self, writer: &Self::Entity, sample: &T) -> Result<(), DDSError>
+    where
+        T: Clone + Send + 'static;
+
+    /// Take up to `max` samples from a reader. The callback gets `None` for
+    /// samples that carry no data.
+    ///
+    /// # Safety
+    /// `T` must be the in-memory sample type of the reader's topic.
+    unsafe fn take<T, F>(&self, reader: &Self::Entity, max: usize, f: F) -> Result<usize, DDSError>
+    where
+        T: 'static,
+        F: FnMut(Option<&T>, &dds_sample_info_t);
+
+    /// Resets the change counters
+    fn publication_matched_status(
+        &self,
+        writer: &Self::Entity,
+    ) -> Result<PublicationMatched, DDSError>;
+
+    /// Resets the change counters
+    fn subscription_matched_status(
+        &self,
+        reader: &Self::Entity,
+    ) -> Result<SubscriptionMatched, DDSError>;
+
+    /// Resets the change counters
+    fn sample_lost_status(&self, reader: &Self::Entity) -> Result<SampleLost, DDSError>;
+
+    /// Call `listener` whenever data arrives on `reader`, replacing any
+    /// listener set before
+    fn set_data_available<F>(&self, reader: &Self::Entity, listener: F) -> Result<(), DDSError>
+    where
+        F: Fn(&Self::Entity) + Send + Sync + 'static;
+
+    fn clear_data_available(&self, reader: &Self::Entity) -> Result<(), DDSError>;
+}
+
+/// The Cyclone DDS implementation
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ddsc;
+
+const DATA_AVAILABLE_KEY: &str = "backend_data_available";
+
+type DataAvailable = Box<dyn Fn(&DdsEntity) + Send + Sync>;
+
+#[derive(Default)]
+struct ListenerSlot {
+    listener: RwLock<Option<DataAvailable>>,
+}
+
+impl ListenerSlot {
+    fn set(&self, listener: Option<DataAvailable>) {
+        *self.listener.write().unwrap_or_else(|e| e.into_inner()) = listener;
+    }
+}
+
+unsafe extern "C" fn data_available_trampoline(reader: dds_entity_t, arg: *mut c_void) {
+    if arg.is_null() {
+        return;
+    }
+    let slot = &*(arg as *const ListenerSlot);
+    let listener = slot.listener.read().unwrap_or_else(|e| e.into_inner());
+    if let Some(f) = listener.as_ref() {
+        let reader = DdsEntity(reader);
+        let _ = catch_unwind(AssertUnwindSafe(|| f(&reader)));
+    }
+}
+
+fn qos_ptr(qos: Option<&DdsQos>) -> *const crate::dds_qos_t {
+    qos.map_or(ptr::null(), DdsQos::as_ptr)
+}
+
+fn entity(ret: dds_entity_t) -> Result<DdsEntity, DDSError> {
+    DDSError::check(ret).map(|_| DdsEntity(ret))
+}
+
+impl Backend for Ddsc {
+    type Entity = DdsEntity;
+
+    fn create_participant(&self, domain: dds_domainid_t) -> Result<DdsEntity, DDSError> {
+        entity(unsafe { dds_create_participant(domain, ptr::null(), ptr::null()) })
+    }
+
+    fn create_topic(
+        &self,
+        participant: &DdsEntity,
+        descriptor: &'static dds_topic_descriptor_t,
+        name: &str,
+        qos: Option<&DdsQos>,
+    ) -> Result<DdsEntity, DDSError> {
+        let name = CString::new(name).map_err(|_| DDSError::BadParameter)?;
+        entity(unsafe {
+            dds_create_topic(
+                participant.0,
+                descriptor,
+                name.as_ptr(),
+                qos_ptr(qos),
+                ptr::null(),
+            )
+        })
+    }
+
+    fn create_writer(
+        &self,
+        participant: &DdsEntity,
+        topic: &DdsEntity,
+        qos: Option<&DdsQos>,
+    ) -> Result<DdsEntity, DDSError> {
+        entity(unsafe { dds_create_writer(participant.0, topic.0, qos_ptr(qos), ptr::null()) })
+    }
+
+    fn create_reader(
+        &self,
+        participant: &DdsEntity,
+        topic: &DdsEntity,
+        qos: Option<&DdsQos>,
+    ) -> Result<DdsEntity, DDSError> {
+        entity(unsafe { dds_create_reader(participant.0, topic.0, qos_ptr(qos), ptr::null()) })
+    }
+
+    fn delete(&self, entity: DdsEntity) -> Result<(), DDSError> {
+        entity.delete()
+    }
+
+    unsafe fn write<T>(&self, writer: &DdsEntity, sample: &T) -> Result<(), DDSError>
+    where
+        T: Clone + Send + 'static,
+    {
+        DDSError::check(dds_write(writer.0, sample as *const T as *const c_void)).map(|_| ())
+    }
+
+    unsafe fn take<T, F>(&self, reader: &DdsEntity, max: usize, f: F) -> Result<usize, DDSError>
+    where
+        T: 'static,
+        F: FnMut(Option<&T>, &dds_sample_info_t),
+    {
+        loan_samples(reader.0, max, true, f)
+    }
+
+    fn publication_matched_status(
+        &self,
+        writer: &DdsEntity,
+    ) -> Result<PublicationMatched, DDSError> {
+        writer.publication_matched_status()
+    }
+
+    fn subscription_matched_status(
+        &self,
+        reader: &DdsEntity,
+    ) -> Result<SubscriptionMatched, DDSError> {
+        reader.subscription_matched_status()
+    }
+
+    fn sample_lost_status(&self, reader: &DdsEntity) -> Result<SampleLost, DDSError> {
+        reader.sample_lost_status()
+    }
+
+    /// Installs a listener on the reader, replacing listeners set through
+    /// other means.
+    fn set_data_available<F>(&self, reader: &DdsEntity, listener: F) -> Result<(), DDSError>
+    where
+        F: Fn(&DdsEntity) + Send + Sync + 'static,
+    {
+        entity_state::purge();
+        let slot: Arc<ListenerSlot> =
+            entity_state::get_or_insert_with(reader.0, DATA_AVAILABLE_KEY, ListenerSlot::default);
+        slot.set(Some(Box::new(listener)));
+        unsafe {
+            let listener = dds_create_listener(Arc::as_ptr(&slot) as *mut c_void);
+            dds_lset_data_available(listener, Some(data_available_trampoline));
+            let ret = dds_set_listener(reader.0, listener);
+            dds_delete_listener(listener);
+            DDSError::check(ret).map(|_| ())
+        }
+    }
+
+    fn clear_data_available(&self, reader: &DdsEntity) -> Result<(), DDSError> {
+        DDSError::check(unsafe { dds_set_listener(reader.0, ptr::null()) })?;
+        let slot: Arc<ListenerSlot> =
+            entity_state::get_or_insert_with(reader.0, DATA_AVAILABLE_KEY, ListenerSlot::default);
+        slot.set(None);
+        Ok(())
+    }
+}
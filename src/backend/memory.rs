@@ -0,0 +1,778 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! A pure Rust backend that delivers samples within the process.
+//!
+//! Writers and readers match when they are in the same domain and use a
+//! topic with the same name and type name. `write` copies the sample into
+//! every matched reader before it returns, in the order the readers were
+//! created, so tests see the same result on every run. QoS is ignored and
+//! readers keep everything until it is taken.
+//!
+//! Samples carry the instance handle of their key, read from the members
+//! the serializer ops of the topic descriptor mark as key. Samples with
+//! equal keys share a handle across all writers of the topic; keyless
+//! topics, and descriptors without ops, have a single instance.
+//!
+//! Loss is injected with [`MemoryBackend::set_loss`]: the closure sees every
+//! delivery and drops it by returning true. Dropped samples show up in the
+//! reader's sample lost status.
+
+use std::any::Any;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::ffi::{c_void, CStr};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use super::Backend;
+use crate::sample_layout::SampleLayout;
+use crate::status::{PublicationMatched, SampleLost, SubscriptionMatched};
+use crate::{
+    dds_domainid_t, dds_instance_handle_t, dds_instance_state_DDS_IST_ALIVE, dds_sample_info_t,
+    dds_sample_state_DDS_SST_NOT_READ, dds_topic_descriptor_t, dds_view_state_DDS_VST_NEW,
+    DDSError, DdsQos,
+};
+
+/// An entity of the in-memory backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MemEntity(u32);
+
+impl MemEntity {
+    pub fn id(&self) -> u32 {
+        self.0
+    }
+}
+
+/// A sample on its way from a writer to a reader
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Delivery {
+    pub writer: MemEntity,
+    pub reader: MemEntity,
+    /// Counts the samples written by `writer`, starting at 1
+    pub sequence: u64,
+}
+
+type LossFn = Arc<Mutex<dyn FnMut(&Delivery) -> bool + Send>>;
+type Listener = Arc<dyn Fn(&MemEntity) + Send + Sync>;
+
+/// Domain, topic name and type name
+type TopicKey = (dds_domainid_t, String, String);
+
+/// The key members of a sample as text that is equal for equal keys
+unsafe fn instance_key(layout: &SampleLayout, sample: *const c_void) -> String {
+    layout
+        .fields()
+        .iter()
+        .filter(|field| field.key)
+        .map(|field| format!("{:?}", layout.read(field, sample)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+struct Stored {
+    sample: Box<dyn Any + Send>,
+    info: dds_sample_info_t,
+}
+
+/// Matched endpoints, with the counts last reported in a status
+#[derive(Default)]
+struct Matched {
+    current: BTreeSet<u32>,
+    total: u32,
+    reported_total: u32,
+    reported_current: u32,
+    last: u32,
+}
+
+impl Matched {
+    fn add(&mut self, id: u32) {
+        if self.current.insert(id) {
+            self.total += 1;
+            self.last = id;
+        }
+    }
+
+    /// (total, total change, current, current change), resetting the changes
+    fn report(&mut self) -> (u32, i32, u32, i32) {
+        let current = self.current.len() as u32;
+        let changes = (
+            (self.total - self.reported_total) as i32,
+            current as i32 - self.reported_current as i32,
+        );
+        self.reported_total = self.total;
+        self.reported_current = current;
+        (self.total, changes.0, current, changes.1)
+    }
+}
+
+enum Kind {
+    Participant {
+        domain: dds_domainid_t,
+    },
+    Topic {
+        name: String,
+        type_name: String,
+        layout: Option<SampleLayout>,
+    },
+    Writer {
+        key: TopicKey,
+        layout: Option<SampleLayout>,
+        sequence: u64,
+        matched: Matched,
+    },
+    Reader {
+        key: TopicKey,
+        queue: VecDeque<Stored>,
+        matched: Matched,
+        lost: u32,
+        reported_lost: u32,
+        listener: Option<Listener>,
+    },
+}
+
+struct Node {
+    parent: Option<u32>,
+    kind: Kind,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u32,
+    clock: i64,
+    nodes: BTreeMap<u32, Node>,
+    instances: BTreeMap<(TopicKey, String), dds_instance_handle_t>,
+    loss: Option<LossFn>,
+}
+
+impl State {
+    fn add(&mut self, parent: Option<u32>, kind: Kind) -> MemEntity {
+        self.next_id += 1;
+        self.nodes.insert(self.next_id, Node { parent, kind });
+        MemEntity(self.next_id)
+    }
+
+    fn node(&mut self, entity: &MemEntity) -> Result<&mut Node, DDSError> {
+        self.nodes
+            .get_mut(&entity.0)
+            .ok_or(DDSError::AlreadyDeleted)
+    }
+
+    fn domain(&self, participant: &MemEntity) -> Result<dds_domainid_t, DDSError> {
+        match self.nodes.get(&participant.0).map(|n| &n.kind) {
+            Some(Kind::Participant { domain }) => Ok(*domain),
+            Some(_) => Err(DDSError::IllegalOperation),
+            None => Err(DDSError::AlreadyDeleted),
+        }
+    }
+
+    fn topic_key(
+        &self,
+        participant: &MemEntity,
+        topic: &MemEntity,
+    ) -> Result<(TopicKey, Option<SampleLayout>), DDSError> {
+        let domain = self.domain(participant)?;
+        match self.nodes.get(&topic.0).map(|n| &n.kind) {
+            Some(Kind::Topic {
+                name,
+                type_name,
+                layout,
+            }) => Ok(((domain, name.clone(), type_name.clone()), layout.clone())),
+            Some(_) => Err(DDSError::IllegalOperation),
+            None => Err(DDSError::AlreadyDeleted),
+        }
+    }
+
+    /// The handle of the instance with key `key` of `topic`
+    fn instance(&mut self, topic: &TopicKey, key: String) -> dds_instance_handle_t {
+        let next = self.instances.len() as dds_instance_handle_t + 1;
+        *self.instances.entry((topic.clone(), key)).or_insert(next)
+    }
+
+    fn matched_mut(&mut self, id: u32) -> Option<&mut Matched> {
+        match self.nodes.get_mut(&id).map(|n| &mut n.kind) {
+            Some(Kind::Writer { matched, .. }) | Some(Kind::Reader { matched, .. }) => {
+                Some(matched)
+            }
+            _ => None,
+        }
+    }
+
+    /// Create a writer or reader and match it with the existing endpoints
+    /// on the other side
+    fn add_endpoint(&mut self, participant: &MemEntity, kind: Kind, key: &TopicKey) -> MemEntity {
+        let writer = matches!(kind, Kind::Writer { .. });
+        let peers: Vec<u32> = self
+            .nodes
+            .iter()
+            .filter_map(|(id, node)| match &node.kind {
+                Kind::Reader { key: k, .. } if writer && k == key => Some(*id),
+                Kind::Writer { key: k, .. } if !writer && k == key => Some(*id),
+                _ => None,
+            })
+            .collect();
+        let entity = self.add(Some(participant.0), kind);
+        for peer in peers {
+            if let Some(m) = self.matched_mut(entity.0) {
+                m.add(peer);
+            }
+            if let Some(m) = self.matched_mut(peer) {
+                m.add(entity.0);
+            }
+        }
+        entity
+    }
+}
+
+/// An in-process stand-in for Cyclone, see the module documentation
+#[derive(Default)]
+pub struct MemoryBackend {
+    state: Mutex<State>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Drop the deliveries for which `loss` returns true. The closure runs
+    /// without the backend locked and may query it, but must not write or
+    /// replace the closure.
+    pub fn set_loss<F>(&self, loss: F)
+    where
+        F: FnMut(&Delivery) -> bool + Send + 'static,
+    {
+        self.lock().loss = Some(Arc::new(Mutex::new(loss)));
+    }
+
+    /// Deliver everything again
+    pub fn clear_loss(&self) {
+        self.lock().loss = None;
+    }
+
+    /// Number of samples waiting to be taken from `reader`
+    pub fn pending(&self, reader: &MemEntity) -> Result<usize, DDSError> {
+        match &self.lock().node(reader)?.kind {
+            Kind::Reader { queue, .. } => Ok(queue.len()),
+            _ => Err(DDSError::IllegalOperation),
+        }
+    }
+}
+
+impl Backend for MemoryBackend {
+    type Entity = MemEntity;
+
+    fn create_participant(&self, domain: dds_domainid_t) -> Result<MemEntity, DDSError> {
+        Ok(self.lock().add(None, Kind::Participant { domain }))
+    }
+
+    fn create_topic(
+        &self,
+        participant: &MemEntity,
+        descriptor: &'static dds_topic_descriptor_t,
+        name: &str,
+        _qos: Option<&DdsQos>,
+    ) -> Result<MemEntity, DDSError> {
+        let type_name = if descriptor.m_typename.is_null() {
+            String::new()
+        } else {
+            unsafe { CStr::from_ptr(descriptor.m_typename) }
+                .to_string_lossy()
+                .into_owned()
+        };
+        // only the key flags matter, so the members are named by position
+        let layout = unsafe {
+            SampleLayout::leaf_count(descriptor).and_then(|n| {
+                let names: Vec<String> = (0..n).map(|i| i.to_string()).collect();
+                SampleLayout::from_descriptor(descriptor, &names)
+            })
+        };
+        let mut state = self.lock();
+        state.domain(participant)?;
+        Ok(state.add(
+            Some(participant.0),
+            Kind::Topic {
+                name: name.to_owned(),
+                type_name,
+                layout: layout.ok(),
+            },
+        ))
+    }
+
+    fn create_writer(
+        &self,
+        participant: &MemEntity,
+        topic: &MemEntity,
+        _qos: Option<&DdsQos>,
+    ) -> Result<MemEntity, DDSError> {
+        let mut state = self.lock();
+        let (key, layout) = state.topic_key(participant, topic)?;
+        let kind = Kind::Writer {
+            key: key.clone(),
+            layout,
+            sequence: 0,
+            matched: Matched::default(),
+        };
+        Ok(state.add_endpoint(participant, kind, &key))
+    }
+
+    fn create_reader(
+        &self,
+        participant: &MemEntity,
+        topic: &MemEntity,
+        _qos: Option<&DdsQos>,
+    ) -> Result<MemEntity, DDSError> {
+        let mut state = self.lock();
+        let (key, _) = state.topic_key(participant, topic)?;
+        let kind = Kind::Reader {
+            key: key.clone(),
+            queue: VecDeque::new(),
+            matched: Matched::default(),
+            lost: 0,
+            reported_lost: 0,
+            listener: None,
+        };
+        Ok(state.add_endpoint(participant, kind, &key))
+    }
+
+    fn delete(&self, entity: MemEntity) -> Result<(), DDSError> {
+        let mut state = self.lock();
+        state.node(&entity)?;
+
+        let mut doomed = vec![entity.0];
+        let mut i = 0;
+        while i < doomed.len() {
+            let parent = doomed[i];
+            doomed.extend(
+                state
+                    .nodes
+                    .iter()
+                    .filter(|(_, n)| n.parent == Some(parent))
+                    .map(|(id, _)| *id),
+            );
+            i += 1;
+        }
+
+        for id in &doomed {
+            let peers = match state.nodes.remove(id).map(|n| n.kind) {
+                Some(Kind::Writer { matched, .. }) | Some(Kind::Reader { matched, .. }) => {
+                    matched.current
+                }
+                _ => continue,
+            };
+            for peer in peers {
+                if let Some(m) = state.matched_mut(peer) {
+                    m.current.remove(id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the sample into every matched reader. A reader taking a
+    /// different type gets an error.
+    unsafe fn write<T>(&self, writer: &MemEntity, sample: &T) -> Result<(), DDSError>
+    where
+        T: Clone + Send + 'static,
+    {
+        let (sequence, readers, timestamp, instance_handle, loss) = {
+            let mut state = self.lock();
+            state.clock += 1;
+            let timestamp = state.clock;
+            let (sequence, readers, topic, key) = match &mut state.node(writer)?.kind {
+                Kind::Writer {
+                    key,
+                    layout,
+                    sequence,
+                    matched,
+                } => {
+                    *sequence += 1;
+                    let instance = layout.as_ref().map_or_else(String::new, |layout| {
+                        instance_key(layout, sample as *const T as *const c_void)
+                    });
+                    (*sequence, matched.current.clone(), key.clone(), instance)
+                }
+                _ => return Err(DDSError::IllegalOperation),
+            };
+            let instance_handle = state.instance(&topic, key);
+            (
+                sequence,
+                readers,
+                timestamp,
+                instance_handle,
+                state.loss.clone(),
+            )
+        };
+        // decided before relocking so the closure can query the backend
+        let deliveries: Vec<(u32, bool)> = readers
+            .into_iter()
+            .map(|reader| {
+                let dropped = loss.as_ref().is_some_and(|loss| {
+                    let mut loss = loss.lock().unwrap_or_else(|e| e.into_inner());
+                    loss(&Delivery {
+                        writer: *writer,
+                        reader: MemEntity(reader),
+                        sequence,
+                    })
+                });
+                (reader, dropped)
+            })
+            .collect();
+
+        let mut listeners = Vec::new();
+        {
+            let mut state = self.lock();
+            for (reader, dropped) in deliveries {
+                if let Some(Kind::Reader {
+                    queue,
+                    lost,
+                    listener,
+                    ..
+                }) = state.nodes.get_mut(&reader).map(|n| &mut n.kind)
+                {
+                    if dropped {
+                        *lost += 1;
+                        continue;
+                    }
+                    let mut info: dds_sample_info_t = std::mem::zeroed();
+                    info.sample_state = dds_sample_state_DDS_SST_NOT_READ;
+                    info.view_state = dds_view_state_DDS_VST_NEW;
+                    info.instance_state = dds_instance_state_DDS_IST_ALIVE;
+                    info.valid_data = true;
+                    info.source_timestamp = timestamp;
+                    info.publication_handle = writer.0 as u64;
+                    info.instance_handle = instance_handle;
+                    queue.push_back(Stored {
+                        sample: Box::new(sample.clone()),
+                        info,
+                    });
+                    if let Some(l) = listener {
+                        listeners.push((MemEntity(reader), l.clone()));
+                    }
+                }
+            }
+        }
+        // outside the lock so listeners can take
+        for (reader, listener) in listeners {
+            listener(&reader);
+        }
+        Ok(())
+    }
+
+    unsafe fn take<T, F>(&self, reader: &MemEntity, max: usize, mut f: F) -> Result<usize, DDSError>
+    where
+        T: 'static,
+        F: FnMut(Option<&T>, &dds_sample_info_t),
+    {
+        let taken: Vec<Stored> = {
+            let mut state = self.lock();
+            let queue = match &mut state.node(reader)?.kind {
+                Kind::Reader { queue, .. } => queue,
+                _ => return Err(DDSError::IllegalOperation),
+            };
+            if queue.iter().take(max).any(|s| !s.sample.is::<T>()) {
+                return Err(DDSError::BadParameter);
+            }
+            let n = max.min(queue.len());
+            queue.drain(..n).collect()
+        };
+        for stored in &taken {
+            f(stored.sample.downcast_ref::<T>(), &stored.info);
+        }
+        Ok(taken.len())
+    }
+
+    fn publication_matched_status(
+        &self,
+        writer: &MemEntity,
+    ) -> Result<PublicationMatched, DDSError> {
+        match &mut self.lock().node(writer)?.kind {
+            Kind::Writer { matched, .. } => {
+                let (total_count, total_count_change, current_count, current_count_change) =
+                    matched.report();
+                Ok(PublicationMatched {
+                    total_count,
+                    total_count_change,
+                    current_count,
+                    current_count_change,
+                    last_subscription_handle: matched.last as u64,
+                })
+            }
+            _ => Err(DDSError::IllegalOperation),
+        }
+    }
+
+    fn subscription_matched_status(
+        &self,
+        reader: &MemEntity,
+    ) -> Result<SubscriptionMatched, DDSError> {
+        match &mut self.lock().node(reader)?.kind {
+            Kind::Reader { matched, .. } => {
+                let (total_count, total_count_change, current_count, current_count_change) =
+                    matched.report();
+                Ok(SubscriptionMatched {
+                    total_count,
+                    total_count_change,
+                    current_count,
+                    current_count_change,
+                    last_publication_handle: matched.last as u64,
+                })
+            }
+            _ => Err(DDSError::IllegalOperation),
+        }
+    }
+
+    fn sample_lost_status(&self, reader: &MemEntity) -> Result<SampleLost, DDSError> {
+        match &mut self.lock().node(reader)?.kind {
+            Kind::Reader {
+                lost,
+                reported_lost,
+                ..
+            } => {
+                let change = (*lost - *reported_lost) as i32;
+                *reported_lost = *lost;
+                Ok(SampleLost {
+                    total_count: *lost,
+                    total_count_change: change,
+                })
+            }
+            _ => Err(DDSError::IllegalOperation),
+        }
+    }
+
+    /// The listener runs on the writing thread, after `write` released the
+    /// backend, so it may take from the reader.
+    fn set_data_available<F>(&self, reader: &MemEntity, f: F) -> Result<(), DDSError>
+    where
+        F: Fn(&MemEntity) + Send + Sync + 'static,
+    {
+        match &mut self.lock().node(reader)?.kind {
+            Kind::Reader { listener, .. } => {
+                *listener = Some(Arc::new(f));
+                Ok(())
+            }
+            _ => Err(DDSError::IllegalOperation),
+        }
+    }
+
+    fn clear_data_available(&self, reader: &MemEntity) -> Result<(), DDSError> {
+        match &mut self.lock().node(reader)?.kind {
+            Kind::Reader { listener, .. } => {
+                *listener = None;
+                Ok(())
+            }
+            _ => Err(DDSError::IllegalOperation),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_layout::{DDS_OP_FLAG_KEY, DDS_OP_FLAG_SGN};
+    use crate::{dds_stream_opcode_DDS_OP_ADR, dds_stream_typecode_primary_DDS_OP_TYPE_4BY};
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Sample {
+        id: i32,
+        value: i32,
+    }
+
+    const INT32: u32 = dds_stream_opcode_DDS_OP_ADR
+        | dds_stream_typecode_primary_DDS_OP_TYPE_4BY
+        | DDS_OP_FLAG_SGN;
+    static OPS: [u32; 5] = [INT32 | DDS_OP_FLAG_KEY, 0, INT32, 4, 0];
+
+    fn descriptor() -> &'static dds_topic_descriptor_t {
+        let mut desc: dds_topic_descriptor_t = unsafe { std::mem::zeroed() };
+        desc.m_size = 8;
+        desc.m_align = 4;
+        desc.m_nkeys = 1;
+        desc.m_typename = b"test::Sample\0".as_ptr() as *const _;
+        desc.m_nops = 3;
+        desc.m_ops = OPS.as_ptr();
+        Box::leak(Box::new(desc))
+    }
+
+    struct Fixture {
+        backend: MemoryBackend,
+        participant: MemEntity,
+        topic: MemEntity,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let backend = MemoryBackend::new();
+            let participant = backend.create_participant(0).unwrap();
+            let topic = backend
+                .create_topic(&participant, descriptor(), "Test", None)
+                .unwrap();
+            Fixture {
+                backend,
+                participant,
+                topic,
+            }
+        }
+
+        fn writer(&self) -> MemEntity {
+            self.backend
+                .create_writer(&self.participant, &self.topic, None)
+                .unwrap()
+        }
+
+        fn reader(&self) -> MemEntity {
+            self.backend
+                .create_reader(&self.participant, &self.topic, None)
+                .unwrap()
+        }
+
+        fn write(&self, writer: &MemEntity, id: i32, value: i32) {
+            unsafe { self.backend.write(writer, &Sample { id, value }) }.unwrap();
+        }
+
+        fn take(&self, reader: &MemEntity) -> Vec<(Sample, dds_sample_info_t)> {
+            let mut taken = Vec::new();
+            unsafe {
+                self.backend
+                    .take::<Sample, _>(reader, 100, |s, info| taken.push((*s.unwrap(), *info)))
+            }
+            .unwrap();
+            taken
+        }
+    }
+
+    #[test]
+    fn delivers_in_order() {
+        let f = Fixture::new();
+        let first = f.reader();
+        let second = f.reader();
+        let writer = f.writer();
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        for reader in &[first, second] {
+            let order = order.clone();
+            f.backend
+                .set_data_available(reader, move |r| order.lock().unwrap().push(*r))
+                .unwrap();
+        }
+        for value in 0..5 {
+            f.write(&writer, 1, value);
+        }
+
+        for reader in &[first, second] {
+            let values: Vec<i32> = f.take(reader).iter().map(|(s, _)| s.value).collect();
+            assert_eq!(values, vec![0, 1, 2, 3, 4]);
+        }
+        let expected: Vec<MemEntity> = (0..5).flat_map(|_| vec![first, second]).collect();
+        assert_eq!(*order.lock().unwrap(), expected);
+        assert_eq!(f.backend.pending(&first).unwrap(), 0);
+    }
+
+    #[test]
+    fn injects_loss() {
+        let f = Fixture::new();
+        let kept = f.reader();
+        let lossy = f.reader();
+        let writer = f.writer();
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        {
+            let seen = seen.clone();
+            f.backend.set_loss(move |d| {
+                seen.lock().unwrap().push(*d);
+                d.reader == lossy && d.sequence % 2 == 0
+            });
+        }
+        for value in 1..=4 {
+            f.write(&writer, 1, value);
+        }
+        f.backend.clear_loss();
+        f.write(&writer, 1, 6);
+
+        let values = |r| f.take(r).iter().map(|(s, _)| s.value).collect::<Vec<_>>();
+        assert_eq!(values(&kept), vec![1, 2, 3, 4, 6]);
+        assert_eq!(values(&lossy), vec![1, 3, 6]);
+        assert_eq!(seen.lock().unwrap().len(), 8);
+
+        let lost = f.backend.sample_lost_status(&lossy).unwrap();
+        assert_eq!((lost.total_count, lost.total_count_change), (2, 2));
+        let lost = f.backend.sample_lost_status(&lossy).unwrap();
+        assert_eq!((lost.total_count, lost.total_count_change), (2, 0));
+        assert_eq!(f.backend.sample_lost_status(&kept).unwrap().total_count, 0);
+    }
+
+    #[test]
+    fn keyed_instances() {
+        let f = Fixture::new();
+        let reader = f.reader();
+        let a = f.writer();
+        let b = f.writer();
+
+        f.write(&a, 1, 10);
+        f.write(&a, 2, 20);
+        f.write(&b, 1, 30);
+        f.write(&b, 3, 40);
+
+        let handles: Vec<_> = f
+            .take(&reader)
+            .iter()
+            .map(|(_, info)| info.instance_handle)
+            .collect();
+        assert_ne!(handles[0], 0);
+        assert_ne!(handles[0], handles[1]);
+        assert_eq!(handles[0], handles[2]);
+        assert_ne!(handles[3], handles[0]);
+        assert_ne!(handles[3], handles[1]);
+    }
+
+    #[test]
+    fn matching_and_deletion() {
+        let f = Fixture::new();
+        let writer = f.writer();
+        let reader = f.reader();
+        let status = f.backend.publication_matched_status(&writer).unwrap();
+        assert_eq!((status.current_count, status.total_count), (1, 1));
+
+        f.backend.delete(reader).unwrap();
+        let status = f.backend.publication_matched_status(&writer).unwrap();
+        assert_eq!((status.current_count, status.current_count_change), (0, -1));
+        assert!(f.backend.pending(&reader).is_err());
+
+        // other domains do not match
+        let other = f.backend.create_participant(1).unwrap();
+        let topic = f
+            .backend
+            .create_topic(&other, descriptor(), "Test", None)
+            .unwrap();
+        let far = f.backend.create_reader(&other, &topic, None).unwrap();
+        f.write(&writer, 1, 1);
+        assert_eq!(f.backend.pending(&far).unwrap(), 0);
+    }
+
+    #[test]
+    fn take_checks_the_type() {
+        let f = Fixture::new();
+        let reader = f.reader();
+        let writer = f.writer();
+        f.write(&writer, 1, 1);
+        let wrong = unsafe { f.backend.take::<u64, _>(&reader, 10, |_, _| {}) };
+        assert_eq!(wrong.unwrap_err(), DDSError::BadParameter);
+        assert_eq!(f.take(&reader).len(), 1);
+    }
+}
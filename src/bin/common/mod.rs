@@ -0,0 +1,205 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Options and setup shared by the command line tools. Not every tool uses
+//! all of it.
+
+#![allow(dead_code)]
+
+use std::process::exit;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use cyclonedds_sys::dynamic::DynType;
+use cyclonedds_sys::*;
+
+pub const COMMON_USAGE: &str =
+    "  -d DOMAIN          domain id (default from the Cyclone configuration)
+  -p PARTITION       partition, may be repeated
+  -r KIND            reliability: reliable (default) or best-effort
+  --durability KIND  volatile, transient-local, transient or persistent
+  --history KIND     keep-all or keep-last=N
+  --deadline SECS    deadline period
+  --names A,B,...    member names, one per leaf member with nested structs
+                     flattened (pos.x); without them structs are JSON arrays
+  -t SECONDS         time to wait for the topic to be discovered (default 5)";
+
+/// Longest time accepted on the command line, about 136 years
+pub const MAX_SECONDS: f64 = u32::MAX as f64;
+
+/// A positive number no larger than `MAX_SECONDS`, e.g. seconds or a rate
+pub fn positive(v: &str) -> Option<f64> {
+    v.parse::<f64>()
+        .ok()
+        .filter(|n| *n > 0.0 && *n <= MAX_SECONDS)
+}
+
+/// A number of seconds from zero to `MAX_SECONDS`
+pub fn seconds(v: &str) -> Option<f64> {
+    v.parse::<f64>()
+        .ok()
+        .filter(|n| *n >= 0.0 && *n <= MAX_SECONDS)
+}
+
+pub static STOP: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_signal(_: libc::c_int) {
+    STOP.store(true, Ordering::SeqCst);
+}
+
+pub fn catch_signals() {
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+        libc::signal(
+            libc::SIGTERM,
+            on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
+
+pub fn stopped() -> bool {
+    STOP.load(Ordering::SeqCst)
+}
+
+/// Make `stopped` return true, as if interrupted
+pub fn stop() {
+    STOP.store(true, Ordering::SeqCst);
+}
+
+pub fn fail(msg: &str) -> ! {
+    eprintln!("{}: {}", env!("CARGO_BIN_NAME"), msg);
+    exit(1)
+}
+
+pub fn usage(text: &str) -> ! {
+    eprintln!("{}", text);
+    exit(2)
+}
+
+#[derive(Clone)]
+pub struct CommonOptions {
+    pub domain: dds_domainid_t,
+    pub qos: DdsQos,
+    pub names: Option<Vec<String>>,
+    pub timeout: f64,
+}
+
+impl CommonOptions {
+    pub fn new() -> Self {
+        let mut qos = DdsQos::new();
+        qos.reliability(
+            dds_reliability_kind::DDS_RELIABILITY_RELIABLE,
+            DdsDuration::from_millis(100),
+        );
+        CommonOptions {
+            domain: DDS_DOMAIN_DEFAULT,
+            qos,
+            names: None,
+            timeout: 5.0,
+        }
+    }
+
+    /// Handle `arg` if it is a common option, taking its value from `value`.
+    /// Returns `Ok(false)` for other arguments.
+    pub fn parse(
+        &mut self,
+        arg: &str,
+        mut value: impl FnMut() -> Option<String>,
+    ) -> Result<bool, String> {
+        let mut value = || value().ok_or_else(|| format!("{} needs a value", arg));
+        let positive = |v: String| {
+            positive(&v).ok_or_else(|| format!("{} needs a positive number, not {}", arg, v))
+        };
+        match arg {
+            "-d" | "--domain" => {
+                let v = value()?;
+                self.domain = v.parse().map_err(|_| format!("bad domain {}", v))?;
+            }
+            "-p" | "--partition" => {
+                let mut partitions = self.qos.get_partition().unwrap_or_default();
+                partitions.push(value()?);
+                self.qos.partition(&partitions);
+            }
+            "-r" | "--reliability" => {
+                let kind = match value()?.as_str() {
+                    "reliable" => dds_reliability_kind::DDS_RELIABILITY_RELIABLE,
+                    "best-effort" => dds_reliability_kind::DDS_RELIABILITY_BEST_EFFORT,
+                    other => return Err(format!("bad reliability {}", other)),
+                };
+                self.qos.reliability(kind, DdsDuration::from_millis(100));
+            }
+            "--durability" => {
+                let kind = match value()?.as_str() {
+                    "volatile" => dds_durability_kind::DDS_DURABILITY_VOLATILE,
+                    "transient-local" => dds_durability_kind::DDS_DURABILITY_TRANSIENT_LOCAL,
+                    "transient" => dds_durability_kind::DDS_DURABILITY_TRANSIENT,
+                    "persistent" => dds_durability_kind::DDS_DURABILITY_PERSISTENT,
+                    other => return Err(format!("bad durability {}", other)),
+                };
+                self.qos.durability(kind);
+            }
+            "--history" => {
+                let v = value()?;
+                match v.strip_prefix("keep-last=") {
+                    Some(depth) => {
+                        let depth = depth.parse().map_err(|_| format!("bad history {}", v))?;
+                        self.qos
+                            .history(dds_history_kind::DDS_HISTORY_KEEP_LAST, depth);
+                    }
+                    None if v == "keep-all" => {
+                        self.qos.history(dds_history_kind::DDS_HISTORY_KEEP_ALL, 0);
+                    }
+                    None => return Err(format!("bad history {}", v)),
+                }
+            }
+            "--deadline" => {
+                let secs = positive(value()?)?;
+                self.qos.deadline(Duration::from_secs_f64(secs));
+            }
+            "--names" => {
+                self.names = Some(value()?.split(',').map(|s| s.trim().to_owned()).collect());
+            }
+            "-t" | "--timeout" => self.timeout = positive(value()?)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    pub fn participant(&self) -> DdsEntity {
+        let p = unsafe { dds_create_participant(self.domain, ptr::null(), ptr::null()) };
+        if p < 0 {
+            fail(&format!("cannot create participant: {}", DDSError::from(p)));
+        }
+        unsafe { DdsEntity::new(p) }
+    }
+
+    /// The type of `topic`, named with `--names` if given
+    pub fn discover(&self, participant: &DdsEntity, topic: &str) -> DynType {
+        let timeout = Duration::from_secs_f64(self.timeout);
+        let ty = DynType::discover(participant, topic, timeout.into())
+            .unwrap_or_else(|e| fail(&format!("{}: {}", topic, e)));
+        match &self.names {
+            Some(names) => ty
+                .with_names(names)
+                .unwrap_or_else(|e| fail(&format!("--names: {}", e))),
+            None => ty,
+        }
+    }
+}
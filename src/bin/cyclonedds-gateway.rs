@@ -0,0 +1,834 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Serve topics to web clients as JSON over HTTP and WebSocket.
+//!
+//! ```text
+//! cyclonedds-gateway [OPTIONS] [-l ADDRESS] CONFIG
+//! ```
+//!
+//! Only the topics of the JSON configuration file are served:
+//!
+//! ```text
+//! {
+//!   "listen": "127.0.0.1:8080",
+//!   "origins": ["http://localhost:3000"],
+//!   "topics": {
+//!     "Position": { "names": ["id", "x", "y"], "reliability": "best-effort" },
+//!     "Command": { "access": "read-write", "partition": ["robot1"],
+//!                  "durability": "transient-local", "history": "keep-last=1" }
+//!   }
+//! }
+//! ```
+//!
+//! `access` is `read` (the default), `write` or `read-write`. The other
+//! keys of a topic are the options of `ddssub` without the dashes; the
+//! options on the command line are the defaults for all topics and
+//! partitions add to those given there. The type of a topic is found
+//! through type discovery, unless the configuration gives its `type` name
+//! together with the serializer `ops` of its descriptor.
+//!
+//! * `GET /topics` lists the topics.
+//! * `GET /topics/NAME` streams the samples of a topic, one JSON object per
+//!   line, with `topic`, `source_timestamp`, `instance_state` and `data`.
+//! * `GET /topics/NAME` as a WebSocket upgrade sends the same objects as
+//!   text messages and publishes the text messages it receives, answering
+//!   `{"error": ...}` for those that cannot be published.
+//! * `POST /topics/NAME` publishes the samples of the body, one JSON value
+//!   per line.
+//!
+//! Requests from web pages, those with an `Origin` header, are refused
+//! unless their origin is listed in `origins`. Clients that do not keep up
+//! lose samples. At most 64 connections are served at once, further ones
+//! are answered with 503 until one closes.
+
+mod common;
+
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::exit;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use common::{catch_signals, fail, stopped, CommonOptions, COMMON_USAGE};
+use cyclonedds_sys::dynamic::DynType;
+use cyclonedds_sys::*;
+use serde_json::{json, Value};
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+const DEFAULT_LISTEN: &str = "127.0.0.1:8080";
+/// Topic keys that are `CommonOptions`
+const OPTION_KEYS: &[&str] = &[
+    "partition",
+    "reliability",
+    "durability",
+    "history",
+    "deadline",
+    "names",
+];
+/// Samples queued for a client before it starts losing them
+const QUEUE: usize = 256;
+const MAX_HEAD: u64 = 16 * 1024;
+const MAX_BODY: usize = 16 * 1024 * 1024;
+const POLL: Duration = Duration::from_millis(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Connections served at once, streams only end when the client leaves
+const MAX_CONNECTIONS: usize = 64;
+
+const OK: &str = "200 OK";
+const NO_CONTENT: &str = "204 No Content";
+const BAD_REQUEST: &str = "400 Bad Request";
+const FORBIDDEN: &str = "403 Forbidden";
+const NOT_FOUND: &str = "404 Not Found";
+const METHOD_NOT_ALLOWED: &str = "405 Method Not Allowed";
+const LENGTH_REQUIRED: &str = "411 Length Required";
+const PAYLOAD_TOO_LARGE: &str = "413 Payload Too Large";
+const INTERNAL_ERROR: &str = "500 Internal Server Error";
+const UNAVAILABLE: &str = "503 Service Unavailable";
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: cyclonedds-gateway [OPTIONS] [-l ADDRESS] CONFIG\n{}\n  -l ADDRESS         address to listen on (default from CONFIG, else {})\n\nThe QoS options are the defaults for the topics of CONFIG.",
+        COMMON_USAGE, DEFAULT_LISTEN
+    );
+    exit(2)
+}
+
+fn instance_state(state: dds_instance_state_t) -> &'static str {
+    if state == dds_instance_state_DDS_IST_ALIVE {
+        "alive"
+    } else if state == dds_instance_state_DDS_IST_NOT_ALIVE_DISPOSED {
+        "disposed"
+    } else {
+        "no-writers"
+    }
+}
+
+struct TopicConfig {
+    name: String,
+    read: bool,
+    write: bool,
+    ty: Option<DynType>,
+    options: CommonOptions,
+}
+
+struct Config {
+    listen: String,
+    origins: Vec<String>,
+    topics: Vec<TopicConfig>,
+}
+
+fn strings(values: &[Value]) -> Option<Vec<String>> {
+    values
+        .iter()
+        .map(|v| v.as_str().map(str::to_owned))
+        .collect()
+}
+
+fn topic_config(
+    name: &str,
+    config: &Value,
+    defaults: &CommonOptions,
+) -> Result<TopicConfig, String> {
+    let config = config.as_object().ok_or("must be an object")?;
+    let mut options = defaults.clone();
+    let mut access = (true, false);
+    let mut type_name = None;
+    let mut ops = None;
+    for (key, value) in config {
+        match key.as_str() {
+            "access" => {
+                access = match value.as_str() {
+                    Some("read") => (true, false),
+                    Some("write") => (false, true),
+                    Some("read-write") => (true, true),
+                    _ => return Err("access must be read, write or read-write".into()),
+                }
+            }
+            "type" => type_name = Some(value.as_str().ok_or("type must be a string")?),
+            "ops" => {
+                ops = Some(
+                    value
+                        .as_array()
+                        .and_then(|ops| {
+                            ops.iter()
+                                .map(|op| op.as_u64().filter(|&op| op <= u64::from(u32::MAX)))
+                                .map(|op| op.map(|op| op as u32))
+                                .collect::<Option<Vec<u32>>>()
+                        })
+                        .ok_or("ops must be an array of 32-bit numbers")?,
+                )
+            }
+            key if OPTION_KEYS.contains(&key) => {
+                let args = match (key, value) {
+                    (_, Value::String(s)) => Some(vec![s.clone()]),
+                    (_, Value::Number(n)) => Some(vec![n.to_string()]),
+                    ("partition", Value::Array(a)) => strings(a),
+                    ("names", Value::Array(a)) => strings(a).map(|names| vec![names.join(",")]),
+                    _ => None,
+                };
+                for arg in args.ok_or_else(|| format!("bad value for {}", key))? {
+                    options.parse(&format!("--{}", key), || Some(arg.clone()))?;
+                }
+            }
+            other => return Err(format!("unknown key {}", other)),
+        }
+    }
+    let ty = match (type_name, ops) {
+        (Some(type_name), Some(ops)) => {
+            let ty = DynType::from_ops(type_name, &ops).and_then(|ty| match &options.names {
+                Some(names) => ty.with_names(names),
+                None => Ok(ty),
+            });
+            Some(ty.map_err(|e| e.to_string())?)
+        }
+        (None, None) => None,
+        _ => return Err("type and ops must be given together".into()),
+    };
+    Ok(TopicConfig {
+        name: name.to_owned(),
+        read: access.0,
+        write: access.1,
+        ty,
+        options,
+    })
+}
+
+fn load_config(path: &str, defaults: &CommonOptions) -> Result<Config, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let json: Value = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+    let mut config = Config {
+        listen: DEFAULT_LISTEN.to_owned(),
+        origins: Vec::new(),
+        topics: Vec::new(),
+    };
+    for (key, value) in json.as_object().ok_or("must be a JSON object")? {
+        match key.as_str() {
+            "listen" => config.listen = value.as_str().ok_or("listen must be a string")?.to_owned(),
+            "origins" => {
+                config.origins = value
+                    .as_array()
+                    .and_then(|a| strings(a))
+                    .ok_or("origins must be an array of strings")?
+            }
+            "topics" => {
+                for (name, topic) in value.as_object().ok_or("topics must be an object")? {
+                    let topic = topic_config(name, topic, defaults)
+                        .map_err(|e| format!("topic {}: {}", name, e))?;
+                    config.topics.push(topic);
+                }
+            }
+            other => return Err(format!("unknown key {}", other)),
+        }
+    }
+    if config.topics.is_empty() {
+        return Err("no topics".into());
+    }
+    Ok(config)
+}
+
+/// An error answered with its status and `{"error": message}`
+struct HttpError {
+    status: &'static str,
+    message: String,
+}
+
+impl HttpError {
+    fn new(status: &'static str, message: impl Into<String>) -> Self {
+        HttpError {
+            status,
+            message: message.into(),
+        }
+    }
+
+    fn send(&self, stream: &TcpStream, origin: Option<&str>) {
+        let body = json!({ "error": self.message }).to_string();
+        respond(stream, self.status, origin, "application/json", &body);
+    }
+}
+
+fn cors(origin: Option<&str>) -> String {
+    origin.map_or_else(String::new, |origin| {
+        format!(
+            "Access-Control-Allow-Origin: {}\r\nVary: Origin\r\n",
+            origin
+        )
+    })
+}
+
+/// Write a complete response. A client that went away is not our problem.
+fn respond(
+    mut stream: &TcpStream,
+    status: &str,
+    origin: Option<&str>,
+    content_type: &str,
+    body: &str,
+) {
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        cors(origin),
+        body
+    );
+}
+
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Whether the comma separated header `name` holds `token`
+    fn has_token(&self, name: &str, token: &str) -> bool {
+        self.header(name)
+            .is_some_and(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+    }
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            out.push(u8::from_str_radix(s.get(i + 1..i + 3)?, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+fn read_request(reader: &mut BufReader<&TcpStream>) -> Result<Request, HttpError> {
+    let bad = |message: &str| HttpError::new(BAD_REQUEST, message);
+    let mut head = Vec::new();
+    // the head ends with an empty line
+    while !head.ends_with(b"\n\n") && !head.ends_with(b"\r\n\r\n") {
+        let limit = MAX_HEAD.saturating_sub(head.len() as u64);
+        match reader.by_ref().take(limit).read_until(b'\n', &mut head) {
+            Ok(0) => return Err(bad("incomplete or too large request head")),
+            Ok(_) => {}
+            Err(e) => return Err(bad(&e.to_string())),
+        }
+    }
+    let head = std::str::from_utf8(&head).map_err(|_| bad("request head is not UTF-8"))?;
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (method, target) = match (request_line.next(), request_line.next()) {
+        (Some(method), Some(target)) if !method.is_empty() => (method, target),
+        _ => return Err(bad("bad request line")),
+    };
+    let path = target.split('?').next().unwrap_or_default();
+    let mut request = Request {
+        method: method.to_owned(),
+        path: percent_decode(path).ok_or_else(|| bad("bad path"))?,
+        headers: Vec::new(),
+        body: Vec::new(),
+    };
+    for line in lines.filter(|l| !l.is_empty()) {
+        let (name, value) = line.split_once(':').ok_or_else(|| bad("bad header"))?;
+        request
+            .headers
+            .push((name.trim().to_owned(), value.trim().to_owned()));
+    }
+
+    if request.method == "POST" {
+        if request.header("Transfer-Encoding").is_some() {
+            return Err(HttpError::new(LENGTH_REQUIRED, "send a Content-Length"));
+        }
+        let length: usize = request
+            .header("Content-Length")
+            .ok_or_else(|| HttpError::new(LENGTH_REQUIRED, "send a Content-Length"))?
+            .parse()
+            .map_err(|_| bad("bad Content-Length"))?;
+        if length > MAX_BODY {
+            return Err(HttpError::new(PAYLOAD_TOO_LARGE, "body too large"));
+        }
+        request.body = vec![0; length];
+        reader
+            .read_exact(&mut request.body)
+            .map_err(|e| bad(&e.to_string()))?;
+    }
+    Ok(request)
+}
+
+struct Topic {
+    name: String,
+    ty: DynType,
+    reader: Option<DdsEntity>,
+    writer: Option<DdsEntity>,
+    clients: Mutex<Vec<SyncSender<Arc<str>>>>,
+}
+
+impl Topic {
+    fn lock_clients(&self) -> MutexGuard<'_, Vec<SyncSender<Arc<str>>>> {
+        self.clients.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// A queue of the samples to come, `None` if the topic is not readable
+    fn subscribe(&self) -> Option<Receiver<Arc<str>>> {
+        self.reader.as_ref()?;
+        let (tx, rx) = mpsc::sync_channel(QUEUE);
+        self.lock_clients().push(tx);
+        Some(rx)
+    }
+
+    /// Publish a sample given as JSON
+    fn publish(&self, json: &str) -> Result<(), HttpError> {
+        let writer = self.writer.as_ref().ok_or_else(|| {
+            HttpError::new(FORBIDDEN, format!("topic {} is not writable", self.name))
+        })?;
+        let json: Value =
+            serde_json::from_str(json).map_err(|e| HttpError::new(BAD_REQUEST, e.to_string()))?;
+        let data = self
+            .ty
+            .from_json(&json)
+            .and_then(|value| self.ty.encode(&value))
+            .map_err(|e| HttpError::new(BAD_REQUEST, e.to_string()))?;
+        writer
+            .write_raw(&data)
+            .map_err(|e| HttpError::new(INTERNAL_ERROR, format!("write failed: {}", e)))
+    }
+
+    /// Take what the reader has and queue it for the clients
+    fn pump(&self) {
+        let reader = match &self.reader {
+            Some(reader) => reader,
+            None => return,
+        };
+        let samples = match reader.take_raw(64) {
+            Ok(samples) => samples,
+            Err(e) => {
+                eprintln!("{}: take failed: {}", self.name, e);
+                return;
+            }
+        };
+        if samples.is_empty() {
+            return;
+        }
+        let mut clients = self.lock_clients();
+        for sample in samples {
+            let data = if sample.valid_data {
+                match self.ty.decode(&sample.data) {
+                    Ok(value) => self.ty.to_json(&value),
+                    Err(e) => {
+                        eprintln!("{}: cannot decode sample: {}", self.name, e);
+                        continue;
+                    }
+                }
+            } else {
+                Value::Null
+            };
+            let line: Arc<str> = json!({
+                "topic": self.name,
+                "source_timestamp": sample.source_timestamp.as_nanos(),
+                "instance_state": instance_state(sample.instance_state),
+                "data": data,
+            })
+            .to_string()
+            .into();
+            clients.retain(|client| {
+                !matches!(
+                    client.try_send(line.clone()),
+                    Err(TrySendError::Disconnected(_))
+                )
+            });
+        }
+    }
+}
+
+struct Gateway {
+    topics: BTreeMap<String, Topic>,
+    origins: Vec<String>,
+}
+
+impl Gateway {
+    fn list(&self) -> Value {
+        self.topics
+            .values()
+            .map(|topic| {
+                json!({
+                    "name": topic.name,
+                    "type": topic.ty.type_name(),
+                    "read": topic.reader.is_some(),
+                    "write": topic.writer.is_some(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// One of the `MAX_CONNECTIONS` connections, given back on drop
+struct Slot(Arc<AtomicUsize>);
+
+impl Slot {
+    fn take(connections: &Arc<AtomicUsize>) -> Option<Slot> {
+        if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            connections.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        Some(Slot(connections.clone()))
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn serve(gateway: &Gateway, stream: TcpStream) {
+    if stream.set_read_timeout(Some(REQUEST_TIMEOUT)).is_err() {
+        return;
+    }
+    let mut reader = BufReader::new(&stream);
+    let request = match read_request(&mut reader) {
+        Ok(request) => request,
+        Err(e) => return e.send(&stream, None),
+    };
+    // WebSocket clients may send frames right after the head
+    let buffered = reader.buffer().to_vec();
+
+    let origin = request.header("Origin");
+    if let Some(origin) = origin.filter(|o| !gateway.origins.iter().any(|a| a == o)) {
+        let message = format!("origin {} is not allowed", origin);
+        return HttpError::new(FORBIDDEN, message).send(&stream, None);
+    }
+    if let Err(e) = dispatch(gateway, &stream, &request, origin, buffered) {
+        e.send(&stream, origin);
+    }
+}
+
+fn dispatch(
+    gateway: &Gateway,
+    stream: &TcpStream,
+    request: &Request,
+    origin: Option<&str>,
+    buffered: Vec<u8>,
+) -> Result<(), HttpError> {
+    let not_allowed = || HttpError::new(METHOD_NOT_ALLOWED, "method not allowed");
+    if request.path == "/topics" {
+        return match request.method.as_str() {
+            "GET" => {
+                let body = gateway.list().to_string();
+                respond(stream, OK, origin, "application/json", &body);
+                Ok(())
+            }
+            "OPTIONS" => preflight(stream, origin),
+            _ => Err(not_allowed()),
+        };
+    }
+    let topic = request
+        .path
+        .strip_prefix("/topics/")
+        .and_then(|name| gateway.topics.get(name))
+        .ok_or_else(|| HttpError::new(NOT_FOUND, format!("{} is not served", request.path)))?;
+    match request.method.as_str() {
+        "GET"
+            if request.has_token("Connection", "upgrade")
+                && request.has_token("Upgrade", "websocket") =>
+        {
+            websocket(topic, stream, request, buffered)
+        }
+        "GET" => stream_samples(topic, stream, origin),
+        "POST" => post(topic, stream, request, origin),
+        "OPTIONS" => preflight(stream, origin),
+        _ => Err(not_allowed()),
+    }
+}
+
+fn preflight(mut stream: &TcpStream, origin: Option<&str>) -> Result<(), HttpError> {
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {}\r\nAccess-Control-Allow-Methods: GET, POST\r\nAccess-Control-Allow-Headers: Content-Type\r\nConnection: close\r\n{}\r\n",
+        NO_CONTENT,
+        cors(origin)
+    );
+    Ok(())
+}
+
+fn not_readable(topic: &Topic) -> HttpError {
+    HttpError::new(FORBIDDEN, format!("topic {} is not readable", topic.name))
+}
+
+fn stream_samples(
+    topic: &Topic,
+    mut stream: &TcpStream,
+    origin: Option<&str>,
+) -> Result<(), HttpError> {
+    let samples = topic.subscribe().ok_or_else(|| not_readable(topic))?;
+    // without a length the body ends when the connection does
+    let head = write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/x-ndjson\r\nCache-Control: no-cache\r\nConnection: close\r\n{}\r\n",
+        OK,
+        cors(origin)
+    );
+    if head.is_err() {
+        return Ok(());
+    }
+    while !stopped() {
+        match samples.recv_timeout(Duration::from_millis(100)) {
+            Ok(line) => {
+                if writeln!(stream, "{}", line).is_err() {
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    Ok(())
+}
+
+fn post(
+    topic: &Topic,
+    stream: &TcpStream,
+    request: &Request,
+    origin: Option<&str>,
+) -> Result<(), HttpError> {
+    let body = std::str::from_utf8(&request.body)
+        .map_err(|_| HttpError::new(BAD_REQUEST, "body is not UTF-8"))?;
+    let mut written = 0;
+    for (n, line) in body.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        topic.publish(line).map_err(|e| {
+            let message = format!("line {}: {} ({} written)", n + 1, e.message, written);
+            HttpError::new(e.status, message)
+        })?;
+        written += 1;
+    }
+    let body = json!({ "written": written }).to_string();
+    respond(stream, OK, origin, "application/json", &body);
+    Ok(())
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+fn websocket(
+    topic: &Topic,
+    stream: &TcpStream,
+    request: &Request,
+    buffered: Vec<u8>,
+) -> Result<(), HttpError> {
+    let key = request
+        .header("Sec-WebSocket-Key")
+        .ok_or_else(|| HttpError::new(BAD_REQUEST, "missing Sec-WebSocket-Key"))?;
+    if topic.writer.is_none() && topic.reader.is_none() {
+        return Err(not_readable(topic));
+    }
+    let samples = topic.subscribe();
+    let mut stream = stream
+        .try_clone()
+        .map_err(|e| HttpError::new(INTERNAL_ERROR, e.to_string()))?;
+    let accepted = write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        derive_accept_key(key.as_bytes())
+    );
+    // reads time out so that samples go out while the client is quiet
+    if accepted.is_err() || stream.set_read_timeout(Some(POLL)).is_err() {
+        return Ok(());
+    }
+
+    let mut ws = WebSocket::from_partially_read(stream, buffered, Role::Server, None);
+    while !stopped() {
+        if let Some(samples) = &samples {
+            for line in samples.try_iter() {
+                if ws.write(Message::Text(line.to_string())).is_err() {
+                    return Ok(());
+                }
+            }
+            if ws.flush().is_err() {
+                return Ok(());
+            }
+        }
+        match ws.read() {
+            Ok(Message::Text(json)) => {
+                if let Err(e) = topic.publish(&json) {
+                    let reply = json!({ "error": e.message }).to_string();
+                    if ws.send(Message::Text(reply)).is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+            // pings and the closing handshake are answered by tungstenite
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e)) if is_timeout(&e) => {}
+            Err(_) => return Ok(()),
+        }
+    }
+    let _ = ws.close(None);
+    let _ = ws.flush();
+    Ok(())
+}
+
+fn main() {
+    let mut common = CommonOptions::new();
+    let mut listen = None;
+    let mut config_path = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match common.parse(&arg, || args.next()) {
+            Ok(true) => continue,
+            Ok(false) => {}
+            Err(e) => {
+                eprintln!("{}", e);
+                usage()
+            }
+        }
+        match arg.as_str() {
+            "-l" | "--listen" => listen = Some(args.next().unwrap_or_else(|| usage())),
+            "-h" | "--help" => usage(),
+            _ if arg.starts_with('-') || config_path.is_some() => usage(),
+            _ => config_path = Some(arg),
+        }
+    }
+    let config_path = config_path.unwrap_or_else(|| usage());
+    let config = load_config(&config_path, &common)
+        .unwrap_or_else(|e| fail(&format!("{}: {}", config_path, e)));
+    let listen = listen.unwrap_or(config.listen);
+    catch_signals();
+
+    let participant = common.participant();
+    let mut topics = BTreeMap::new();
+    for topic in config.topics {
+        let TopicConfig {
+            name,
+            read,
+            write,
+            ty,
+            options,
+        } = topic;
+        let ty = ty.unwrap_or_else(|| options.discover(&participant, &name));
+        let cannot_serve = |e: DDSError| -> ! { fail(&format!("cannot serve {}: {}", name, e)) };
+        let entity = |e: dds_entity_t| {
+            DDSError::check(e)
+                .map(|_| unsafe { DdsEntity::new(e) })
+                .unwrap_or_else(|e| cannot_serve(e))
+        };
+        let topic = participant
+            .create_raw_topic_with_type(&name, &ty, None)
+            .unwrap_or_else(|e| cannot_serve(e));
+        let qos = options.qos.as_ptr();
+        let reader = if read {
+            Some(entity(unsafe {
+                dds_create_reader(participant.entity(), topic.entity(), qos, ptr::null())
+            }))
+        } else {
+            None
+        };
+        let writer = if write {
+            Some(entity(unsafe {
+                dds_create_writer(participant.entity(), topic.entity(), qos, ptr::null())
+            }))
+        } else {
+            None
+        };
+        let topic = Topic {
+            name: name.clone(),
+            ty,
+            reader,
+            writer,
+            clients: Mutex::new(Vec::new()),
+        };
+        topics.insert(name, topic);
+    }
+    let gateway = Arc::new(Gateway {
+        topics,
+        origins: config.origins,
+    });
+
+    let listener = TcpListener::bind(&listen)
+        .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+        .unwrap_or_else(|e| fail(&format!("cannot listen on {}: {}", listen, e)));
+    if let Ok(address) = listener.local_addr() {
+        eprintln!(
+            "serving {} topics on http://{}/topics",
+            gateway.topics.len(),
+            address
+        );
+    }
+
+    let pump = {
+        let gateway = gateway.clone();
+        thread::spawn(move || {
+            while !stopped() {
+                for topic in gateway.topics.values() {
+                    topic.pump();
+                }
+                thread::sleep(POLL);
+            }
+        })
+    };
+
+    let connections = Arc::new(AtomicUsize::new(0));
+    while !stopped() {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                if stream.set_nonblocking(false).is_err() {
+                    continue;
+                }
+                let slot = match Slot::take(&connections) {
+                    Some(slot) => slot,
+                    None => {
+                        let _ = stream.set_write_timeout(Some(POLL));
+                        HttpError::new(UNAVAILABLE, "too many connections").send(&stream, None);
+                        continue;
+                    }
+                };
+                let gateway = gateway.clone();
+                thread::spawn(move || {
+                    serve(&gateway, stream);
+                    drop(slot);
+                });
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL),
+            Err(e) => eprintln!("accept failed: {}", e),
+        }
+    }
+    let _ = pump.join();
+    let _ = participant.delete();
+}
@@ -0,0 +1,870 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Latency and throughput measurements over the Rust API, along the lines
+//! of Cyclone's `ddsperf`.
+//!
+//! ```text
+//! cyclonedds-perf [OPTIONS] ping|pong|pub|sub
+//! ```
+//!
+//! `ping` sends a sample and waits for `pong` to send it back, measuring
+//! the round trip. `pub` writes as fast as it can (or at `--rate`) and
+//! `sub` reports the rate, lost samples and the one-way latency; the
+//! latter compares clocks, so it only means something when both run on
+//! the same machine. With `--local` the other side runs in a thread of the
+//! same process; otherwise start it in another process with the same
+//! `-s`, `-r` and `-w` options.
+//!
+//! Every sample is a fixed size struct, `uint32 seq; uint32 pad; int64
+//! stamp; octet payload[N]`, so the type name carries the size and
+//! differently sized peers do not match.
+
+mod common;
+
+use std::convert::TryInto;
+use std::ffi::{c_void, CString};
+use std::ptr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{catch_signals, fail, seconds, stop, stopped};
+
+use cyclonedds_sys::*;
+
+const USAGE: &str = "usage: cyclonedds-perf [OPTIONS] MODE
+  MODE is one of
+    ping  measure the round-trip latency to pong
+    pong  send every ping back
+    pub   publish as fast as possible (or at --rate)
+    sub   measure the throughput and one-way latency of pub
+  -d DOMAIN    domain id (default from the Cyclone configuration)
+  -s SIZE      sample size in bytes, at least 16 (default 16)
+  -r KIND      reliable (default) or best-effort
+  -w PATH      how samples are written and read: copy (default), loan
+               (shared memory loans, needs the shm feature and iceoryx) or
+               serdata (serialized samples through a raw topic)
+  -D SECONDS   stop after this long (default: when interrupted)
+  --rate HZ    samples (round trips for ping) per second (default: no limit)
+  -l, --local  run pong (for ping) or sub (for pub) in this process
+  -H           print the latency histogram at the end";
+
+const PING_TOPIC: &str = "CyclonePerfPing";
+const PONG_TOPIC: &str = "CyclonePerfPong";
+const DATA_TOPIC: &str = "CyclonePerfData";
+
+/// Sequence number, padding and timestamp in front of the payload
+const HEADER: usize = 16;
+
+/// Samples taken at once
+const BATCH: usize = 64;
+
+/// A ping without an answer in this time counts as lost
+const PONG_TIMEOUT: Duration = Duration::from_secs(1);
+
+fn usage() -> ! {
+    common::usage(USAGE)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Ping,
+    Pong,
+    Pub,
+    Sub,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum WritePath {
+    /// `dds_write` of an in-memory sample, `dds_take` with loaned samples
+    Copy,
+    /// `dds_loan_sample` before writing, so shared memory can skip copies
+    Loan,
+    /// Serialized samples through `write_raw`/`take_raw`
+    Serdata,
+}
+
+#[derive(Clone)]
+struct Options {
+    mode: Mode,
+    domain: dds_domainid_t,
+    size: usize,
+    reliable: bool,
+    path: WritePath,
+    duration: Option<Duration>,
+    rate: Option<f64>,
+    local: bool,
+    histogram: bool,
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Options {
+    let mut mode = None;
+    let mut options = Options {
+        mode: Mode::Ping,
+        domain: DDS_DOMAIN_DEFAULT,
+        size: HEADER,
+        reliable: true,
+        path: WritePath::Copy,
+        duration: None,
+        rate: None,
+        local: false,
+        histogram: false,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .unwrap_or_else(|| fail(&format!("{} needs a value", name)))
+        };
+        match arg.as_str() {
+            "-d" | "--domain" => options.domain = value(&arg).parse().unwrap_or_else(|_| usage()),
+            "-s" | "--size" => options.size = value(&arg).parse().unwrap_or_else(|_| usage()),
+            "-r" | "--reliability" => {
+                options.reliable = match value(&arg).as_str() {
+                    "reliable" => true,
+                    "best-effort" => false,
+                    _ => usage(),
+                }
+            }
+            "-w" | "--write" => {
+                options.path = match value(&arg).as_str() {
+                    "copy" => WritePath::Copy,
+                    "loan" => WritePath::Loan,
+                    "serdata" => WritePath::Serdata,
+                    _ => usage(),
+                }
+            }
+            "-D" | "--duration" => {
+                let secs = seconds(&value(&arg)).unwrap_or_else(|| usage());
+                options.duration = Some(Duration::from_secs_f64(secs))
+            }
+            "--rate" => {
+                let rate: f64 = value(&arg).parse().unwrap_or_else(|_| usage());
+                options.rate = Some(rate).filter(|r| *r > 0.0);
+            }
+            "-l" | "--local" => options.local = true,
+            "-H" | "--histogram" => options.histogram = true,
+            "ping" if mode.is_none() => mode = Some(Mode::Ping),
+            "pong" if mode.is_none() => mode = Some(Mode::Pong),
+            "pub" if mode.is_none() => mode = Some(Mode::Pub),
+            "sub" if mode.is_none() => mode = Some(Mode::Sub),
+            _ => usage(),
+        }
+    }
+    options.mode = mode.unwrap_or_else(|| usage());
+    if options.size < HEADER {
+        fail(&format!(
+            "the sample size must be at least {} bytes",
+            HEADER
+        ));
+    }
+    if options.local && matches!(options.mode, Mode::Pong | Mode::Sub) {
+        fail("--local goes with ping or pub");
+    }
+    options
+}
+
+/// Round `n` up to a multiple of `align`, a power of two
+fn round_up(n: usize, align: usize) -> usize {
+    (n + align - 1) & !(align - 1)
+}
+
+/// The descriptor of the sample type. It lives as long as the process:
+/// topics keep pointing into it.
+fn descriptor(type_name: &str, size: usize) -> &'static dds_topic_descriptor_t {
+    let adr = dds_stream_opcode_DDS_OP_ADR;
+    let mut ops = vec![
+        adr | dds_stream_typecode_primary_DDS_OP_TYPE_4BY,
+        0,
+        adr | dds_stream_typecode_primary_DDS_OP_TYPE_4BY,
+        4,
+        adr | dds_stream_typecode_primary_DDS_OP_TYPE_8BY,
+        8,
+    ];
+    if size > HEADER {
+        ops.extend_from_slice(&[
+            adr | dds_stream_typecode_primary_DDS_OP_TYPE_ARR
+                | dds_stream_typecode_subtype_DDS_OP_SUBTYPE_1BY,
+            HEADER as u32,
+            (size - HEADER) as u32,
+        ]);
+    }
+    ops.push(dds_stream_opcode_DDS_OP_RTS);
+    let ops: &'static [u32] = Box::leak(ops.into_boxed_slice());
+    let type_name = Box::leak(CString::new(type_name).unwrap().into_boxed_c_str());
+    Box::leak(Box::new(dds_topic_descriptor_t {
+        m_size: round_up(size, 8) as u32,
+        m_align: 8,
+        // fixed size types are what shared memory loans need
+        m_flagset: DDS_TOPIC_FIXED_SIZE,
+        m_nkeys: 0,
+        m_typename: type_name.as_ptr(),
+        m_keys: ptr::null(),
+        m_nops: ops.len() as u32,
+        m_ops: ops.as_ptr(),
+        m_meta: b"\0".as_ptr() as *const _,
+        ..Default::default()
+    }))
+}
+
+fn participant(options: &Options) -> DdsEntity {
+    let p = unsafe { dds_create_participant(options.domain, ptr::null(), ptr::null()) };
+    if p < 0 {
+        fail(&format!("cannot create participant: {}", DDSError::from(p)));
+    }
+    unsafe { DdsEntity::new(p) }
+}
+
+fn topic(participant: &DdsEntity, options: &Options, name: &str) -> DdsEntity {
+    let type_name = format!("CyclonePerf::Sample_{}", options.size);
+    match options.path {
+        WritePath::Serdata => participant.create_raw_topic(name, &type_name, None),
+        WritePath::Copy | WritePath::Loan => {
+            let descriptor = descriptor(&type_name, options.size);
+            let c_name = CString::new(name).unwrap();
+            let t = unsafe {
+                dds_create_topic(
+                    participant.entity(),
+                    descriptor,
+                    c_name.as_ptr(),
+                    ptr::null(),
+                    ptr::null(),
+                )
+            };
+            DDSError::check(t).map(|_| unsafe { DdsEntity::new(t) })
+        }
+    }
+    .unwrap_or_else(|e| fail(&format!("cannot create topic {}: {}", name, e)))
+}
+
+fn qos(options: &Options) -> DdsQos {
+    let mut qos = DdsQos::new();
+    let kind = if options.reliable {
+        dds_reliability_kind::DDS_RELIABILITY_RELIABLE
+    } else {
+        dds_reliability_kind::DDS_RELIABILITY_BEST_EFFORT
+    };
+    qos.reliability(kind, DdsDuration::from_secs(1));
+    match options.path {
+        // iceoryx only carries keep-last data
+        WritePath::Loan => qos.history(dds_history_kind::DDS_HISTORY_KEEP_LAST, 16),
+        WritePath::Copy | WritePath::Serdata => {
+            qos.history(dds_history_kind::DDS_HISTORY_KEEP_ALL, 0)
+        }
+    };
+    qos
+}
+
+#[derive(Clone, Copy)]
+struct Header {
+    seq: u32,
+    stamp: i64,
+}
+
+fn now() -> i64 {
+    DdsTime::now().as_nanos()
+}
+
+struct Sender {
+    writer: DdsEntity,
+    path: WritePath,
+    size: usize,
+    /// In-memory sample for the copy path, `u64`s for the alignment
+    sample: Vec<u64>,
+    /// Little endian CDR for the serdata path
+    cdr: Vec<u8>,
+}
+
+impl Sender {
+    fn new(participant: &DdsEntity, options: &Options, topic_name: &str) -> Self {
+        let topic = topic(participant, options, topic_name);
+        let w = unsafe {
+            dds_create_writer(
+                participant.entity(),
+                topic.entity(),
+                qos(options).as_ptr(),
+                ptr::null(),
+            )
+        };
+        if w < 0 {
+            fail(&format!("cannot create writer: {}", DDSError::from(w)));
+        }
+        let writer = unsafe { DdsEntity::new(w) };
+        if options.path == WritePath::Loan && !unsafe { dds_is_loan_available(writer.entity()) } {
+            fail("loans are not available: build with the shm feature and enable shared memory in the Cyclone configuration");
+        }
+        let mut cdr = vec![0; 4 + options.size];
+        cdr[1] = 1;
+        Sender {
+            writer,
+            path: options.path,
+            size: options.size,
+            sample: vec![0; round_up(options.size, 8) / 8],
+            cdr,
+        }
+    }
+
+    fn send(&mut self, header: Header) -> Result<(), DDSError> {
+        let ret = match self.path {
+            WritePath::Copy => unsafe {
+                let sample = self.sample.as_mut_ptr() as *mut u8;
+                write_header(sample, header);
+                dds_write(self.writer.entity(), sample as *const c_void)
+            },
+            WritePath::Loan => unsafe {
+                let mut sample = ptr::null_mut();
+                DDSError::check(dds_loan_sample(self.writer.entity(), &mut sample))?;
+                let sample = sample as *mut u8;
+                write_header(sample, header);
+                ptr::write_bytes(sample.add(HEADER), 0, self.size - HEADER);
+                let ret = dds_write(self.writer.entity(), sample as *const c_void);
+                if ret < 0 {
+                    // the loan is only taken over by a successful write
+                    let mut sample = sample as *mut c_void;
+                    dds_return_loan(self.writer.entity(), &mut sample, 1);
+                }
+                ret
+            },
+            WritePath::Serdata => {
+                self.cdr[4..8].copy_from_slice(&header.seq.to_le_bytes());
+                self.cdr[12..20].copy_from_slice(&header.stamp.to_le_bytes());
+                return self.writer.write_raw(&self.cdr);
+            }
+        };
+        DDSError::check(ret).map(drop)
+    }
+}
+
+unsafe fn write_header(sample: *mut u8, header: Header) {
+    ptr::write(sample as *mut u32, header.seq);
+    ptr::write(sample.add(8) as *mut i64, header.stamp);
+}
+
+unsafe fn read_header(sample: *const u8) -> Header {
+    Header {
+        seq: ptr::read(sample as *const u32),
+        stamp: ptr::read(sample.add(8) as *const i64),
+    }
+}
+
+/// The header of a serialized sample, in either byte order
+fn parse_cdr(data: &[u8]) -> Option<Header> {
+    if data.len() < 4 + HEADER {
+        return None;
+    }
+    let little_endian = data[1] & 1 == 1;
+    let seq: [u8; 4] = data[4..8].try_into().unwrap();
+    let stamp: [u8; 8] = data[12..20].try_into().unwrap();
+    Some(if little_endian {
+        Header {
+            seq: u32::from_le_bytes(seq),
+            stamp: i64::from_le_bytes(stamp),
+        }
+    } else {
+        Header {
+            seq: u32::from_be_bytes(seq),
+            stamp: i64::from_be_bytes(stamp),
+        }
+    })
+}
+
+struct Receiver {
+    reader: DdsEntity,
+    waitset: DdsEntity,
+    path: WritePath,
+}
+
+impl Receiver {
+    fn new(participant: &DdsEntity, options: &Options, topic_name: &str) -> Self {
+        let topic = topic(participant, options, topic_name);
+        let create = || -> Result<Receiver, DDSError> {
+            let r = unsafe {
+                dds_create_reader(
+                    participant.entity(),
+                    topic.entity(),
+                    qos(options).as_ptr(),
+                    ptr::null(),
+                )
+            };
+            DDSError::check(r)?;
+            let reader = unsafe { DdsEntity::new(r) };
+            reader.set_status_mask(StatusKind::DdsDataAvailableStatus)?;
+            let ws = unsafe { dds_create_waitset(participant.entity()) };
+            DDSError::check(ws)?;
+            let waitset = unsafe { DdsEntity::new(ws) };
+            DDSError::check(unsafe { dds_waitset_attach(ws, r, 0) })?;
+            Ok(Receiver {
+                reader,
+                waitset,
+                path: options.path,
+            })
+        };
+        create().unwrap_or_else(|e| fail(&format!("cannot create reader: {}", e)))
+    }
+
+    /// Wait until data arrives, returning false after `timeout`
+    fn wait(&self, timeout: Duration) -> bool {
+        let timeout = DdsDuration::from(timeout).as_nanos();
+        unsafe { dds_waitset_wait(self.waitset.entity(), ptr::null_mut(), 0, timeout) > 0 }
+    }
+
+    fn take(&self, headers: &mut Vec<Header>) -> Result<(), DDSError> {
+        headers.clear();
+        match self.path {
+            WritePath::Serdata => {
+                let samples = self.reader.take_raw(BATCH)?;
+                headers.extend(
+                    samples
+                        .iter()
+                        .filter(|s| s.valid_data)
+                        .filter_map(|s| parse_cdr(&s.data)),
+                );
+            }
+            WritePath::Copy | WritePath::Loan => unsafe {
+                let mut samples = [ptr::null_mut::<c_void>(); BATCH];
+                let mut infos: [dds_sample_info_t; BATCH] = std::mem::zeroed();
+                let n = DDSError::check(dds_take(
+                    self.reader.entity(),
+                    samples.as_mut_ptr(),
+                    infos.as_mut_ptr(),
+                    BATCH as size_t,
+                    BATCH as u32,
+                ))?;
+                for (sample, info) in samples.iter().zip(&infos).take(n as usize) {
+                    if info.valid_data {
+                        headers.push(read_header(*sample as *const u8));
+                    }
+                }
+                DDSError::check(dds_return_loan(
+                    self.reader.entity(),
+                    samples.as_mut_ptr(),
+                    n,
+                ))?;
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Latencies in nanoseconds, in buckets of an eighth of a power of two
+struct Histogram {
+    buckets: Vec<u64>,
+    count: u64,
+    sum: u64,
+    min: u64,
+    max: u64,
+}
+
+const SUB_BITS: u32 = 3;
+
+impl Histogram {
+    fn new() -> Self {
+        Histogram {
+            buckets: vec![0; ((64 - SUB_BITS as usize) + 1) << SUB_BITS],
+            count: 0,
+            sum: 0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+
+    fn index(value: u64) -> usize {
+        if value < 1 << SUB_BITS {
+            return value as usize;
+        }
+        let exp = 63 - value.leading_zeros();
+        let sub = (value >> (exp - SUB_BITS)) & ((1 << SUB_BITS) - 1);
+        (((exp - SUB_BITS + 1) as usize) << SUB_BITS) + sub as usize
+    }
+
+    /// The smallest value in bucket `index`
+    fn lower(index: usize) -> u64 {
+        if index < 1 << SUB_BITS {
+            return index as u64;
+        }
+        let exp = (index >> SUB_BITS) as u32 + SUB_BITS - 1;
+        let sub = (index & ((1 << SUB_BITS) - 1)) as u64;
+        (1 << exp) | (sub << (exp - SUB_BITS))
+    }
+
+    fn record(&mut self, value: u64) {
+        self.buckets[Self::index(value)] += 1;
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    fn clear(&mut self) {
+        *self = Histogram::new();
+    }
+
+    fn percentile(&self, p: f64) -> u64 {
+        let target = ((p / 100.0) * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (index, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return Self::lower(index).clamp(self.min, self.max);
+            }
+        }
+        self.max
+    }
+
+    fn summary(&self) -> String {
+        if self.count == 0 {
+            return "no samples".to_owned();
+        }
+        let us = |ns: u64| ns as f64 / 1000.0;
+        format!(
+            "{} samples  min {:.1}  mean {:.1}  50% {:.1}  90% {:.1}  99% {:.1}  99.9% {:.1}  max {:.1} us",
+            self.count,
+            us(self.min),
+            us(self.sum / self.count),
+            us(self.percentile(50.0)),
+            us(self.percentile(90.0)),
+            us(self.percentile(99.0)),
+            us(self.percentile(99.9)),
+            us(self.max),
+        )
+    }
+
+    fn print(&self) {
+        let largest = self.buckets.iter().copied().max().unwrap_or(0).max(1);
+        for (index, &count) in self.buckets.iter().enumerate() {
+            if count > 0 {
+                let bar = ((count * 50) / largest).max(1) as usize;
+                println!(
+                    "{:>12.1} us {:>10} {}",
+                    Self::lower(index) as f64 / 1000.0,
+                    count,
+                    "#".repeat(bar)
+                );
+            }
+        }
+    }
+}
+
+/// Spaces out samples when there is a rate limit
+struct Pacer {
+    period: Option<Duration>,
+    next: Instant,
+}
+
+impl Pacer {
+    fn new(rate: Option<f64>) -> Self {
+        Pacer {
+            period: rate.map(|r| Duration::from_secs_f64((1.0 / r).min(u32::MAX as f64))),
+            next: Instant::now(),
+        }
+    }
+
+    fn wait(&mut self) {
+        if let Some(period) = self.period {
+            let now = Instant::now();
+            if self.next > now {
+                thread::sleep(self.next - now);
+            } else if now - self.next > period {
+                // fell behind: do not try to catch up
+                self.next = now;
+            }
+            self.next += period;
+        }
+    }
+}
+
+/// Prints a line a second
+struct Reporter {
+    start: Instant,
+    next: Instant,
+}
+
+impl Reporter {
+    fn new() -> Self {
+        let start = Instant::now();
+        Reporter {
+            start,
+            next: start + Duration::from_secs(1),
+        }
+    }
+
+    /// The elapsed time when a report is due
+    fn due(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        if now < self.next {
+            return None;
+        }
+        self.next += Duration::from_secs(1);
+        Some(now - self.start)
+    }
+}
+
+fn running(options: &Options, start: Instant) -> bool {
+    let within = match options.duration {
+        Some(d) => start.elapsed() < d,
+        None => true,
+    };
+    !stopped() && within
+}
+
+/// Wait until the writer has a reader, returning false when interrupted
+fn wait_for_peer(writer: &DdsEntity, peer: &str) -> bool {
+    let matched = || {
+        writer
+            .publication_matched_status()
+            .is_ok_and(|s| s.current_count > 0)
+    };
+    if !matched() {
+        eprintln!("waiting for {} ...", peer);
+    }
+    while !matched() {
+        if stopped() {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    true
+}
+
+fn ping(options: &Options) {
+    let participant = participant(options);
+    let mut sender = Sender::new(&participant, options, PING_TOPIC);
+    let receiver = Receiver::new(&participant, options, PONG_TOPIC);
+    if !wait_for_peer(&sender.writer, "pong") {
+        return;
+    }
+    // the pong writer also has to have matched our reader
+    while !stopped()
+        && receiver
+            .reader
+            .subscription_matched_status()
+            .is_ok_and(|s| s.current_count == 0)
+    {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let mut total = Histogram::new();
+    let mut interval = Histogram::new();
+    let mut pacer = Pacer::new(options.rate);
+    let mut reporter = Reporter::new();
+    let mut headers = Vec::new();
+    let mut seq = 0u32;
+    let mut lost = 0;
+    let start = Instant::now();
+    'ping: while running(options, start) {
+        pacer.wait();
+        seq = seq.wrapping_add(1);
+        sender
+            .send(Header { seq, stamp: now() })
+            .unwrap_or_else(|e| fail(&format!("write failed: {}", e)));
+        let timeout = Instant::now() + PONG_TIMEOUT;
+        'reply: loop {
+            let left = timeout.saturating_duration_since(Instant::now());
+            if stopped() {
+                break 'ping;
+            }
+            if left.is_zero() {
+                lost += 1;
+                break;
+            }
+            if !receiver.wait(left) {
+                continue;
+            }
+            receiver
+                .take(&mut headers)
+                .unwrap_or_else(|e| fail(&format!("take failed: {}", e)));
+            let t = now();
+            // answers to earlier pings that timed out are dropped
+            if let Some(header) = headers.iter().find(|h| h.seq == seq) {
+                let rtt = (t - header.stamp).max(0) as u64;
+                interval.record(rtt);
+                total.record(rtt);
+                break 'reply;
+            }
+        }
+        if let Some(elapsed) = reporter.due() {
+            println!(
+                "ping {:>5}s  rtt {}  lost {}",
+                elapsed.as_secs(),
+                interval.summary(),
+                lost
+            );
+            interval.clear();
+        }
+    }
+    println!("ping total  rtt {}  lost {}", total.summary(), lost);
+    if options.histogram {
+        total.print();
+    }
+}
+
+fn pong(options: &Options) {
+    let participant = participant(options);
+    let receiver = Receiver::new(&participant, options, PING_TOPIC);
+    let mut sender = Sender::new(&participant, options, PONG_TOPIC);
+    let mut headers = Vec::new();
+    let start = Instant::now();
+    while running(options, start) {
+        if !receiver.wait(Duration::from_millis(100)) {
+            continue;
+        }
+        receiver
+            .take(&mut headers)
+            .unwrap_or_else(|e| fail(&format!("take failed: {}", e)));
+        for header in &headers {
+            sender
+                .send(*header)
+                .unwrap_or_else(|e| fail(&format!("write failed: {}", e)));
+        }
+    }
+}
+
+fn megabits(samples: u64, size: usize, time: Duration) -> f64 {
+    (samples * size as u64 * 8) as f64 / time.as_secs_f64() / 1e6
+}
+
+fn publish(options: &Options) {
+    let participant = participant(options);
+    let mut sender = Sender::new(&participant, options, DATA_TOPIC);
+    if !wait_for_peer(&sender.writer, "sub") {
+        return;
+    }
+
+    let mut pacer = Pacer::new(options.rate);
+    let mut reporter = Reporter::new();
+    let mut seq = 0u32;
+    let mut sent = 0;
+    let mut last_sent = 0;
+    let mut last_report = Duration::from_secs(0);
+    let start = Instant::now();
+    while running(options, start) {
+        pacer.wait();
+        let header = Header {
+            seq: seq.wrapping_add(1),
+            stamp: now(),
+        };
+        match sender.send(header) {
+            Ok(()) => {
+                seq = header.seq;
+                sent += 1;
+            }
+            // a reliable writer waiting for slow readers: try again
+            Err(DDSError::Timeout) => {}
+            Err(e) => fail(&format!("write failed: {}", e)),
+        }
+        if let Some(elapsed) = reporter.due() {
+            let n = sent - last_sent;
+            let period = elapsed - last_report;
+            println!(
+                "pub  {:>5}s  {:.0} samples/s  {:.2} Mb/s",
+                elapsed.as_secs(),
+                n as f64 / period.as_secs_f64(),
+                megabits(n, options.size, period)
+            );
+            last_sent = sent;
+            last_report = elapsed;
+        }
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "pub  total  {} samples  {:.0} samples/s  {:.2} Mb/s",
+        sent,
+        sent as f64 / elapsed.as_secs_f64(),
+        megabits(sent, options.size, elapsed)
+    );
+}
+
+fn subscribe(options: &Options) {
+    let participant = participant(options);
+    let receiver = Receiver::new(&participant, options, DATA_TOPIC);
+
+    let mut total = Histogram::new();
+    let mut interval = Histogram::new();
+    let mut reporter = Reporter::new();
+    let mut headers = Vec::new();
+    let mut last_seq: Option<u32> = None;
+    let mut lost = 0;
+    let mut last_report = Duration::from_secs(0);
+    let start = Instant::now();
+    while running(options, start) {
+        if receiver.wait(Duration::from_millis(100)) {
+            receiver
+                .take(&mut headers)
+                .unwrap_or_else(|e| fail(&format!("take failed: {}", e)));
+            let t = now();
+            for header in &headers {
+                // gaps are lost samples; going back means pub restarted
+                if let Some(last) = last_seq {
+                    if header.seq > last {
+                        lost += (header.seq - last - 1) as u64;
+                    }
+                }
+                last_seq = Some(header.seq);
+                let latency = (t - header.stamp).max(0) as u64;
+                interval.record(latency);
+                total.record(latency);
+            }
+        }
+        if let Some(elapsed) = reporter.due() {
+            let period = elapsed - last_report;
+            println!(
+                "sub  {:>5}s  {:.0} samples/s  {:.2} Mb/s  lost {}  latency {}",
+                elapsed.as_secs(),
+                interval.count as f64 / period.as_secs_f64(),
+                megabits(interval.count, options.size, period),
+                lost,
+                interval.summary()
+            );
+            interval.clear();
+            last_report = elapsed;
+        }
+    }
+    println!("sub  total  lost {}  latency {}", lost, total.summary());
+    if options.histogram {
+        total.print();
+    }
+}
+
+fn main() {
+    let options = parse_options(std::env::args().skip(1));
+    catch_signals();
+
+    // the local peer runs until the measurement is done
+    let peer = if options.local {
+        let peer_options = Options {
+            duration: None,
+            histogram: false,
+            ..options.clone()
+        };
+        Some(thread::spawn(move || match peer_options.mode {
+            Mode::Ping => pong(&peer_options),
+            _ => subscribe(&peer_options),
+        }))
+    } else {
+        None
+    };
+
+    match options.mode {
+        Mode::Ping => ping(&options),
+        Mode::Pong => pong(&options),
+        Mode::Pub => publish(&options),
+        Mode::Sub => subscribe(&options),
+    }
+
+    stop();
+    if let Some(peer) = peer {
+        let _ = peer.join();
+    }
+}
@@ -0,0 +1,532 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Record the serialized samples of a domain to a file and play them back.
+//!
+//! ```text
+//! cyclonedds-record record [-d DOMAIN] [-o FILE] [-t SECONDS] [PATTERN...]
+//! cyclonedds-record replay [-d DOMAIN] [-s SPEED] [-w SECONDS] FILE
+//! cyclonedds-record convert [--schema TYPE=FILE]... IN OUT
+//! ```
+//!
+//! Recordings are written in the format of `cyclonedds_sys::recording`, or
+//! as MCAP when the output name ends in `.mcap` or `-f mcap` is given. MCAP
+//! schemas are read from IDL (`omgidl`) or ROS 2 `.msg` (`ros2msg`) files
+//! named with `--schema`; other types get an `omgidl` schema generated from
+//! the type found by type discovery, or a channel without schema if that
+//! fails. Channels differing only in partitions are kept apart.
+//!
+//! The recorder looks up the type of every topic through type discovery and
+//! stores it with the channel, so replay can key its samples the way the
+//! original writers did. Replayed samples keep their source timestamps.
+
+mod common;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::ptr;
+use std::time::{Duration, Instant};
+
+use common::{catch_signals, fail, seconds, stopped};
+
+use cyclonedds_sys::discovery::{glob_match, EndpointDiscovery, EndpointEvent};
+use cyclonedds_sys::dynamic::TypeMeta;
+use cyclonedds_sys::matched::MatchedEndpoint;
+use cyclonedds_sys::mcap::{
+    omgidl_schema_for_type, ros2msg_schema_name, McapWriter, Schema, SCHEMA_ENCODING_OMGIDL,
+    SCHEMA_ENCODING_ROS2MSG,
+};
+use cyclonedds_sys::recording::{Record, RecordedSample, RecordingReader, RecordingWriter};
+use cyclonedds_sys::*;
+
+const USAGE: &str = "usage:
+  cyclonedds-record record [-d DOMAIN] [-o FILE] [-f FORMAT] [-t SECONDS]
+                           [--schema TYPE=FILE]... [PATTERN...]
+      record the topics matching the glob PATTERNs (all if none) to FILE
+      (default recording.ddsrec or .mcap) until interrupted or SECONDS have passed;
+      FORMAT is ddsrec or mcap (default from the extension of FILE)
+  cyclonedds-record replay [-d DOMAIN] [-s SPEED] [-w SECONDS] FILE
+      publish the samples in FILE with their original timing, SPEED times
+      faster (0 for no delays), after waiting SECONDS (default 1) for
+      readers to be discovered
+  cyclonedds-record convert [-f FORMAT] [--schema TYPE=FILE]... IN OUT
+      convert the ddsrec recording IN to OUT";
+
+fn usage() -> ! {
+    common::usage(USAGE)
+}
+
+struct Options {
+    domain: dds_domainid_t,
+    output: Option<String>,
+    format: Option<String>,
+    schemas: HashMap<String, Schema>,
+    duration: Option<f64>,
+    speed: f64,
+    wait: f64,
+    args: Vec<String>,
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Options {
+    let mut options = Options {
+        domain: DDS_DOMAIN_DEFAULT,
+        output: None,
+        format: None,
+        schemas: HashMap::new(),
+        duration: None,
+        speed: 1.0,
+        wait: 1.0,
+        args: Vec::new(),
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .unwrap_or_else(|| fail(&format!("{} needs a value", name)))
+        };
+        match arg.as_str() {
+            "-d" | "--domain" => options.domain = value(&arg).parse().unwrap_or_else(|_| usage()),
+            "-o" | "--output" => options.output = Some(value(&arg)),
+            "-f" | "--format" => options.format = Some(value(&arg)),
+            "--schema" => {
+                let (type_name, schema) = read_schema(&value(&arg));
+                options.schemas.insert(type_name, schema);
+            }
+            "-t" | "--duration" => {
+                options.duration = Some(seconds(&value(&arg)).unwrap_or_else(|| usage()))
+            }
+            "-s" | "--speed" => options.speed = value(&arg).parse().unwrap_or_else(|_| usage()),
+            "-w" | "--wait" => options.wait = seconds(&value(&arg)).unwrap_or_else(|| usage()),
+            "-h" | "--help" => usage(),
+            _ if arg.starts_with('-') => usage(),
+            _ => options.args.push(arg),
+        }
+    }
+    options
+}
+
+/// Read `TYPE=FILE`, the encoding following from the extension of FILE
+fn read_schema(arg: &str) -> (String, Schema) {
+    let (type_name, path) = match arg.split_once('=') {
+        Some(parts) => parts,
+        None => usage(),
+    };
+    let data = std::fs::read_to_string(path).unwrap_or_else(|e| fail(&format!("{}: {}", path, e)));
+    let schema = if path.ends_with(".msg") {
+        Schema {
+            name: ros2msg_schema_name(type_name),
+            encoding: SCHEMA_ENCODING_ROS2MSG.to_owned(),
+            data,
+        }
+    } else {
+        Schema {
+            name: type_name.to_owned(),
+            encoding: SCHEMA_ENCODING_OMGIDL.to_owned(),
+            data,
+        }
+    };
+    (type_name.to_owned(), schema)
+}
+
+enum Output {
+    Ddsrec(RecordingWriter<BufWriter<File>>),
+    Mcap(McapWriter<BufWriter<File>>, HashMap<String, Schema>),
+}
+
+impl Output {
+    fn create(path: &str, format: Option<&str>, schemas: &HashMap<String, Schema>) -> Self {
+        let mcap = match format {
+            Some("mcap") => true,
+            Some("ddsrec") => false,
+            Some(_) => usage(),
+            None => path.ends_with(".mcap"),
+        };
+        let output = File::create(path).map(BufWriter::new).and_then(|file| {
+            if mcap {
+                McapWriter::new(file, "cyclonedds-record").map(|w| Output::Mcap(w, schemas.clone()))
+            } else {
+                RecordingWriter::new(file).map(Output::Ddsrec)
+            }
+        });
+        output.unwrap_or_else(|e| fail(&format!("{}: {}", path, e)))
+    }
+
+    fn add_channel(
+        &mut self,
+        topic_name: &str,
+        type_name: &str,
+        partitions: &[String],
+        meta: Option<&TypeMeta>,
+    ) -> io::Result<u32> {
+        match self {
+            Output::Ddsrec(w) => w.add_channel(topic_name, type_name, partitions, meta),
+            Output::Mcap(w, schemas) => {
+                let partitions = partitions.join(",");
+                let discovered = match schemas.get(type_name) {
+                    Some(_) => None,
+                    None => meta.and_then(|meta| omgidl_schema_for_type(meta).ok()),
+                };
+                w.channel(
+                    topic_name,
+                    type_name,
+                    schemas.get(type_name).or(discovered.as_ref()),
+                    &[("partitions", &partitions)],
+                )
+                .map(u32::from)
+            }
+        }
+    }
+
+    fn write_sample(&mut self, sample: &RecordedSample) -> io::Result<()> {
+        match self {
+            Output::Ddsrec(w) => w.write_sample(sample),
+            Output::Mcap(w, _) => w.write_message(
+                sample.channel as u16,
+                sample.log_time,
+                sample.source_timestamp,
+                &sample.data,
+            ),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Ddsrec(w) => w.flush(),
+            Output::Mcap(w, _) => w.flush(),
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Output::Ddsrec(mut w) => w.flush(),
+            Output::Mcap(w, _) => w.finish().map(drop),
+        }
+    }
+}
+
+/// How long the recorder waits for type discovery of a new topic
+const TYPE_LOOKUP_TIMEOUT: Duration = Duration::from_secs(1);
+
+fn participant(domain: dds_domainid_t) -> DdsEntity {
+    let p = unsafe { dds_create_participant(domain, ptr::null(), ptr::null()) };
+    if p < 0 {
+        fail(&format!("cannot create participant: {}", DDSError::from(p)));
+    }
+    unsafe { DdsEntity::new(p) }
+}
+
+/// A raw topic, keyed as the original if its type is known
+fn raw_topic(
+    participant: &DdsEntity,
+    topic_name: &str,
+    type_name: &str,
+    meta: Option<&TypeMeta>,
+) -> Result<DdsEntity, DDSError> {
+    match meta.map(TypeMeta::dyn_type) {
+        Some(Ok(ty)) => participant.create_raw_topic_with_type(topic_name, &ty, None),
+        _ => participant.create_raw_topic(topic_name, type_name, None),
+    }
+}
+
+/// Reliable, keeping everything, and in the same partitions and with the
+/// same durability as the writer the channel was found through
+fn channel_qos(partitions: &[String], durability: Option<dds_durability_kind>) -> DdsQos {
+    let mut qos = DdsQos::new();
+    qos.reliability(
+        dds_reliability_kind::DDS_RELIABILITY_RELIABLE,
+        DdsDuration::from_millis(100),
+    )
+    .history(dds_history_kind::DDS_HISTORY_KEEP_ALL, 0);
+    if !partitions.is_empty() {
+        qos.partition(partitions);
+    }
+    if let Some(durability) = durability {
+        qos.durability(durability);
+    }
+    qos
+}
+
+struct Recorder {
+    reader: DdsEntity,
+    channel: u32,
+    writers: HashMap<dds_instance_handle_t, Guid>,
+}
+
+fn record(options: Options) {
+    let path = match (&options.output, options.format.as_deref()) {
+        (Some(path), _) => path.as_str(),
+        (None, Some("mcap")) => "recording.mcap",
+        (None, _) => "recording.ddsrec",
+    };
+    let mut out = Output::create(path, options.format.as_deref(), &options.schemas);
+
+    let participant = participant(options.domain);
+    let own = participant.guid().ok();
+    let discovery = EndpointDiscovery::publications(&participant)
+        .unwrap_or_else(|e| fail(&format!("cannot read DCPSPublication: {}", e)));
+
+    let selected =
+        |topic: &str| options.args.is_empty() || options.args.iter().any(|p| glob_match(p, topic));
+
+    let deadline = options
+        .duration
+        .map(|secs| Instant::now() + Duration::from_secs_f64(secs));
+    let mut recorders: HashMap<(String, String, Vec<String>), Recorder> = HashMap::new();
+    let mut count: u64 = 0;
+
+    let before_deadline = || match deadline {
+        Some(d) => Instant::now() < d,
+        None => true,
+    };
+    while !stopped() && before_deadline() {
+        for event in discovery.take().unwrap_or_default() {
+            let endpoint: MatchedEndpoint = match event {
+                EndpointEvent::Alive(endpoint) => endpoint,
+                EndpointEvent::Gone(_) => continue,
+            };
+            if Some(endpoint.participant_guid) == own || !selected(&endpoint.topic_name) {
+                continue;
+            }
+            let partitions = endpoint.qos.get_partition().unwrap_or_default();
+            let key = (
+                endpoint.topic_name.clone(),
+                endpoint.type_name.clone(),
+                partitions.clone(),
+            );
+            if recorders.contains_key(&key) {
+                continue;
+            }
+            let qos = channel_qos(&partitions, endpoint.qos.get_durability());
+            let meta = TypeMeta::discover(
+                &participant,
+                &endpoint.topic_name,
+                DdsDuration::from(TYPE_LOOKUP_TIMEOUT),
+            )
+            .ok()
+            .filter(|meta| meta.type_name == endpoint.type_name);
+            let reader = raw_topic(
+                &participant,
+                &endpoint.topic_name,
+                &endpoint.type_name,
+                meta.as_ref(),
+            )
+            .and_then(|topic| {
+                let r = unsafe {
+                    dds_create_reader(
+                        participant.entity(),
+                        topic.entity(),
+                        qos.as_ptr(),
+                        ptr::null(),
+                    )
+                };
+                DDSError::check(r).map(|_| unsafe { DdsEntity::new(r) })
+            });
+            let reader = match reader {
+                Ok(reader) => reader,
+                Err(e) => {
+                    eprintln!(
+                        "cannot record {} ({}): {}",
+                        endpoint.topic_name, endpoint.type_name, e
+                    );
+                    continue;
+                }
+            };
+            let channel = out
+                .add_channel(
+                    &endpoint.topic_name,
+                    &endpoint.type_name,
+                    &partitions,
+                    meta.as_ref(),
+                )
+                .unwrap_or_else(|e| fail(&format!("{}: {}", path, e)));
+            eprintln!("recording {} ({})", endpoint.topic_name, endpoint.type_name);
+            recorders.insert(
+                key,
+                Recorder {
+                    reader,
+                    channel,
+                    writers: HashMap::new(),
+                },
+            );
+        }
+
+        for recorder in recorders.values_mut() {
+            let samples = recorder.reader.take_raw(256).unwrap_or_default();
+            let log_time = DdsTime::now();
+            for sample in samples.into_iter().filter(|s| s.valid_data) {
+                let reader = &recorder.reader;
+                let writer = *recorder
+                    .writers
+                    .entry(sample.publication_handle)
+                    .or_insert_with(|| {
+                        reader
+                            .matched_writer(sample.publication_handle)
+                            .map(|w| w.guid)
+                            .unwrap_or_default()
+                    });
+                out.write_sample(&RecordedSample {
+                    channel: recorder.channel,
+                    log_time,
+                    source_timestamp: sample.source_timestamp,
+                    writer,
+                    data: sample.data,
+                })
+                .unwrap_or_else(|e| fail(&format!("{}: {}", path, e)));
+                count += 1;
+            }
+        }
+        out.flush()
+            .unwrap_or_else(|e| fail(&format!("{}: {}", path, e)));
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    eprintln!(
+        "recorded {} samples on {} topics to {}",
+        count,
+        recorders.len(),
+        path
+    );
+    out.finish()
+        .unwrap_or_else(|e| fail(&format!("{}: {}", path, e)));
+    let _ = participant.delete();
+}
+
+fn open(path: &str) -> RecordingReader<BufReader<File>> {
+    let file = File::open(path).unwrap_or_else(|e| fail(&format!("{}: {}", path, e)));
+    RecordingReader::new(BufReader::new(file)).unwrap_or_else(|e| fail(&format!("{}: {}", path, e)))
+}
+
+fn replay(options: Options) {
+    let path = match options.args.as_slice() {
+        [path] => path.clone(),
+        _ => usage(),
+    };
+    let participant = participant(options.domain);
+
+    // create all writers up front so they are discovered before the first
+    // sample goes out
+    let mut writers: HashMap<u32, DdsEntity> = HashMap::new();
+    for record in open(&path) {
+        if let Record::Channel(channel) =
+            record.unwrap_or_else(|e| fail(&format!("{}: {}", path, e)))
+        {
+            let qos = channel_qos(&channel.partitions, None);
+            let meta = channel.type_meta();
+            let writer = raw_topic(
+                &participant,
+                &channel.topic_name,
+                &channel.type_name,
+                meta.as_ref(),
+            )
+            .and_then(|topic| {
+                let w = unsafe {
+                    dds_create_writer(
+                        participant.entity(),
+                        topic.entity(),
+                        qos.as_ptr(),
+                        ptr::null(),
+                    )
+                };
+                DDSError::check(w).map(|_| unsafe { DdsEntity::new(w) })
+            })
+            .unwrap_or_else(|e| fail(&format!("cannot publish {}: {}", channel.topic_name, e)));
+            writers.insert(channel.id, writer);
+        }
+    }
+    std::thread::sleep(Duration::from_secs_f64(options.wait));
+
+    let start = Instant::now();
+    let mut first: Option<i64> = None;
+    let mut count: u64 = 0;
+    for record in open(&path) {
+        if stopped() {
+            break;
+        }
+        let sample = match record.unwrap_or_else(|e| fail(&format!("{}: {}", path, e))) {
+            Record::Sample(sample) => sample,
+            Record::Channel(_) => continue,
+        };
+        let t = sample.log_time.as_nanos();
+        let t0 = *first.get_or_insert(t);
+        if options.speed > 0.0 {
+            let due = start + Duration::from_nanos(((t - t0).max(0) as f64 / options.speed) as u64);
+            let now = Instant::now();
+            if due > now {
+                std::thread::sleep(due - now);
+            }
+        }
+        if let Some(writer) = writers.get(&sample.channel) {
+            match writer.write_raw_ts(&sample.data, sample.source_timestamp) {
+                Ok(()) => count += 1,
+                Err(e) => eprintln!("write failed: {}", e),
+            }
+        }
+    }
+    eprintln!("replayed {} samples", count);
+    let _ = participant.delete();
+}
+
+fn convert(options: Options) {
+    let (input, output) = match options.args.as_slice() {
+        [input, output] => (input, output),
+        _ => usage(),
+    };
+    let mut out = Output::create(output, options.format.as_deref(), &options.schemas);
+    // channel ids in the input to those in the output
+    let mut channels: HashMap<u32, u32> = HashMap::new();
+    let mut count: u64 = 0;
+    for record in open(input) {
+        let written = match record.unwrap_or_else(|e| fail(&format!("{}: {}", input, e))) {
+            Record::Channel(channel) => out
+                .add_channel(
+                    &channel.topic_name,
+                    &channel.type_name,
+                    &channel.partitions,
+                    channel.type_meta().as_ref(),
+                )
+                .map(|id| {
+                    channels.insert(channel.id, id);
+                }),
+            Record::Sample(mut sample) => match channels.get(&sample.channel) {
+                Some(id) => {
+                    sample.channel = *id;
+                    count += 1;
+                    out.write_sample(&sample)
+                }
+                None => Ok(()),
+            },
+        };
+        written.unwrap_or_else(|e| fail(&format!("{}: {}", output, e)));
+    }
+    out.finish()
+        .unwrap_or_else(|e| fail(&format!("{}: {}", output, e)));
+    eprintln!("converted {} samples", count);
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let command = args.next().unwrap_or_else(|| usage());
+    let options = parse_options(args);
+
+    catch_signals();
+
+    match command.as_str() {
+        "record" => record(options),
+        "replay" => replay(options),
+        "convert" => convert(options),
+        _ => usage(),
+    }
+}
@@ -0,0 +1,559 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! List the participants, topics, writers and readers in a domain.
+//!
+//! ```text
+//! ddsls [-d DOMAIN] [-p PARTITION]... [-w SECONDS] [--watch] [--json] [WHAT...]
+//! ```
+//!
+//! Topics are those used by the discovered writers and readers. Matched
+//! counts are worked out from the discovered QoS (topic, type, partitions,
+//! reliability and durability), so they are what the endpoints should see
+//! rather than what they report.
+
+mod common;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ptr;
+use std::time::Duration;
+
+use common::{catch_signals, fail, seconds, stopped};
+
+use cyclonedds_sys::discovery::{
+    glob_match, DiscoveredParticipant, EndpointDiscovery, EndpointEvent, ParticipantDiscovery,
+    ParticipantEvent,
+};
+use cyclonedds_sys::matched::MatchedEndpoint;
+use cyclonedds_sys::*;
+
+const USAGE: &str =
+    "usage: ddsls [-d DOMAIN] [-p PARTITION]... [-w SECONDS] [--watch] [--json] [WHAT...]
+  WHAT is any of participants, topics, publications and subscriptions
+  (default all of them)
+  -p   only show writers and readers in a partition matching PARTITION
+  -w   time to wait for discovery before listing (default 1)
+  --watch  keep running and print changes as they are discovered
+  --json   print JSON, one object per change with --watch";
+
+fn usage() -> ! {
+    common::usage(USAGE)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    Participants,
+    Topics,
+    Publications,
+    Subscriptions,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Participants => "participant",
+            Kind::Topics => "topic",
+            Kind::Publications => "publication",
+            Kind::Subscriptions => "subscription",
+        }
+    }
+}
+
+struct Options {
+    domain: dds_domainid_t,
+    partitions: Vec<String>,
+    wait: f64,
+    watch: bool,
+    json: bool,
+    show: BTreeSet<Kind>,
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Options {
+    let mut options = Options {
+        domain: DDS_DOMAIN_DEFAULT,
+        partitions: Vec::new(),
+        wait: 1.0,
+        watch: false,
+        json: false,
+        show: BTreeSet::new(),
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .unwrap_or_else(|| fail(&format!("{} needs a value", name)))
+        };
+        match arg.as_str() {
+            "-d" | "--domain" => options.domain = value(&arg).parse().unwrap_or_else(|_| usage()),
+            "-p" | "--partition" => options.partitions.push(value(&arg)),
+            "-w" | "--wait" => options.wait = seconds(&value(&arg)).unwrap_or_else(|| usage()),
+            "--watch" => options.watch = true,
+            "--json" => options.json = true,
+            "participants" => drop(options.show.insert(Kind::Participants)),
+            "topics" => drop(options.show.insert(Kind::Topics)),
+            "publications" | "writers" => drop(options.show.insert(Kind::Publications)),
+            "subscriptions" | "readers" => drop(options.show.insert(Kind::Subscriptions)),
+            _ => usage(),
+        }
+    }
+    if options.show.is_empty() {
+        options.show = [
+            Kind::Participants,
+            Kind::Topics,
+            Kind::Publications,
+            Kind::Subscriptions,
+        ]
+        .iter()
+        .copied()
+        .collect();
+    }
+    options
+}
+
+/// Partitions of an endpoint, the default partition being the empty name
+fn partitions(qos: &DdsQos) -> Vec<String> {
+    match qos.get_partition() {
+        Some(p) if !p.is_empty() => p,
+        _ => vec![String::new()],
+    }
+}
+
+fn partitions_match(a: &[String], b: &[String]) -> bool {
+    a.iter()
+        .any(|a| b.iter().any(|b| glob_match(a, b) || glob_match(b, a)))
+}
+
+/// Whether the writer `w` and the reader `r` should match
+fn endpoints_match(w: &MatchedEndpoint, r: &MatchedEndpoint) -> bool {
+    let reliable = |qos: &DdsQos| {
+        qos.get_reliability()
+            .is_some_and(|(kind, _)| kind == dds_reliability_kind::DDS_RELIABILITY_RELIABLE)
+    };
+    let durability = |qos: &DdsQos| {
+        qos.get_durability()
+            .unwrap_or(dds_durability_kind::DDS_DURABILITY_VOLATILE) as u32
+    };
+    w.topic_name == r.topic_name
+        && w.type_name == r.type_name
+        && partitions_match(&partitions(&w.qos), &partitions(&r.qos))
+        && (reliable(&w.qos) || !reliable(&r.qos))
+        && durability(&w.qos) >= durability(&r.qos)
+}
+
+/// A short description of the QoS that decide matching and delivery
+fn qos_summary(qos: &DdsQos) -> Vec<(&'static str, String)> {
+    let mut summary = Vec::new();
+    if let Some((kind, _)) = qos.get_reliability() {
+        let kind = match kind {
+            dds_reliability_kind::DDS_RELIABILITY_RELIABLE => "reliable",
+            dds_reliability_kind::DDS_RELIABILITY_BEST_EFFORT => "best-effort",
+        };
+        summary.push(("reliability", kind.to_owned()));
+    }
+    if let Some(kind) = qos.get_durability() {
+        let kind = match kind {
+            dds_durability_kind::DDS_DURABILITY_VOLATILE => "volatile",
+            dds_durability_kind::DDS_DURABILITY_TRANSIENT_LOCAL => "transient-local",
+            dds_durability_kind::DDS_DURABILITY_TRANSIENT => "transient",
+            dds_durability_kind::DDS_DURABILITY_PERSISTENT => "persistent",
+        };
+        summary.push(("durability", kind.to_owned()));
+    }
+    if let Some((kind, depth)) = qos.get_history() {
+        let history = match kind {
+            dds_history_kind::DDS_HISTORY_KEEP_LAST => format!("keep-last {}", depth),
+            dds_history_kind::DDS_HISTORY_KEEP_ALL => "keep-all".to_owned(),
+        };
+        summary.push(("history", history));
+    }
+    if let Some(dds_ownership_kind::DDS_OWNERSHIP_EXCLUSIVE) = qos.get_ownership() {
+        summary.push(("ownership", "exclusive".to_owned()));
+    }
+    if let Some(deadline) = qos.get_deadline().filter(|d| !d.is_infinite()) {
+        summary.push((
+            "deadline",
+            format!("{:?}", deadline.to_std().unwrap_or_default()),
+        ));
+    }
+    if let Some(p) = qos.get_partition().filter(|p| !p.is_empty()) {
+        summary.push(("partitions", p.join(",")));
+    }
+    summary
+}
+
+fn userdata(qos: &DdsQos) -> Option<String> {
+    qos.get_userdata()
+        .filter(|u| !u.is_empty())
+        .map(|u| String::from_utf8_lossy(&u).into_owned())
+}
+
+fn json_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_object(fields: &[(&str, String)]) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(k, v)| format!("{}:{}", json_str(k), v))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+#[derive(Default)]
+struct Domain {
+    participants: BTreeMap<dds_instance_handle_t, DiscoveredParticipant>,
+    publications: BTreeMap<dds_instance_handle_t, MatchedEndpoint>,
+    subscriptions: BTreeMap<dds_instance_handle_t, MatchedEndpoint>,
+}
+
+impl Domain {
+    fn matched(&self, kind: Kind, endpoint: &MatchedEndpoint) -> usize {
+        match kind {
+            Kind::Publications => self
+                .subscriptions
+                .values()
+                .filter(|r| endpoints_match(endpoint, r))
+                .count(),
+            _ => self
+                .publications
+                .values()
+                .filter(|w| endpoints_match(w, endpoint))
+                .count(),
+        }
+    }
+
+    fn endpoints(&self, kind: Kind) -> &BTreeMap<dds_instance_handle_t, MatchedEndpoint> {
+        match kind {
+            Kind::Publications => &self.publications,
+            _ => &self.subscriptions,
+        }
+    }
+
+    /// (topic, type) to (writers, readers)
+    fn topics(&self) -> BTreeMap<(&str, &str), (usize, usize)> {
+        let mut topics = BTreeMap::new();
+        for w in self.publications.values() {
+            topics
+                .entry((w.topic_name.as_str(), w.type_name.as_str()))
+                .or_insert((0, 0))
+                .0 += 1;
+        }
+        for r in self.subscriptions.values() {
+            topics
+                .entry((r.topic_name.as_str(), r.type_name.as_str()))
+                .or_insert((0, 0))
+                .1 += 1;
+        }
+        topics
+    }
+
+    fn endpoint_counts(&self, participant: &Guid) -> (usize, usize) {
+        let count = |m: &BTreeMap<_, MatchedEndpoint>| {
+            m.values()
+                .filter(|e| e.participant_guid == *participant)
+                .count()
+        };
+        (count(&self.publications), count(&self.subscriptions))
+    }
+}
+
+fn participant_json(domain: &Domain, p: &DiscoveredParticipant) -> Vec<(&'static str, String)> {
+    let (writers, readers) = domain.endpoint_counts(&p.guid);
+    let mut fields = vec![
+        ("guid", json_str(&p.guid.to_string())),
+        ("writers", writers.to_string()),
+        ("readers", readers.to_string()),
+    ];
+    if let Some(u) = userdata(&p.qos) {
+        fields.push(("userdata", json_str(&u)));
+    }
+    fields
+}
+
+fn participant_text(domain: &Domain, p: &DiscoveredParticipant) -> String {
+    let (writers, readers) = domain.endpoint_counts(&p.guid);
+    let mut line = format!("{}  writers {}  readers {}", p.guid, writers, readers);
+    if let Some(u) = userdata(&p.qos) {
+        line.push_str(&format!("  userdata {:?}", u));
+    }
+    line
+}
+
+fn endpoint_json(domain: &Domain, kind: Kind, e: &MatchedEndpoint) -> Vec<(&'static str, String)> {
+    let qos = qos_summary(&e.qos)
+        .into_iter()
+        .map(|(k, v)| (k, json_str(&v)))
+        .collect::<Vec<_>>();
+    vec![
+        ("guid", json_str(&e.guid.to_string())),
+        ("participant", json_str(&e.participant_guid.to_string())),
+        ("topic", json_str(&e.topic_name)),
+        ("type", json_str(&e.type_name)),
+        ("qos", json_object(&qos)),
+        ("matched", domain.matched(kind, e).to_string()),
+    ]
+}
+
+fn endpoint_text(domain: &Domain, kind: Kind, e: &MatchedEndpoint) -> String {
+    let qos: Vec<String> = qos_summary(&e.qos)
+        .into_iter()
+        .map(|(k, v)| match k {
+            "partitions" | "deadline" => format!("{}={}", k, v),
+            _ => v,
+        })
+        .collect();
+    format!(
+        "{}  {}  {}  [{}]  matched {}",
+        e.guid,
+        e.topic_name,
+        e.type_name,
+        qos.join(" "),
+        domain.matched(kind, e)
+    )
+}
+
+fn list(domain: &Domain, options: &Options) {
+    let topics = domain.topics();
+    if options.json {
+        let mut sections = Vec::new();
+        for kind in &options.show {
+            let items: Vec<String> = match kind {
+                Kind::Participants => domain
+                    .participants
+                    .values()
+                    .map(|p| json_object(&participant_json(domain, p)))
+                    .collect(),
+                Kind::Topics => topics
+                    .iter()
+                    .map(|((topic, type_name), (w, r))| {
+                        json_object(&[
+                            ("name", json_str(topic)),
+                            ("type", json_str(type_name)),
+                            ("writers", w.to_string()),
+                            ("readers", r.to_string()),
+                        ])
+                    })
+                    .collect(),
+                _ => domain
+                    .endpoints(*kind)
+                    .values()
+                    .map(|e| json_object(&endpoint_json(domain, *kind, e)))
+                    .collect(),
+            };
+            let name = match kind {
+                Kind::Participants => "participants",
+                Kind::Topics => "topics",
+                Kind::Publications => "publications",
+                Kind::Subscriptions => "subscriptions",
+            };
+            sections.push((name, format!("[{}]", items.join(","))));
+        }
+        println!("{}", json_object(&sections));
+        return;
+    }
+
+    for kind in &options.show {
+        match kind {
+            Kind::Participants => {
+                println!("PARTICIPANTS ({})", domain.participants.len());
+                for p in domain.participants.values() {
+                    println!("  {}", participant_text(domain, p));
+                }
+            }
+            Kind::Topics => {
+                println!("TOPICS ({})", topics.len());
+                for ((topic, type_name), (w, r)) in &topics {
+                    println!("  {}  {}  writers {}  readers {}", topic, type_name, w, r);
+                }
+            }
+            _ => {
+                let endpoints = domain.endpoints(*kind);
+                let title = match kind {
+                    Kind::Publications => "PUBLICATIONS",
+                    _ => "SUBSCRIPTIONS",
+                };
+                println!("{} ({})", title, endpoints.len());
+                for e in endpoints.values() {
+                    println!("  {}", endpoint_text(domain, *kind, e));
+                }
+            }
+        }
+    }
+}
+
+/// Print a change in watch mode
+fn change(domain: &Domain, options: &Options, kind: Kind, alive: bool, item: Item) {
+    if !options.show.contains(&kind) {
+        return;
+    }
+    let event = if alive { "alive" } else { "gone" };
+    if options.json {
+        let mut fields = vec![("event", json_str(event)), ("kind", json_str(kind.name()))];
+        match item {
+            Item::Participant(p) => fields.extend(participant_json(domain, p)),
+            Item::Endpoint(e) => fields.extend(endpoint_json(domain, kind, e)),
+        }
+        println!("{}", json_object(&fields));
+    } else {
+        let text = match item {
+            Item::Participant(p) => participant_text(domain, p),
+            Item::Endpoint(e) => endpoint_text(domain, kind, e),
+        };
+        println!("{} {} {}", if alive { "+" } else { "-" }, kind.name(), text);
+    }
+}
+
+enum Item<'a> {
+    Participant(&'a DiscoveredParticipant),
+    Endpoint(&'a MatchedEndpoint),
+}
+
+struct Readers {
+    participants: ParticipantDiscovery,
+    publications: EndpointDiscovery,
+    subscriptions: EndpointDiscovery,
+}
+
+/// Apply the discovered changes to `domain`, reporting them if `report`
+fn update(
+    domain: &mut Domain,
+    readers: &Readers,
+    own: Option<Guid>,
+    options: &Options,
+    report: bool,
+) {
+    let shown = |e: &MatchedEndpoint| {
+        Some(e.participant_guid) != own
+            && (options.partitions.is_empty()
+                || partitions_match(&options.partitions, &partitions(&e.qos)))
+    };
+
+    for event in readers.participants.take().unwrap_or_default() {
+        match event {
+            ParticipantEvent::Alive(p) if Some(p.guid) != own => {
+                let ih = p.instance_handle;
+                let new = domain.participants.insert(ih, p).is_none();
+                if report && new {
+                    change(
+                        domain,
+                        options,
+                        Kind::Participants,
+                        true,
+                        Item::Participant(&domain.participants[&ih]),
+                    );
+                }
+            }
+            ParticipantEvent::Alive(_) => {}
+            ParticipantEvent::Gone(ih) => {
+                if let Some(p) = domain.participants.remove(&ih) {
+                    if report {
+                        change(
+                            domain,
+                            options,
+                            Kind::Participants,
+                            false,
+                            Item::Participant(&p),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    for (kind, reader) in [
+        (Kind::Publications, &readers.publications),
+        (Kind::Subscriptions, &readers.subscriptions),
+    ] {
+        for event in reader.take().unwrap_or_default() {
+            let endpoints = match kind {
+                Kind::Publications => &mut domain.publications,
+                _ => &mut domain.subscriptions,
+            };
+            match event {
+                EndpointEvent::Alive(e) if shown(&e) => {
+                    let ih = e.instance_handle;
+                    endpoints.insert(ih, e);
+                    if report {
+                        change(
+                            domain,
+                            options,
+                            kind,
+                            true,
+                            Item::Endpoint(&domain.endpoints(kind)[&ih]),
+                        );
+                    }
+                }
+                EndpointEvent::Alive(_) => {}
+                EndpointEvent::Gone(ih) => {
+                    if let Some(e) = endpoints.remove(&ih) {
+                        if report {
+                            change(domain, options, kind, false, Item::Endpoint(&e));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn builtin_failed<T>(e: DDSError) -> T {
+    fail(&format!("cannot read the builtin topics: {}", e))
+}
+
+fn main() {
+    let options = parse_options(std::env::args().skip(1));
+
+    catch_signals();
+
+    let p = unsafe { dds_create_participant(options.domain, ptr::null(), ptr::null()) };
+    if p < 0 {
+        fail(&format!("cannot create participant: {}", DDSError::from(p)));
+    }
+    let participant = unsafe { DdsEntity::new(p) };
+    let own = participant.guid().ok();
+    let readers = Readers {
+        participants: ParticipantDiscovery::new(&participant).unwrap_or_else(builtin_failed),
+        publications: EndpointDiscovery::publications(&participant).unwrap_or_else(builtin_failed),
+        subscriptions: EndpointDiscovery::subscriptions(&participant)
+            .unwrap_or_else(builtin_failed),
+    };
+
+    std::thread::sleep(Duration::from_secs_f64(options.wait));
+    let mut domain = Domain::default();
+    update(&mut domain, &readers, own, &options, false);
+    list(&domain, &options);
+
+    if options.watch {
+        while !stopped() {
+            std::thread::sleep(Duration::from_millis(100));
+            update(&mut domain, &readers, own, &options, true);
+        }
+    }
+    let _ = participant.delete();
+}
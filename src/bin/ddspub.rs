@@ -0,0 +1,163 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Publish samples given as JSON.
+//!
+//! ```text
+//! ddspub [OPTIONS] [-n COUNT] [--rate HZ] [-w SECONDS] TOPIC [JSON]
+//! ```
+//!
+//! Without JSON on the command line, every line of standard input is a
+//! sample. The type is found through type discovery, so a writer or reader
+//! of the topic must be running.
+
+mod common;
+
+use std::io::BufRead;
+use std::process::exit;
+use std::ptr;
+use std::time::{Duration, Instant};
+
+use common::{catch_signals, fail, stopped, CommonOptions, COMMON_USAGE, MAX_SECONDS};
+use cyclonedds_sys::dynamic::DynType;
+use cyclonedds_sys::*;
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: ddspub [OPTIONS] [-n COUNT] [--rate HZ] [-w SECONDS] TOPIC [JSON]\n{}\n  -n COUNT           publish JSON COUNT times (default 1)\n  --rate HZ          samples per second when repeating (default 1)\n  -w SECONDS         time to wait for a reader before publishing (default 2)",
+        COMMON_USAGE
+    );
+    exit(2)
+}
+
+fn encode(ty: &DynType, json: &str) -> Result<Vec<u8>, String> {
+    let json: serde_json::Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let value = ty.from_json(&json).map_err(|e| e.to_string())?;
+    ty.encode(&value).map_err(|e| e.to_string())
+}
+
+fn main() {
+    let mut common = CommonOptions::new();
+    let mut count: u64 = 1;
+    let mut rate = 1.0;
+    let mut wait = 2.0;
+    let mut positional = Vec::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match common.parse(&arg, || args.next()) {
+            Ok(true) => continue,
+            Ok(false) => {}
+            Err(e) => {
+                eprintln!("{}", e);
+                usage()
+            }
+        }
+        let mut number = |valid: fn(f64) -> bool| {
+            args.next()
+                .and_then(|v| v.parse::<f64>().ok())
+                .filter(|n| valid(*n) && *n <= MAX_SECONDS)
+                .unwrap_or_else(|| usage())
+        };
+        match arg.as_str() {
+            "-n" | "--count" => count = number(|n| n >= 0.0) as u64,
+            "--rate" => rate = number(|n| n > 0.0),
+            "-w" | "--wait" => wait = number(|n| n >= 0.0),
+            "-h" | "--help" => usage(),
+            _ if arg.starts_with('-') && arg.len() > 1 => usage(),
+            _ => positional.push(arg),
+        }
+    }
+    let (topic_name, json) = match positional.as_slice() {
+        [topic] => (topic.clone(), None),
+        [topic, json] => (topic.clone(), Some(json.clone())),
+        _ => usage(),
+    };
+    catch_signals();
+
+    let participant = common.participant();
+    let ty = common.discover(&participant, &topic_name);
+    // check the sample before anything is published
+    let sample = json
+        .as_deref()
+        .map(|json| encode(&ty, json).unwrap_or_else(|e| fail(&e)));
+
+    let writer = participant
+        .create_raw_topic_with_type(&topic_name, &ty, None)
+        .and_then(|topic| {
+            let w = unsafe {
+                dds_create_writer(
+                    participant.entity(),
+                    topic.entity(),
+                    common.qos.as_ptr(),
+                    ptr::null(),
+                )
+            };
+            DDSError::check(w).map(|_| unsafe { DdsEntity::new(w) })
+        })
+        .unwrap_or_else(|e| fail(&format!("cannot publish on {}: {}", topic_name, e)));
+
+    let deadline = Instant::now() + Duration::from_secs_f64(wait);
+    while !stopped()
+        && Instant::now() < deadline
+        && writer
+            .publication_matched_status()
+            .map_or(true, |s| s.current_count == 0)
+    {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    let write = |data: &[u8]| {
+        writer
+            .write_raw(data)
+            .unwrap_or_else(|e| fail(&format!("write failed: {}", e)))
+    };
+    match sample {
+        Some(data) => {
+            let period = Duration::from_secs_f64((1.0 / rate).min(MAX_SECONDS));
+            let start = Instant::now();
+            for i in 0..count {
+                if stopped() {
+                    break;
+                }
+                let due = start + period.mul_f64(i as f64);
+                if let Some(delay) = due.checked_duration_since(Instant::now()) {
+                    std::thread::sleep(delay);
+                }
+                write(&data);
+            }
+        }
+        None => {
+            for line in std::io::stdin().lock().lines() {
+                let line = line.unwrap_or_else(|e| fail(&e.to_string()));
+                if stopped() {
+                    break;
+                }
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match encode(&ty, &line) {
+                    Ok(data) => write(&data),
+                    Err(e) => eprintln!("skipping sample: {}", e),
+                }
+            }
+        }
+    }
+
+    // give reliable readers the chance to receive everything
+    let _ = writer.wait_for_acks(Duration::from_secs(1));
+    let _ = participant.delete();
+}
@@ -0,0 +1,144 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Print the samples of a topic as JSON, one per line.
+//!
+//! ```text
+//! ddssub [OPTIONS] [-n COUNT] [--info] TOPIC
+//! ```
+//!
+//! The type is found through type discovery, so a writer or reader of the
+//! topic must be running.
+
+mod common;
+
+use std::process::exit;
+use std::ptr;
+use std::time::Duration;
+
+use common::{catch_signals, fail, stopped, CommonOptions, COMMON_USAGE};
+use cyclonedds_sys::*;
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: ddssub [OPTIONS] [-n COUNT] [--info] TOPIC\n{}\n  -n COUNT           exit after COUNT samples\n  --info             wrap each sample with its timestamp and instance state",
+        COMMON_USAGE
+    );
+    exit(2)
+}
+
+fn instance_state(state: dds_instance_state_t) -> &'static str {
+    if state == dds_instance_state_DDS_IST_ALIVE {
+        "alive"
+    } else if state == dds_instance_state_DDS_IST_NOT_ALIVE_DISPOSED {
+        "disposed"
+    } else {
+        "no-writers"
+    }
+}
+
+fn main() {
+    let mut common = CommonOptions::new();
+    let mut count: Option<u64> = None;
+    let mut info = false;
+    let mut topic = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match common.parse(&arg, || args.next()) {
+            Ok(true) => continue,
+            Ok(false) => {}
+            Err(e) => {
+                eprintln!("{}", e);
+                usage()
+            }
+        }
+        match arg.as_str() {
+            "-n" | "--count" => {
+                count = Some(
+                    args.next()
+                        .and_then(|v| v.parse().ok())
+                        .unwrap_or_else(|| usage()),
+                )
+            }
+            "--info" => info = true,
+            "-h" | "--help" => usage(),
+            _ if arg.starts_with('-') || topic.is_some() => usage(),
+            _ => topic = Some(arg),
+        }
+    }
+    let topic_name = topic.unwrap_or_else(|| usage());
+    catch_signals();
+
+    let participant = common.participant();
+    let ty = common.discover(&participant, &topic_name);
+    let reader = participant
+        .create_raw_topic_with_type(&topic_name, &ty, None)
+        .and_then(|topic| {
+            let r = unsafe {
+                dds_create_reader(
+                    participant.entity(),
+                    topic.entity(),
+                    common.qos.as_ptr(),
+                    ptr::null(),
+                )
+            };
+            DDSError::check(r).map(|_| unsafe { DdsEntity::new(r) })
+        })
+        .unwrap_or_else(|e| fail(&format!("cannot subscribe to {}: {}", topic_name, e)));
+
+    // without -n, take until interrupted
+    let wanted = count.unwrap_or(u64::MAX);
+    let mut received = 0;
+    while !stopped() && received < wanted {
+        let samples = reader
+            .take_raw(64)
+            .unwrap_or_else(|e| fail(&format!("take failed: {}", e)));
+        for sample in samples {
+            if received >= wanted {
+                break;
+            }
+            // disposes and unregisters are only shown with --info
+            if !sample.valid_data && !info {
+                continue;
+            }
+            received += 1;
+            let data = if sample.valid_data {
+                match ty.decode(&sample.data) {
+                    Ok(value) => ty.to_json(&value),
+                    Err(e) => {
+                        eprintln!("cannot decode sample: {}", e);
+                        continue;
+                    }
+                }
+            } else {
+                serde_json::Value::Null
+            };
+            let line = if info {
+                serde_json::json!({
+                    "source_timestamp": sample.source_timestamp.as_nanos(),
+                    "instance_state": instance_state(sample.instance_state),
+                    "data": data,
+                })
+            } else {
+                data
+            };
+            println!("{}", line);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let _ = participant.delete();
+}
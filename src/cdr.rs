@@ -0,0 +1,1140 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! CDR encoding of serde data types.
+//!
+//! The serde data model maps onto IDL as follows:
+//!
+//! | serde | IDL |
+//! |-------|-----|
+//! | `bool`, integers, `f32`, `f64` | `boolean`, `int8` .. `uint64`, `float`, `double` |
+//! | `char` | `char` (Latin-1 only) |
+//! | strings | `string` |
+//! | sequences, sets, byte buffers | `sequence<T>` |
+//! | maps | `sequence` of key/value structs |
+//! | tuples, arrays, structs | members in order, as in a `@final` struct or an array |
+//! | `Option<T>` | `@optional` member, see below |
+//! | unit enum variants | `enum`, the variant index |
+//! | other enum variants | `union` switching on `uint32`, the variant index |
+//! | `()`, unit structs | nothing |
+//!
+//! CDR is not self-describing, so data can only be read with the type it
+//! was written with, and `deserialize_any` (`#[serde(flatten)]`, untagged
+//! enums, `serde_json::Value`) is not supported.
+//!
+//! [`to_vec`] writes XCDR1 in little endian, [`to_vec_with`] either
+//! version in either byte order; [`from_slice`] reads all of those, going
+//! by the encapsulation header. XCDR2 is that of `@final` types: eight byte
+//! values aligned to four and no DHEADERs.
+//!
+//! An `@optional` member is a `boolean` and, when true, the value in XCDR2.
+//! In XCDR1 it is a parameter header (the member id, counting the members of
+//! the struct from 0, and the length of the value) followed by the value,
+//! aligned as if it started the stream; an absent member has length 0.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use serde::de::{self, IntoDeserializer};
+use serde::{ser, Deserialize, Serialize};
+
+use crate::dynamic::{CDR2_BE, CDR2_LE, CDR_BE, CDR_LE};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CdrError {
+    Message(String),
+    Truncated,
+    /// An encapsulation this module does not read
+    Encoding(u16),
+    /// `deserialize_any` and friends, which need a self-describing format
+    NotSelfDescribing,
+    /// An XCDR1 optional member with an id or a length that does not fit
+    /// the parameter header
+    Optional,
+    Bool(u8),
+    Char(char),
+    Utf8,
+    TooLong,
+}
+
+impl fmt::Display for CdrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CdrError::Message(msg) => write!(f, "{}", msg),
+            CdrError::Truncated => write!(f, "unexpected end of data"),
+            CdrError::Encoding(e) => write!(f, "unsupported encoding {:#06x}", e),
+            CdrError::NotSelfDescribing => write!(f, "CDR can only be read into a known type"),
+            CdrError::Optional => write!(f, "optional member does not fit a parameter header"),
+            CdrError::Bool(b) => write!(f, "{} is not a boolean", b),
+            CdrError::Char(c) => write!(f, "{:?} is not a Latin-1 character", c),
+            CdrError::Utf8 => write!(f, "string is not UTF-8"),
+            CdrError::TooLong => write!(f, "more than 2^32 - 1 elements"),
+        }
+    }
+}
+
+impl std::error::Error for CdrError {}
+
+impl ser::Error for CdrError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        CdrError::Message(msg.to_string())
+    }
+}
+
+impl de::Error for CdrError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        CdrError::Message(msg.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Xcdr1,
+    Xcdr2,
+}
+
+impl Encoding {
+    fn max_align(self) -> usize {
+        match self {
+            Encoding::Xcdr1 => 8,
+            Encoding::Xcdr2 => 4,
+        }
+    }
+}
+
+/// Serialize `value` as little endian XCDR1, with the encapsulation header
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, CdrError> {
+    to_vec_with(value, Encoding::Xcdr1, false)
+}
+
+/// Serialize `value` with the encapsulation header
+pub fn to_vec_with<T: Serialize + ?Sized>(
+    value: &T,
+    encoding: Encoding,
+    big_endian: bool,
+) -> Result<Vec<u8>, CdrError> {
+    let id = match (encoding, big_endian) {
+        (Encoding::Xcdr1, true) => CDR_BE,
+        (Encoding::Xcdr1, false) => CDR_LE,
+        (Encoding::Xcdr2, true) => CDR2_BE,
+        (Encoding::Xcdr2, false) => CDR2_LE,
+    };
+    let mut out = id.to_be_bytes().to_vec();
+    out.extend_from_slice(&[0, 0]);
+    let mut serializer = Serializer::new(out, encoding, big_endian);
+    value.serialize(&mut serializer)?;
+    // pad to a multiple of 4, the padding goes in the options
+    let mut out = serializer.out;
+    let padding = (4 - out.len() % 4) % 4;
+    out.resize(out.len() + padding, 0);
+    out[3] = padding as u8;
+    Ok(out)
+}
+
+/// Serialize `value` without a header, the way key hashes are made:
+/// big endian XCDR2. Also tells whether every value of the type has that
+/// size, which it does unless it has strings, sequences, maps, optional
+/// members or enum variants with data.
+pub(crate) fn to_key_bytes<T: Serialize + ?Sized>(value: &T) -> Result<(Vec<u8>, bool), CdrError> {
+    let mut serializer = Serializer::new(Vec::new(), Encoding::Xcdr2, true);
+    value.serialize(&mut serializer)?;
+    Ok((serializer.out, !serializer.variable))
+}
+
+/// Deserialize a value from CDR that starts with the encapsulation header
+pub fn from_slice<'de, T: Deserialize<'de>>(data: &'de [u8]) -> Result<T, CdrError> {
+    if data.len() < 4 {
+        return Err(CdrError::Truncated);
+    }
+    let (big_endian, encoding) = match u16::from_be_bytes([data[0], data[1]]) {
+        CDR_BE => (true, Encoding::Xcdr1),
+        CDR_LE => (false, Encoding::Xcdr1),
+        CDR2_BE => (true, Encoding::Xcdr2),
+        CDR2_LE => (false, Encoding::Xcdr2),
+        other => return Err(CdrError::Encoding(other)),
+    };
+    let mut deserializer = Deserializer {
+        input: &data[4..],
+        pos: 0,
+        base: 0,
+        big_endian,
+        encoding,
+    };
+    T::deserialize(&mut deserializer)
+}
+
+pub struct Serializer {
+    out: Vec<u8>,
+    /// Where the payload (or an XCDR1 optional member) starts, alignment is
+    /// relative to it
+    base: usize,
+    big_endian: bool,
+    encoding: Encoding,
+    /// The id of the next member of each struct being serialized
+    next_member: Vec<u32>,
+    /// The id of the member being serialized
+    member: u32,
+    /// Whether something of variable size was serialized
+    variable: bool,
+}
+
+macro_rules! put_number {
+    ($name:ident, $ty:ty) => {
+        fn $name(&mut self, value: $ty) {
+            self.align(std::mem::size_of::<$ty>());
+            if self.big_endian {
+                self.out.extend_from_slice(&value.to_be_bytes());
+            } else {
+                self.out.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+    };
+}
+
+impl Serializer {
+    fn new(out: Vec<u8>, encoding: Encoding, big_endian: bool) -> Self {
+        Serializer {
+            base: out.len(),
+            out,
+            big_endian,
+            encoding,
+            next_member: Vec::new(),
+            member: 0,
+            variable: false,
+        }
+    }
+
+    fn align(&mut self, n: usize) {
+        let n = n.min(self.encoding.max_align());
+        let pad = (n - (self.out.len() - self.base) % n) % n;
+        self.out.resize(self.out.len() + pad, 0);
+    }
+
+    put_number!(put_u16, u16);
+    put_number!(put_u32, u32);
+    put_number!(put_u64, u64);
+
+    fn put_len(&mut self, len: usize) -> Result<(), CdrError> {
+        if len > u32::MAX as usize {
+            return Err(CdrError::TooLong);
+        }
+        self.variable = true;
+        self.put_u32(len as u32);
+        Ok(())
+    }
+
+    /// Start a sequence whose length is filled in when it ends
+    fn start_sequence(&mut self) -> Result<SequenceSerializer<'_>, CdrError> {
+        self.variable = true;
+        self.put_u32(0);
+        Ok(SequenceSerializer {
+            len_pos: self.out.len() - 4,
+            ser: self,
+            count: 0,
+        })
+    }
+
+    fn patch_u16(&mut self, pos: usize, value: u16) {
+        let bytes = if self.big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        };
+        self.out[pos..pos + 2].copy_from_slice(&bytes);
+    }
+
+    /// Write the XCDR1 parameter header of the current member with length
+    /// 0, returning where the value starts
+    fn put_member_header(&mut self) -> Result<usize, CdrError> {
+        // ids from 0x3f00 are reserved
+        if self.member >= 0x3f00 {
+            return Err(CdrError::Optional);
+        }
+        self.align(4);
+        self.put_u16(self.member as u16);
+        self.put_u16(0);
+        Ok(self.out.len())
+    }
+
+    /// Start a struct (or tuple), whose members are numbered from 0
+    fn start_members(&mut self) {
+        self.next_member.push(0);
+    }
+
+    fn next_member(&mut self) {
+        if let Some(next) = self.next_member.last_mut() {
+            self.member = *next;
+            *next += 1;
+        }
+    }
+
+    fn end_members(&mut self) {
+        self.next_member.pop();
+    }
+}
+
+impl<'a> ser::Serializer for &'a mut Serializer {
+    type Ok = ();
+    type Error = CdrError;
+    type SerializeSeq = SequenceSerializer<'a>;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = SequenceSerializer<'a>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, v: bool) -> Result<(), CdrError> {
+        self.out.push(v as u8);
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), CdrError> {
+        self.out.push(v as u8);
+        Ok(())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), CdrError> {
+        self.put_u16(v as u16);
+        Ok(())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), CdrError> {
+        self.put_u32(v as u32);
+        Ok(())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), CdrError> {
+        self.put_u64(v as u64);
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), CdrError> {
+        self.out.push(v);
+        Ok(())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), CdrError> {
+        self.put_u16(v);
+        Ok(())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), CdrError> {
+        self.put_u32(v);
+        Ok(())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), CdrError> {
+        self.put_u64(v);
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), CdrError> {
+        self.put_u32(v.to_bits());
+        Ok(())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), CdrError> {
+        self.put_u64(v.to_bits());
+        Ok(())
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), CdrError> {
+        if v as u32 > 0xff {
+            return Err(CdrError::Char(v));
+        }
+        self.out.push(v as u8);
+        Ok(())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), CdrError> {
+        self.put_len(v.len() + 1)?;
+        self.out.extend_from_slice(v.as_bytes());
+        self.out.push(0);
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), CdrError> {
+        self.put_len(v.len())?;
+        self.out.extend_from_slice(v);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), CdrError> {
+        self.variable = true;
+        match self.encoding {
+            Encoding::Xcdr1 => self.put_member_header().map(drop),
+            Encoding::Xcdr2 => {
+                self.out.push(0);
+                Ok(())
+            }
+        }
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), CdrError> {
+        self.variable = true;
+        if self.encoding == Encoding::Xcdr2 {
+            self.out.push(1);
+            return value.serialize(self);
+        }
+        let start = self.put_member_header()?;
+        let base = std::mem::replace(&mut self.base, start);
+        let result = value.serialize(&mut *self);
+        self.base = base;
+        result?;
+        let len = u16::try_from(self.out.len() - start).map_err(|_| CdrError::Optional)?;
+        self.patch_u16(start - 2, len);
+        Ok(())
+    }
+
+    fn serialize_unit(self) -> Result<(), CdrError> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<(), CdrError> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        index: u32,
+        _: &'static str,
+    ) -> Result<(), CdrError> {
+        self.put_u32(index);
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), CdrError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        index: u32,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), CdrError> {
+        self.variable = true;
+        self.put_u32(index);
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<SequenceSerializer<'a>, CdrError> {
+        self.start_sequence()
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self, CdrError> {
+        self.start_members();
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, _: usize) -> Result<Self, CdrError> {
+        self.start_members();
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        index: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self, CdrError> {
+        self.variable = true;
+        self.put_u32(index);
+        self.start_members();
+        Ok(self)
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<SequenceSerializer<'a>, CdrError> {
+        self.start_sequence()
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self, CdrError> {
+        self.start_members();
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        index: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self, CdrError> {
+        self.variable = true;
+        self.put_u32(index);
+        self.start_members();
+        Ok(self)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+/// A sequence or map, counting the elements to patch the length
+pub struct SequenceSerializer<'a> {
+    ser: &'a mut Serializer,
+    len_pos: usize,
+    count: usize,
+}
+
+impl SequenceSerializer<'_> {
+    fn end(self) -> Result<(), CdrError> {
+        if self.count > u32::MAX as usize {
+            return Err(CdrError::TooLong);
+        }
+        let count = self.count as u32;
+        let bytes = if self.ser.big_endian {
+            count.to_be_bytes()
+        } else {
+            count.to_le_bytes()
+        };
+        self.ser.out[self.len_pos..self.len_pos + 4].copy_from_slice(&bytes);
+        Ok(())
+    }
+}
+
+impl ser::SerializeSeq for SequenceSerializer<'_> {
+    type Ok = ();
+    type Error = CdrError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), CdrError> {
+        self.count += 1;
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<(), CdrError> {
+        SequenceSerializer::end(self)
+    }
+}
+
+impl ser::SerializeMap for SequenceSerializer<'_> {
+    type Ok = ();
+    type Error = CdrError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), CdrError> {
+        self.count += 1;
+        key.serialize(&mut *self.ser)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), CdrError> {
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<(), CdrError> {
+        SequenceSerializer::end(self)
+    }
+}
+
+macro_rules! serialize_members {
+    ($($trait:ident :: $method:ident),*) => {
+        $(
+            impl ser::$trait for &mut Serializer {
+                type Ok = ();
+                type Error = CdrError;
+
+                fn $method<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), CdrError> {
+                    self.next_member();
+                    value.serialize(&mut **self)
+                }
+
+                fn end(self) -> Result<(), CdrError> {
+                    self.end_members();
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+serialize_members!(
+    SerializeTuple::serialize_element,
+    SerializeTupleStruct::serialize_field,
+    SerializeTupleVariant::serialize_field
+);
+
+impl ser::SerializeStruct for &mut Serializer {
+    type Ok = ();
+    type Error = CdrError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), CdrError> {
+        self.next_member();
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), CdrError> {
+        self.end_members();
+        Ok(())
+    }
+}
+
+impl ser::SerializeStructVariant for &mut Serializer {
+    type Ok = ();
+    type Error = CdrError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), CdrError> {
+        self.next_member();
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), CdrError> {
+        self.end_members();
+        Ok(())
+    }
+}
+
+pub struct Deserializer<'de> {
+    /// The payload, after the encapsulation header
+    input: &'de [u8],
+    pos: usize,
+    /// Where an XCDR1 optional member starts, alignment is relative to it
+    base: usize,
+    big_endian: bool,
+    encoding: Encoding,
+}
+
+macro_rules! get_number {
+    ($name:ident, $ty:ty) => {
+        fn $name(&mut self) -> Result<$ty, CdrError> {
+            const N: usize = std::mem::size_of::<$ty>();
+            self.align(N);
+            let mut bytes = [0u8; N];
+            bytes.copy_from_slice(self.take(N)?);
+            Ok(if self.big_endian {
+                <$ty>::from_be_bytes(bytes)
+            } else {
+                <$ty>::from_le_bytes(bytes)
+            })
+        }
+    };
+}
+
+impl<'de> Deserializer<'de> {
+    fn align(&mut self, n: usize) {
+        let n = n.min(self.encoding.max_align());
+        self.pos += (n - (self.pos - self.base) % n) % n;
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'de [u8], CdrError> {
+        let end = self.pos.checked_add(n).ok_or(CdrError::Truncated)?;
+        let bytes = self.input.get(self.pos..end).ok_or(CdrError::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn get_u8(&mut self) -> Result<u8, CdrError> {
+        Ok(self.take(1)?[0])
+    }
+
+    get_number!(get_u16, u16);
+    get_number!(get_u32, u32);
+    get_number!(get_u64, u64);
+
+    fn get_bool(&mut self) -> Result<bool, CdrError> {
+        match self.get_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => Err(CdrError::Bool(b)),
+        }
+    }
+
+    fn get_str(&mut self) -> Result<&'de str, CdrError> {
+        let len = self.get_u32()? as usize;
+        let bytes = self.take(len)?;
+        let bytes = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+        std::str::from_utf8(bytes).map_err(|_| CdrError::Utf8)
+    }
+}
+
+/// The elements of a sequence, tuple or struct, or the entries of a map
+struct Elements<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    remaining: usize,
+}
+
+impl<'de> de::SeqAccess<'de> for Elements<'_, 'de> {
+    type Error = CdrError;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, CdrError> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+impl<'de> de::MapAccess<'de> for Elements<'_, 'de> {
+    type Error = CdrError;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, CdrError> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, CdrError> {
+        seed.deserialize(&mut *self.de)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+impl<'de> de::EnumAccess<'de> for &mut Deserializer<'de> {
+    type Error = CdrError;
+    type Variant = Self;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self), CdrError> {
+        let index = self.get_u32()?;
+        let variant = seed.deserialize(IntoDeserializer::<CdrError>::into_deserializer(index))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for &mut Deserializer<'de> {
+    type Error = CdrError;
+
+    fn unit_variant(self) -> Result<(), CdrError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, CdrError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: de::Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, CdrError> {
+        visitor.visit_seq(Elements {
+            de: self,
+            remaining: len,
+        })
+    }
+
+    fn struct_variant<V: de::Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, CdrError> {
+        visitor.visit_seq(Elements {
+            de: self,
+            remaining: fields.len(),
+        })
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = CdrError;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, _: V) -> Result<V::Value, CdrError> {
+        Err(CdrError::NotSelfDescribing)
+    }
+
+    fn deserialize_bool<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, CdrError> {
+        visitor.visit_bool(self.get_bool()?)
+    }
+
+    fn deserialize_i8<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, CdrError> {
+        visitor.visit_i8(self.get_u8()? as i8)
+    }
+
+    fn deserialize_i16<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, CdrError> {
+        visitor.visit_i16(self.get_u16()? as i16)
+    }
+
+    fn deserialize_i32<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, CdrError> {
+        visitor.visit_i32(self.get_u32()? as i32)
+    }
+
+    fn deserialize_i64<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, CdrError> {
+        visitor.visit_i64(self.get_u64()? as i64)
+    }
+
+    fn deserialize_u8<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, CdrError> {
+        visitor.visit_u8(self.get_u8()?)
+    }
+
+    fn deserialize_u16<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, CdrError> {
+        visitor.visit_u16(self.get_u16()?)
+    }
+
+    fn deserialize_u32<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, CdrError> {
+        visitor.visit_u32(self.get_u32()?)
+    }
+
+    fn deserialize_u64<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, CdrError> {
+        visitor.visit_u64(self.get_u64()?)
+    }
+
+    fn deserialize_f32<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, CdrError> {
+        visitor.visit_f32(f32::from_bits(self.get_u32()?))
+    }
+
+    fn deserialize_f64<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, CdrError> {
+        visitor.visit_f64(f64::from_bits(self.get_u64()?))
+    }
+
+    fn deserialize_char<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, CdrError> {
+        visitor.visit_char(self.get_u8()? as char)
+    }
+
+    fn deserialize_str<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, CdrError> {
+        visitor.visit_borrowed_str(self.get_str()?)
+    }
+
+    fn deserialize_string<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, CdrError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, CdrError> {
+        let len = self.get_u32()? as usize;
+        visitor.visit_borrowed_bytes(self.take(len)?)
+    }
+
+    fn deserialize_byte_buf<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, CdrError> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, CdrError> {
+        if self.encoding == Encoding::Xcdr2 {
+            return if self.get_bool()? {
+                visitor.visit_some(self)
+            } else {
+                visitor.visit_none()
+            };
+        }
+        // the member id is not checked, members come in order
+        self.align(4);
+        self.get_u16()?;
+        let len = self.get_u16()? as usize;
+        if len == 0 {
+            return visitor.visit_none();
+        }
+        let start = self.pos;
+        let end = start.checked_add(len).ok_or(CdrError::Truncated)?;
+        if end > self.input.len() {
+            return Err(CdrError::Truncated);
+        }
+        let base = std::mem::replace(&mut self.base, start);
+        let value = visitor.visit_some(&mut *self);
+        self.base = base;
+        let value = value?;
+        if self.pos > end {
+            return Err(CdrError::Optional);
+        }
+        self.pos = end;
+        Ok(value)
+    }
+
+    fn deserialize_unit<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, CdrError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: de::Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, CdrError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, CdrError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, CdrError> {
+        let len = self.get_u32()? as usize;
+        visitor.visit_seq(Elements {
+            de: self,
+            remaining: len,
+        })
+    }
+
+    fn deserialize_tuple<V: de::Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, CdrError> {
+        visitor.visit_seq(Elements {
+            de: self,
+            remaining: len,
+        })
+    }
+
+    fn deserialize_tuple_struct<V: de::Visitor<'de>>(
+        self,
+        _: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, CdrError> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, CdrError> {
+        let len = self.get_u32()? as usize;
+        visitor.visit_map(Elements {
+            de: self,
+            remaining: len,
+        })
+    }
+
+    fn deserialize_struct<V: de::Visitor<'de>>(
+        self,
+        _: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, CdrError> {
+        self.deserialize_tuple(fields.len(), visitor)
+    }
+
+    fn deserialize_enum<V: de::Visitor<'de>>(
+        self,
+        _: &'static str,
+        _: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, CdrError> {
+        visitor.visit_enum(self)
+    }
+
+    fn deserialize_identifier<V: de::Visitor<'de>>(self, _: V) -> Result<V::Value, CdrError> {
+        Err(CdrError::NotSelfDescribing)
+    }
+
+    fn deserialize_ignored_any<V: de::Visitor<'de>>(self, _: V) -> Result<V::Value, CdrError> {
+        Err(CdrError::NotSelfDescribing)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Aligned {
+        a: u8,
+        b: u64,
+        c: u16,
+        d: u32,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Shape {
+        Point,
+        Circle(f64),
+        Rect { w: i32, h: i32 },
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Everything {
+        flag: bool,
+        small: i8,
+        name: String,
+        values: Vec<i16>,
+        pair: (u8, f64),
+        shapes: Vec<Shape>,
+        map: BTreeMap<u32, String>,
+        maybe: Option<u64>,
+        never: Option<String>,
+        letter: char,
+    }
+
+    fn everything() -> Everything {
+        Everything {
+            flag: true,
+            small: -3,
+            name: "cyclone".to_owned(),
+            values: vec![1, -2, 3],
+            pair: (7, 2.5),
+            shapes: vec![
+                Shape::Point,
+                Shape::Circle(1.5),
+                Shape::Rect { w: 2, h: -4 },
+            ],
+            map: vec![(1, "one".to_owned()), (2, "two".to_owned())]
+                .into_iter()
+                .collect(),
+            maybe: Some(42),
+            never: None,
+            letter: 'é',
+        }
+    }
+
+    const ENCODINGS: [(Encoding, bool); 4] = [
+        (Encoding::Xcdr1, false),
+        (Encoding::Xcdr1, true),
+        (Encoding::Xcdr2, false),
+        (Encoding::Xcdr2, true),
+    ];
+
+    #[test]
+    fn xcdr1_aligns_to_the_size() {
+        let value = Aligned {
+            a: 1,
+            b: 2,
+            c: 3,
+            d: 4,
+        };
+        let data = to_vec(&value).unwrap();
+        let mut expected = vec![0, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0];
+        expected.extend_from_slice(&2u64.to_le_bytes());
+        expected.extend_from_slice(&[3, 0, 0, 0, 4, 0, 0, 0]);
+        assert_eq!(data, expected);
+        assert_eq!(from_slice::<Aligned>(&data).unwrap(), value);
+    }
+
+    #[test]
+    fn xcdr2_aligns_to_at_most_four() {
+        let value = Aligned {
+            a: 1,
+            b: 2,
+            c: 3,
+            d: 4,
+        };
+        let data = to_vec_with(&value, Encoding::Xcdr2, true).unwrap();
+        let mut expected = vec![0, 6, 0, 0, 1, 0, 0, 0];
+        expected.extend_from_slice(&2u64.to_be_bytes());
+        expected.extend_from_slice(&[0, 3, 0, 0, 0, 0, 0, 4]);
+        assert_eq!(data, expected);
+        assert_eq!(from_slice::<Aligned>(&data).unwrap(), value);
+    }
+
+    #[test]
+    fn round_trips_in_every_encoding() {
+        for (encoding, big_endian) in ENCODINGS.iter() {
+            let data = to_vec_with(&everything(), *encoding, *big_endian).unwrap();
+            assert_eq!(data.len() % 4, 0);
+            assert_eq!(
+                from_slice::<Everything>(&data).unwrap(),
+                everything(),
+                "{:?}, big endian {}",
+                encoding,
+                big_endian
+            );
+        }
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Optional {
+        a: u8,
+        b: Option<u64>,
+        c: Option<u32>,
+    }
+
+    #[test]
+    fn optional_members() {
+        let value = Optional {
+            a: 1,
+            b: Some(5),
+            c: None,
+        };
+        // XCDR1: parameter headers with the member id and length, the value
+        // aligned from its start
+        let data = to_vec(&value).unwrap();
+        assert_eq!(
+            data,
+            vec![0, 1, 0, 0, 1, 0, 0, 0, 1, 0, 8, 0, 5, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0]
+        );
+        assert_eq!(from_slice::<Optional>(&data).unwrap(), value);
+        // XCDR2: a flag before the value
+        let data = to_vec_with(&value, Encoding::Xcdr2, false).unwrap();
+        assert_eq!(
+            data,
+            vec![0, 7, 0, 3, 1, 1, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(from_slice::<Optional>(&data).unwrap(), value);
+    }
+
+    #[test]
+    fn truncated_and_unknown_data_are_errors() {
+        let data = to_vec(&everything()).unwrap();
+        assert_eq!(
+            from_slice::<Everything>(&data[..data.len() - 8]),
+            Err(CdrError::Truncated)
+        );
+        assert_eq!(
+            from_slice::<u32>(&[0, 9, 0, 0, 0, 0, 0, 0]),
+            Err(CdrError::Encoding(9))
+        );
+        assert_eq!(from_slice::<bool>(&[0, 1, 0, 0, 2]), Err(CdrError::Bool(2)));
+        assert_eq!(to_vec(&'€'), Err(CdrError::Char('€')));
+    }
+
+    #[test]
+    fn key_bytes_tell_whether_the_size_is_fixed() {
+        assert_eq!(
+            to_key_bytes(&(1u8, 2u32)).unwrap(),
+            (vec![1, 0, 0, 0, 0, 0, 0, 2], true)
+        );
+        assert_eq!(
+            to_key_bytes(&"a").unwrap(),
+            (vec![0, 0, 0, 2, b'a', 0], false)
+        );
+        assert!(!to_key_bytes(&vec![1u8]).unwrap().1);
+        assert!(to_key_bytes(&Shape::Point).unwrap().1);
+        assert!(!to_key_bytes(&Shape::Circle(1.0)).unwrap().1);
+    }
+}
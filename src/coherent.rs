@@ -0,0 +1,232 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Scoped coherent sets and group access.
+//!
+//! `dds_begin_coherent`/`dds_end_coherent` silently do nothing useful unless
+//! the presentation QoS of the publisher (or subscriber) asks for it.
+//! [`create_coherent_publisher`](DdsEntity::create_coherent_publisher) and
+//! [`create_group_subscriber`](DdsEntity::create_group_subscriber) refuse a
+//! QoS that does not, so the mistake shows up when the entity is created.
+//! The guards check the QoS again before starting, for publishers and
+//! subscribers created some other way.
+
+use std::fmt;
+
+use crate::{
+    dds_begin_coherent, dds_create_publisher, dds_create_subscriber, dds_end_coherent,
+    dds_get_parent, dds_presentation_access_scope_kind, DDSError, DdsEntity, DdsQos,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CoherentError {
+    Dds(DDSError),
+    /// The presentation QoS does not enable coherent access
+    NotCoherent,
+    /// Group access needs `DDS_PRESENTATION_GROUP` with coherent or ordered
+    /// access
+    NotGroupAccess,
+}
+
+impl fmt::Display for CoherentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoherentError::Dds(e) => write!(f, "{}", e),
+            CoherentError::NotCoherent => {
+                write!(f, "presentation QoS has coherent access disabled")
+            }
+            CoherentError::NotGroupAccess => write!(
+                f,
+                "presentation QoS is not GROUP with coherent or ordered access"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CoherentError {}
+
+impl From<DDSError> for CoherentError {
+    fn from(e: DDSError) -> Self {
+        CoherentError::Dds(e)
+    }
+}
+
+/// Ends the coherent set (or access) when dropped
+#[must_use = "the coherent set ends when the guard is dropped"]
+pub struct CoherentGuard<'a> {
+    entity: &'a DdsEntity,
+    active: bool,
+}
+
+impl<'a> CoherentGuard<'a> {
+    pub fn entity(&self) -> &'a DdsEntity {
+        self.entity
+    }
+
+    /// End the set explicitly to see the result
+    pub fn end(mut self) -> Result<(), DDSError> {
+        self.active = false;
+        DDSError::check(unsafe { dds_end_coherent(self.entity.0) }).map(|_| ())
+    }
+}
+
+impl<'a> Drop for CoherentGuard<'a> {
+    fn drop(&mut self) {
+        if self.active {
+            unsafe { dds_end_coherent(self.entity.0) };
+        }
+    }
+}
+
+type Presentation = (dds_presentation_access_scope_kind, bool, bool);
+
+fn check_coherent(presentation: Option<Presentation>) -> Result<(), CoherentError> {
+    match presentation {
+        Some((_, true, _)) => Ok(()),
+        _ => Err(CoherentError::NotCoherent),
+    }
+}
+
+fn check_group_access(presentation: Option<Presentation>) -> Result<(), CoherentError> {
+    match presentation {
+        Some((dds_presentation_access_scope_kind::DDS_PRESENTATION_GROUP, coherent, ordered))
+            if coherent || ordered =>
+        {
+            Ok(())
+        }
+        _ => Err(CoherentError::NotGroupAccess),
+    }
+}
+
+/// The presentation policy that applies to an entity: its own for a
+/// publisher or subscriber, the parent's for a writer or reader.
+fn presentation(entity: &DdsEntity) -> Result<Presentation, DDSError> {
+    if let Some(p) = DdsQos::of(entity)?.get_presentation() {
+        return Ok(p);
+    }
+    let parent = DdsEntity(DDSError::check(unsafe { dds_get_parent(entity.0) })?);
+    Ok(DdsQos::of(&parent)?.get_presentation().unwrap_or((
+        dds_presentation_access_scope_kind::DDS_PRESENTATION_INSTANCE,
+        false,
+        false,
+    )))
+}
+
+impl DdsEntity {
+    /// Create a publisher on this participant for coherent sets. Fails with
+    /// `NotCoherent` unless `qos` has a presentation policy with coherent
+    /// access enabled.
+    pub fn create_coherent_publisher(&self, qos: &DdsQos) -> Result<DdsEntity, CoherentError> {
+        check_coherent(qos.get_presentation())?;
+        let publisher = unsafe { dds_create_publisher(self.0, qos.as_ptr(), std::ptr::null()) };
+        Ok(DdsEntity(DDSError::check(publisher)?))
+    }
+
+    /// Create a subscriber on this participant for group access. Fails with
+    /// `NotGroupAccess` unless `qos` has `DDS_PRESENTATION_GROUP` with
+    /// coherent or ordered access.
+    pub fn create_group_subscriber(&self, qos: &DdsQos) -> Result<DdsEntity, CoherentError> {
+        check_group_access(qos.get_presentation())?;
+        let subscriber = unsafe { dds_create_subscriber(self.0, qos.as_ptr(), std::ptr::null()) };
+        Ok(DdsEntity(DDSError::check(subscriber)?))
+    }
+
+    fn begin_guard(&self) -> Result<CoherentGuard<'_>, CoherentError> {
+        DDSError::check(unsafe { dds_begin_coherent(self.0) })?;
+        Ok(CoherentGuard {
+            entity: self,
+            active: true,
+        })
+    }
+
+    /// Start a coherent set on a publisher or writer. Fails with
+    /// `NotCoherent` unless the publisher has coherent access enabled.
+    pub fn begin_coherent(&self) -> Result<CoherentGuard<'_>, CoherentError> {
+        check_coherent(Some(presentation(self)?))?;
+        self.begin_guard()
+    }
+
+    /// Run `f` inside a coherent set. The set is ended even if `f` returns
+    /// early or panics.
+    pub fn coherent<F, R>(&self, f: F) -> Result<R, CoherentError>
+    where
+        F: FnOnce(&DdsEntity) -> R,
+    {
+        let guard = self.begin_coherent()?;
+        let result = f(self);
+        guard.end()?;
+        Ok(result)
+    }
+
+    /// Begin access on a subscriber with GROUP presentation, so that
+    /// samples read from its readers respect the coherent sets and order
+    /// of the publishers.
+    pub fn begin_access(&self) -> Result<CoherentGuard<'_>, CoherentError> {
+        check_group_access(Some(presentation(self)?))?;
+        self.begin_guard()
+    }
+
+    /// Run `f` between begin and end access on a subscriber
+    pub fn with_access<F, R>(&self, f: F) -> Result<R, CoherentError>
+    where
+        F: FnOnce(&DdsEntity) -> R,
+    {
+        let guard = self.begin_access()?;
+        let result = f(self);
+        guard.end()?;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dds_presentation_access_scope_kind::*;
+
+    #[test]
+    fn coherent_sets_need_coherent_access() {
+        assert_eq!(
+            check_coherent(Some((DDS_PRESENTATION_TOPIC, true, false))),
+            Ok(())
+        );
+        assert_eq!(
+            check_coherent(Some((DDS_PRESENTATION_GROUP, false, true))),
+            Err(CoherentError::NotCoherent)
+        );
+        assert_eq!(check_coherent(None), Err(CoherentError::NotCoherent));
+    }
+
+    #[test]
+    fn group_access_needs_group_scope() {
+        assert_eq!(
+            check_group_access(Some((DDS_PRESENTATION_GROUP, false, true))),
+            Ok(())
+        );
+        assert_eq!(
+            check_group_access(Some((DDS_PRESENTATION_GROUP, true, false))),
+            Ok(())
+        );
+        assert_eq!(
+            check_group_access(Some((DDS_PRESENTATION_GROUP, false, false))),
+            Err(CoherentError::NotGroupAccess)
+        );
+        assert_eq!(
+            check_group_access(Some((DDS_PRESENTATION_TOPIC, true, true))),
+            Err(CoherentError::NotGroupAccess)
+        );
+        assert_eq!(check_group_access(None), Err(CoherentError::NotGroupAccess));
+    }
+}
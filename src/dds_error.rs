@@ -86,3 +86,15 @@ impl From<dds_return_t> for DDSError {
         }
     }
 }
+
+impl DDSError {
+    /// Turn a return code (or entity handle) into a Result. Non-negative
+    /// values are passed through unchanged.
+    pub fn check(ret: dds_return_t) -> Result<dds_return_t, DDSError> {
+        if ret < 0 {
+            Err(DDSError::from(ret))
+        } else {
+            Ok(ret)
+        }
+    }
+}
@@ -0,0 +1,402 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! `dds_time_t` and `dds_duration_t` as Rust types.
+//!
+//! Both are nanoseconds in an `i64` with `i64::MAX` reserved for "never" and
+//! "infinite". Conversions from the std types saturate: anything that does
+//! not fit becomes `Never`/`Infinite`, negative durations become zero.
+//! Going back, `Infinite` becomes `Duration::MAX`.
+
+use std::convert::TryFrom;
+use std::ffi::c_void;
+use std::ops::{Add, Sub};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::{
+    dds_attach_t, dds_dispose_ts, dds_duration_t, dds_time, dds_time_t, dds_unregister_instance_ts,
+    dds_waitset_wait, dds_waitset_wait_until, dds_write_ts, dds_writedispose_ts, DDSError,
+    DdsEntity, DDS_INFINITY, DDS_NEVER,
+};
+
+/// A relative time. Maps to `dds_duration_t`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum DdsDuration {
+    Finite(Duration),
+    Infinite,
+}
+
+impl DdsDuration {
+    pub const ZERO: DdsDuration = DdsDuration::Finite(Duration::from_secs(0));
+
+    pub fn from_secs(secs: u64) -> Self {
+        Duration::from_secs(secs).into()
+    }
+
+    pub fn from_millis(millis: u64) -> Self {
+        Duration::from_millis(millis).into()
+    }
+
+    pub fn from_nanos(nanos: i64) -> Self {
+        nanos.into()
+    }
+
+    pub fn is_infinite(&self) -> bool {
+        *self == DdsDuration::Infinite
+    }
+
+    /// The duration as a std `Duration`, `None` when infinite
+    pub fn to_std(&self) -> Option<Duration> {
+        match self {
+            DdsDuration::Finite(d) => Some(*d),
+            DdsDuration::Infinite => None,
+        }
+    }
+
+    pub fn as_nanos(&self) -> dds_duration_t {
+        (*self).into()
+    }
+}
+
+impl Default for DdsDuration {
+    fn default() -> Self {
+        DdsDuration::ZERO
+    }
+}
+
+impl From<Duration> for DdsDuration {
+    fn from(d: Duration) -> Self {
+        match i64::try_from(d.as_nanos()) {
+            Ok(nanos) if nanos < DDS_INFINITY => DdsDuration::Finite(d),
+            _ => DdsDuration::Infinite,
+        }
+    }
+}
+
+impl From<dds_duration_t> for DdsDuration {
+    fn from(nanos: dds_duration_t) -> Self {
+        if nanos == DDS_INFINITY {
+            DdsDuration::Infinite
+        } else if nanos <= 0 {
+            DdsDuration::ZERO
+        } else {
+            DdsDuration::Finite(Duration::from_nanos(nanos as u64))
+        }
+    }
+}
+
+impl From<DdsDuration> for dds_duration_t {
+    fn from(d: DdsDuration) -> Self {
+        match d {
+            DdsDuration::Finite(d) => match i64::try_from(d.as_nanos()) {
+                Ok(nanos) => nanos,
+                Err(_) => DDS_INFINITY,
+            },
+            DdsDuration::Infinite => DDS_INFINITY,
+        }
+    }
+}
+
+impl From<DdsDuration> for Duration {
+    fn from(d: DdsDuration) -> Self {
+        match d {
+            DdsDuration::Finite(d) => d,
+            DdsDuration::Infinite => Duration::MAX,
+        }
+    }
+}
+
+impl Add for DdsDuration {
+    type Output = DdsDuration;
+
+    fn add(self, other: DdsDuration) -> DdsDuration {
+        match (self, other) {
+            (DdsDuration::Finite(a), DdsDuration::Finite(b)) => match a.checked_add(b) {
+                Some(sum) => sum.into(),
+                None => DdsDuration::Infinite,
+            },
+            _ => DdsDuration::Infinite,
+        }
+    }
+}
+
+/// An absolute time in nanoseconds since the UNIX epoch. Maps to `dds_time_t`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum DdsTime {
+    At(dds_time_t),
+    Never,
+}
+
+impl DdsTime {
+    /// The current time as seen by Cyclone (wall clock)
+    pub fn now() -> Self {
+        unsafe { dds_time() }.into()
+    }
+
+    pub fn is_never(&self) -> bool {
+        *self == DdsTime::Never
+    }
+
+    /// The time as a `SystemTime`, `None` for `Never`
+    pub fn to_system_time(&self) -> Option<SystemTime> {
+        match self {
+            DdsTime::At(nanos) if *nanos >= 0 => {
+                UNIX_EPOCH.checked_add(Duration::from_nanos(*nanos as u64))
+            }
+            DdsTime::At(nanos) => {
+                UNIX_EPOCH.checked_sub(Duration::from_nanos(nanos.unsigned_abs()))
+            }
+            DdsTime::Never => None,
+        }
+    }
+
+    /// The time as an `Instant`, mapped from the wall clock using the
+    /// current offset between the two clocks. `None` for `Never` and for
+    /// times an `Instant` cannot represent.
+    pub fn to_instant(&self) -> Option<Instant> {
+        let t = self.to_system_time()?;
+        let now = Instant::now();
+        let wall = SystemTime::now();
+        match t.duration_since(wall) {
+            Ok(ahead) => now.checked_add(ahead),
+            Err(behind) => now.checked_sub(behind.duration()),
+        }
+    }
+
+    pub fn as_nanos(&self) -> dds_time_t {
+        (*self).into()
+    }
+}
+
+impl From<dds_time_t> for DdsTime {
+    fn from(nanos: dds_time_t) -> Self {
+        if nanos == DDS_NEVER {
+            DdsTime::Never
+        } else {
+            DdsTime::At(nanos)
+        }
+    }
+}
+
+impl From<DdsTime> for dds_time_t {
+    fn from(t: DdsTime) -> Self {
+        match t {
+            DdsTime::At(nanos) => nanos,
+            DdsTime::Never => DDS_NEVER,
+        }
+    }
+}
+
+impl From<SystemTime> for DdsTime {
+    fn from(t: SystemTime) -> Self {
+        match t.duration_since(UNIX_EPOCH) {
+            Ok(since) => match i64::try_from(since.as_nanos()) {
+                Ok(nanos) if nanos < DDS_NEVER => DdsTime::At(nanos),
+                _ => DdsTime::Never,
+            },
+            Err(before) => {
+                let nanos = i64::try_from(before.duration().as_nanos()).unwrap_or(i64::MAX);
+                DdsTime::At(-nanos)
+            }
+        }
+    }
+}
+
+impl From<Instant> for DdsTime {
+    /// Instants are monotonic, so they are mapped onto the wall clock using
+    /// the current offset between the two clocks.
+    fn from(t: Instant) -> Self {
+        let now = Instant::now();
+        let wall = SystemTime::now();
+        let wall = if t >= now {
+            wall.checked_add(t - now)
+        } else {
+            wall.checked_sub(now - t)
+        };
+        match wall {
+            Some(wall) => wall.into(),
+            None if t >= now => DdsTime::Never,
+            None => DdsTime::At(i64::MIN + 1),
+        }
+    }
+}
+
+impl Add<DdsDuration> for DdsTime {
+    type Output = DdsTime;
+
+    fn add(self, d: DdsDuration) -> DdsTime {
+        match (self, d) {
+            (DdsTime::At(t), DdsDuration::Finite(_)) => match t.checked_add(d.as_nanos()) {
+                Some(sum) => sum.into(),
+                None => DdsTime::Never,
+            },
+            _ => DdsTime::Never,
+        }
+    }
+}
+
+impl Sub for DdsTime {
+    type Output = DdsDuration;
+
+    /// Time elapsed from `other` to `self`, zero if `other` is later.
+    fn sub(self, other: DdsTime) -> DdsDuration {
+        match (self, other) {
+            (DdsTime::At(a), DdsTime::At(b)) => a.saturating_sub(b).into(),
+            (DdsTime::Never, DdsTime::At(_)) => DdsDuration::Infinite,
+            _ => DdsDuration::ZERO,
+        }
+    }
+}
+
+impl DdsEntity {
+    /// Write a sample with an explicit source timestamp
+    ///
+    /// # Safety
+    /// The entity must be a writer and `data` a sample of its topic's type.
+    pub unsafe fn write_ts(&self, data: *const c_void, timestamp: DdsTime) -> Result<(), DDSError> {
+        DDSError::check(dds_write_ts(self.entity(), data, timestamp.into())).map(|_| ())
+    }
+
+    /// Write and dispose a sample with an explicit source timestamp
+    ///
+    /// # Safety
+    /// The entity must be a writer and `data` a sample of its topic's type.
+    pub unsafe fn writedispose_ts(
+        &self,
+        data: *const c_void,
+        timestamp: DdsTime,
+    ) -> Result<(), DDSError> {
+        DDSError::check(dds_writedispose_ts(self.entity(), data, timestamp.into())).map(|_| ())
+    }
+
+    /// Dispose the instance of a sample with an explicit source timestamp
+    ///
+    /// # Safety
+    /// The entity must be a writer and `data` a sample of its topic's type.
+    pub unsafe fn dispose_ts(
+        &self,
+        data: *const c_void,
+        timestamp: DdsTime,
+    ) -> Result<(), DDSError> {
+        DDSError::check(dds_dispose_ts(self.entity(), data, timestamp.into())).map(|_| ())
+    }
+
+    /// Unregister the instance of a sample with an explicit source timestamp
+    ///
+    /// # Safety
+    /// The entity must be a writer and `data` a sample of its topic's type.
+    pub unsafe fn unregister_instance_ts(
+        &self,
+        data: *const c_void,
+        timestamp: DdsTime,
+    ) -> Result<(), DDSError> {
+        DDSError::check(dds_unregister_instance_ts(
+            self.entity(),
+            data,
+            timestamp.into(),
+        ))
+        .map(|_| ())
+    }
+
+    /// Wait on this waitset for at most `timeout`. The attach values of the
+    /// triggered entities are stored in `xs`; returns how many triggered.
+    pub fn waitset_wait(
+        &self,
+        xs: &mut [dds_attach_t],
+        timeout: DdsDuration,
+    ) -> Result<usize, DDSError> {
+        let ret =
+            unsafe { dds_waitset_wait(self.0, xs.as_mut_ptr(), xs.len() as _, timeout.into()) };
+        DDSError::check(ret).map(|n| n as usize)
+    }
+
+    /// Wait on this waitset until `deadline`
+    pub fn waitset_wait_until(
+        &self,
+        xs: &mut [dds_attach_t],
+        deadline: DdsTime,
+    ) -> Result<usize, DDSError> {
+        let ret = unsafe {
+            dds_waitset_wait_until(self.0, xs.as_mut_ptr(), xs.len() as _, deadline.into())
+        };
+        DDSError::check(ret).map(|n| n as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_convert_both_ways() {
+        let d = Duration::from_millis(1500);
+        assert_eq!(Duration::from(DdsDuration::from(d)), d);
+        assert_eq!(DdsDuration::from(d).as_nanos(), 1_500_000_000);
+        assert_eq!(DdsDuration::from(DDS_INFINITY), DdsDuration::Infinite);
+        assert_eq!(DdsDuration::from(-5i64), DdsDuration::ZERO);
+        assert_eq!(DdsDuration::from(Duration::MAX), DdsDuration::Infinite);
+        assert_eq!(Duration::from(DdsDuration::Infinite), Duration::MAX);
+        assert_eq!(DdsDuration::Infinite.as_nanos(), DDS_INFINITY);
+        assert_eq!(
+            DdsDuration::from_secs(1) + DdsDuration::from_millis(1),
+            DdsDuration::from_nanos(1_001_000_000)
+        );
+        assert_eq!(
+            DdsDuration::from_secs(1) + DdsDuration::Infinite,
+            DdsDuration::Infinite
+        );
+    }
+
+    #[test]
+    fn times_convert_to_and_from_system_time() {
+        let t = UNIX_EPOCH + Duration::from_nanos(1_234_567_891);
+        assert_eq!(DdsTime::from(t), DdsTime::At(1_234_567_891));
+        assert_eq!(DdsTime::At(1_234_567_891).to_system_time(), Some(t));
+
+        let before = UNIX_EPOCH - Duration::from_secs(2);
+        assert_eq!(DdsTime::from(before), DdsTime::At(-2_000_000_000));
+        assert_eq!(DdsTime::At(-2_000_000_000).to_system_time(), Some(before));
+
+        assert_eq!(DdsTime::from(DDS_NEVER), DdsTime::Never);
+        assert_eq!(DdsTime::Never.to_system_time(), None);
+        assert_eq!(DdsTime::Never.to_instant(), None);
+    }
+
+    #[test]
+    fn times_convert_to_and_from_instants() {
+        let slack = Duration::from_millis(50);
+        let now = Instant::now();
+        for t in &[now, now + Duration::from_secs(3600)] {
+            let back = DdsTime::from(*t).to_instant().unwrap();
+            let error = if back > *t { back - *t } else { *t - back };
+            assert!(error < slack, "{:?} off by {:?}", t, error);
+        }
+    }
+
+    #[test]
+    fn time_arithmetic_saturates() {
+        let t = DdsTime::At(1_000);
+        assert_eq!(t + DdsDuration::from_nanos(500), DdsTime::At(1_500));
+        assert_eq!(t + DdsDuration::Infinite, DdsTime::Never);
+        assert_eq!(
+            DdsTime::At(i64::MAX - 1) + DdsDuration::from_secs(1),
+            DdsTime::Never
+        );
+        assert_eq!(DdsTime::At(1_500) - t, DdsDuration::from_nanos(500));
+        assert_eq!(t - DdsTime::At(1_500), DdsDuration::ZERO);
+        assert_eq!(DdsTime::Never - t, DdsDuration::Infinite);
+    }
+}
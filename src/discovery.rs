@@ -0,0 +1,195 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Following the participants, writers and readers in a domain through the
+//! DCPSParticipant, DCPSPublication and DCPSSubscription builtin topics.
+
+use std::ptr;
+
+use crate::matched::MatchedEndpoint;
+use crate::query_condition::loan_samples;
+use crate::{
+    dds_builtintopic_endpoint_t, dds_builtintopic_participant_t, dds_copy_qos, dds_create_reader,
+    dds_entity_t, dds_instance_handle_t, dds_instance_state_DDS_IST_ALIVE, DDSError, DdsEntity,
+    DdsQos, Guid, BUILTIN_TOPIC_DCPSPARTICIPANT, BUILTIN_TOPIC_DCPSPUBLICATION,
+    BUILTIN_TOPIC_DCPSSUBSCRIPTION,
+};
+
+/// Match `name` against a glob pattern with `*` (any run of characters)
+/// and `?` (any single character)
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    let (mut pi, mut ni) = (0, 0);
+    // position of the last `*` and where the name was when it was seen
+    let mut star: Option<(usize, usize)> = None;
+    while ni < n.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+            pi += 1;
+            ni += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ni));
+            pi += 1;
+        } else if let Some((sp, sn)) = star {
+            pi = sp + 1;
+            ni = sn + 1;
+            star = Some((sp, sn + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+/// A change in the set of endpoints
+#[derive(Debug, Clone)]
+pub enum EndpointEvent {
+    /// A new endpoint, or one whose QoS changed
+    Alive(MatchedEndpoint),
+    /// The endpoint with this instance handle is gone
+    Gone(dds_instance_handle_t),
+}
+
+/// A reader of one of the endpoint builtin topics
+pub struct EndpointDiscovery {
+    reader: DdsEntity,
+}
+
+impl EndpointDiscovery {
+    fn new(participant: &DdsEntity, topic: dds_entity_t) -> Result<Self, DDSError> {
+        let reader = DDSError::check(unsafe {
+            dds_create_reader(participant.0, topic, ptr::null(), ptr::null())
+        })?;
+        Ok(EndpointDiscovery {
+            reader: DdsEntity(reader),
+        })
+    }
+
+    /// Follow the writers in the domain
+    pub fn publications(participant: &DdsEntity) -> Result<Self, DDSError> {
+        Self::new(participant, BUILTIN_TOPIC_DCPSPUBLICATION)
+    }
+
+    /// Follow the readers in the domain
+    pub fn subscriptions(participant: &DdsEntity) -> Result<Self, DDSError> {
+        Self::new(participant, BUILTIN_TOPIC_DCPSSUBSCRIPTION)
+    }
+
+    pub fn reader(&self) -> &DdsEntity {
+        &self.reader
+    }
+
+    /// The changes since the last call. The first call returns every
+    /// endpoint known so far.
+    pub fn take(&self) -> Result<Vec<EndpointEvent>, DDSError> {
+        let mut events = Vec::new();
+        loop {
+            let n = unsafe {
+                loan_samples::<dds_builtintopic_endpoint_t, _>(
+                    self.reader.0,
+                    64,
+                    true,
+                    |endpoint, info| match endpoint {
+                        Some(e) if info.instance_state == dds_instance_state_DDS_IST_ALIVE => {
+                            events.push(EndpointEvent::Alive(MatchedEndpoint::from_builtin(
+                                info.instance_handle,
+                                e,
+                            )))
+                        }
+                        _ => events.push(EndpointEvent::Gone(info.instance_handle)),
+                    },
+                )
+            }?;
+            if n < 64 {
+                return Ok(events);
+            }
+        }
+    }
+}
+
+/// An owned copy of a `dds_builtintopic_participant_t`
+#[derive(Debug, Clone)]
+pub struct DiscoveredParticipant {
+    pub instance_handle: dds_instance_handle_t,
+    pub guid: Guid,
+    pub qos: DdsQos,
+}
+
+/// A change in the set of participants
+#[derive(Debug, Clone)]
+pub enum ParticipantEvent {
+    /// A new participant, or one whose QoS changed
+    Alive(DiscoveredParticipant),
+    /// The participant with this instance handle is gone
+    Gone(dds_instance_handle_t),
+}
+
+/// A reader of the DCPSParticipant builtin topic
+pub struct ParticipantDiscovery {
+    reader: DdsEntity,
+}
+
+impl ParticipantDiscovery {
+    pub fn new(participant: &DdsEntity) -> Result<Self, DDSError> {
+        let reader = DDSError::check(unsafe {
+            dds_create_reader(
+                participant.0,
+                BUILTIN_TOPIC_DCPSPARTICIPANT,
+                ptr::null(),
+                ptr::null(),
+            )
+        })?;
+        Ok(ParticipantDiscovery {
+            reader: DdsEntity(reader),
+        })
+    }
+
+    pub fn reader(&self) -> &DdsEntity {
+        &self.reader
+    }
+
+    /// The changes since the last call. The first call returns every
+    /// participant known so far, including the local ones.
+    pub fn take(&self) -> Result<Vec<ParticipantEvent>, DDSError> {
+        let mut events = Vec::new();
+        loop {
+            let n = unsafe {
+                loan_samples::<dds_builtintopic_participant_t, _>(
+                    self.reader.0,
+                    64,
+                    true,
+                    |participant, info| match participant {
+                        Some(p) if info.instance_state == dds_instance_state_DDS_IST_ALIVE => {
+                            let mut qos = DdsQos::new();
+                            if !p.qos.is_null() {
+                                dds_copy_qos(qos.as_mut_ptr(), p.qos);
+                            }
+                            events.push(ParticipantEvent::Alive(DiscoveredParticipant {
+                                instance_handle: info.instance_handle,
+                                guid: p.key.into(),
+                                qos,
+                            }))
+                        }
+                        _ => events.push(ParticipantEvent::Gone(info.instance_handle)),
+                    },
+                )
+            }?;
+            if n < 64 {
+                return Ok(events);
+            }
+        }
+    }
+}
@@ -0,0 +1,975 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Build the XML configuration passed to `dds_create_domain`.
+//!
+//! A `DomainConfig` is a tree of XML elements rooted at `<CycloneDDS>` with
+//! one or more `<Domain>` elements. Cyclone applies every `<Domain>` whose
+//! id matches the domain being created (or is `any`), in order. The typed
+//! setters and getters work on the selected `<Domain>`, the first one unless
+//! [`select_domain`](DomainConfig::select_domain) or
+//! [`add_domain`](DomainConfig::add_domain) picked another. Anything else
+//! read from a file is kept as is, so a configuration loaded from
+//! `CYCLONEDDS_URI` can be adjusted and handed to `dds_create_domain`
+//! without losing settings.
+//!
+//! ```no_run
+//! # use cyclonedds_sys::domain_config::*;
+//! let mut config = DomainConfig::from_env().unwrap_or_default();
+//! config
+//!     .network_interface("eth0")
+//!     .allow_multicast(AllowMulticast::False)
+//!     .peer("192.168.1.10");
+//! let domain = config.create_domain(0).unwrap();
+//! ```
+
+use std::ffi::CString;
+use std::fmt;
+use std::path::Path;
+
+use crate::{dds_create_domain, dds_domainid_t, DDSError, DdsDuration, DdsEntity};
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    /// The text is not well formed XML
+    Xml(String),
+    /// The root element is not `CycloneDDS` (or `Domain`)
+    UnexpectedRoot(String),
+    /// An element holds a value that could not be interpreted
+    InvalidValue {
+        element: String,
+        value: String,
+    },
+    /// `CYCLONEDDS_URI` is not set
+    NoUri,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "cannot read configuration: {}", e),
+            ConfigError::Xml(e) => write!(f, "invalid configuration XML: {}", e),
+            ConfigError::UnexpectedRoot(name) => {
+                write!(f, "unexpected root element <{}>", name)
+            }
+            ConfigError::InvalidValue { element, value } => {
+                write!(f, "invalid value \"{}\" for {}", value, element)
+            }
+            ConfigError::NoUri => write!(f, "CYCLONEDDS_URI is not set"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(e: std::io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    text: Option<String>,
+    children: Vec<Element>,
+}
+
+impl Element {
+    fn new(name: &str) -> Self {
+        Element {
+            name: name.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    fn from_node(node: roxmltree::Node) -> Self {
+        let mut element = Element::new(node.tag_name().name());
+        element.attributes = node
+            .attributes()
+            .filter(|a| a.namespace().is_none())
+            .map(|a| (a.name().to_owned(), a.value().to_owned()))
+            .collect();
+        for child in node.children() {
+            if child.is_element() {
+                element.children.push(Element::from_node(child));
+            } else if child.is_text() {
+                let text = child.text().unwrap_or("").trim();
+                if !text.is_empty() {
+                    element.text = Some(text.to_owned());
+                }
+            }
+        }
+        element
+    }
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn set_attribute(&mut self, name: &str, value: &str) {
+        match self.attributes.iter_mut().find(|(n, _)| n == name) {
+            Some((_, v)) => *v = value.to_owned(),
+            None => self.attributes.push((name.to_owned(), value.to_owned())),
+        }
+    }
+
+    fn child(&self, path: &[&str]) -> Option<&Element> {
+        match path.split_first() {
+            None => Some(self),
+            Some((first, rest)) => self
+                .children
+                .iter()
+                .find(|c| c.name == *first)
+                .and_then(|c| c.child(rest)),
+        }
+    }
+
+    fn child_mut(&mut self, path: &[&str]) -> &mut Element {
+        match path.split_first() {
+            None => self,
+            Some((first, rest)) => {
+                let index = match self.children.iter().position(|c| c.name == *first) {
+                    Some(index) => index,
+                    None => {
+                        self.children.push(Element::new(first));
+                        self.children.len() - 1
+                    }
+                };
+                self.children[index].child_mut(rest)
+            }
+        }
+    }
+
+    fn text_at(&self, path: &[&str]) -> Option<&str> {
+        self.child(path).and_then(|e| e.text.as_deref())
+    }
+
+    fn set_text_at(&mut self, path: &[&str], text: String) {
+        let element = self.child_mut(path);
+        element.children.clear();
+        element.text = Some(text);
+    }
+
+    /// Overlay `other` onto this element. Leaves and lists of same-named
+    /// elements are replaced, other containers are merged recursively.
+    fn merge(&mut self, other: &Element) {
+        for (name, value) in &other.attributes {
+            self.set_attribute(name, value);
+        }
+        if other.text.is_some() {
+            self.text = other.text.clone();
+        }
+        let mut names: Vec<&str> = Vec::new();
+        for child in &other.children {
+            if !names.contains(&child.name.as_str()) {
+                names.push(&child.name);
+            }
+        }
+        for name in names {
+            let group: Vec<&Element> = other.children.iter().filter(|c| c.name == name).collect();
+            let target = self.children.iter_mut().find(|c| c.name == name);
+            match (target, group.as_slice()) {
+                (Some(target), [single]) if !single.children.is_empty() => target.merge(single),
+                _ => {
+                    self.children.retain(|c| c.name != name);
+                    self.children.extend(group.into_iter().cloned());
+                }
+            }
+        }
+    }
+
+    fn write(&self, out: &mut String, depth: usize) {
+        let indent = "  ".repeat(depth);
+        out.push_str(&indent);
+        out.push('<');
+        out.push_str(&self.name);
+        for (name, value) in &self.attributes {
+            out.push_str(&format!(" {}=\"{}\"", name, escape(value)));
+        }
+        if self.children.is_empty() {
+            match &self.text {
+                Some(text) => out.push_str(&format!(">{}</{}>\n", escape(text), self.name)),
+                None => out.push_str("/>\n"),
+            }
+        } else {
+            out.push_str(">\n");
+            for child in &self.children {
+                child.write(out, depth + 1);
+            }
+            out.push_str(&format!("{}</{}>\n", indent, self.name));
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The `General/AllowMulticast` setting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllowMulticast {
+    Default,
+    True,
+    False,
+    /// Multicast only for participant discovery
+    Spdp,
+    Asm,
+    Ssm,
+}
+
+impl AllowMulticast {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AllowMulticast::Default => "default",
+            AllowMulticast::True => "true",
+            AllowMulticast::False => "false",
+            AllowMulticast::Spdp => "spdp",
+            AllowMulticast::Asm => "asm",
+            AllowMulticast::Ssm => "ssm",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "default" => Some(AllowMulticast::Default),
+            "true" => Some(AllowMulticast::True),
+            "false" => Some(AllowMulticast::False),
+            "spdp" => Some(AllowMulticast::Spdp),
+            "asm" => Some(AllowMulticast::Asm),
+            "ssm" => Some(AllowMulticast::Ssm),
+            _ => None,
+        }
+    }
+}
+
+/// The `Discovery/ParticipantIndex` setting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParticipantIndex {
+    Auto,
+    None,
+    Index(u32),
+}
+
+/// The `Tracing/Verbosity` setting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verbosity {
+    None,
+    Severe,
+    Warning,
+    Info,
+    Config,
+    Fine,
+    Finer,
+    Finest,
+}
+
+impl Verbosity {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Verbosity::None => "none",
+            Verbosity::Severe => "severe",
+            Verbosity::Warning => "warning",
+            Verbosity::Info => "info",
+            Verbosity::Config => "config",
+            Verbosity::Fine => "fine",
+            Verbosity::Finer => "finer",
+            Verbosity::Finest => "finest",
+        }
+    }
+}
+
+/// The `SharedMemory/LogLevel` setting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShmLogLevel {
+    Off,
+    Fatal,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Verbose,
+}
+
+impl ShmLogLevel {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ShmLogLevel::Off => "off",
+            ShmLogLevel::Fatal => "fatal",
+            ShmLogLevel::Error => "error",
+            ShmLogLevel::Warn => "warn",
+            ShmLogLevel::Info => "info",
+            ShmLogLevel::Debug => "debug",
+            ShmLogLevel::Verbose => "verbose",
+        }
+    }
+}
+
+/// Render a duration the way Cyclone's configuration expects it
+fn format_duration(d: DdsDuration) -> String {
+    let nanos = match d {
+        DdsDuration::Infinite => return "inf".to_owned(),
+        DdsDuration::Finite(_) => d.as_nanos(),
+    };
+    for (unit, scale) in [("s", 1_000_000_000), ("ms", 1_000_000), ("us", 1_000)].iter() {
+        if nanos != 0 && nanos % scale == 0 {
+            return format!("{} {}", nanos / scale, unit);
+        }
+    }
+    format!("{} ns", nanos)
+}
+
+fn parse_duration(s: &str) -> Option<DdsDuration> {
+    let s = s.trim();
+    if s == "inf" {
+        return Some(DdsDuration::Infinite);
+    }
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let value: f64 = s[..split].parse().ok()?;
+    let scale = match s[split..].trim() {
+        "ns" => 1e0,
+        "us" => 1e3,
+        "ms" => 1e6,
+        "s" => 1e9,
+        "min" => 60e9,
+        "hr" => 3600e9,
+        "day" => 86400e9,
+        _ => return None,
+    };
+    Some(DdsDuration::from_nanos((value * scale) as i64))
+}
+
+fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let value: u64 = s[..split].parse().ok()?;
+    let scale = match s[split..].trim() {
+        "" | "B" => 1,
+        "kB" | "KB" => 1_000,
+        "KiB" => 1 << 10,
+        "MB" => 1_000_000,
+        "MiB" => 1 << 20,
+        "GB" => 1_000_000_000,
+        "GiB" => 1 << 30,
+        _ => return None,
+    };
+    value.checked_mul(scale)
+}
+
+fn parse_domain_id(domain: &Element) -> Result<Option<dds_domainid_t>, ConfigError> {
+    match domain_key(domain) {
+        "any" => Ok(None),
+        id => id.parse().map(Some).map_err(|_| ConfigError::InvalidValue {
+            element: "Domain/@id".to_owned(),
+            value: id.to_owned(),
+        }),
+    }
+}
+
+const GENERAL: &str = "General";
+const DISCOVERY: &str = "Discovery";
+const TRACING: &str = "Tracing";
+const SHARED_MEMORY: &str = "SharedMemory";
+
+const DOMAIN: &str = "Domain";
+
+/// The id of a `<Domain>` element, `any` when it has none
+fn domain_key(element: &Element) -> &str {
+    element.attribute("id").map_or("any", str::trim)
+}
+
+fn id_text(id: Option<dds_domainid_t>) -> String {
+    id.map_or_else(|| "any".to_owned(), |id| id.to_string())
+}
+
+/// A Cyclone DDS domain configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DomainConfig {
+    root: Element,
+    /// Index of the selected `<Domain>` among the `<Domain>` elements
+    selected: usize,
+}
+
+impl Default for DomainConfig {
+    fn default() -> Self {
+        let mut root = Element::new("CycloneDDS");
+        let mut domain = Element::new(DOMAIN);
+        domain.set_attribute("id", "any");
+        root.children.push(domain);
+        DomainConfig { root, selected: 0 }
+    }
+}
+
+impl DomainConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a configuration. Like Cyclone, this accepts a full document,
+    /// a bare `<Domain>` element or a sequence of elements that go inside
+    /// a `<Domain>`.
+    pub fn from_xml(xml: &str) -> Result<Self, ConfigError> {
+        let trimmed = xml.trim();
+        let body = trimmed
+            .strip_prefix("<?xml")
+            .and_then(|rest| rest.find("?>").map(|end| rest[end + 2..].trim_start()))
+            .unwrap_or(trimmed);
+        let wrapped;
+        let text = if body.starts_with("<CycloneDDS") {
+            body
+        } else if body.starts_with("<Domain") {
+            wrapped = format!("<CycloneDDS>{}</CycloneDDS>", body);
+            &wrapped
+        } else {
+            wrapped = format!("<CycloneDDS><Domain>{}</Domain></CycloneDDS>", body);
+            &wrapped
+        };
+
+        let doc = roxmltree::Document::parse(text).map_err(|e| ConfigError::Xml(e.to_string()))?;
+        let root = Element::from_node(doc.root_element());
+        if root.name != "CycloneDDS" {
+            return Err(ConfigError::UnexpectedRoot(root.name));
+        }
+        let mut config = DomainConfig { root, selected: 0 };
+        config.domain_mut();
+        Ok(config)
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        Self::from_xml(&std::fs::read_to_string(path)?)
+    }
+
+    /// Load a configuration from a `CYCLONEDDS_URI` style value: inline
+    /// XML, or a comma separated list of files (optionally `file://`
+    /// prefixed) where later files override earlier ones.
+    pub fn from_uri(uri: &str) -> Result<Self, ConfigError> {
+        let uri = uri.trim();
+        if uri.starts_with('<') {
+            return Self::from_xml(uri);
+        }
+        let mut config = DomainConfig::new();
+        for file in uri.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            let path = file.strip_prefix("file://").unwrap_or(file);
+            config.merge(&Self::from_file(path)?);
+        }
+        Ok(config)
+    }
+
+    /// Load the configuration from `CYCLONEDDS_URI`
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let uri = std::env::var("CYCLONEDDS_URI").map_err(|_| ConfigError::NoUri)?;
+        Self::from_uri(&uri)
+    }
+
+    /// Overlay the settings of `other` onto this configuration. Each
+    /// `<Domain>` of `other` is merged into the first one with the same id,
+    /// or added if there is none.
+    pub fn merge(&mut self, other: &DomainConfig) -> &mut Self {
+        let mut rest = other.root.clone();
+        rest.children.retain(|c| c.name != DOMAIN);
+        self.root.merge(&rest);
+
+        for domain in other.root.children.iter().filter(|c| c.name == DOMAIN) {
+            let target = self
+                .root
+                .children
+                .iter_mut()
+                .find(|c| c.name == DOMAIN && domain_key(c) == domain_key(domain));
+            match target {
+                Some(target) => target.merge(domain),
+                None => self.root.children.push(domain.clone()),
+            }
+        }
+        self
+    }
+
+    /// The ids of the `<Domain>` elements in order, `None` for `any`
+    pub fn domain_ids(&self) -> Result<Vec<Option<dds_domainid_t>>, ConfigError> {
+        self.domains().map(parse_domain_id).collect()
+    }
+
+    /// Select the first `<Domain>` with id `id` (`None` for `any`) for the
+    /// typed setters and getters, adding one at the end if there is none
+    pub fn select_domain(&mut self, id: Option<dds_domainid_t>) -> &mut Self {
+        let key = id_text(id);
+        let index = self.domains().position(|d| domain_key(d) == key);
+        match index {
+            Some(index) => self.selected = index,
+            None => {
+                self.add_domain(id);
+            }
+        }
+        self
+    }
+
+    /// Add a `<Domain>` with id `id` (`None` for `any`) at the end and select it
+    pub fn add_domain(&mut self, id: Option<dds_domainid_t>) -> &mut Self {
+        let mut domain = Element::new(DOMAIN);
+        domain.set_attribute("id", &id_text(id));
+        self.selected = self.domains().count();
+        self.root.children.push(domain);
+        self
+    }
+
+    /// The XML text to hand to `dds_create_domain`
+    pub fn to_xml(&self) -> String {
+        let mut out = String::new();
+        self.root.write(&mut out, 0);
+        out
+    }
+
+    /// Create a domain with this configuration
+    pub fn create_domain(&self, domain: dds_domainid_t) -> Result<DdsEntity, DDSError> {
+        let xml = CString::new(self.to_xml()).map_err(|_| DDSError::BadParameter)?;
+        let entity = DDSError::check(unsafe { dds_create_domain(domain, xml.as_ptr()) })?;
+        Ok(unsafe { DdsEntity::new(entity) })
+    }
+
+    fn domains(&self) -> impl Iterator<Item = &Element> {
+        self.root.children.iter().filter(|c| c.name == DOMAIN)
+    }
+
+    fn domain(&self) -> Option<&Element> {
+        self.domains().nth(self.selected)
+    }
+
+    fn domain_mut(&mut self) -> &mut Element {
+        let count = self.domains().count();
+        if count == 0 {
+            self.root.children.push(Element::new(DOMAIN));
+        }
+        self.selected = self.selected.min(count.max(1) - 1);
+        let selected = self.selected;
+        self.root
+            .children
+            .iter_mut()
+            .filter(|c| c.name == DOMAIN)
+            .nth(selected)
+            .unwrap()
+    }
+
+    fn set(&mut self, path: &[&str], value: String) -> &mut Self {
+        self.domain_mut().set_text_at(path, value);
+        self
+    }
+
+    fn get(&self, path: &[&str]) -> Option<&str> {
+        self.domain().and_then(|d| d.text_at(path))
+    }
+
+    fn get_parsed<T, F>(&self, path: &[&str], parse: F) -> Result<Option<T>, ConfigError>
+    where
+        F: Fn(&str) -> Option<T>,
+    {
+        match self.get(path) {
+            None => Ok(None),
+            Some(value) => parse(value)
+                .map(Some)
+                .ok_or_else(|| ConfigError::InvalidValue {
+                    element: path.join("/"),
+                    value: value.to_owned(),
+                }),
+        }
+    }
+
+    /// Set the id of the selected `<Domain>`, `None` for `any`
+    pub fn domain_id(&mut self, id: Option<dds_domainid_t>) -> &mut Self {
+        self.domain_mut().set_attribute("id", &id_text(id));
+        self
+    }
+
+    pub fn get_domain_id(&self) -> Result<Option<dds_domainid_t>, ConfigError> {
+        match self.domain() {
+            None => Ok(None),
+            Some(domain) => parse_domain_id(domain),
+        }
+    }
+
+    /// Add a network interface by name
+    pub fn network_interface(&mut self, name: &str) -> &mut Self {
+        self.push_interface("name", name)
+    }
+
+    /// Add a network interface by address
+    pub fn network_interface_address(&mut self, address: &str) -> &mut Self {
+        self.push_interface("address", address)
+    }
+
+    fn push_interface(&mut self, key: &str, value: &str) -> &mut Self {
+        let mut interface = Element::new("NetworkInterface");
+        interface.set_attribute(key, value);
+        self.domain_mut()
+            .child_mut(&[GENERAL, "Interfaces"])
+            .children
+            .push(interface);
+        self
+    }
+
+    pub fn clear_network_interfaces(&mut self) -> &mut Self {
+        self.domain_mut()
+            .child_mut(&[GENERAL])
+            .children
+            .retain(|c| c.name != "Interfaces" && c.name != "NetworkInterfaceAddress");
+        self
+    }
+
+    /// Names (or addresses) of the configured interfaces
+    pub fn get_network_interfaces(&self) -> Vec<String> {
+        let mut interfaces: Vec<String> = self
+            .domain()
+            .and_then(|d| d.child(&[GENERAL, "Interfaces"]))
+            .map(|e| {
+                e.children
+                    .iter()
+                    .filter_map(|i| i.attribute("name").or_else(|| i.attribute("address")))
+                    .map(str::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default();
+        // the pre 0.10 spelling
+        if let Some(list) = self.get(&[GENERAL, "NetworkInterfaceAddress"]) {
+            interfaces.extend(list.split(',').map(|s| s.trim().to_owned()));
+        }
+        interfaces
+    }
+
+    pub fn allow_multicast(&mut self, multicast: AllowMulticast) -> &mut Self {
+        self.set(&[GENERAL, "AllowMulticast"], multicast.as_str().to_owned())
+    }
+
+    pub fn get_allow_multicast(&self) -> Result<Option<AllowMulticast>, ConfigError> {
+        self.get_parsed(&[GENERAL, "AllowMulticast"], AllowMulticast::parse)
+    }
+
+    /// Largest RTPS message, in bytes
+    pub fn max_message_size(&mut self, bytes: u32) -> &mut Self {
+        self.set(&[GENERAL, "MaxMessageSize"], format!("{}B", bytes))
+    }
+
+    pub fn get_max_message_size(&self) -> Result<Option<u64>, ConfigError> {
+        self.get_parsed(&[GENERAL, "MaxMessageSize"], parse_size)
+    }
+
+    /// Add a unicast discovery peer (an address or host name, with an
+    /// optional port)
+    pub fn peer(&mut self, address: &str) -> &mut Self {
+        let mut peer = Element::new("Peer");
+        peer.set_attribute("address", address);
+        self.domain_mut()
+            .child_mut(&[DISCOVERY, "Peers"])
+            .children
+            .push(peer);
+        self
+    }
+
+    pub fn clear_peers(&mut self) -> &mut Self {
+        self.domain_mut()
+            .child_mut(&[DISCOVERY])
+            .children
+            .retain(|c| c.name != "Peers");
+        self
+    }
+
+    pub fn get_peers(&self) -> Vec<String> {
+        self.domain()
+            .and_then(|d| d.child(&[DISCOVERY, "Peers"]))
+            .map(|e| {
+                e.children
+                    .iter()
+                    .filter_map(|p| p.attribute("address"))
+                    .map(str::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn participant_index(&mut self, index: ParticipantIndex) -> &mut Self {
+        let value = match index {
+            ParticipantIndex::Auto => "auto".to_owned(),
+            ParticipantIndex::None => "none".to_owned(),
+            ParticipantIndex::Index(i) => i.to_string(),
+        };
+        self.set(&[DISCOVERY, "ParticipantIndex"], value)
+    }
+
+    pub fn get_participant_index(&self) -> Result<Option<ParticipantIndex>, ConfigError> {
+        self.get_parsed(&[DISCOVERY, "ParticipantIndex"], |s| match s {
+            "auto" | "default" => Some(ParticipantIndex::Auto),
+            "none" => Some(ParticipantIndex::None),
+            n => n.parse().ok().map(ParticipantIndex::Index),
+        })
+    }
+
+    pub fn max_auto_participant_index(&mut self, max: u32) -> &mut Self {
+        self.set(&[DISCOVERY, "MaxAutoParticipantIndex"], max.to_string())
+    }
+
+    /// The RTPS port number base
+    pub fn port_base(&mut self, base: u16) -> &mut Self {
+        self.set(&[DISCOVERY, "Ports", "Base"], base.to_string())
+    }
+
+    pub fn get_port_base(&self) -> Result<Option<u16>, ConfigError> {
+        self.get_parsed(&[DISCOVERY, "Ports", "Base"], |s| s.parse().ok())
+    }
+
+    pub fn domain_gain(&mut self, gain: u16) -> &mut Self {
+        self.set(&[DISCOVERY, "Ports", "DomainGain"], gain.to_string())
+    }
+
+    pub fn participant_gain(&mut self, gain: u16) -> &mut Self {
+        self.set(&[DISCOVERY, "Ports", "ParticipantGain"], gain.to_string())
+    }
+
+    /// Only participants with the same tag discover each other
+    pub fn discovery_tag(&mut self, tag: &str) -> &mut Self {
+        self.set(&[DISCOVERY, "Tag"], tag.to_owned())
+    }
+
+    pub fn get_discovery_tag(&self) -> Option<&str> {
+        self.get(&[DISCOVERY, "Tag"])
+    }
+
+    /// Interval between participant announcements
+    pub fn spdp_interval<D: Into<DdsDuration>>(&mut self, interval: D) -> &mut Self {
+        self.set(
+            &[DISCOVERY, "SPDPInterval"],
+            format_duration(interval.into()),
+        )
+    }
+
+    pub fn get_spdp_interval(&self) -> Result<Option<DdsDuration>, ConfigError> {
+        self.get_parsed(&[DISCOVERY, "SPDPInterval"], parse_duration)
+    }
+
+    /// Trace categories, e.g. `["discovery", "config"]`
+    pub fn trace_categories<S: AsRef<str>>(&mut self, categories: &[S]) -> &mut Self {
+        let list: Vec<&str> = categories.iter().map(AsRef::as_ref).collect();
+        self.set(&[TRACING, "Category"], list.join(","))
+    }
+
+    pub fn trace_verbosity(&mut self, verbosity: Verbosity) -> &mut Self {
+        self.set(&[TRACING, "Verbosity"], verbosity.as_str().to_owned())
+    }
+
+    /// Trace output file, `stdout` and `stderr` are understood by Cyclone
+    pub fn trace_output<P: AsRef<Path>>(&mut self, file: P) -> &mut Self {
+        let file = file.as_ref().to_string_lossy().into_owned();
+        self.set(&[TRACING, "OutputFile"], file)
+    }
+
+    pub fn shared_memory(&mut self, enable: bool) -> &mut Self {
+        self.set(&[SHARED_MEMORY, "Enable"], enable.to_string())
+    }
+
+    pub fn get_shared_memory(&self) -> Result<Option<bool>, ConfigError> {
+        self.get_parsed(&[SHARED_MEMORY, "Enable"], |s| s.parse().ok())
+    }
+
+    pub fn shm_log_level(&mut self, level: ShmLogLevel) -> &mut Self {
+        self.set(&[SHARED_MEMORY, "LogLevel"], level.as_str().to_owned())
+    }
+
+    pub fn shm_sub_queue_capacity(&mut self, capacity: u32) -> &mut Self {
+        self.set(&[SHARED_MEMORY, "SubQueueCapacity"], capacity.to_string())
+    }
+
+    pub fn shm_sub_history_request(&mut self, history: u32) -> &mut Self {
+        self.set(&[SHARED_MEMORY, "SubHistoryRequest"], history.to_string())
+    }
+
+    pub fn shm_pub_history_capacity(&mut self, capacity: u32) -> &mut Self {
+        self.set(&[SHARED_MEMORY, "PubHistoryCapacity"], capacity.to_string())
+    }
+
+    /// Set any element below `<Domain>` by its path, e.g.
+    /// `set_raw(&["Internal", "Watermarks", "WhcHigh"], "500kB")`
+    pub fn set_raw(&mut self, path: &[&str], value: &str) -> &mut Self {
+        self.set(path, value.to_owned())
+    }
+
+    pub fn get_raw(&self, path: &[&str]) -> Option<&str> {
+        self.get(path)
+    }
+}
+
+impl fmt::Display for DomainConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_xml())
+    }
+}
+
+impl std::str::FromStr for DomainConfig {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_xml(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xml_round_trips() {
+        let mut config = DomainConfig::new();
+        config
+            .domain_id(Some(7))
+            .network_interface("lo")
+            .allow_multicast(AllowMulticast::Spdp)
+            .peer("10.0.0.1")
+            .peer("10.0.0.2")
+            .participant_index(ParticipantIndex::Auto)
+            .spdp_interval(DdsDuration::from_millis(250))
+            .set_raw(&["Internal", "Watermarks", "WhcHigh"], "500kB");
+        let xml = config.to_xml();
+        let parsed = DomainConfig::from_xml(&xml).unwrap();
+        assert_eq!(parsed, config);
+        assert_eq!(parsed.to_xml(), xml);
+        assert_eq!(parsed.get_domain_id().unwrap(), Some(7));
+        assert_eq!(parsed.get_peers(), vec!["10.0.0.1", "10.0.0.2"]);
+        assert_eq!(
+            parsed.get_spdp_interval().unwrap(),
+            Some(DdsDuration::from_millis(250))
+        );
+        assert_eq!(
+            parsed.get_raw(&["Internal", "Watermarks", "WhcHigh"]),
+            Some("500kB")
+        );
+    }
+
+    #[test]
+    fn fragments_are_wrapped_in_a_domain() {
+        let config =
+            DomainConfig::from_xml("<General><AllowMulticast>false</AllowMulticast></General>")
+                .unwrap();
+        assert_eq!(
+            config.get_allow_multicast().unwrap(),
+            Some(AllowMulticast::False)
+        );
+        assert!(matches!(
+            DomainConfig::from_xml("<General>"),
+            Err(ConfigError::Xml(_))
+        ));
+    }
+
+    #[test]
+    fn several_domains_are_kept_and_selected() {
+        let xml = r#"<?xml version="1.0"?>
+            <CycloneDDS>
+              <Domain id="any"><General><MaxMessageSize>1400B</MaxMessageSize></General></Domain>
+              <Domain id="3"><General><MaxMessageSize>64kB</MaxMessageSize></General></Domain>
+            </CycloneDDS>"#;
+        let mut config = DomainConfig::from_xml(xml).unwrap();
+        assert_eq!(config.domain_ids().unwrap(), vec![None, Some(3)]);
+        assert_eq!(config.get_max_message_size().unwrap(), Some(1400));
+
+        config.select_domain(Some(3)).peer("127.0.0.1");
+        assert_eq!(config.get_max_message_size().unwrap(), Some(64_000));
+        assert_eq!(config.get_peers(), vec!["127.0.0.1"]);
+        config.select_domain(None);
+        assert!(config.get_peers().is_empty());
+
+        config.add_domain(Some(5)).max_message_size(9000);
+        assert_eq!(config.domain_ids().unwrap(), vec![None, Some(3), Some(5)]);
+        let parsed = DomainConfig::from_xml(&config.to_xml()).unwrap();
+        assert_eq!(parsed.domain_ids().unwrap(), vec![None, Some(3), Some(5)]);
+    }
+
+    #[test]
+    fn merge_overlays_domains_by_id() {
+        let mut base = DomainConfig::from_xml(
+            r#"<CycloneDDS>
+                 <Domain id="any">
+                   <General><AllowMulticast>true</AllowMulticast></General>
+                   <Discovery><Peers><Peer address="a"/><Peer address="b"/></Peers></Discovery>
+                 </Domain>
+                 <Domain id="1"><Discovery><ParticipantIndex>3</ParticipantIndex></Discovery></Domain>
+               </CycloneDDS>"#,
+        )
+        .unwrap();
+        let overlay = DomainConfig::from_xml(
+            r#"<CycloneDDS>
+                 <Domain id="1"><Discovery><ParticipantIndex>auto</ParticipantIndex></Discovery></Domain>
+                 <Domain id="any"><Discovery><Peers><Peer address="c"/></Peers></Discovery></Domain>
+                 <Domain id="2"><General><MaxMessageSize>1kB</MaxMessageSize></General></Domain>
+               </CycloneDDS>"#,
+        )
+        .unwrap();
+        base.merge(&overlay);
+
+        assert_eq!(base.domain_ids().unwrap(), vec![None, Some(1), Some(2)]);
+        assert_eq!(
+            base.get_allow_multicast().unwrap(),
+            Some(AllowMulticast::True)
+        );
+        assert_eq!(base.get_peers(), vec!["c"]);
+        base.select_domain(Some(1));
+        assert_eq!(
+            base.get_participant_index().unwrap(),
+            Some(ParticipantIndex::Auto)
+        );
+        base.select_domain(Some(2));
+        assert_eq!(base.get_max_message_size().unwrap(), Some(1000));
+    }
+
+    #[test]
+    fn durations_parse_and_format() {
+        assert_eq!(parse_duration("inf"), Some(DdsDuration::Infinite));
+        assert_eq!(
+            parse_duration("100 ms"),
+            Some(DdsDuration::from_millis(100))
+        );
+        assert_eq!(parse_duration("1.5s"), Some(DdsDuration::from_millis(1500)));
+        assert_eq!(parse_duration("2 min"), Some(DdsDuration::from_secs(120)));
+        assert_eq!(parse_duration("10"), None);
+        assert_eq!(parse_duration("5 weeks"), None);
+        for d in &[
+            DdsDuration::Infinite,
+            DdsDuration::from_secs(3),
+            DdsDuration::from_millis(20),
+            DdsDuration::from_nanos(1_500),
+            DdsDuration::from_nanos(7),
+            DdsDuration::ZERO,
+        ] {
+            assert_eq!(parse_duration(&format_duration(*d)), Some(*d));
+        }
+    }
+
+    #[test]
+    fn sizes_parse() {
+        assert_eq!(parse_size("1400"), Some(1400));
+        assert_eq!(parse_size("1400B"), Some(1400));
+        assert_eq!(parse_size("64 kB"), Some(64_000));
+        assert_eq!(parse_size("64KiB"), Some(65_536));
+        assert_eq!(parse_size("2MiB"), Some(2 << 20));
+        assert_eq!(parse_size("1 GB"), Some(1_000_000_000));
+        assert_eq!(parse_size("1 TB"), None);
+        assert_eq!(parse_size("-1"), None);
+        assert_eq!(parse_size("99999999999999 GiB"), None);
+    }
+}
@@ -0,0 +1,1437 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Types known only at runtime, built from the serializer ops of a topic
+//! descriptor, and their serialized samples.
+//!
+//! [`DynType::discover`] finds a topic in the domain and builds its type
+//! through type discovery. [`DynType::decode`] and [`DynType::encode`]
+//! convert between serialized samples (XCDR1 or XCDR2, with the
+//! encapsulation header) and [`DynValue`]s, so samples can be read and
+//! written with the raw topics of [`crate::raw`] without a generated type.
+//! [`TypeMeta`] keeps what discovery found (the ops and the serialized type
+//! information and type map) for storing alongside recorded samples, and
+//! [`DynType::key_bytes`] gives what the key hash of a sample is made of.
+//!
+//! The ops carry no member names. Structs are positional unless names are
+//! given with [`DynType::with_names`], in the flattened form used by
+//! [`crate::sample_layout`]. Unions, bitmasks and mutable types are not
+//! supported.
+
+use std::cell::Cell;
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::ptr;
+
+use crate::sample_layout::{
+    op_code, op_jmp, op_jsr, op_subtype, op_type, DDS_OP_FLAG_FP, DDS_OP_FLAG_KEY, DDS_OP_FLAG_SGN,
+};
+use crate::*;
+
+pub(crate) const CDR_BE: u16 = 0x0000;
+pub(crate) const CDR_LE: u16 = 0x0001;
+pub(crate) const CDR2_BE: u16 = 0x0006;
+pub(crate) const CDR2_LE: u16 = 0x0007;
+const D_CDR2_BE: u16 = 0x0008;
+const D_CDR2_LE: u16 = 0x0009;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DynError {
+    Dds(DDSError),
+    /// No topic with this name was found in time
+    TopicNotFound(String),
+    /// An instruction that is not understood was found at the given op index
+    UnsupportedOp(usize, u32),
+    /// The type uses something that cannot be handled
+    Unsupported(&'static str),
+    /// The encapsulation is not one of the plain or delimited CDR encodings
+    Encoding(u16),
+    /// The serialized sample ends early
+    Truncated,
+    /// The number of names does not match the number of leaf members
+    NameCount {
+        expected: usize,
+        got: usize,
+    },
+    /// A value does not fit the type, at the given member path
+    Value(String, String),
+}
+
+impl std::error::Error for DynError {}
+
+impl fmt::Display for DynError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DynError::Dds(e) => write!(f, "{}", e),
+            DynError::TopicNotFound(name) => write!(f, "topic {} not found", name),
+            DynError::UnsupportedOp(index, insn) => {
+                write!(f, "unsupported op {:#010x} at index {}", insn, index)
+            }
+            DynError::Unsupported(what) => write!(f, "{} are not supported", what),
+            DynError::Encoding(id) => write!(f, "unsupported encoding {:#06x}", id),
+            DynError::Truncated => write!(f, "sample is truncated"),
+            DynError::NameCount { expected, got } => write!(
+                f,
+                "type has {} leaf members but {} names were given",
+                expected, got
+            ),
+            DynError::Value(path, msg) => {
+                if path.is_empty() {
+                    write!(f, "{}", msg)
+                } else {
+                    write!(f, "{}: {}", path, msg)
+                }
+            }
+        }
+    }
+}
+
+impl From<DDSError> for DynError {
+    fn from(e: DDSError) -> Self {
+        DynError::Dds(e)
+    }
+}
+
+/// The type of a member
+#[derive(Debug, Clone, PartialEq)]
+pub enum DynKind {
+    Bool,
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Int64,
+    UInt64,
+    Float32,
+    Float64,
+    /// Enumerators are not in the ops, so enums are their ordinal
+    Enum,
+    String,
+    /// Bound excluding the terminating NUL
+    BoundedString(usize),
+    Sequence(Box<DynKind>, Option<usize>),
+    Array(Box<DynKind>, usize),
+    Struct(DynStruct),
+}
+
+impl DynKind {
+    /// Whether XCDR2 serializes a sequence or array of this without a
+    /// DHEADER
+    fn is_primitive(&self) -> bool {
+        !matches!(
+            self,
+            DynKind::String
+                | DynKind::BoundedString(_)
+                | DynKind::Sequence(..)
+                | DynKind::Array(..)
+                | DynKind::Struct(_)
+        )
+    }
+
+    /// The value of a member that is not given
+    pub fn default_value(&self) -> DynValue {
+        match self {
+            DynKind::Bool => DynValue::Bool(false),
+            DynKind::Int8 | DynKind::Int16 | DynKind::Int32 | DynKind::Int64 => DynValue::Int(0),
+            DynKind::UInt8
+            | DynKind::UInt16
+            | DynKind::UInt32
+            | DynKind::UInt64
+            | DynKind::Enum => DynValue::UInt(0),
+            DynKind::Float32 | DynKind::Float64 => DynValue::Float(0.0),
+            DynKind::String | DynKind::BoundedString(_) => DynValue::Str(String::new()),
+            DynKind::Sequence(..) => DynValue::Seq(Vec::new()),
+            DynKind::Array(elem, n) => DynValue::Seq(vec![elem.default_value(); *n]),
+            DynKind::Struct(s) => {
+                DynValue::Struct(s.members.iter().map(|m| m.kind.default_value()).collect())
+            }
+        }
+    }
+
+    fn has_appendable(&self) -> bool {
+        match self {
+            DynKind::Sequence(elem, _) | DynKind::Array(elem, _) => elem.has_appendable(),
+            DynKind::Struct(s) => s.appendable || s.members.iter().any(|m| m.kind.has_appendable()),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynMember {
+    pub name: Option<String>,
+    pub kind: DynKind,
+    pub key: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynStruct {
+    pub members: Vec<DynMember>,
+    /// `@appendable`, serialized with a DHEADER in XCDR2
+    pub appendable: bool,
+}
+
+/// A sample or part of one
+#[derive(Debug, Clone, PartialEq)]
+pub enum DynValue {
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str(String),
+    /// Sequences and arrays
+    Seq(Vec<DynValue>),
+    /// Struct members in declaration order
+    Struct(Vec<DynValue>),
+}
+
+/// Reads the ops at `ops`. `len` bounds the reads when the number of words
+/// is known, `used` is one past the last word read.
+struct OpsParser {
+    ops: *const u32,
+    len: Option<usize>,
+    used: Cell<usize>,
+}
+
+impl OpsParser {
+    fn new(ops: *const u32, len: Option<usize>) -> Self {
+        OpsParser {
+            ops,
+            len,
+            used: Cell::new(0),
+        }
+    }
+
+    fn word(&self, index: usize) -> Result<u32, DynError> {
+        match self.len {
+            Some(len) if index >= len => Err(DynError::Truncated),
+            _ => {
+                self.used.set(self.used.get().max(index + 1));
+                Ok(unsafe { *self.ops.add(index) })
+            }
+        }
+    }
+
+    fn parse_struct(&self, start: usize) -> Result<DynStruct, DynError> {
+        let mut s = DynStruct {
+            members: Vec::new(),
+            appendable: false,
+        };
+        let mut index = start;
+        loop {
+            let insn = self.word(index)?;
+            match op_code(insn) {
+                dds_stream_opcode_DDS_OP_RTS => return Ok(s),
+                dds_stream_opcode_DDS_OP_DLC => {
+                    s.appendable = true;
+                    index += 1;
+                }
+                dds_stream_opcode_DDS_OP_PLC => return Err(DynError::Unsupported("mutable types")),
+                dds_stream_opcode_DDS_OP_ADR => {
+                    let (kind, len) = self.parse_member(index)?;
+                    s.members.push(DynMember {
+                        name: None,
+                        kind,
+                        key: insn & DDS_OP_FLAG_KEY != 0,
+                    });
+                    index += len;
+                }
+                _ => return Err(DynError::UnsupportedOp(index, insn)),
+            }
+        }
+    }
+
+    /// The kind of the member at `index` and the number of op words it takes
+    fn parse_member(&self, index: usize) -> Result<(DynKind, usize), DynError> {
+        let insn = self.word(index)?;
+        let typecode = op_type(insn);
+        if let Some(kind) = primitive(insn, typecode) {
+            return Ok((kind, 2));
+        }
+        match typecode {
+            dds_stream_typecode_DDS_OP_VAL_STR => Ok((DynKind::String, 2)),
+            dds_stream_typecode_DDS_OP_VAL_BST => Ok((
+                DynKind::BoundedString((self.word(index + 2)? as usize).saturating_sub(1)),
+                3,
+            )),
+            dds_stream_typecode_DDS_OP_VAL_ENU => Ok((DynKind::Enum, 3)),
+            dds_stream_typecode_DDS_OP_VAL_SEQ | dds_stream_typecode_DDS_OP_VAL_BSQ => {
+                let bounded = typecode == dds_stream_typecode_DDS_OP_VAL_BSQ;
+                let b = bounded as usize;
+                let bound = if bounded {
+                    Some(self.word(index + 2)? as usize)
+                } else {
+                    None
+                };
+                let (elem, len) = match op_subtype(insn) {
+                    dds_stream_typecode_DDS_OP_VAL_STR => (DynKind::String, 2 + b),
+                    dds_stream_typecode_DDS_OP_VAL_BST => (
+                        DynKind::BoundedString(
+                            (self.word(index + 2 + b)? as usize).saturating_sub(1),
+                        ),
+                        3 + b,
+                    ),
+                    dds_stream_typecode_DDS_OP_VAL_ENU => (DynKind::Enum, 4 + b),
+                    dds_stream_typecode_DDS_OP_VAL_BMK => {
+                        return Err(DynError::Unsupported("bitmasks"))
+                    }
+                    subtype => match primitive(insn, subtype) {
+                        Some(kind) => (kind, 2 + b),
+                        None => self.complex_element(index, subtype, 3 + b, 4 + b)?,
+                    },
+                };
+                Ok((DynKind::Sequence(Box::new(elem), bound), len))
+            }
+            dds_stream_typecode_DDS_OP_VAL_ARR => {
+                let count = self.word(index + 2)? as usize;
+                let (elem, len) = match op_subtype(insn) {
+                    dds_stream_typecode_DDS_OP_VAL_STR => (DynKind::String, 3),
+                    dds_stream_typecode_DDS_OP_VAL_BST => (
+                        DynKind::BoundedString((self.word(index + 4)? as usize).saturating_sub(1)),
+                        5,
+                    ),
+                    dds_stream_typecode_DDS_OP_VAL_ENU => (DynKind::Enum, 4),
+                    dds_stream_typecode_DDS_OP_VAL_BMK => {
+                        return Err(DynError::Unsupported("bitmasks"))
+                    }
+                    subtype => match primitive(insn, subtype) {
+                        Some(kind) => (kind, 3),
+                        None => self.complex_element(index, subtype, 3, 5)?,
+                    },
+                };
+                Ok((DynKind::Array(Box::new(elem), count), len))
+            }
+            dds_stream_typecode_DDS_OP_VAL_EXT => {
+                // @external only changes the C representation, not the wire
+                let jump = self.word(index + 2)?;
+                let nested = (index as isize + op_jsr(jump)) as usize;
+                let len = match op_jmp(jump) {
+                    0 => 3,
+                    jmp => jmp,
+                };
+                Ok((DynKind::Struct(self.parse_struct(nested)?), len))
+            }
+            dds_stream_typecode_DDS_OP_VAL_UNI => Err(DynError::Unsupported("unions")),
+            dds_stream_typecode_DDS_OP_VAL_BMK => Err(DynError::Unsupported("bitmasks")),
+            _ => Err(DynError::UnsupportedOp(index, insn)),
+        }
+    }
+
+    /// The element of a sequence or array of structs, sequences or arrays,
+    /// whose ops are a subroutine found through the jump word at
+    /// `index + jump_at`
+    fn complex_element(
+        &self,
+        index: usize,
+        subtype: u32,
+        jump_at: usize,
+        default_len: usize,
+    ) -> Result<(DynKind, usize), DynError> {
+        let jump = self.word(index + jump_at)?;
+        let nested = (index as isize + op_jsr(jump)) as usize;
+        let len = match op_jmp(jump) {
+            0 => default_len,
+            jmp => jmp,
+        };
+        let elem = match subtype {
+            dds_stream_typecode_DDS_OP_VAL_STU | dds_stream_typecode_DDS_OP_VAL_EXT => {
+                DynKind::Struct(self.parse_struct(nested)?)
+            }
+            _ => self.parse_member(nested)?.0,
+        };
+        Ok((elem, len))
+    }
+}
+
+fn primitive(insn: u32, typecode: u32) -> Option<DynKind> {
+    let signed = insn & DDS_OP_FLAG_SGN != 0;
+    let float = insn & DDS_OP_FLAG_FP != 0;
+    let kind = match typecode {
+        dds_stream_typecode_DDS_OP_VAL_BLN => DynKind::Bool,
+        dds_stream_typecode_DDS_OP_VAL_1BY if signed => DynKind::Int8,
+        dds_stream_typecode_DDS_OP_VAL_1BY => DynKind::UInt8,
+        dds_stream_typecode_DDS_OP_VAL_2BY if signed => DynKind::Int16,
+        dds_stream_typecode_DDS_OP_VAL_2BY => DynKind::UInt16,
+        dds_stream_typecode_DDS_OP_VAL_4BY if float => DynKind::Float32,
+        dds_stream_typecode_DDS_OP_VAL_4BY if signed => DynKind::Int32,
+        dds_stream_typecode_DDS_OP_VAL_4BY => DynKind::UInt32,
+        dds_stream_typecode_DDS_OP_VAL_8BY if float => DynKind::Float64,
+        dds_stream_typecode_DDS_OP_VAL_8BY if signed => DynKind::Int64,
+        dds_stream_typecode_DDS_OP_VAL_8BY => DynKind::UInt64,
+        _ => return None,
+    };
+    Some(kind)
+}
+
+/// A topic type reconstructed from its serializer ops
+#[derive(Debug, Clone)]
+pub struct DynType {
+    type_name: String,
+    root: DynStruct,
+}
+
+impl DynType {
+    /// Build the type from the ops of a topic descriptor
+    ///
+    /// # Safety
+    /// `desc` must point to a valid topic descriptor whose `m_ops` array is
+    /// terminated as generated by idlc.
+    pub unsafe fn from_descriptor(desc: *const dds_topic_descriptor_t) -> Result<Self, DynError> {
+        let desc = &*desc;
+        if desc.m_ops.is_null() {
+            return Err(DynError::Unsupported("descriptors without ops"));
+        }
+        let type_name = if desc.m_typename.is_null() {
+            String::new()
+        } else {
+            CStr::from_ptr(desc.m_typename)
+                .to_string_lossy()
+                .into_owned()
+        };
+        let parser = OpsParser::new(desc.m_ops, None);
+        Ok(DynType {
+            type_name,
+            root: parser.parse_struct(0)?,
+        })
+    }
+
+    /// Build the type from a complete ops array
+    pub fn from_ops(type_name: &str, ops: &[u32]) -> Result<Self, DynError> {
+        let parser = OpsParser::new(ops.as_ptr(), Some(ops.len()));
+        Ok(DynType {
+            type_name: type_name.to_owned(),
+            root: parser.parse_struct(0)?,
+        })
+    }
+
+    /// Find the topic `name` in the domain of `participant`, waiting up to
+    /// `timeout` for it to be discovered, and build its type from the type
+    /// information its endpoints advertise
+    pub fn discover(
+        participant: &DdsEntity,
+        name: &str,
+        timeout: DdsDuration,
+    ) -> Result<Self, DynError> {
+        TypeMeta::discover(participant, name, timeout)?.dyn_type()
+    }
+
+    /// Name the members, one name per leaf member in declaration order with
+    /// the members of nested structs flattened (`position.x`). Sequences and
+    /// arrays are leaves; structs inside them stay positional.
+    pub fn with_names<S: AsRef<str>>(mut self, names: &[S]) -> Result<Self, DynError> {
+        let expected = self.leaf_count();
+        if names.len() != expected {
+            return Err(DynError::NameCount {
+                expected,
+                got: names.len(),
+            });
+        }
+        assign_names(&mut self.root, names, &mut 0, 0);
+        Ok(self)
+    }
+
+    pub fn type_name(&self) -> &str {
+        &self.type_name
+    }
+
+    pub fn root(&self) -> &DynStruct {
+        &self.root
+    }
+
+    /// Number of names [`DynType::with_names`] expects
+    pub fn leaf_count(&self) -> usize {
+        fn count(s: &DynStruct) -> usize {
+            s.members
+                .iter()
+                .map(|m| match &m.kind {
+                    DynKind::Struct(nested) => count(nested),
+                    _ => 1,
+                })
+                .sum()
+        }
+        count(&self.root)
+    }
+
+    /// Decode a serialized sample, including its encapsulation header
+    pub fn decode(&self, data: &[u8]) -> Result<DynValue, DynError> {
+        CdrReader::new(data)?.read_struct(&self.root)
+    }
+
+    /// Whether the type has key members
+    pub fn is_keyed(&self) -> bool {
+        self.root.members.iter().any(|m| m.key)
+    }
+
+    /// The key of a serialized sample in the form the key hash is computed
+    /// from (XTypes 7.6.8): the key members in declaration order as
+    /// big-endian XCDR2 without DHEADERs. Empty if the type has no key.
+    pub fn key_bytes(&self, data: &[u8]) -> Result<Vec<u8>, DynError> {
+        self.value_key_bytes(&self.decode(data)?)
+    }
+
+    /// [`key_bytes`](Self::key_bytes) of a serialized key, as sent with a
+    /// dispose or unregister: only the key members, in the encoding of its
+    /// encapsulation header
+    pub fn key_bytes_of_key(&self, data: &[u8]) -> Result<Vec<u8>, DynError> {
+        let value = CdrReader::new(data)?.read_key(&self.root, false)?;
+        self.value_key_bytes(&value)
+    }
+
+    fn value_key_bytes(&self, value: &DynValue) -> Result<Vec<u8>, DynError> {
+        let mut writer = CdrWriter {
+            buf: Vec::new(),
+            xcdr2: true,
+            big_endian: true,
+        };
+        writer.write_key(&self.root, value, false, "")?;
+        Ok(writer.buf)
+    }
+
+    /// Whether every key of the type fits in the 16 bytes of a key hash,
+    /// which then is the zero-padded key rather than its MD5 hash
+    pub fn key_fits_keyhash(&self) -> bool {
+        let mut pos = 0;
+        max_key_size(&self.root, false, &mut pos).is_some() && pos <= 16
+    }
+
+    /// Serialize a sample with an encapsulation header: XCDR1 if the type
+    /// has no appendable structs, XCDR2 otherwise
+    pub fn encode(&self, value: &DynValue) -> Result<Vec<u8>, DynError> {
+        let xcdr2 = DynKind::Struct(self.root.clone()).has_appendable();
+        let id = match (xcdr2, self.root.appendable) {
+            (false, _) => CDR_LE,
+            (true, false) => CDR2_LE,
+            (true, true) => D_CDR2_LE,
+        };
+        let mut writer = CdrWriter {
+            buf: Vec::new(),
+            xcdr2,
+            big_endian: false,
+        };
+        writer.write_struct(&self.root, value, "")?;
+        let padding = (4 - writer.buf.len() % 4) % 4;
+        writer.buf.resize(writer.buf.len() + padding, 0);
+        let mut out = Vec::with_capacity(4 + writer.buf.len());
+        out.extend_from_slice(&id.to_be_bytes());
+        out.extend_from_slice(&[0, padding as u8]);
+        out.extend_from_slice(&writer.buf);
+        Ok(out)
+    }
+}
+
+/// The members of `s` that are part of the key: those marked as key, and
+/// in a key member of struct type without key members, all of them
+fn key_members(s: &DynStruct, nested: bool) -> impl Iterator<Item = (usize, &DynMember)> {
+    let all = nested && !s.members.iter().any(|m| m.key);
+    s.members
+        .iter()
+        .enumerate()
+        .filter(move |(_, m)| all || m.key)
+}
+
+/// Advance `pos` over an aligned primitive of `n` bytes in XCDR2
+fn add_aligned(pos: &mut usize, n: usize) {
+    let align = n.min(4);
+    *pos = ((*pos + align - 1) & !(align - 1)) + n;
+}
+
+/// Advance `pos` over the largest key of `s` in XCDR2, `None` if it is
+/// unbounded. Only exact up to the size of a key hash.
+fn max_key_size(s: &DynStruct, nested: bool, pos: &mut usize) -> Option<()> {
+    for (_, m) in key_members(s, nested) {
+        match &m.kind {
+            DynKind::Struct(inner) => max_key_size(inner, true, pos)?,
+            kind => max_size(kind, pos)?,
+        }
+    }
+    Some(())
+}
+
+fn max_size(kind: &DynKind, pos: &mut usize) -> Option<()> {
+    match kind {
+        DynKind::Bool | DynKind::Int8 | DynKind::UInt8 => add_aligned(pos, 1),
+        DynKind::Int16 | DynKind::UInt16 => add_aligned(pos, 2),
+        DynKind::Int32 | DynKind::UInt32 | DynKind::Float32 | DynKind::Enum => add_aligned(pos, 4),
+        DynKind::Int64 | DynKind::UInt64 | DynKind::Float64 => add_aligned(pos, 8),
+        DynKind::String | DynKind::Sequence(_, None) => return None,
+        DynKind::BoundedString(bound) => {
+            add_aligned(pos, 4);
+            *pos += bound + 1;
+        }
+        DynKind::Sequence(elem, Some(n)) | DynKind::Array(elem, n) => {
+            if !elem.is_primitive() {
+                add_aligned(pos, 4);
+            }
+            if let DynKind::Sequence(..) = kind {
+                add_aligned(pos, 4);
+            }
+            // past 16 bytes the exact size no longer matters
+            for _ in 0..*n {
+                max_size(elem, pos)?;
+                if *pos > 16 {
+                    break;
+                }
+            }
+        }
+        DynKind::Struct(s) => {
+            if s.appendable {
+                add_aligned(pos, 4);
+            }
+            for m in &s.members {
+                max_size(&m.kind, pos)?;
+            }
+        }
+    }
+    Some(())
+}
+
+/// What type discovery tells about the type of a topic: its serializer ops
+/// and its XTypes type information and type map, serialized
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TypeMeta {
+    pub type_name: String,
+    /// The ops up to the last one the type uses
+    pub ops: Vec<u32>,
+    pub type_information: Vec<u8>,
+    pub type_mapping: Vec<u8>,
+}
+
+impl TypeMeta {
+    /// Copy the type from a topic descriptor
+    ///
+    /// # Safety
+    /// `desc` must point to a valid topic descriptor whose `m_ops` array is
+    /// terminated as generated by idlc.
+    pub unsafe fn from_descriptor(desc: *const dds_topic_descriptor_t) -> Result<Self, DynError> {
+        let ty = DynType::from_descriptor(desc)?;
+        let desc = &*desc;
+        // parse again to find out how many words the type takes
+        let parser = OpsParser::new(desc.m_ops, None);
+        parser.parse_struct(0)?;
+        let meta = |m: &dds_type_meta_ser| {
+            if m.data.is_null() {
+                Vec::new()
+            } else {
+                std::slice::from_raw_parts(m.data, m.sz as usize).to_vec()
+            }
+        };
+        Ok(TypeMeta {
+            type_name: ty.type_name,
+            ops: std::slice::from_raw_parts(desc.m_ops, parser.used.get()).to_vec(),
+            type_information: meta(&desc.type_information),
+            type_mapping: meta(&desc.type_mapping),
+        })
+    }
+
+    /// Find the topic `name` in the domain of `participant`, waiting up to
+    /// `timeout` for it to be discovered, and copy the type its endpoints
+    /// advertise
+    pub fn discover(
+        participant: &DdsEntity,
+        name: &str,
+        timeout: DdsDuration,
+    ) -> Result<Self, DynError> {
+        let cname = CString::new(name).map_err(|_| DynError::TopicNotFound(name.to_owned()))?;
+        unsafe {
+            let topic = dds_find_topic(
+                dds_find_scope_DDS_FIND_SCOPE_GLOBAL,
+                participant.0,
+                cname.as_ptr(),
+                ptr::null(),
+                timeout.as_nanos(),
+            );
+            if topic == 0 {
+                return Err(DynError::TopicNotFound(name.to_owned()));
+            }
+            let topic = DdsEntity(DDSError::check(topic)?);
+
+            let mut info: *mut dds_typeinfo_t = ptr::null_mut();
+            let found = DDSError::check(dds_get_typeinfo(topic.0, &mut info));
+            let _ = topic.delete();
+            found?;
+
+            let mut desc: *mut dds_topic_descriptor_t = ptr::null_mut();
+            let created = DDSError::check(dds_create_topic_descriptor(
+                dds_find_scope_DDS_FIND_SCOPE_GLOBAL,
+                participant.0,
+                info,
+                timeout.as_nanos(),
+                &mut desc,
+            ));
+            dds_free_typeinfo(info);
+            created?;
+
+            let meta = TypeMeta::from_descriptor(desc);
+            dds_delete_topic_descriptor(desc);
+            meta
+        }
+    }
+
+    /// The type, positional
+    pub fn dyn_type(&self) -> Result<DynType, DynError> {
+        DynType::from_ops(&self.type_name, &self.ops)
+    }
+}
+
+/// Name the members of `s` from `names[*next..]`, dropping the first `depth`
+/// segments of each name
+fn assign_names<S: AsRef<str>>(s: &mut DynStruct, names: &[S], next: &mut usize, depth: usize) {
+    for member in &mut s.members {
+        let segments: Vec<&str> = names
+            .get(*next)
+            .map_or(Vec::new(), |n| n.as_ref().split('.').collect());
+        let from = depth.min(segments.len().saturating_sub(1));
+        match &mut member.kind {
+            DynKind::Struct(nested) => {
+                member.name = segments.get(from).map(|n| n.to_string());
+                assign_names(nested, names, next, depth + 1);
+            }
+            _ => {
+                member.name = Some(segments[from..].join("."));
+                *next += 1;
+            }
+        }
+    }
+}
+
+struct CdrReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    le: bool,
+    xcdr2: bool,
+}
+
+impl<'a> CdrReader<'a> {
+    /// A reader for the payload of `data` in the encoding of its header
+    fn new(data: &'a [u8]) -> Result<Self, DynError> {
+        if data.len() < 4 {
+            return Err(DynError::Truncated);
+        }
+        let id = u16::from_be_bytes([data[0], data[1]]);
+        let (le, xcdr2) = match id {
+            CDR_BE => (false, false),
+            CDR_LE => (true, false),
+            CDR2_BE | D_CDR2_BE => (false, true),
+            CDR2_LE | D_CDR2_LE => (true, true),
+            _ => return Err(DynError::Encoding(id)),
+        };
+        Ok(CdrReader {
+            buf: &data[4..],
+            pos: 0,
+            le,
+            xcdr2,
+        })
+    }
+
+    fn align(&mut self, n: usize) {
+        let n = if self.xcdr2 { n.min(4) } else { n };
+        self.pos = (self.pos + n - 1) & !(n - 1);
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], DynError> {
+        let end = self.pos.checked_add(n).ok_or(DynError::Truncated)?;
+        let b = self.buf.get(self.pos..end).ok_or(DynError::Truncated)?;
+        self.pos = end;
+        Ok(b)
+    }
+
+    fn fixed<const N: usize>(&mut self) -> Result<[u8; N], DynError> {
+        self.align(N);
+        let mut b = [0u8; N];
+        b.copy_from_slice(self.bytes(N)?);
+        if !self.le {
+            b.reverse();
+        }
+        Ok(b)
+    }
+
+    fn u32(&mut self) -> Result<u32, DynError> {
+        self.fixed::<4>().map(u32::from_le_bytes)
+    }
+
+    fn read(&mut self, kind: &DynKind) -> Result<DynValue, DynError> {
+        let value = match kind {
+            DynKind::Bool => DynValue::Bool(self.bytes(1)?[0] != 0),
+            DynKind::Int8 => DynValue::Int(self.bytes(1)?[0] as i8 as i64),
+            DynKind::UInt8 => DynValue::UInt(self.bytes(1)?[0] as u64),
+            DynKind::Int16 => DynValue::Int(i16::from_le_bytes(self.fixed()?) as i64),
+            DynKind::UInt16 => DynValue::UInt(u16::from_le_bytes(self.fixed()?) as u64),
+            DynKind::Int32 => DynValue::Int(i32::from_le_bytes(self.fixed()?) as i64),
+            DynKind::UInt32 | DynKind::Enum => DynValue::UInt(self.u32()? as u64),
+            DynKind::Int64 => DynValue::Int(i64::from_le_bytes(self.fixed()?)),
+            DynKind::UInt64 => DynValue::UInt(u64::from_le_bytes(self.fixed()?)),
+            DynKind::Float32 => DynValue::Float(f32::from_le_bytes(self.fixed()?) as f64),
+            DynKind::Float64 => DynValue::Float(f64::from_le_bytes(self.fixed()?)),
+            DynKind::String | DynKind::BoundedString(_) => {
+                let len = self.u32()? as usize;
+                let b = self.bytes(len)?;
+                let b = b.strip_suffix(&[0]).unwrap_or(b);
+                DynValue::Str(String::from_utf8_lossy(b).into_owned())
+            }
+            DynKind::Sequence(elem, _) => {
+                if self.xcdr2 && !elem.is_primitive() {
+                    self.u32()?;
+                }
+                let n = self.u32()? as usize;
+                // every element takes at least a byte, so a bogus count fails
+                // on the data rather than on the allocation
+                let mut items = Vec::with_capacity(n.min(self.buf.len()));
+                for _ in 0..n {
+                    items.push(self.read(elem)?);
+                }
+                DynValue::Seq(items)
+            }
+            DynKind::Array(elem, n) => {
+                if self.xcdr2 && !elem.is_primitive() {
+                    self.u32()?;
+                }
+                let items = (0..*n).map(|_| self.read(elem)).collect::<Result<_, _>>()?;
+                DynValue::Seq(items)
+            }
+            DynKind::Struct(s) => self.read_struct(s)?,
+        };
+        Ok(value)
+    }
+
+    fn read_struct(&mut self, s: &DynStruct) -> Result<DynValue, DynError> {
+        let end = if self.xcdr2 && s.appendable {
+            let size = self.u32()? as usize;
+            Some(self.pos + size)
+        } else {
+            None
+        };
+        let members = s
+            .members
+            .iter()
+            .map(|m| self.read(&m.kind))
+            .collect::<Result<_, _>>()?;
+        // skip members appended by a newer version of the type
+        if let Some(end) = end {
+            self.pos = self.pos.max(end);
+        }
+        Ok(DynValue::Struct(members))
+    }
+
+    /// Read the key members of `s`, which are all that is serialized, and
+    /// give the others their default value
+    fn read_key(&mut self, s: &DynStruct, nested: bool) -> Result<DynValue, DynError> {
+        let mut members: Vec<DynValue> = s.members.iter().map(|m| m.kind.default_value()).collect();
+        for (i, m) in key_members(s, nested) {
+            members[i] = match &m.kind {
+                DynKind::Struct(inner) => self.read_key(inner, true)?,
+                kind => self.read(kind)?,
+            };
+        }
+        Ok(DynValue::Struct(members))
+    }
+}
+
+struct CdrWriter {
+    buf: Vec<u8>,
+    xcdr2: bool,
+    big_endian: bool,
+}
+
+fn member_path(path: &str, name: String) -> String {
+    if path.is_empty() {
+        name
+    } else {
+        format!("{}.{}", path, name)
+    }
+}
+
+fn mismatch(path: &str, kind: &DynKind) -> DynError {
+    let expected = match kind {
+        DynKind::Bool => "a boolean",
+        DynKind::String | DynKind::BoundedString(_) => "a string",
+        DynKind::Sequence(..) | DynKind::Array(..) => "a list",
+        DynKind::Struct(_) => "a struct",
+        _ => "a number",
+    };
+    DynError::Value(path.to_owned(), format!("expected {}", expected))
+}
+
+impl CdrWriter {
+    fn align(&mut self, n: usize) {
+        let n = if self.xcdr2 { n.min(4) } else { n };
+        let len = (self.buf.len() + n - 1) & !(n - 1);
+        self.buf.resize(len, 0);
+    }
+
+    /// Append a primitive given in little endian
+    fn put(&mut self, b: &[u8]) {
+        self.align(b.len());
+        let start = self.buf.len();
+        self.buf.extend_from_slice(b);
+        if self.big_endian {
+            self.buf[start..].reverse();
+        }
+    }
+
+    /// Start a DHEADER, returning where it is to be patched
+    fn begin_dheader(&mut self) -> usize {
+        self.put(&[0; 4]);
+        self.buf.len()
+    }
+
+    fn end_dheader(&mut self, start: usize) {
+        let size = (self.buf.len() - start) as u32;
+        let size = if self.big_endian {
+            size.to_be_bytes()
+        } else {
+            size.to_le_bytes()
+        };
+        self.buf[start - 4..start].copy_from_slice(&size);
+    }
+
+    fn write(&mut self, kind: &DynKind, value: &DynValue, path: &str) -> Result<(), DynError> {
+        match (kind, value) {
+            (DynKind::Bool, DynValue::Bool(v)) => self.put(&[*v as u8]),
+            (DynKind::Float32, DynValue::Float(v)) => self.put(&(*v as f32).to_le_bytes()),
+            (DynKind::Float64, DynValue::Float(v)) => self.put(&v.to_le_bytes()),
+            (_, DynValue::Int(_)) | (_, DynValue::UInt(_)) => {
+                self.write_integer(kind, value, path)?
+            }
+            (DynKind::String, DynValue::Str(s)) | (DynKind::BoundedString(_), DynValue::Str(s)) => {
+                if let DynKind::BoundedString(bound) = kind {
+                    if s.len() > *bound {
+                        return Err(DynError::Value(
+                            path.to_owned(),
+                            format!("longer than {} bytes", bound),
+                        ));
+                    }
+                }
+                if s.contains('\0') {
+                    return Err(DynError::Value(path.to_owned(), "contains NUL".to_owned()));
+                }
+                self.put(&(s.len() as u32 + 1).to_le_bytes());
+                self.buf.extend_from_slice(s.as_bytes());
+                self.buf.push(0);
+            }
+            (DynKind::Sequence(elem, bound), DynValue::Seq(items)) => {
+                if let Some(bound) = bound {
+                    if items.len() > *bound {
+                        return Err(DynError::Value(
+                            path.to_owned(),
+                            format!("more than {} elements", bound),
+                        ));
+                    }
+                }
+                let dheader = (self.xcdr2 && !elem.is_primitive()).then(|| self.begin_dheader());
+                self.put(&(items.len() as u32).to_le_bytes());
+                for (i, item) in items.iter().enumerate() {
+                    self.write(elem, item, &format!("{}[{}]", path, i))?;
+                }
+                if let Some(start) = dheader {
+                    self.end_dheader(start);
+                }
+            }
+            (DynKind::Array(elem, n), DynValue::Seq(items)) => {
+                if items.len() != *n {
+                    return Err(DynError::Value(
+                        path.to_owned(),
+                        format!("expected {} elements", n),
+                    ));
+                }
+                let dheader = (self.xcdr2 && !elem.is_primitive()).then(|| self.begin_dheader());
+                for (i, item) in items.iter().enumerate() {
+                    self.write(elem, item, &format!("{}[{}]", path, i))?;
+                }
+                if let Some(start) = dheader {
+                    self.end_dheader(start);
+                }
+            }
+            (DynKind::Struct(s), value) => self.write_struct(s, value, path)?,
+            _ => return Err(mismatch(path, kind)),
+        }
+        Ok(())
+    }
+
+    fn write_integer(
+        &mut self,
+        kind: &DynKind,
+        value: &DynValue,
+        path: &str,
+    ) -> Result<(), DynError> {
+        // i128 holds every i64 and u64
+        let v = match value {
+            DynValue::Int(v) => *v as i128,
+            DynValue::UInt(v) => *v as i128,
+            _ => return Err(mismatch(path, kind)),
+        };
+        let fits = |min: i128, max: i128| {
+            if v < min || v > max {
+                Err(DynError::Value(
+                    path.to_owned(),
+                    format!("{} is out of range", v),
+                ))
+            } else {
+                Ok(())
+            }
+        };
+        let bytes = match kind {
+            DynKind::Int8 => fits(i8::MIN as i128, i8::MAX as i128).map(|_| vec![v as u8])?,
+            DynKind::UInt8 => fits(0, u8::MAX as i128).map(|_| vec![v as u8])?,
+            DynKind::Int16 => fits(i16::MIN as i128, i16::MAX as i128)
+                .map(|_| (v as i16).to_le_bytes().to_vec())?,
+            DynKind::UInt16 => {
+                fits(0, u16::MAX as i128).map(|_| (v as u16).to_le_bytes().to_vec())?
+            }
+            DynKind::Int32 => fits(i32::MIN as i128, i32::MAX as i128)
+                .map(|_| (v as i32).to_le_bytes().to_vec())?,
+            DynKind::UInt32 | DynKind::Enum => {
+                fits(0, u32::MAX as i128).map(|_| (v as u32).to_le_bytes().to_vec())?
+            }
+            DynKind::Int64 => fits(i64::MIN as i128, i64::MAX as i128)
+                .map(|_| (v as i64).to_le_bytes().to_vec())?,
+            DynKind::UInt64 => {
+                fits(0, u64::MAX as i128).map(|_| (v as u64).to_le_bytes().to_vec())?
+            }
+            DynKind::Float32 => (v as f32).to_le_bytes().to_vec(),
+            DynKind::Float64 => (v as f64).to_le_bytes().to_vec(),
+            _ => return Err(mismatch(path, kind)),
+        };
+        self.put(&bytes);
+        Ok(())
+    }
+
+    fn write_struct(
+        &mut self,
+        s: &DynStruct,
+        value: &DynValue,
+        path: &str,
+    ) -> Result<(), DynError> {
+        let values = match value {
+            DynValue::Struct(values) if values.len() == s.members.len() => values,
+            _ => {
+                return Err(DynError::Value(
+                    path.to_owned(),
+                    format!("expected a struct of {} members", s.members.len()),
+                ))
+            }
+        };
+        let dheader = (self.xcdr2 && s.appendable).then(|| self.begin_dheader());
+        for (i, (member, value)) in s.members.iter().zip(values).enumerate() {
+            let name = member.name.clone().unwrap_or_else(|| i.to_string());
+            self.write(&member.kind, value, &member_path(path, name))?;
+        }
+        if let Some(start) = dheader {
+            self.end_dheader(start);
+        }
+        Ok(())
+    }
+
+    /// Write the key members of `s`, leaving out the DHEADERs of the structs
+    fn write_key(
+        &mut self,
+        s: &DynStruct,
+        value: &DynValue,
+        nested: bool,
+        path: &str,
+    ) -> Result<(), DynError> {
+        let values = match value {
+            DynValue::Struct(values) if values.len() == s.members.len() => values,
+            _ => {
+                return Err(DynError::Value(
+                    path.to_owned(),
+                    format!("expected a struct of {} members", s.members.len()),
+                ))
+            }
+        };
+        for (i, member) in key_members(s, nested) {
+            let name = member.name.clone().unwrap_or_else(|| i.to_string());
+            let path = member_path(path, name);
+            match &member.kind {
+                DynKind::Struct(inner) => self.write_key(inner, &values[i], true, &path)?,
+                kind => self.write(kind, &values[i], &path)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "json")]
+impl DynType {
+    /// The JSON form of a sample: structs are objects when their members are
+    /// named and arrays otherwise, non-finite floats are `null`
+    pub fn to_json(&self, value: &DynValue) -> serde_json::Value {
+        struct_to_json(&self.root, value)
+    }
+
+    /// A sample from its JSON form. Members missing from an object (or from
+    /// the end of an array) get their default value.
+    pub fn from_json(&self, json: &serde_json::Value) -> Result<DynValue, DynError> {
+        struct_from_json(&self.root, json, "")
+    }
+}
+
+#[cfg(feature = "json")]
+fn struct_to_json(s: &DynStruct, value: &DynValue) -> serde_json::Value {
+    use serde_json::Value;
+    let values = match value {
+        DynValue::Struct(values) => values,
+        other => return value_to_json(other),
+    };
+    let named = s.members.iter().all(|m| m.name.is_some());
+    let members = s.members.iter().zip(values);
+    if named {
+        Value::Object(
+            members
+                .map(|(m, v)| (m.name.clone().unwrap_or_default(), kind_to_json(&m.kind, v)))
+                .collect(),
+        )
+    } else {
+        Value::Array(members.map(|(m, v)| kind_to_json(&m.kind, v)).collect())
+    }
+}
+
+#[cfg(feature = "json")]
+fn kind_to_json(kind: &DynKind, value: &DynValue) -> serde_json::Value {
+    match (kind, value) {
+        (DynKind::Struct(s), v) => struct_to_json(s, v),
+        (DynKind::Sequence(elem, _), DynValue::Seq(items))
+        | (DynKind::Array(elem, _), DynValue::Seq(items)) => {
+            serde_json::Value::Array(items.iter().map(|v| kind_to_json(elem, v)).collect())
+        }
+        (_, v) => value_to_json(v),
+    }
+}
+
+#[cfg(feature = "json")]
+fn value_to_json(value: &DynValue) -> serde_json::Value {
+    use serde_json::Value;
+    match value {
+        DynValue::Bool(v) => Value::from(*v),
+        DynValue::Int(v) => Value::from(*v),
+        DynValue::UInt(v) => Value::from(*v),
+        DynValue::Float(v) => serde_json::Number::from_f64(*v).map_or(Value::Null, Value::Number),
+        DynValue::Str(v) => Value::from(v.as_str()),
+        DynValue::Seq(items) | DynValue::Struct(items) => {
+            Value::Array(items.iter().map(value_to_json).collect())
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+fn struct_from_json(
+    s: &DynStruct,
+    json: &serde_json::Value,
+    path: &str,
+) -> Result<DynValue, DynError> {
+    use serde_json::Value;
+    let name = |i: usize, m: &DynMember| m.name.clone().unwrap_or_else(|| i.to_string());
+    let values = match json {
+        Value::Object(map) => {
+            if let Some(unknown) = map
+                .keys()
+                .find(|k| !s.members.iter().enumerate().any(|(i, m)| name(i, m) == **k))
+            {
+                return Err(DynError::Value(
+                    member_path(path, unknown.clone()),
+                    "no such member".to_owned(),
+                ));
+            }
+            s.members
+                .iter()
+                .enumerate()
+                .map(|(i, m)| match map.get(&name(i, m)) {
+                    Some(v) => kind_from_json(&m.kind, v, &member_path(path, name(i, m))),
+                    None => Ok(m.kind.default_value()),
+                })
+                .collect::<Result<_, _>>()?
+        }
+        Value::Array(items) if items.len() <= s.members.len() => s
+            .members
+            .iter()
+            .enumerate()
+            .map(|(i, m)| match items.get(i) {
+                Some(v) => kind_from_json(&m.kind, v, &member_path(path, name(i, m))),
+                None => Ok(m.kind.default_value()),
+            })
+            .collect::<Result<_, _>>()?,
+        _ => {
+            return Err(DynError::Value(
+                path.to_owned(),
+                format!(
+                    "expected an object or an array of at most {} members",
+                    s.members.len()
+                ),
+            ))
+        }
+    };
+    Ok(DynValue::Struct(values))
+}
+
+#[cfg(feature = "json")]
+fn kind_from_json(
+    kind: &DynKind,
+    json: &serde_json::Value,
+    path: &str,
+) -> Result<DynValue, DynError> {
+    use serde_json::Value;
+    let value = match (kind, json) {
+        (DynKind::Struct(s), v) => return struct_from_json(s, v, path),
+        (DynKind::Bool, Value::Bool(v)) => DynValue::Bool(*v),
+        (DynKind::Float32, Value::Number(n)) | (DynKind::Float64, Value::Number(n)) => {
+            DynValue::Float(n.as_f64().unwrap_or_default())
+        }
+        (DynKind::String, Value::String(s)) | (DynKind::BoundedString(_), Value::String(s)) => {
+            DynValue::Str(s.clone())
+        }
+        (DynKind::Sequence(elem, _), Value::Array(items))
+        | (DynKind::Array(elem, _), Value::Array(items)) => DynValue::Seq(
+            items
+                .iter()
+                .enumerate()
+                .map(|(i, v)| kind_from_json(elem, v, &format!("{}[{}]", path, i)))
+                .collect::<Result<_, _>>()?,
+        ),
+        (_, Value::Number(n)) if kind.is_primitive() && *kind != DynKind::Bool => {
+            match (n.as_i64(), n.as_u64()) {
+                (Some(v), _) => DynValue::Int(v),
+                (None, Some(v)) => DynValue::UInt(v),
+                _ => {
+                    return Err(DynError::Value(
+                        path.to_owned(),
+                        format!("{} is not an integer", n),
+                    ))
+                }
+            }
+        }
+        _ => return Err(mismatch(path, kind)),
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_layout::tests::{descriptor, ADR, EXT, INT32, RTS, STRING};
+
+    const INT16: u32 = ADR | dds_stream_typecode_primary_DDS_OP_TYPE_2BY | DDS_OP_FLAG_SGN;
+    const INT64: u32 = ADR | dds_stream_typecode_primary_DDS_OP_TYPE_8BY | DDS_OP_FLAG_SGN;
+    const SEQ_STRUCT: u32 = ADR
+        | dds_stream_typecode_primary_DDS_OP_TYPE_SEQ
+        | (dds_stream_typecode_DDS_OP_VAL_STU << 8);
+
+    /// struct { @key int32 id; string name; @key int16 k; }
+    fn keyed() -> DynType {
+        let ops = [
+            INT32 | DDS_OP_FLAG_KEY,
+            0,
+            STRING,
+            8,
+            INT16 | DDS_OP_FLAG_KEY,
+            16,
+            RTS,
+        ];
+        DynType::from_ops("test::Keyed", &ops).unwrap()
+    }
+
+    #[test]
+    fn keys_are_big_endian_xcdr2() {
+        let ty = keyed();
+        assert!(ty.is_keyed());
+        assert!(ty.key_fits_keyhash());
+        let value = DynValue::Struct(vec![
+            DynValue::Int(0x0102_0304),
+            DynValue::Str("abc".to_owned()),
+            DynValue::Int(-2),
+        ]);
+        let data = ty.encode(&value).unwrap();
+        assert_eq!(
+            ty.key_bytes(&data).unwrap(),
+            vec![0x01, 0x02, 0x03, 0x04, 0xff, 0xfe]
+        );
+    }
+
+    #[test]
+    fn serialized_keys_hold_only_key_members() {
+        let ty = keyed();
+        let data = [0x00, 0x01, 0x00, 0x00, 0x04, 0x03, 0x02, 0x01, 0xfe, 0xff];
+        assert_eq!(
+            ty.key_bytes_of_key(&data).unwrap(),
+            vec![0x01, 0x02, 0x03, 0x04, 0xff, 0xfe]
+        );
+    }
+
+    #[test]
+    fn unbounded_keys_do_not_fit_a_keyhash() {
+        let ty = DynType::from_ops("test::S", &[STRING | DDS_OP_FLAG_KEY, 0, RTS]).unwrap();
+        assert!(!ty.key_fits_keyhash());
+        let ty = DynType::from_ops("test::I", &[INT32, 0, RTS]).unwrap();
+        assert!(!ty.is_keyed());
+        let data = ty
+            .encode(&DynValue::Struct(vec![DynValue::Int(7)]))
+            .unwrap();
+        assert_eq!(ty.key_bytes(&data).unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn key_structs_without_keys_are_keys_entirely() {
+        // struct { @key struct { int32 x; int32 y; } pos; int32 v; }
+        let ops = [
+            EXT | DDS_OP_FLAG_KEY,
+            0,
+            (3 << 16) | 6,
+            INT32,
+            8,
+            RTS,
+            INT32,
+            0,
+            INT32,
+            4,
+            RTS,
+        ];
+        let ty = DynType::from_ops("test::Pos", &ops).unwrap();
+        let value = DynValue::Struct(vec![
+            DynValue::Struct(vec![DynValue::Int(1), DynValue::Int(2)]),
+            DynValue::Int(3),
+        ]);
+        let data = ty.encode(&value).unwrap();
+        assert_eq!(ty.key_bytes(&data).unwrap(), vec![0, 0, 0, 1, 0, 0, 0, 2]);
+    }
+
+    #[test]
+    fn type_meta_copies_the_ops_in_use() {
+        // ops followed by a key descriptor list, as idlc generates
+        let desc = descriptor(4, vec![INT32 | DDS_OP_FLAG_KEY, 0, RTS, 0xdead, 0xbeef], 2);
+        let meta = unsafe { TypeMeta::from_descriptor(desc) }.unwrap();
+        assert_eq!(meta.type_name, "test::Sample");
+        assert_eq!(meta.ops, vec![INT32 | DDS_OP_FLAG_KEY, 0, RTS]);
+        assert!(meta.type_information.is_empty());
+        assert!(meta.dyn_type().unwrap().is_keyed());
+    }
+
+    /// struct { int16 a; int64 b; sequence<struct { int32 x; string s; }> q; },
+    /// `@appendable` when `appendable`
+    fn nested(appendable: bool) -> DynType {
+        let mut ops = vec![
+            INT16,
+            0,
+            INT64,
+            8,
+            SEQ_STRUCT,
+            16,
+            16,
+            (4 << 16) | 5,
+            RTS,
+            INT32,
+            0,
+            STRING,
+            8,
+            RTS,
+        ];
+        if appendable {
+            ops.insert(0, dds_stream_opcode_DDS_OP_DLC);
+        }
+        DynType::from_ops("test::Nested", &ops).unwrap()
+    }
+
+    fn nested_value() -> DynValue {
+        DynValue::Struct(vec![
+            DynValue::Int(-3),
+            DynValue::Int(1 << 40),
+            DynValue::Seq(vec![
+                DynValue::Struct(vec![DynValue::Int(1), DynValue::Str("a".to_owned())]),
+                DynValue::Struct(vec![DynValue::Int(2), DynValue::Str("bc".to_owned())]),
+            ]),
+        ])
+    }
+
+    fn u32_at(data: &[u8], pos: usize) -> u32 {
+        let mut word = [0u8; 4];
+        word.copy_from_slice(&data[4 + pos..8 + pos]);
+        u32::from_le_bytes(word)
+    }
+
+    #[test]
+    fn xcdr1_round_trips() {
+        let ty = nested(false);
+        let value = nested_value();
+        let data = ty.encode(&value).unwrap();
+        // 43 bytes of payload, padded to 44
+        assert_eq!(&data[..4], &[0x00, 0x01, 0x00, 0x01]);
+        assert_eq!(data.len(), 4 + 44);
+        // int64 aligned to 8, no DHEADERs
+        assert_eq!(&data[4 + 8..4 + 16], &(1i64 << 40).to_le_bytes());
+        assert_eq!(u32_at(&data, 16), 2);
+        assert_eq!(u32_at(&data, 20), 1);
+        assert_eq!(ty.decode(&data).unwrap(), value);
+    }
+
+    #[test]
+    fn xcdr2_round_trips() {
+        let ty = nested(true);
+        let value = nested_value();
+        let data = ty.encode(&value).unwrap();
+        assert_eq!(&data[..4], &[0x00, 0x09, 0x00, 0x01]);
+        assert_eq!(data.len(), 4 + 48);
+        // DHEADER of the struct, int64 aligned to 4, DHEADER of the sequence
+        assert_eq!(u32_at(&data, 0), 43);
+        assert_eq!(&data[4 + 8..4 + 16], &(1i64 << 40).to_le_bytes());
+        assert_eq!(u32_at(&data, 16), 27);
+        assert_eq!(u32_at(&data, 20), 2);
+        assert_eq!(ty.decode(&data).unwrap(), value);
+    }
+
+    #[test]
+    fn primitives_round_trip() {
+        let ops = [
+            ADR | dds_stream_typecode_primary_DDS_OP_TYPE_BLN,
+            0,
+            ADR | dds_stream_typecode_primary_DDS_OP_TYPE_1BY,
+            1,
+            ADR | dds_stream_typecode_primary_DDS_OP_TYPE_8BY | DDS_OP_FLAG_FP,
+            8,
+            ADR | dds_stream_typecode_primary_DDS_OP_TYPE_4BY | DDS_OP_FLAG_FP,
+            16,
+            ADR | dds_stream_typecode_primary_DDS_OP_TYPE_ARR
+                | (dds_stream_typecode_DDS_OP_VAL_4BY << 8)
+                | DDS_OP_FLAG_SGN,
+            20,
+            3,
+            RTS,
+        ];
+        let ty = DynType::from_ops("test::Prims", &ops).unwrap();
+        let value = DynValue::Struct(vec![
+            DynValue::Bool(true),
+            DynValue::UInt(200),
+            DynValue::Float(-0.25),
+            DynValue::Float(1.5),
+            DynValue::Seq(vec![DynValue::Int(-1), DynValue::Int(0), DynValue::Int(7)]),
+        ]);
+        let data = ty.encode(&value).unwrap();
+        assert_eq!(ty.decode(&data).unwrap(), value);
+    }
+
+    #[test]
+    fn big_endian_samples_decode() {
+        let ty = DynType::from_ops("test::BE", &[INT32, 0, INT16, 4, RTS]).unwrap();
+        let expected = DynValue::Struct(vec![DynValue::Int(258), DynValue::Int(-2)]);
+        let xcdr1 = [0x00, 0x00, 0x00, 0x02, 0, 0, 1, 2, 0xff, 0xfe, 0, 0];
+        assert_eq!(ty.decode(&xcdr1).unwrap(), expected);
+        let xcdr2 = [0x00, 0x06, 0x00, 0x02, 0, 0, 1, 2, 0xff, 0xfe, 0, 0];
+        assert_eq!(ty.decode(&xcdr2).unwrap(), expected);
+        assert_eq!(
+            ty.decode(&[0x00, 0x02, 0, 0, 0, 0, 0, 0]),
+            Err(DynError::Encoding(2))
+        );
+        assert_eq!(ty.decode(&xcdr1[..6]), Err(DynError::Truncated));
+    }
+}
@@ -0,0 +1,80 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Rust state that has to live exactly as long as a DDS entity.
+//!
+//! Callbacks registered with Cyclone carry a raw pointer to state owned
+//! on the Rust side. That state is parked here, keyed by the entity it
+//! belongs to, and dropped once the entity is gone.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use lazy_static::lazy_static;
+
+use crate::{dds_entity_t, dds_get_parent};
+
+type StateMap = HashMap<(dds_entity_t, &'static str), Arc<dyn Any + Send + Sync>>;
+
+lazy_static! {
+    static ref ENTITY_STATE: Mutex<StateMap> = Mutex::new(HashMap::new());
+}
+
+fn lock() -> std::sync::MutexGuard<'static, StateMap> {
+    // a panic while holding the lock cannot leave the map half updated
+    ENTITY_STATE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Return the state stored under `key` for `entity`, creating it if needed.
+pub(crate) fn get_or_insert_with<S, F>(entity: dds_entity_t, key: &'static str, init: F) -> Arc<S>
+where
+    S: Any + Send + Sync,
+    F: FnOnce() -> S,
+{
+    let mut map = lock();
+    let state = map
+        .entry((entity, key))
+        .or_insert_with(|| Arc::new(init()))
+        .clone();
+    state
+        .downcast::<S>()
+        .unwrap_or_else(|_| panic!("entity state '{}' stored with a different type", key))
+}
+
+/// Drop the state stored under `key` for `entity`, e.g. right after the
+/// entity itself was deleted.
+pub(crate) fn remove(entity: dds_entity_t, key: &'static str) {
+    // dropped outside the lock, the state may have a Drop of its own
+    let state = lock().remove(&(entity, key));
+    drop(state);
+}
+
+/// Drop the state of entities that no longer exist. Deleting an entity
+/// deletes its children too, so this sweeps the whole map.
+pub(crate) fn purge() {
+    // like remove, the swept state is dropped outside the lock
+    let dead: Vec<_> = {
+        let mut map = lock();
+        let keys: Vec<_> = map
+            .keys()
+            .filter(|(entity, _)| unsafe { dds_get_parent(*entity) } < 0)
+            .copied()
+            .collect();
+        keys.iter().filter_map(|k| map.remove(k)).collect()
+    };
+    drop(dead);
+}
@@ -0,0 +1,1139 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Content filter expressions in the SQL subset of the DDS specification
+//! (Annex B), e.g. `"speed > %0 AND id = 'x'"`.
+//!
+//! An expression is parsed once into a [`FilterExpression`] and compiled
+//! against a [`SampleLayout`] into a [`CompiledFilter`]. The compiled filter
+//! can be installed as a topic filter or used for a query condition, and its
+//! `%n` parameters can be changed while it is installed.
+
+use std::cmp::Ordering;
+use std::ffi::c_void;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+use crate::query_condition::QueryCondition;
+use crate::sample_layout::{Field, FieldKind, FieldValue, SampleLayout};
+use crate::{DDSError, DdsEntity, StateMask};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterError {
+    /// Syntax error at the given byte position
+    Parse { position: usize, message: String },
+    /// The expression refers to a field that is not in the layout
+    UnknownField(String),
+    /// The field exists but its value cannot be read (sequences, unions etc)
+    UnsupportedField(String),
+    /// A `%n` parameter has no value
+    MissingParameter(usize),
+    /// A parameter or literal does not fit the field it is compared with
+    TypeMismatch(String),
+}
+
+impl std::error::Error for FilterError {}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FilterError::Parse { position, message } => {
+                write!(f, "parse error at {}: {}", position, message)
+            }
+            FilterError::UnknownField(name) => write!(f, "unknown field '{}'", name),
+            FilterError::UnsupportedField(name) => {
+                write!(f, "field '{}' cannot be filtered on", name)
+            }
+            FilterError::MissingParameter(index) => write!(f, "no value for parameter %{}", index),
+            FilterError::TypeMismatch(what) => write!(f, "type mismatch: {}", what),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RelOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Like,
+}
+
+impl RelOp {
+    /// The operator to use when the operands are swapped
+    fn mirrored(self) -> RelOp {
+        match self {
+            RelOp::Lt => RelOp::Gt,
+            RelOp::Le => RelOp::Ge,
+            RelOp::Gt => RelOp::Lt,
+            RelOp::Ge => RelOp::Le,
+            op => op,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Field(String),
+    Literal(FieldValue),
+    Parameter(usize),
+}
+
+/// Parsed filter expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    Not(Box<Condition>),
+    Compare(Operand, RelOp, Operand),
+    Between {
+        operand: Operand,
+        low: Operand,
+        high: Operand,
+        negated: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Literal(FieldValue),
+    Parameter(usize),
+    Op(RelOp),
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Between,
+}
+
+fn parse_error<T>(position: usize, message: &str) -> Result<T, FilterError> {
+    Err(FilterError::Parse {
+        position,
+        message: message.to_owned(),
+    })
+}
+
+fn parse_number(text: &str) -> Option<FieldValue> {
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        return u64::from_str_radix(hex, 16).ok().map(FieldValue::UInt);
+    }
+    if let Ok(v) = text.parse::<i64>() {
+        return Some(FieldValue::Int(v));
+    }
+    if let Ok(v) = text.parse::<u64>() {
+        return Some(FieldValue::UInt(v));
+    }
+    text.parse::<f64>().ok().map(FieldValue::Float)
+}
+
+fn tokenize(expr: &str) -> Result<Vec<(usize, Token)>, FilterError> {
+    let chars: Vec<(usize, char)> = expr.char_indices().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let (pos, c) = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let token = match c {
+            '(' => {
+                i += 1;
+                Token::LParen
+            }
+            ')' => {
+                i += 1;
+                Token::RParen
+            }
+            '=' => {
+                i += 1;
+                Token::Op(RelOp::Eq)
+            }
+            '!' if i + 1 < chars.len() && chars[i + 1].1 == '=' => {
+                i += 2;
+                Token::Op(RelOp::Ne)
+            }
+            '<' | '>' => {
+                let next = chars.get(i + 1).map(|(_, c)| *c);
+                let (op, len) = match (c, next) {
+                    ('<', Some('=')) => (RelOp::Le, 2),
+                    ('<', Some('>')) => (RelOp::Ne, 2),
+                    ('<', _) => (RelOp::Lt, 1),
+                    ('>', Some('=')) => (RelOp::Ge, 2),
+                    _ => (RelOp::Gt, 1),
+                };
+                i += len;
+                Token::Op(op)
+            }
+            '%' => {
+                let start = i + 1;
+                let mut end = start;
+                while end < chars.len() && chars[end].1.is_ascii_digit() {
+                    end += 1;
+                }
+                if end == start {
+                    return parse_error(pos, "expected parameter number after '%'");
+                }
+                let text: String = chars[start..end].iter().map(|(_, c)| c).collect();
+                i = end;
+                match text.parse::<usize>() {
+                    Ok(n) => Token::Parameter(n),
+                    Err(_) => return parse_error(pos, "parameter number out of range"),
+                }
+            }
+            '\'' | '"' => {
+                // SQL style quoting, a doubled quote stands for a single one
+                let quote = c;
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return parse_error(pos, "unterminated string"),
+                        Some((_, ch)) if *ch == quote => {
+                            if chars.get(i + 1).map(|(_, c)| *c) == Some(quote) {
+                                text.push(quote);
+                                i += 2;
+                            } else {
+                                i += 1;
+                                break;
+                            }
+                        }
+                        Some((_, ch)) => {
+                            text.push(*ch);
+                            i += 1;
+                        }
+                    }
+                }
+                Token::Literal(FieldValue::Str(text))
+            }
+            c if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' => {
+                let start = i;
+                i += 1;
+                while i < chars.len() {
+                    let ch = chars[i].1;
+                    let exponent_sign = (ch == '-' || ch == '+')
+                        && matches!(chars[i - 1].1, 'e' | 'E')
+                        && !chars[start..i].iter().any(|(_, c)| *c == 'x' || *c == 'X');
+                    if ch.is_ascii_alphanumeric() || ch == '.' || exponent_sign {
+                        i += 1;
+                    } else {
+                        break;
+                    }
+                }
+                let text: String = chars[start..i].iter().map(|(_, c)| c).collect();
+                match parse_number(&text) {
+                    Some(v) => Token::Literal(v),
+                    None => return parse_error(pos, "invalid number"),
+                }
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() {
+                    let ch = chars[i].1;
+                    if ch.is_alphanumeric() || ch == '_' || ch == '.' || ch == '[' || ch == ']' {
+                        i += 1;
+                    } else {
+                        break;
+                    }
+                }
+                let word: String = chars[start..i].iter().map(|(_, c)| c).collect();
+                match word.to_ascii_uppercase().as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    "BETWEEN" => Token::Between,
+                    "LIKE" => Token::Op(RelOp::Like),
+                    "TRUE" => Token::Literal(FieldValue::Bool(true)),
+                    "FALSE" => Token::Literal(FieldValue::Bool(false)),
+                    _ => Token::Ident(word),
+                }
+            }
+            _ => return parse_error(pos, &format!("unexpected character '{}'", c)),
+        };
+        tokens.push((pos, token));
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map(|(p, _)| *p)
+            .unwrap_or(self.end)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(_, t)| t.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn accept(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or_condition(&mut self) -> Result<Condition, FilterError> {
+        let mut left = self.and_condition()?;
+        while self.accept(&Token::Or) {
+            let right = self.and_condition()?;
+            left = Condition::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn and_condition(&mut self) -> Result<Condition, FilterError> {
+        let mut left = self.not_condition()?;
+        while self.accept(&Token::And) {
+            let right = self.not_condition()?;
+            left = Condition::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn not_condition(&mut self) -> Result<Condition, FilterError> {
+        if self.accept(&Token::Not) {
+            let inner = self.not_condition()?;
+            return Ok(Condition::Not(Box::new(inner)));
+        }
+        if self.accept(&Token::LParen) {
+            let inner = self.or_condition()?;
+            if !self.accept(&Token::RParen) {
+                return parse_error(self.position(), "expected ')'");
+            }
+            return Ok(inner);
+        }
+        self.predicate()
+    }
+
+    fn operand(&mut self) -> Result<Operand, FilterError> {
+        let position = self.position();
+        match self.next() {
+            Some(Token::Ident(name)) => Ok(Operand::Field(name)),
+            Some(Token::Literal(value)) => Ok(Operand::Literal(value)),
+            Some(Token::Parameter(n)) => Ok(Operand::Parameter(n)),
+            _ => parse_error(position, "expected a field name, value or parameter"),
+        }
+    }
+
+    fn predicate(&mut self) -> Result<Condition, FilterError> {
+        let left = self.operand()?;
+        let negated = self.accept(&Token::Not);
+        let position = self.position();
+        match self.next() {
+            Some(Token::Between) => {
+                let low = self.operand()?;
+                if !self.accept(&Token::And) {
+                    return parse_error(self.position(), "expected AND in BETWEEN");
+                }
+                let high = self.operand()?;
+                Ok(Condition::Between {
+                    operand: left,
+                    low,
+                    high,
+                    negated,
+                })
+            }
+            Some(Token::Op(RelOp::Like)) => {
+                let right = self.operand()?;
+                let like = Condition::Compare(left, RelOp::Like, right);
+                Ok(if negated {
+                    Condition::Not(Box::new(like))
+                } else {
+                    like
+                })
+            }
+            Some(Token::Op(op)) if !negated => {
+                let right = self.operand()?;
+                Ok(Condition::Compare(left, op, right))
+            }
+            _ => parse_error(position, "expected a comparison, LIKE or BETWEEN"),
+        }
+    }
+}
+
+/// A parsed, not yet compiled filter expression
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterExpression {
+    condition: Condition,
+}
+
+impl FilterExpression {
+    pub fn parse(expr: &str) -> Result<Self, FilterError> {
+        let mut parser = Parser {
+            tokens: tokenize(expr)?,
+            pos: 0,
+            end: expr.len(),
+        };
+        let condition = parser.or_condition()?;
+        if parser.pos < parser.tokens.len() {
+            return parse_error(parser.position(), "unexpected input after expression");
+        }
+        Ok(FilterExpression { condition })
+    }
+
+    pub fn condition(&self) -> &Condition {
+        &self.condition
+    }
+
+    /// Highest parameter index used plus one, i.e. the number of parameters
+    /// the expression needs.
+    pub fn parameter_count(&self) -> usize {
+        fn operand(o: &Operand) -> usize {
+            match o {
+                Operand::Parameter(n) => n + 1,
+                _ => 0,
+            }
+        }
+        fn walk(c: &Condition) -> usize {
+            match c {
+                Condition::And(a, b) | Condition::Or(a, b) => walk(a).max(walk(b)),
+                Condition::Not(a) => walk(a),
+                Condition::Compare(l, _, r) => operand(l).max(operand(r)),
+                Condition::Between {
+                    operand: o,
+                    low,
+                    high,
+                    ..
+                } => operand(o).max(operand(low)).max(operand(high)),
+            }
+        }
+        walk(&self.condition)
+    }
+
+    /// Resolve the field names against `layout` and bind the initial parameter
+    /// values. Parameters are given as strings, the way DDS passes them, and
+    /// are converted to the type of the field they are compared with.
+    pub fn compile<S: AsRef<str>>(
+        &self,
+        layout: Arc<SampleLayout>,
+        parameters: &[S],
+    ) -> Result<CompiledFilter, FilterError> {
+        let mut bound = BoundParameters::default();
+        let program = compile_condition(&self.condition, &layout, &mut bound)?;
+        let values = bound.convert(parameters)?;
+        Ok(CompiledFilter {
+            inner: Arc::new(Compiled {
+                layout,
+                program,
+                kinds: bound.kinds,
+                parameters: RwLock::new(values),
+            }),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Field(Field),
+    Literal(FieldValue),
+    Parameter(usize),
+}
+
+#[derive(Debug, Clone)]
+enum Program {
+    And(Box<Program>, Box<Program>),
+    Or(Box<Program>, Box<Program>),
+    Not(Box<Program>),
+    Compare(Value, RelOp, Value),
+    Between(Value, Value, Value),
+}
+
+/// The kind each parameter is compared with, so that parameter strings can be
+/// converted once when they are set instead of on every sample.
+#[derive(Default)]
+struct BoundParameters {
+    kinds: Vec<Option<FieldKind>>,
+}
+
+impl BoundParameters {
+    fn bind(&mut self, index: usize, kind: FieldKind) {
+        if self.kinds.len() <= index {
+            self.kinds.resize(index + 1, None);
+        }
+        self.kinds[index].get_or_insert(kind);
+    }
+
+    fn convert<S: AsRef<str>>(&self, parameters: &[S]) -> Result<Vec<FieldValue>, FilterError> {
+        self.kinds
+            .iter()
+            .enumerate()
+            .map(|(index, kind)| {
+                let text = parameters
+                    .get(index)
+                    .ok_or(FilterError::MissingParameter(index))?;
+                convert_parameter(text.as_ref(), kind.unwrap_or(FieldKind::String))
+            })
+            .collect()
+    }
+}
+
+fn convert_parameter(text: &str, kind: FieldKind) -> Result<FieldValue, FilterError> {
+    let text = text.trim();
+    if kind.is_string() {
+        let unquoted = text
+            .strip_prefix('\'')
+            .and_then(|t| t.strip_suffix('\''))
+            .unwrap_or(text);
+        return Ok(FieldValue::Str(unquoted.replace("''", "'")));
+    }
+    if kind == FieldKind::Bool {
+        return match text.to_ascii_uppercase().as_str() {
+            "TRUE" | "1" => Ok(FieldValue::Bool(true)),
+            "FALSE" | "0" => Ok(FieldValue::Bool(false)),
+            _ => Err(FilterError::TypeMismatch(format!(
+                "'{}' is not a boolean",
+                text
+            ))),
+        };
+    }
+    parse_number(text)
+        .ok_or_else(|| FilterError::TypeMismatch(format!("'{}' is not a number", text)))
+}
+
+fn compile_operand(operand: &Operand, layout: &SampleLayout) -> Result<Value, FilterError> {
+    match operand {
+        Operand::Field(name) => {
+            let field = layout
+                .field(name)
+                .ok_or_else(|| FilterError::UnknownField(name.clone()))?;
+            if field.kind == FieldKind::Unsupported {
+                return Err(FilterError::UnsupportedField(name.clone()));
+            }
+            Ok(Value::Field(field.clone()))
+        }
+        Operand::Literal(v) => Ok(Value::Literal(v.clone())),
+        Operand::Parameter(n) => Ok(Value::Parameter(*n)),
+    }
+}
+
+fn field_kind(values: &[&Value]) -> Option<FieldKind> {
+    values.iter().find_map(|v| match v {
+        Value::Field(f) => Some(f.kind),
+        _ => None,
+    })
+}
+
+fn check_literal(value: &Value, kind: FieldKind, op: RelOp) -> Result<(), FilterError> {
+    if let Value::Literal(literal) = value {
+        let ok = match literal {
+            FieldValue::Str(_) => kind.is_string(),
+            FieldValue::Bool(_) => kind == FieldKind::Bool,
+            _ => kind.is_numeric(),
+        };
+        if !ok {
+            return Err(FilterError::TypeMismatch(format!(
+                "{} cannot be compared with a {:?} field",
+                literal, kind
+            )));
+        }
+    }
+    if op == RelOp::Like && !kind.is_string() {
+        return Err(FilterError::TypeMismatch(
+            "LIKE needs a string field".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+fn compile_condition(
+    condition: &Condition,
+    layout: &SampleLayout,
+    bound: &mut BoundParameters,
+) -> Result<Program, FilterError> {
+    Ok(match condition {
+        Condition::And(a, b) => Program::And(
+            Box::new(compile_condition(a, layout, bound)?),
+            Box::new(compile_condition(b, layout, bound)?),
+        ),
+        Condition::Or(a, b) => Program::Or(
+            Box::new(compile_condition(a, layout, bound)?),
+            Box::new(compile_condition(b, layout, bound)?),
+        ),
+        Condition::Not(a) => Program::Not(Box::new(compile_condition(a, layout, bound)?)),
+        Condition::Compare(left, op, right) => {
+            let mut left = compile_operand(left, layout)?;
+            let mut right = compile_operand(right, layout)?;
+            let mut op = *op;
+            let kind = field_kind(&[&left, &right]).ok_or_else(|| {
+                FilterError::TypeMismatch("a comparison needs at least one field".to_owned())
+            })?;
+            // keep the field on the left, LIKE patterns on the right
+            if !matches!(left, Value::Field(_)) {
+                std::mem::swap(&mut left, &mut right);
+                op = op.mirrored();
+            }
+            for value in [&left, &right].iter() {
+                check_literal(value, kind, op)?;
+                if let Value::Parameter(n) = value {
+                    bound.bind(*n, kind);
+                }
+            }
+            Program::Compare(left, op, right)
+        }
+        Condition::Between {
+            operand,
+            low,
+            high,
+            negated,
+        } => {
+            let operand = compile_operand(operand, layout)?;
+            let low = compile_operand(low, layout)?;
+            let high = compile_operand(high, layout)?;
+            let kind = match &operand {
+                Value::Field(f) => f.kind,
+                _ => {
+                    return Err(FilterError::TypeMismatch(
+                        "BETWEEN needs a field on the left".to_owned(),
+                    ))
+                }
+            };
+            for value in [&low, &high].iter() {
+                check_literal(value, kind, RelOp::Ge)?;
+                if let Value::Parameter(n) = value {
+                    bound.bind(*n, kind);
+                }
+            }
+            let between = Program::Between(operand, low, high);
+            if *negated {
+                Program::Not(Box::new(between))
+            } else {
+                between
+            }
+        }
+    })
+}
+
+fn compare_values(a: &FieldValue, b: &FieldValue) -> Option<Ordering> {
+    use FieldValue::*;
+    match (a, b) {
+        (Int(x), Int(y)) => Some(x.cmp(y)),
+        (UInt(x), UInt(y)) => Some(x.cmp(y)),
+        (Int(x), UInt(y)) => Some((*x as i128).cmp(&(*y as i128))),
+        (UInt(x), Int(y)) => Some((*x as i128).cmp(&(*y as i128))),
+        (Float(x), Float(y)) => x.partial_cmp(y),
+        (Float(x), Int(y)) => x.partial_cmp(&(*y as f64)),
+        (Float(x), UInt(y)) => x.partial_cmp(&(*y as f64)),
+        (Int(x), Float(y)) => (*x as f64).partial_cmp(y),
+        (UInt(x), Float(y)) => (*x as f64).partial_cmp(y),
+        (Bool(x), Bool(y)) => Some(x.cmp(y)),
+        (Str(x), Str(y)) => Some(x.cmp(y)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pattern {
+    /// `%`
+    Any,
+    /// `_`
+    One,
+    Char(char),
+}
+
+/// SQL LIKE: `%` matches any run of characters, `_` exactly one. A
+/// backslash makes the character after it match only itself, so `50\%`
+/// matches just "50%".
+pub fn like(text: &str, pattern: &str) -> bool {
+    let text: Vec<char> = text.chars().collect();
+    let mut compiled = Vec::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        compiled.push(match c {
+            '\\' => Pattern::Char(chars.next().unwrap_or('\\')),
+            '%' => Pattern::Any,
+            '_' => Pattern::One,
+            c => Pattern::Char(c),
+        });
+    }
+    let pattern = compiled;
+    let (mut t, mut p) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        let step = match pattern.get(p) {
+            Some(Pattern::One) => true,
+            Some(Pattern::Char(c)) => *c == text[t],
+            _ => false,
+        };
+        if step {
+            t += 1;
+            p += 1;
+        } else if pattern.get(p) == Some(&Pattern::Any) {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((bp, bt)) = backtrack {
+            p = bp + 1;
+            t = bt + 1;
+            backtrack = Some((bp, bt + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == Pattern::Any)
+}
+
+struct Compiled {
+    layout: Arc<SampleLayout>,
+    program: Program,
+    kinds: Vec<Option<FieldKind>>,
+    parameters: RwLock<Vec<FieldValue>>,
+}
+
+impl Compiled {
+    unsafe fn value(
+        &self,
+        value: &Value,
+        sample: *const c_void,
+        params: &[FieldValue],
+    ) -> Option<FieldValue> {
+        match value {
+            Value::Field(f) => self.layout.read(f, sample),
+            Value::Literal(v) => Some(v.clone()),
+            Value::Parameter(n) => params.get(*n).cloned(),
+        }
+    }
+
+    unsafe fn eval(&self, program: &Program, sample: *const c_void, params: &[FieldValue]) -> bool {
+        match program {
+            Program::And(a, b) => self.eval(a, sample, params) && self.eval(b, sample, params),
+            Program::Or(a, b) => self.eval(a, sample, params) || self.eval(b, sample, params),
+            Program::Not(a) => !self.eval(a, sample, params),
+            Program::Compare(l, op, r) => {
+                let (l, r) = match (self.value(l, sample, params), self.value(r, sample, params)) {
+                    (Some(l), Some(r)) => (l, r),
+                    _ => return false,
+                };
+                if *op == RelOp::Like {
+                    return match (&l, &r) {
+                        (FieldValue::Str(text), FieldValue::Str(pattern)) => like(text, pattern),
+                        _ => false,
+                    };
+                }
+                match compare_values(&l, &r) {
+                    Some(ordering) => match op {
+                        RelOp::Eq => ordering == Ordering::Equal,
+                        RelOp::Ne => ordering != Ordering::Equal,
+                        RelOp::Lt => ordering == Ordering::Less,
+                        RelOp::Le => ordering != Ordering::Greater,
+                        RelOp::Gt => ordering == Ordering::Greater,
+                        RelOp::Ge => ordering != Ordering::Less,
+                        RelOp::Like => false,
+                    },
+                    None => false,
+                }
+            }
+            Program::Between(v, low, high) => {
+                let v = self.value(v, sample, params);
+                let low = self.value(low, sample, params);
+                let high = self.value(high, sample, params);
+                match (v, low, high) {
+                    (Some(v), Some(low), Some(high)) => {
+                        matches!(
+                            compare_values(&v, &low),
+                            Some(Ordering::Equal) | Some(Ordering::Greater)
+                        ) && matches!(
+                            compare_values(&v, &high),
+                            Some(Ordering::Equal) | Some(Ordering::Less)
+                        )
+                    }
+                    _ => false,
+                }
+            }
+        }
+    }
+}
+
+/// A filter expression bound to a sample layout. Cloning is cheap and clones
+/// share their parameters.
+#[derive(Clone)]
+pub struct CompiledFilter {
+    inner: Arc<Compiled>,
+}
+
+impl CompiledFilter {
+    pub fn layout(&self) -> &SampleLayout {
+        &self.inner.layout
+    }
+
+    /// Replace all parameter values. Takes effect for the next sample checked,
+    /// including where the filter is already installed.
+    pub fn set_parameters<S: AsRef<str>>(&self, parameters: &[S]) -> Result<(), FilterError> {
+        let bound = BoundParameters {
+            kinds: self.inner.kinds.clone(),
+        };
+        let values = bound.convert(parameters)?;
+        *self
+            .inner
+            .parameters
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = values;
+        Ok(())
+    }
+
+    /// Change the value of a single parameter
+    pub fn set_parameter(&self, index: usize, value: &str) -> Result<(), FilterError> {
+        let kind = match self.inner.kinds.get(index) {
+            Some(kind) => kind.unwrap_or(FieldKind::String),
+            None => return Err(FilterError::MissingParameter(index)),
+        };
+        let value = convert_parameter(value, kind)?;
+        self.inner
+            .parameters
+            .write()
+            .unwrap_or_else(|e| e.into_inner())[index] = value;
+        Ok(())
+    }
+
+    /// Evaluate the filter against a sample
+    ///
+    /// # Safety
+    /// `sample` must point to a valid sample of the type the layout describes.
+    pub unsafe fn matches(&self, sample: *const c_void) -> bool {
+        let params = self
+            .inner
+            .parameters
+            .read()
+            .unwrap_or_else(|e| e.into_inner());
+        self.inner.eval(&self.inner.program, sample, &params)
+    }
+
+    /// Install the filter as the content filter of a topic, replacing any
+    /// filter already set.
+    ///
+    /// # Safety
+    /// The entity must be a topic whose sample type is described by the layout.
+    pub unsafe fn install_on_topic(&self, topic: &DdsEntity) -> Result<(), DDSError> {
+        let filter = self.clone();
+        topic.set_topic_filter_untyped(Some(Box::new(move |sample| unsafe {
+            filter.matches(sample)
+        })))
+    }
+
+    /// Create a query condition on a reader that selects the samples matching
+    /// the filter.
+    ///
+    /// # Safety
+    /// The entity must be a reader whose sample type is described by the layout
+    /// and `T` must be that type (or `c_void` to only use it in waitsets).
+    pub unsafe fn query_condition<T>(
+        &self,
+        reader: &DdsEntity,
+        mask: StateMask,
+    ) -> Result<QueryCondition<T>, DDSError> {
+        let filter = self.clone();
+        reader.query_untyped(
+            mask,
+            Box::new(move |sample| unsafe { filter.matches(sample) }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_layout::tests::{descriptor, ADR, INT32, RTS, STRING};
+    use crate::sample_layout::DDS_OP_FLAG_FP;
+    use crate::{
+        dds_stream_typecode_primary_DDS_OP_TYPE_8BY, dds_stream_typecode_primary_DDS_OP_TYPE_BLN,
+    };
+    use std::ffi::CString;
+    use std::os::raw::c_char;
+
+    #[repr(C)]
+    struct Sample {
+        id: i32,
+        speed: f64,
+        name: *const c_char,
+        flag: bool,
+    }
+
+    fn offset<T>(sample: &Sample, field: &T) -> u32 {
+        (field as *const T as usize - sample as *const Sample as usize) as u32
+    }
+
+    fn layout() -> Arc<SampleLayout> {
+        let s = Sample {
+            id: 0,
+            speed: 0.0,
+            name: std::ptr::null(),
+            flag: false,
+        };
+        let ops = vec![
+            INT32,
+            offset(&s, &s.id),
+            ADR | dds_stream_typecode_primary_DDS_OP_TYPE_8BY | DDS_OP_FLAG_FP,
+            offset(&s, &s.speed),
+            STRING,
+            offset(&s, &s.name),
+            ADR | dds_stream_typecode_primary_DDS_OP_TYPE_BLN,
+            offset(&s, &s.flag),
+            RTS,
+        ];
+        let desc = descriptor(std::mem::size_of::<Sample>() as u32, ops, 5);
+        Arc::new(
+            unsafe { SampleLayout::from_descriptor(desc, &["id", "speed", "name", "flag"]) }
+                .unwrap(),
+        )
+    }
+
+    /// Check `expr` with `params` against samples of (id, speed, name)
+    fn check(expr: &str, params: &[&str], samples: &[(i32, f64, &str)]) -> Vec<bool> {
+        let filter = FilterExpression::parse(expr)
+            .unwrap()
+            .compile(layout(), params)
+            .unwrap();
+        samples
+            .iter()
+            .map(|(id, speed, name)| matches(&filter, *id, *speed, name))
+            .collect()
+    }
+
+    fn matches(filter: &CompiledFilter, id: i32, speed: f64, name: &str) -> bool {
+        let name = CString::new(name).unwrap();
+        let sample = Sample {
+            id,
+            speed,
+            name: name.as_ptr(),
+            flag: id % 2 == 0,
+        };
+        unsafe { filter.matches(&sample as *const Sample as *const c_void) }
+    }
+
+    fn field(name: &str) -> Operand {
+        Operand::Field(name.to_owned())
+    }
+
+    fn int(v: i64) -> Operand {
+        Operand::Literal(FieldValue::Int(v))
+    }
+
+    fn compare(name: &str, op: RelOp, v: i64) -> Box<Condition> {
+        Box::new(Condition::Compare(field(name), op, int(v)))
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let expr = FilterExpression::parse("a = 1 OR b = 2 AND c = 3").unwrap();
+        assert_eq!(
+            expr.condition(),
+            &Condition::Or(
+                compare("a", RelOp::Eq, 1),
+                Box::new(Condition::And(
+                    compare("b", RelOp::Eq, 2),
+                    compare("c", RelOp::Eq, 3)
+                ))
+            )
+        );
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and() {
+        let expr = FilterExpression::parse("NOT a = 1 AND (b < 2 OR c >= 3)").unwrap();
+        assert_eq!(
+            expr.condition(),
+            &Condition::And(
+                Box::new(Condition::Not(compare("a", RelOp::Eq, 1))),
+                Box::new(Condition::Or(
+                    compare("b", RelOp::Lt, 2),
+                    compare("c", RelOp::Ge, 3)
+                ))
+            )
+        );
+        let samples = [(1, 0.0, ""), (2, 0.0, ""), (3, 2.0, "")];
+        assert_eq!(
+            check("NOT id = 1 AND (id < 3 OR speed >= 2)", &[], &samples),
+            [false, true, true]
+        );
+        assert_eq!(
+            check("id = 1 OR id = 2 AND speed > 1", &[], &samples),
+            [true, false, false]
+        );
+    }
+
+    #[test]
+    fn reversed_comparisons_swap_operands() {
+        let samples = [(3, 0.0, "a"), (5, 0.0, "b"), (7, 0.0, "c")];
+        assert_eq!(check("5 < id", &[], &samples), [false, false, true]);
+        assert_eq!(check("5 >= id", &[], &samples), [true, true, false]);
+        assert_eq!(check("'b' = name", &[], &samples), [false, true, false]);
+        assert_eq!(check("%0 > id", &["5"], &samples), [true, false, false]);
+    }
+
+    #[test]
+    fn between_is_inclusive_and_can_be_negated() {
+        let samples = [
+            (0, 0.5, ""),
+            (0, 1.0, ""),
+            (0, 1.5, ""),
+            (0, 2.0, ""),
+            (0, 3.0, ""),
+        ];
+        assert_eq!(
+            check("speed BETWEEN 1 AND 2", &[], &samples),
+            [false, true, true, true, false]
+        );
+        assert_eq!(
+            check("speed NOT BETWEEN 1 AND %0", &["2"], &samples),
+            [true, false, false, false, true]
+        );
+        assert!(matches!(
+            FilterExpression::parse("speed NOT BETWEEN 1 AND 2")
+                .unwrap()
+                .condition(),
+            Condition::Between { negated: true, .. }
+        ));
+    }
+
+    #[test]
+    fn like_wildcards_and_escapes() {
+        assert!(like("hello", "h%o"));
+        assert!(like("ho", "h%o"));
+        assert!(like("hello", "h_llo"));
+        assert!(!like("hllo", "h_llo"));
+        assert!(like("abcbc", "%bc"));
+        assert!(like("", "%"));
+        assert!(!like("", "_"));
+        assert!(like("50%", "50\\%"));
+        assert!(!like("500", "50\\%"));
+        assert!(like("a_b", "a\\_b"));
+        assert!(!like("axb", "a\\_b"));
+        assert!(like("a\\b", "a\\\\b"));
+
+        let samples = [(0, 0.0, "car_1"), (0, 0.0, "carx1"), (0, 0.0, "bus_1")];
+        assert_eq!(
+            check("name LIKE 'car\\_%'", &[], &samples),
+            [true, false, false]
+        );
+        assert_eq!(
+            check("name NOT LIKE %0", &["'car%'"], &samples),
+            [false, false, true]
+        );
+    }
+
+    #[test]
+    fn parameters_can_be_updated() {
+        let filter = FilterExpression::parse("id > %0 AND name = %1")
+            .unwrap()
+            .compile(layout(), &["5", "'bob'"])
+            .unwrap();
+        assert!(matches(&filter, 6, 0.0, "bob"));
+        assert!(!matches(&filter, 6, 0.0, "alice"));
+
+        filter.set_parameter(0, "10").unwrap();
+        assert!(!matches(&filter, 6, 0.0, "bob"));
+        assert!(matches(&filter, 11, 0.0, "bob"));
+
+        filter.set_parameters(&["0", "alice"]).unwrap();
+        assert!(matches(&filter, 6, 0.0, "alice"));
+
+        // clones share their parameters
+        filter.clone().set_parameter(1, "'o''neil'").unwrap();
+        assert!(matches(&filter, 6, 0.0, "o'neil"));
+
+        assert_eq!(
+            filter.set_parameter(2, "1"),
+            Err(FilterError::MissingParameter(2))
+        );
+        assert!(matches!(
+            filter.set_parameter(0, "many"),
+            Err(FilterError::TypeMismatch(_))
+        ));
+        assert_eq!(
+            filter.set_parameters(&["1"]),
+            Err(FilterError::MissingParameter(1))
+        );
+        // failed updates leave the old values in place
+        assert!(matches(&filter, 6, 0.0, "o'neil"));
+    }
+
+    #[test]
+    fn compile_errors() {
+        let compile = |expr: &str, params: &[&str]| {
+            FilterExpression::parse(expr)
+                .unwrap()
+                .compile(layout(), params)
+                .err()
+        };
+        assert_eq!(
+            compile("id = %0", &[]),
+            Some(FilterError::MissingParameter(0))
+        );
+        assert_eq!(
+            compile("nope = 1", &[]),
+            Some(FilterError::UnknownField("nope".to_owned()))
+        );
+        assert!(matches!(
+            compile("id = 'x'", &[]),
+            Some(FilterError::TypeMismatch(_))
+        ));
+        assert!(matches!(
+            compile("id LIKE 'x'", &[]),
+            Some(FilterError::TypeMismatch(_))
+        ));
+        assert!(matches!(
+            compile("1 = 1", &[]),
+            Some(FilterError::TypeMismatch(_))
+        ));
+        assert_eq!(compile("flag = TRUE", &[]), None);
+    }
+
+    #[test]
+    fn parse_errors_have_positions() {
+        let position = |expr: &str| match FilterExpression::parse(expr) {
+            Err(FilterError::Parse { position, .. }) => position,
+            other => panic!("{} parsed as {:?}", expr, other),
+        };
+        assert_eq!(position("id >"), 4);
+        assert_eq!(position("id = 'abc"), 5);
+        assert_eq!(position("(id = 1"), 7);
+        assert_eq!(position("id = 1 )"), 7);
+        assert_eq!(position("id = %"), 5);
+        assert_eq!(position("id @ 1"), 3);
+        assert_eq!(position("id = 1 AND"), 10);
+        assert_eq!(position(""), 0);
+    }
+
+    #[test]
+    fn parameter_count_is_highest_index_plus_one() {
+        let expr = FilterExpression::parse("id = %2 OR speed BETWEEN %0 AND 3").unwrap();
+        assert_eq!(expr.parameter_count(), 3);
+        assert_eq!(
+            FilterExpression::parse("id = 1").unwrap().parameter_count(),
+            0
+        );
+    }
+}
@@ -81,6 +81,36 @@ where
         }
     }
 }
+#[repr(C)]
+#[derive(Default)]
+pub struct __IncompleteArrayField<T>(::std::marker::PhantomData<T>, [T; 0]);
+impl<T> __IncompleteArrayField<T> {
+    #[inline]
+    pub const fn new() -> Self {
+        __IncompleteArrayField(::std::marker::PhantomData, [])
+    }
+    #[inline]
+    pub fn as_ptr(&self) -> *const T {
+        self as *const _ as *const T
+    }
+    #[inline]
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        self as *mut _ as *mut T
+    }
+    #[inline]
+    pub unsafe fn as_slice(&self, len: usize) -> &[T] {
+        ::std::slice::from_raw_parts(self.as_ptr(), len)
+    }
+    #[inline]
+    pub unsafe fn as_mut_slice(&mut self, len: usize) -> &mut [T] {
+        ::std::slice::from_raw_parts_mut(self.as_mut_ptr(), len)
+    }
+}
+impl<T> ::std::fmt::Debug for __IncompleteArrayField<T> {
+    fn fmt(&self, fmt: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        fmt.write_str("__IncompleteArrayField")
+    }
+}
 pub type size_t = ::std::os::raw::c_ulong;
 pub type __uint8_t = ::std::os::raw::c_uchar;
 pub type __int16_t = ::std::os::raw::c_short;
@@ -148,6 +178,137 @@ fn bindgen_test_layout_ddsrt_wctime_t() {
         )
     );
 }
+extern "C" {
+    pub fn dds_time() -> dds_time_t;
+}
+extern "C" {
+    pub fn dds_sleepfor(reltime: dds_duration_t);
+}
+extern "C" {
+    pub fn dds_sleepuntil(abstime: dds_time_t);
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct dds_log_data_t {
+    pub priority: u32,
+    pub domid: u32,
+    pub file: *const ::std::os::raw::c_char,
+    pub line: u32,
+    pub function: *const ::std::os::raw::c_char,
+    pub message: *const ::std::os::raw::c_char,
+    pub size: size_t,
+    pub hdrsize: size_t,
+}
+#[test]
+fn bindgen_test_layout_dds_log_data_t() {
+    assert_eq!(
+        ::std::mem::size_of::<dds_log_data_t>(),
+        56usize,
+        concat!("Size of: ", stringify!(dds_log_data_t))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<dds_log_data_t>(),
+        8usize,
+        concat!("Alignment of ", stringify!(dds_log_data_t))
+    );
+    assert_eq!(
+        unsafe { &(*(::std::ptr::null::<dds_log_data_t>())).priority as *const _ as usize },
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(dds_log_data_t),
+            "::",
+            stringify!(priority)
+        )
+    );
+    assert_eq!(
+        unsafe { &(*(::std::ptr::null::<dds_log_data_t>())).domid as *const _ as usize },
+        4usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(dds_log_data_t),
+            "::",
+            stringify!(domid)
+        )
+    );
+    assert_eq!(
+        unsafe { &(*(::std::ptr::null::<dds_log_data_t>())).file as *const _ as usize },
+        8usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(dds_log_data_t),
+            "::",
+            stringify!(file)
+        )
+    );
+    assert_eq!(
+        unsafe { &(*(::std::ptr::null::<dds_log_data_t>())).line as *const _ as usize },
+        16usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(dds_log_data_t),
+            "::",
+            stringify!(line)
+        )
+    );
+    assert_eq!(
+        unsafe { &(*(::std::ptr::null::<dds_log_data_t>())).function as *const _ as usize },
+        24usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(dds_log_data_t),
+            "::",
+            stringify!(function)
+        )
+    );
+    assert_eq!(
+        unsafe { &(*(::std::ptr::null::<dds_log_data_t>())).message as *const _ as usize },
+        32usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(dds_log_data_t),
+            "::",
+            stringify!(message)
+        )
+    );
+    assert_eq!(
+        unsafe { &(*(::std::ptr::null::<dds_log_data_t>())).size as *const _ as usize },
+        40usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(dds_log_data_t),
+            "::",
+            stringify!(size)
+        )
+    );
+    assert_eq!(
+        unsafe { &(*(::std::ptr::null::<dds_log_data_t>())).hdrsize as *const _ as usize },
+        48usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(dds_log_data_t),
+            "::",
+            stringify!(hdrsize)
+        )
+    );
+}
+impl Default for dds_log_data_t {
+    fn default() -> Self {
+        unsafe { ::std::mem::zeroed() }
+    }
+}
+pub type dds_log_write_fn_t = ::std::option::Option<
+    unsafe extern "C" fn(arg1: *mut ::std::os::raw::c_void, arg2: *const dds_log_data_t),
+>;
+extern "C" {
+    pub fn dds_set_log_mask(cats: u32);
+}
+extern "C" {
+    pub fn dds_set_log_sink(callback: dds_log_write_fn_t, userdata: *mut ::std::os::raw::c_void);
+}
+extern "C" {
+    pub fn dds_set_trace_sink(callback: dds_log_write_fn_t, userdata: *mut ::std::os::raw::c_void);
+}
 pub type dds_return_t = i32;
 pub const dds_free_op_t_DDS_FREE_ALL: dds_free_op_t = 7;
 pub const dds_free_op_t_DDS_FREE_CONTENTS: dds_free_op_t = 3;
@@ -875,6 +1036,13 @@ extern "C" {
 extern "C" {
     pub fn dds_qget_writer_data_lifecycle(qos: *const dds_qos_t, autodispose: *mut bool) -> bool;
 }
+extern "C" {
+    pub fn dds_qget_reader_data_lifecycle(
+        qos: *const dds_qos_t,
+        autopurge_nowriter_samples_delay: *mut dds_duration_t,
+        autopurge_disposed_samples_delay: *mut dds_duration_t,
+    ) -> bool;
+}
 extern "C" {
     pub fn dds_qget_durability_service(
         qos: *const dds_qos_t,
@@ -2306,6 +2474,50 @@ impl Default for dds_builtintopic_endpoint {
     }
 }
 pub type dds_builtintopic_endpoint_t = dds_builtintopic_endpoint;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct dds_builtintopic_participant {
+    pub key: dds_guid_t,
+    pub qos: *mut dds_qos_t,
+}
+#[test]
+fn bindgen_test_layout_dds_builtintopic_participant() {
+    assert_eq!(
+        ::std::mem::size_of::<dds_builtintopic_participant>(),
+        24usize,
+        concat!("Size of: ", stringify!(dds_builtintopic_participant))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<dds_builtintopic_participant>(),
+        8usize,
+        concat!("Alignment of ", stringify!(dds_builtintopic_participant))
+    );
+    assert_eq!(
+        unsafe {
+            &(*(::std::ptr::null::<dds_builtintopic_participant>())).key as *const _ as usize
+        },
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(dds_builtintopic_participant),
+            "::",
+            stringify!(key)
+        )
+    );
+    assert_eq!(
+        unsafe {
+            &(*(::std::ptr::null::<dds_builtintopic_participant>())).qos as *const _ as usize
+        },
+        16usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(dds_builtintopic_participant),
+            "::",
+            stringify!(qos)
+        )
+    );
+}
+pub type dds_builtintopic_participant_t = dds_builtintopic_participant;
 extern "C" {
     pub fn dds_enable(entity: dds_entity_t) -> dds_return_t;
 }
@@ -2330,6 +2542,218 @@ extern "C" {
         ihdl: *mut dds_instance_handle_t,
     ) -> dds_return_t;
 }
+extern "C" {
+    pub fn dds_get_guid(entity: dds_entity_t, guid: *mut dds_guid_t) -> dds_return_t;
+}
+pub const dds_stat_kind_DDS_STAT_KIND_UINT32: dds_stat_kind = 0;
+pub const dds_stat_kind_DDS_STAT_KIND_UINT64: dds_stat_kind = 1;
+pub const dds_stat_kind_DDS_STAT_KIND_LENGTHTIME: dds_stat_kind = 2;
+pub type dds_stat_kind = ::std::os::raw::c_uint;
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dds_stat_keyvalue {
+    pub name: *const ::std::os::raw::c_char,
+    pub kind: dds_stat_kind,
+    pub u: dds_stat_keyvalue__bindgen_ty_1,
+}
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union dds_stat_keyvalue__bindgen_ty_1 {
+    pub u32_: u32,
+    pub u64_: u64,
+    pub lengthtime: u64,
+    _bindgen_union_align: u64,
+}
+#[test]
+fn bindgen_test_layout_dds_stat_keyvalue__bindgen_ty_1() {
+    assert_eq!(
+        ::std::mem::size_of::<dds_stat_keyvalue__bindgen_ty_1>(),
+        8usize,
+        concat!("Size of: ", stringify!(dds_stat_keyvalue__bindgen_ty_1))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<dds_stat_keyvalue__bindgen_ty_1>(),
+        8usize,
+        concat!("Alignment of ", stringify!(dds_stat_keyvalue__bindgen_ty_1))
+    );
+    assert_eq!(
+        unsafe {
+            &(*(::std::ptr::null::<dds_stat_keyvalue__bindgen_ty_1>())).u32_ as *const _ as usize
+        },
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(dds_stat_keyvalue__bindgen_ty_1),
+            "::",
+            stringify!(u32_)
+        )
+    );
+    assert_eq!(
+        unsafe {
+            &(*(::std::ptr::null::<dds_stat_keyvalue__bindgen_ty_1>())).u64_ as *const _ as usize
+        },
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(dds_stat_keyvalue__bindgen_ty_1),
+            "::",
+            stringify!(u64_)
+        )
+    );
+    assert_eq!(
+        unsafe {
+            &(*(::std::ptr::null::<dds_stat_keyvalue__bindgen_ty_1>())).lengthtime as *const _
+                as usize
+        },
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(dds_stat_keyvalue__bindgen_ty_1),
+            "::",
+            stringify!(lengthtime)
+        )
+    );
+}
+impl Default for dds_stat_keyvalue__bindgen_ty_1 {
+    fn default() -> Self {
+        unsafe { ::std::mem::zeroed() }
+    }
+}
+#[test]
+fn bindgen_test_layout_dds_stat_keyvalue() {
+    assert_eq!(
+        ::std::mem::size_of::<dds_stat_keyvalue>(),
+        24usize,
+        concat!("Size of: ", stringify!(dds_stat_keyvalue))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<dds_stat_keyvalue>(),
+        8usize,
+        concat!("Alignment of ", stringify!(dds_stat_keyvalue))
+    );
+    assert_eq!(
+        unsafe { &(*(::std::ptr::null::<dds_stat_keyvalue>())).name as *const _ as usize },
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(dds_stat_keyvalue),
+            "::",
+            stringify!(name)
+        )
+    );
+    assert_eq!(
+        unsafe { &(*(::std::ptr::null::<dds_stat_keyvalue>())).kind as *const _ as usize },
+        8usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(dds_stat_keyvalue),
+            "::",
+            stringify!(kind)
+        )
+    );
+    assert_eq!(
+        unsafe { &(*(::std::ptr::null::<dds_stat_keyvalue>())).u as *const _ as usize },
+        16usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(dds_stat_keyvalue),
+            "::",
+            stringify!(u)
+        )
+    );
+}
+impl Default for dds_stat_keyvalue {
+    fn default() -> Self {
+        unsafe { ::std::mem::zeroed() }
+    }
+}
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct dds_statistics {
+    pub entity: dds_entity_t,
+    pub opaque: u64,
+    pub time: dds_time_t,
+    pub count: size_t,
+    pub kv: __IncompleteArrayField<dds_stat_keyvalue>,
+}
+#[test]
+fn bindgen_test_layout_dds_statistics() {
+    assert_eq!(
+        ::std::mem::size_of::<dds_statistics>(),
+        32usize,
+        concat!("Size of: ", stringify!(dds_statistics))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<dds_statistics>(),
+        8usize,
+        concat!("Alignment of ", stringify!(dds_statistics))
+    );
+    assert_eq!(
+        unsafe { &(*(::std::ptr::null::<dds_statistics>())).entity as *const _ as usize },
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(dds_statistics),
+            "::",
+            stringify!(entity)
+        )
+    );
+    assert_eq!(
+        unsafe { &(*(::std::ptr::null::<dds_statistics>())).opaque as *const _ as usize },
+        8usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(dds_statistics),
+            "::",
+            stringify!(opaque)
+        )
+    );
+    assert_eq!(
+        unsafe { &(*(::std::ptr::null::<dds_statistics>())).time as *const _ as usize },
+        16usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(dds_statistics),
+            "::",
+            stringify!(time)
+        )
+    );
+    assert_eq!(
+        unsafe { &(*(::std::ptr::null::<dds_statistics>())).count as *const _ as usize },
+        24usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(dds_statistics),
+            "::",
+            stringify!(count)
+        )
+    );
+    assert_eq!(
+        unsafe { &(*(::std::ptr::null::<dds_statistics>())).kv as *const _ as usize },
+        32usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(dds_statistics),
+            "::",
+            stringify!(kv)
+        )
+    );
+}
+extern "C" {
+    pub fn dds_create_statistics(entity: dds_entity_t) -> *mut dds_statistics;
+}
+extern "C" {
+    pub fn dds_refresh_statistics(stat: *mut dds_statistics) -> dds_return_t;
+}
+extern "C" {
+    pub fn dds_delete_statistics(stat: *mut dds_statistics);
+}
+extern "C" {
+    pub fn dds_lookup_statistic(
+        stat: *const dds_statistics,
+        name: *const ::std::os::raw::c_char,
+    ) -> *const dds_stat_keyvalue;
+}
 extern "C" {
     pub fn dds_read_status(entity: dds_entity_t, status: *mut u32, mask: u32) -> dds_return_t;
 }
@@ -2436,6 +2860,27 @@ extern "C" {
         timeout: dds_duration_t,
     ) -> dds_entity_t;
 }
+extern "C" {
+    pub fn dds_get_typeinfo(
+        entity: dds_entity_t,
+        type_info: *mut *mut dds_typeinfo_t,
+    ) -> dds_return_t;
+}
+extern "C" {
+    pub fn dds_free_typeinfo(type_info: *mut dds_typeinfo_t) -> dds_return_t;
+}
+extern "C" {
+    pub fn dds_create_topic_descriptor(
+        scope: dds_find_scope_t,
+        participant: dds_entity_t,
+        type_info: *const dds_typeinfo_t,
+        timeout: dds_duration_t,
+        descriptor: *mut *mut dds_topic_descriptor_t,
+    ) -> dds_return_t;
+}
+extern "C" {
+    pub fn dds_delete_topic_descriptor(descriptor: *mut dds_topic_descriptor_t) -> dds_return_t;
+}
 extern "C" {
     pub fn dds_get_name(
         topic: dds_entity_t,
@@ -2452,13 +2897,71 @@ extern "C" {
 }
 pub type dds_topic_filter_sample_fn =
     ::std::option::Option<unsafe extern "C" fn(sample: *const ::std::os::raw::c_void) -> bool>;
+pub type dds_topic_filter_sample_arg_fn = ::std::option::Option<
+    unsafe extern "C" fn(
+        sample: *const ::std::os::raw::c_void,
+        arg: *mut ::std::os::raw::c_void,
+    ) -> bool,
+>;
 pub type dds_topic_filter_fn = dds_topic_filter_sample_fn;
+pub type dds_topic_filter_arg_fn = dds_topic_filter_sample_arg_fn;
 extern "C" {
     pub fn dds_set_topic_filter(topic: dds_entity_t, filter: dds_topic_filter_fn);
 }
+extern "C" {
+    pub fn dds_set_topic_filter_and_arg(
+        topic: dds_entity_t,
+        filter: dds_topic_filter_arg_fn,
+        arg: *mut ::std::os::raw::c_void,
+    );
+}
 extern "C" {
     pub fn dds_get_topic_filter(topic: dds_entity_t) -> dds_topic_filter_fn;
 }
+extern "C" {
+    pub fn dds_get_topic_filter_and_arg(
+        topic: dds_entity_t,
+        fn_: *mut dds_topic_filter_arg_fn,
+        arg: *mut *mut ::std::os::raw::c_void,
+    ) -> dds_return_t;
+}
+pub type dds_topic_filter_sampleinfo_arg_fn = ::std::option::Option<
+    unsafe extern "C" fn(
+        sampleinfo: *const dds_sample_info_t,
+        arg: *mut ::std::os::raw::c_void,
+    ) -> bool,
+>;
+pub const dds_topic_filter_mode_DDS_TOPIC_FILTER_NONE: dds_topic_filter_mode = 0;
+pub const dds_topic_filter_mode_DDS_TOPIC_FILTER_SAMPLE: dds_topic_filter_mode = 1;
+pub const dds_topic_filter_mode_DDS_TOPIC_FILTER_SAMPLE_ARG: dds_topic_filter_mode = 2;
+pub const dds_topic_filter_mode_DDS_TOPIC_FILTER_SAMPLEINFO_ARG: dds_topic_filter_mode = 3;
+pub type dds_topic_filter_mode = ::std::os::raw::c_uint;
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union dds_topic_filter_function_union {
+    pub sample: dds_topic_filter_sample_fn,
+    pub sample_arg: dds_topic_filter_sample_arg_fn,
+    pub sampleinfo_arg: dds_topic_filter_sampleinfo_arg_fn,
+}
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dds_topic_filter {
+    pub mode: dds_topic_filter_mode,
+    pub f: dds_topic_filter_function_union,
+    pub arg: *mut ::std::os::raw::c_void,
+}
+extern "C" {
+    pub fn dds_set_topic_filter_extended(
+        topic: dds_entity_t,
+        filter: *const dds_topic_filter,
+    ) -> dds_return_t;
+}
+extern "C" {
+    pub fn dds_get_topic_filter_extended(
+        topic: dds_entity_t,
+        filter: *mut dds_topic_filter,
+    ) -> dds_return_t;
+}
 extern "C" {
     pub fn dds_create_subscriber(
         participant: dds_entity_t,
@@ -2588,9 +3091,33 @@ extern "C" {
 extern "C" {
     pub fn dds_write_flush(writer: dds_entity_t);
 }
+extern "C" {
+    pub fn dds_write_set_batch(enable: bool);
+}
 extern "C" {
     pub fn dds_writecdr(writer: dds_entity_t, serdata: *mut ddsi_serdata) -> dds_return_t;
 }
+extern "C" {
+    pub fn dds_forwardcdr(writer: dds_entity_t, serdata: *mut ddsi_serdata) -> dds_return_t;
+}
+extern "C" {
+    pub fn dds_readcdr(
+        reader_or_condition: dds_entity_t,
+        buf: *mut *mut ddsi_serdata,
+        maxs: u32,
+        si: *mut dds_sample_info_t,
+        mask: u32,
+    ) -> dds_return_t;
+}
+extern "C" {
+    pub fn dds_takecdr(
+        reader_or_condition: dds_entity_t,
+        buf: *mut *mut ddsi_serdata,
+        maxs: u32,
+        si: *mut dds_sample_info_t,
+        mask: u32,
+    ) -> dds_return_t;
+}
 extern "C" {
     pub fn dds_write_ts(
         writer: dds_entity_t,
@@ -2877,6 +3404,12 @@ extern "C" {
 extern "C" {
     pub fn dds_get_topic(entity: dds_entity_t) -> dds_entity_t;
 }
+extern "C" {
+    pub fn dds_get_entity_sertype(
+        entity: dds_entity_t,
+        sertype: *mut *const ddsi_sertype,
+    ) -> dds_return_t;
+}
 extern "C" {
     pub fn dds_get_matched_subscriptions(
         writer: dds_entity_t,
@@ -2903,6 +3436,14 @@ extern "C" {
         ih: dds_instance_handle_t,
     ) -> *mut dds_builtintopic_endpoint_t;
 }
+extern "C" {
+    pub fn dds_builtintopic_free_endpoint(builtintopic_endpoint: *mut dds_builtintopic_endpoint_t);
+}
+extern "C" {
+    pub fn dds_builtintopic_free_participant(
+        builtintopic_participant: *mut dds_builtintopic_participant_t,
+    );
+}
 extern "C" {
     pub fn dds_assert_liveliness(entity: dds_entity_t) -> dds_return_t;
 }
@@ -6455,6 +6996,13 @@ extern "C" {
 extern "C" {
     pub fn ddsi_serdata_removeref(serdata: *mut ddsi_serdata);
 }
+extern "C" {
+    pub fn ddsrs_copy_fragchain(
+        fragchain: *const nn_rdata,
+        size: size_t,
+        dst: *mut ::std::os::raw::c_uchar,
+    );
+}
 pub const BUILTIN_TOPIC_DCPSPARTICIPANT: ::std::os::raw::c_int = 2147418113;
 pub const BUILTIN_TOPIC_DCPSTOPIC: ::std::os::raw::c_int = 2147418114;
 pub const BUILTIN_TOPIC_DCPSPUBLICATION: ::std::os::raw::c_int = 2147418115;
@@ -0,0 +1,236 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Guard conditions for waking up waitsets and tasks from other threads.
+//!
+//! A guard condition is triggered by hand. Attached to a waitset it wakes
+//! up `dds_waitset_wait`, and [`GuardCondition::triggered`] is a future that
+//! completes once the condition is triggered, so the same handle can stop
+//! both a waitset loop and an async task.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use crate::{
+    dds_attach_t, dds_create_guardcondition, dds_entity_t, dds_read_guardcondition,
+    dds_set_guardcondition, dds_take_guardcondition, dds_waitset_attach, dds_waitset_detach,
+    DDSError, DdsEntity,
+};
+
+/// The wakers of pending futures, one per future so that a dropped future
+/// takes its waker with it
+#[derive(Default)]
+struct Wakers {
+    next_key: u64,
+    waiting: BTreeMap<u64, Waker>,
+}
+
+impl Wakers {
+    /// Store `waker` under `key`, assigning the key on the first call
+    fn register(&mut self, key: &mut Option<u64>, waker: &Waker) {
+        let key = *key.get_or_insert_with(|| {
+            self.next_key += 1;
+            self.next_key
+        });
+        match self.waiting.get(&key) {
+            Some(w) if w.will_wake(waker) => {}
+            _ => drop(self.waiting.insert(key, waker.clone())),
+        }
+    }
+
+    fn remove(&mut self, key: u64) {
+        self.waiting.remove(&key);
+    }
+
+    fn take(&mut self) -> Vec<Waker> {
+        std::mem::take(&mut self.waiting).into_values().collect()
+    }
+}
+
+struct Shared {
+    entity: DdsEntity,
+    wakers: Mutex<Wakers>,
+}
+
+/// A handle to a guard condition. Clones refer to the same condition and
+/// can be moved to other threads.
+#[derive(Clone)]
+pub struct GuardCondition {
+    shared: Arc<Shared>,
+}
+
+impl GuardCondition {
+    /// Create a guard condition owned by `owner`, a participant or domain.
+    /// It is deleted together with its owner.
+    pub fn new(owner: &DdsEntity) -> Result<Self, DDSError> {
+        let entity = DDSError::check(unsafe { dds_create_guardcondition(owner.0) })?;
+        Ok(GuardCondition {
+            shared: Arc::new(Shared {
+                entity: DdsEntity(entity as dds_entity_t),
+                wakers: Mutex::new(Wakers::default()),
+            }),
+        })
+    }
+
+    pub fn entity(&self) -> &DdsEntity {
+        &self.shared.entity
+    }
+
+    /// Set or clear the trigger. Setting it wakes up waitsets the condition
+    /// is attached to and pending [`triggered`](Self::triggered) futures.
+    pub fn set(&self, triggered: bool) -> Result<(), DDSError> {
+        DDSError::check(unsafe { dds_set_guardcondition(self.shared.entity.0, triggered) })?;
+        if triggered {
+            let wakers = self.lock_wakers().take();
+            wakers.into_iter().for_each(Waker::wake);
+        }
+        Ok(())
+    }
+
+    /// Trigger the condition
+    pub fn trigger(&self) -> Result<(), DDSError> {
+        self.set(true)
+    }
+
+    /// Whether the condition is triggered, leaving it as it is
+    pub fn is_triggered(&self) -> Result<bool, DDSError> {
+        let mut triggered = false;
+        DDSError::check(unsafe { dds_read_guardcondition(self.shared.entity.0, &mut triggered) })?;
+        Ok(triggered)
+    }
+
+    /// Whether the condition was triggered, clearing the trigger
+    pub fn take(&self) -> Result<bool, DDSError> {
+        let mut triggered = false;
+        DDSError::check(unsafe { dds_take_guardcondition(self.shared.entity.0, &mut triggered) })?;
+        Ok(triggered)
+    }
+
+    /// Attach the condition to a waitset
+    pub fn attach(&self, waitset: &DdsEntity, x: dds_attach_t) -> Result<(), DDSError> {
+        let ret = unsafe { dds_waitset_attach(waitset.0, self.shared.entity.0, x) };
+        DDSError::check(ret).map(|_| ())
+    }
+
+    /// Detach the condition from a waitset
+    pub fn detach(&self, waitset: &DdsEntity) -> Result<(), DDSError> {
+        let ret = unsafe { dds_waitset_detach(waitset.0, self.shared.entity.0) };
+        DDSError::check(ret).map(|_| ())
+    }
+
+    /// A future that completes once the condition is triggered. The trigger
+    /// is left set; use [`take`](Self::take) to clear it. Only triggers made
+    /// through a `GuardCondition` handle wake the future.
+    pub fn triggered(&self) -> Triggered<'_> {
+        Triggered {
+            condition: self,
+            key: None,
+        }
+    }
+
+    /// Delete the condition. Other handles to it fail from then on.
+    pub fn delete(self) -> Result<(), DDSError> {
+        self.shared.entity.clone().delete()
+    }
+
+    fn lock_wakers(&self) -> std::sync::MutexGuard<'_, Wakers> {
+        self.shared.wakers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Future returned by [`GuardCondition::triggered`]
+#[must_use = "futures do nothing unless polled"]
+pub struct Triggered<'a> {
+    condition: &'a GuardCondition,
+    /// Where the waker is kept, once polled
+    key: Option<u64>,
+}
+
+impl<'a> Triggered<'a> {
+    fn unregister(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.condition.lock_wakers().remove(key);
+        }
+    }
+}
+
+impl<'a> Future for Triggered<'a> {
+    type Output = Result<(), DDSError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // register before checking so a trigger in between is not missed
+        let this = &mut *self;
+        this.condition
+            .lock_wakers()
+            .register(&mut this.key, cx.waker());
+        let ready = match this.condition.is_triggered() {
+            Ok(true) => Ok(()),
+            Ok(false) => return Poll::Pending,
+            Err(e) => Err(e),
+        };
+        this.unregister();
+        Poll::Ready(ready)
+    }
+}
+
+impl<'a> Drop for Triggered<'a> {
+    fn drop(&mut self) {
+        self.unregister();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::Wake;
+
+    struct Counter(AtomicUsize);
+
+    impl Wake for Counter {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn each_future_keeps_one_waker() {
+        let counter = Arc::new(Counter(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let mut wakers = Wakers::default();
+        let (mut a, mut b) = (None, None);
+        for _ in 0..3 {
+            wakers.register(&mut a, &waker);
+        }
+        wakers.register(&mut b, &waker);
+        assert_eq!(wakers.waiting.len(), 2);
+
+        // a future that goes away takes its waker with it
+        wakers.remove(b.unwrap());
+        assert_eq!(wakers.waiting.len(), 1);
+
+        wakers.take().into_iter().for_each(Waker::wake);
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+        assert!(wakers.waiting.is_empty());
+
+        // polled again after the wake up, under the same key
+        wakers.register(&mut a, &waker);
+        assert_eq!(wakers.waiting.keys().collect::<Vec<_>>(), vec![&a.unwrap()]);
+    }
+}
@@ -0,0 +1,318 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! One GUID type for all of Cyclone's GUID representations.
+//!
+//! `dds_guid_t` (and the builtin topic keys) hold the 16 bytes in network
+//! order, `ddsi_guid` holds four `u32`s in host order. `Guid` keeps the
+//! network order bytes, so the derived `Ord` matches the numeric order of
+//! the four words. The text form is the one Cyclone uses in its traces.
+
+use std::convert::TryInto;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{
+    dds_get_guid, dds_guid_t, ddsi_entityid_t, ddsi_guid, ddsi_guid_prefix_t, iceoryx_header,
+    DDSError, DdsEntity,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Guid([u8; 16]);
+
+/// Who defined the entity id, from the top two bits of the kind byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EntitySource {
+    User,
+    Vendor,
+    Builtin,
+    Unknown,
+}
+
+/// Entity kind, from the low six bits of the kind byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EntityKind {
+    Unknown,
+    Participant,
+    WriterWithKey,
+    WriterNoKey,
+    ReaderNoKey,
+    ReaderWithKey,
+    WriterGroup,
+    ReaderGroup,
+    Other(u8),
+}
+
+impl Guid {
+    pub const fn from_bytes(bytes: [u8; 16]) -> Self {
+        Guid(bytes)
+    }
+
+    /// The bytes in network order
+    pub const fn as_bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+
+    pub fn from_parts(prefix: ddsi_guid_prefix_t, entityid: ddsi_entityid_t) -> Self {
+        let (prefix, entityid) = unsafe { (prefix.u, entityid.u) };
+        Guid::from_words([prefix[0], prefix[1], prefix[2], entityid])
+    }
+
+    fn from_words(words: [u32; 4]) -> Self {
+        let mut bytes = [0u8; 16];
+        for (chunk, word) in bytes.chunks_mut(4).zip(words.iter()) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        Guid(bytes)
+    }
+
+    fn words(&self) -> [u32; 4] {
+        let word = |i: usize| u32::from_be_bytes(self.0[i * 4..i * 4 + 4].try_into().unwrap());
+        [word(0), word(1), word(2), word(3)]
+    }
+
+    /// The participant part of the GUID
+    pub fn prefix(&self) -> [u8; 12] {
+        self.0[..12].try_into().unwrap()
+    }
+
+    pub fn prefix_raw(&self) -> ddsi_guid_prefix_t {
+        let w = self.words();
+        ddsi_guid_prefix_t {
+            u: [w[0], w[1], w[2]],
+        }
+    }
+
+    pub fn entity_id(&self) -> u32 {
+        self.words()[3]
+    }
+
+    pub fn entity_id_raw(&self) -> ddsi_entityid_t {
+        ddsi_entityid_t {
+            u: self.entity_id(),
+        }
+    }
+
+    /// Store the GUID as the writer GUID of a shared memory sample
+    pub fn set_in_header(&self, header: &mut iceoryx_header) {
+        header.guid = (*self).into();
+    }
+
+    /// True if both GUIDs belong to the same participant
+    pub fn same_participant(&self, other: &Guid) -> bool {
+        self.0[..12] == other.0[..12]
+    }
+
+    pub fn entity_source(&self) -> EntitySource {
+        match self.0[15] & 0xc0 {
+            0x00 => EntitySource::User,
+            0x40 => EntitySource::Vendor,
+            0xc0 => EntitySource::Builtin,
+            _ => EntitySource::Unknown,
+        }
+    }
+
+    pub fn entity_kind(&self) -> EntityKind {
+        match self.0[15] & 0x3f {
+            0x00 => EntityKind::Unknown,
+            0x01 => EntityKind::Participant,
+            0x02 => EntityKind::WriterWithKey,
+            0x03 => EntityKind::WriterNoKey,
+            0x04 => EntityKind::ReaderNoKey,
+            0x07 => EntityKind::ReaderWithKey,
+            0x08 => EntityKind::WriterGroup,
+            0x09 => EntityKind::ReaderGroup,
+            other => EntityKind::Other(other),
+        }
+    }
+}
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let w = self.words();
+        write!(f, "{:x}:{:x}:{:x}:{:x}", w[0], w[1], w[2], w[3])
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuidParseError(String);
+
+impl fmt::Display for GuidParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid GUID \"{}\"", self.0)
+    }
+}
+
+impl std::error::Error for GuidParseError {}
+
+impl FromStr for Guid {
+    type Err = GuidParseError;
+
+    /// Parses four colon separated hex words of at most 8 digits each
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || GuidParseError(s.to_owned());
+        let mut words = [0u32; 4];
+        let mut parts = s.trim().split(':');
+        for word in words.iter_mut() {
+            let part = parts.next().ok_or_else(err)?;
+            // from_str_radix also takes a sign, only hex digits are a word
+            if part.is_empty() || part.len() > 8 || !part.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(err());
+            }
+            *word = u32::from_str_radix(part, 16).map_err(|_| err())?;
+        }
+        if parts.next().is_some() {
+            return Err(err());
+        }
+        Ok(Guid::from_words(words))
+    }
+}
+
+impl From<[u8; 16]> for Guid {
+    fn from(bytes: [u8; 16]) -> Self {
+        Guid(bytes)
+    }
+}
+
+impl From<Guid> for [u8; 16] {
+    fn from(guid: Guid) -> Self {
+        guid.0
+    }
+}
+
+impl From<dds_guid_t> for Guid {
+    fn from(guid: dds_guid_t) -> Self {
+        Guid(guid.v)
+    }
+}
+
+impl From<Guid> for dds_guid_t {
+    fn from(guid: Guid) -> Self {
+        dds_guid_t { v: guid.0 }
+    }
+}
+
+impl From<ddsi_guid> for Guid {
+    fn from(guid: ddsi_guid) -> Self {
+        Guid::from_parts(guid.prefix, guid.entityid)
+    }
+}
+
+impl From<Guid> for ddsi_guid {
+    fn from(guid: Guid) -> Self {
+        ddsi_guid {
+            prefix: guid.prefix_raw(),
+            entityid: guid.entity_id_raw(),
+        }
+    }
+}
+
+impl From<Guid> for ddsi_guid_prefix_t {
+    fn from(guid: Guid) -> Self {
+        guid.prefix_raw()
+    }
+}
+
+impl From<Guid> for ddsi_entityid_t {
+    fn from(guid: Guid) -> Self {
+        guid.entity_id_raw()
+    }
+}
+
+/// The writer GUID of a shared memory sample
+impl From<&iceoryx_header> for Guid {
+    fn from(header: &iceoryx_header) -> Self {
+        header.guid.into()
+    }
+}
+
+impl DdsEntity {
+    pub fn guid(&self) -> Result<Guid, DDSError> {
+        let mut guid = dds_guid_t::default();
+        DDSError::check(unsafe { dds_get_guid(self.0, &mut guid) })?;
+        Ok(guid.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BYTES: [u8; 16] = [
+        0x01, 0x10, 0x2f, 0x3a, 0x00, 0x00, 0x00, 0x07, 0xde, 0xad, 0xbe, 0xef, 0x00, 0x00, 0x12,
+        0x07,
+    ];
+
+    #[test]
+    fn text_form_round_trips() {
+        let guid = Guid::from_bytes(BYTES);
+        assert_eq!(guid.to_string(), "1102f3a:7:deadbeef:1207");
+        assert_eq!("1102f3a:7:deadbeef:1207".parse::<Guid>(), Ok(guid));
+        assert_eq!(" 1102F3A:7:DEADBEEF:1207 ".parse::<Guid>(), Ok(guid));
+    }
+
+    #[test]
+    fn only_hex_words_parse() {
+        for s in &[
+            "",
+            "1:2:3",
+            "1:2:3:4:5",
+            "1::3:4",
+            "+1:2:3:4",
+            "1:-2:3:4",
+            "1:2:3:x4",
+            "123456789:2:3:4",
+        ] {
+            assert!(s.parse::<Guid>().is_err(), "{:?} parsed", s);
+        }
+    }
+
+    #[test]
+    fn cyclone_representations_round_trip() {
+        let guid = Guid::from_bytes(BYTES);
+        assert_eq!(Guid::from(dds_guid_t::from(guid)), guid);
+        assert_eq!(Guid::from(ddsi_guid::from(guid)), guid);
+        assert_eq!(
+            Guid::from_parts(guid.into(), guid.into()),
+            guid,
+            "prefix and entity id"
+        );
+        assert_eq!(unsafe { ddsi_entityid_t::from(guid).u }, 0x1207);
+        assert_eq!(
+            unsafe { ddsi_guid_prefix_t::from(guid).u },
+            [0x0110_2f3a, 7, 0xdead_beef]
+        );
+
+        let mut header: iceoryx_header = unsafe { std::mem::zeroed() };
+        guid.set_in_header(&mut header);
+        assert_eq!(Guid::from(&header), guid);
+    }
+
+    #[test]
+    fn entity_ids_are_decoded() {
+        let guid = Guid::from_bytes(BYTES);
+        assert_eq!(guid.entity_kind(), EntityKind::ReaderWithKey);
+        assert_eq!(guid.entity_source(), EntitySource::User);
+        assert_eq!(guid.prefix(), BYTES[..12]);
+        let mut other = BYTES;
+        other[15] = 0xc1;
+        let other = Guid::from_bytes(other);
+        assert!(guid.same_participant(&other));
+        assert_eq!(other.entity_kind(), EntityKind::Participant);
+        assert_eq!(other.entity_source(), EntitySource::Builtin);
+        assert!(guid < other);
+    }
+}
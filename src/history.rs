@@ -0,0 +1,282 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Late joiners: historical data for readers created after the writers.
+//!
+//! A reader only receives data written before it existed when both sides
+//! are at least TRANSIENT_LOCAL and RELIABLE, and the reader keeps enough
+//! history. [`DdsQos::state_topic`] sets all of that up; use it for the
+//! topic, the writer and the reader.
+
+use std::fmt;
+use std::ptr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{
+    dds_create_readcondition, dds_create_waitset, dds_delete, dds_durability_kind, dds_entity_t,
+    dds_get_participant, dds_history_kind, dds_reliability_kind, dds_waitset_attach,
+    dds_waitset_wait, DDSError, DdsDuration, DdsEntity, DdsQos, Guid, State, StateMask,
+};
+
+/// How often matched writers are looked at while waiting for one that keeps
+/// history
+const MATCH_POLL: Duration = Duration::from_millis(10);
+
+/// A QoS setting that prevents historical data from being delivered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QosMismatch {
+    /// Durability is VOLATILE, nothing is kept for late joiners
+    Volatile,
+    /// Historical data is only sent over reliable connections
+    BestEffort,
+    /// KEEP_LAST with a depth below what the caller asked for
+    HistoryDepth { depth: i32, required: i32 },
+    /// `max_samples_per_instance` is below the history depth
+    ResourceLimits {
+        max_samples_per_instance: i32,
+        depth: i32,
+    },
+}
+
+impl fmt::Display for QosMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QosMismatch::Volatile => write!(f, "durability is VOLATILE"),
+            QosMismatch::BestEffort => write!(f, "reliability is BEST_EFFORT"),
+            QosMismatch::HistoryDepth { depth, required } => {
+                write!(f, "history depth {} is below {}", depth, required)
+            }
+            QosMismatch::ResourceLimits {
+                max_samples_per_instance,
+                depth,
+            } => write!(
+                f,
+                "max_samples_per_instance {} is below history depth {}",
+                max_samples_per_instance, depth
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HistoryError {
+    Dds(DDSError),
+    /// The reader cannot receive historical data with its QoS
+    Mismatch(Vec<QosMismatch>),
+    /// A matched writer does not keep the historical data asked for
+    WriterMismatch {
+        writer: Guid,
+        mismatches: Vec<QosMismatch>,
+    },
+    /// Historical data did not arrive in time
+    Timeout,
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HistoryError::Dds(e) => write!(f, "{}", e),
+            HistoryError::Mismatch(mismatches) => {
+                write!(f, "QoS does not support late joiners:")?;
+                for m in mismatches {
+                    write!(f, " {};", m)?;
+                }
+                Ok(())
+            }
+            HistoryError::WriterMismatch { writer, mismatches } => {
+                write!(
+                    f,
+                    "writer {} does not keep history for late joiners:",
+                    writer
+                )?;
+                for m in mismatches {
+                    write!(f, " {};", m)?;
+                }
+                Ok(())
+            }
+            HistoryError::Timeout => write!(f, "timeout waiting for historical data"),
+        }
+    }
+}
+
+impl std::error::Error for HistoryError {}
+
+impl From<DDSError> for HistoryError {
+    fn from(e: DDSError) -> Self {
+        HistoryError::Dds(e)
+    }
+}
+
+impl DdsQos {
+    /// TRANSIENT_LOCAL, RELIABLE, KEEP_LAST(1): the last value of every
+    /// instance is kept and delivered to late joining readers.
+    pub fn state_topic() -> Self {
+        Self::state_topic_with_depth(1)
+    }
+
+    /// Like [`state_topic`](Self::state_topic) but keeping the last `depth`
+    /// samples of every instance
+    pub fn state_topic_with_depth(depth: i32) -> Self {
+        let mut qos = DdsQos::new();
+        qos.durability(dds_durability_kind::DDS_DURABILITY_TRANSIENT_LOCAL)
+            .reliability(
+                dds_reliability_kind::DDS_RELIABILITY_RELIABLE,
+                DdsDuration::from_millis(100),
+            )
+            .history(dds_history_kind::DDS_HISTORY_KEEP_LAST, depth)
+            .resource_limits(-1, -1, -1);
+        qos
+    }
+
+    /// Check that an entity with this QoS takes part in delivering (or
+    /// receiving) at least `depth` historical samples per instance. Unset
+    /// policies are taken to be the DDS defaults for readers.
+    pub fn check_late_joiner(&self, depth: i32) -> Result<(), Vec<QosMismatch>> {
+        let mut mismatches = Vec::new();
+
+        let durability = self
+            .get_durability()
+            .unwrap_or(dds_durability_kind::DDS_DURABILITY_VOLATILE);
+        if durability == dds_durability_kind::DDS_DURABILITY_VOLATILE {
+            mismatches.push(QosMismatch::Volatile);
+        }
+
+        let reliability = self
+            .get_reliability()
+            .map(|(kind, _)| kind)
+            .unwrap_or(dds_reliability_kind::DDS_RELIABILITY_BEST_EFFORT);
+        if reliability == dds_reliability_kind::DDS_RELIABILITY_BEST_EFFORT {
+            mismatches.push(QosMismatch::BestEffort);
+        }
+
+        let history = self
+            .get_history()
+            .unwrap_or((dds_history_kind::DDS_HISTORY_KEEP_LAST, 1));
+        let kept = match history {
+            (dds_history_kind::DDS_HISTORY_KEEP_LAST, d) => {
+                if d < depth {
+                    mismatches.push(QosMismatch::HistoryDepth {
+                        depth: d,
+                        required: depth,
+                    });
+                }
+                d
+            }
+            (dds_history_kind::DDS_HISTORY_KEEP_ALL, _) => depth,
+        };
+
+        if let Some((_, _, per_instance)) = self.get_resource_limits() {
+            if per_instance >= 0 && per_instance < kept {
+                mismatches.push(QosMismatch::ResourceLimits {
+                    max_samples_per_instance: per_instance,
+                    depth: kept,
+                });
+            }
+        }
+
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(mismatches)
+        }
+    }
+}
+
+impl DdsEntity {
+    /// Check that this reader and every writer it is matched with support
+    /// delivering at least `depth` historical samples per instance. The
+    /// writers are checked with the QoS they announced in discovery.
+    pub fn check_late_joiner(&self, depth: i32) -> Result<(), HistoryError> {
+        DdsQos::of(self)?
+            .check_late_joiner(depth)
+            .map_err(HistoryError::Mismatch)?;
+        for writer in self.matched_writers()? {
+            if let Err(mismatches) = writer.qos.check_late_joiner(depth) {
+                return Err(HistoryError::WriterMismatch {
+                    writer: writer.guid,
+                    mismatches,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait until this reader is matched with a writer that keeps history
+    /// for late joiners and unread data has arrived. Fails with `Mismatch`
+    /// without waiting if the reader's QoS means no historical data would
+    /// ever arrive.
+    ///
+    /// Cyclone does not tell when a writer has sent all of its history, so
+    /// this returns with the first unread sample after such a writer
+    /// matched. If that writer has not written anything the wait times out.
+    pub fn wait_for_history<D: Into<DdsDuration>>(&self, timeout: D) -> Result<(), HistoryError> {
+        DdsQos::of(self)?
+            .check_late_joiner(1)
+            .map_err(HistoryError::Mismatch)?;
+        let deadline = timeout
+            .into()
+            .to_std()
+            .and_then(|d| Instant::now().checked_add(d));
+        let remaining = || deadline.map(|d| d.saturating_duration_since(Instant::now()));
+
+        loop {
+            let matched = self.matched_writers()?;
+            if matched.iter().any(|w| w.qos.check_late_joiner(1).is_ok()) {
+                break;
+            }
+            match remaining() {
+                Some(left) if left == Duration::default() => return Err(HistoryError::Timeout),
+                Some(left) => thread::sleep(left.min(MATCH_POLL)),
+                None => thread::sleep(MATCH_POLL),
+            }
+        }
+
+        let mask = *StateMask::from(State::DdsNotReadSampleState)
+            | *StateMask::from(State::DdsAnyViewState)
+            | *StateMask::from(State::DdsAnyInstanceState);
+        let condition = DDSError::check(unsafe { dds_create_readcondition(self.0, mask) })?;
+        let result = unsafe { wait_for_condition(condition, remaining()) };
+        unsafe { dds_delete(condition) };
+        match result {
+            Ok(0) => Err(HistoryError::Timeout),
+            Ok(_) => Ok(()),
+            Err(e) => Err(HistoryError::Dds(e)),
+        }
+    }
+}
+
+/// Wait until `condition` triggers; returns the number of triggered entities
+unsafe fn wait_for_condition(
+    condition: dds_entity_t,
+    timeout: Option<Duration>,
+) -> Result<i32, DDSError> {
+    let waitset = DDSError::check(dds_create_waitset(dds_get_participant(condition)))?;
+    let timeout = match timeout {
+        Some(timeout) => DdsDuration::Finite(timeout),
+        None => DdsDuration::Infinite,
+    };
+    let result = DDSError::check(dds_waitset_attach(waitset, condition, 0)).and_then(|_| {
+        DDSError::check(dds_waitset_wait(
+            waitset,
+            ptr::null_mut(),
+            0,
+            timeout.into(),
+        ))
+    });
+    dds_delete(waitset);
+    result
+}
@@ -25,11 +25,76 @@ include!("generated.rs");
 pub mod dds_error;
 pub use dds_error::DDSError;
 
+mod entity_state;
+pub mod topic_filter;
+pub use topic_filter::Topic;
+pub mod query_condition;
+pub mod sample_layout;
+pub mod filter_expression;
+pub mod dds_time;
+pub use dds_time::{DdsDuration, DdsTime};
+pub mod qos;
+pub use qos::DdsQos;
+pub mod status;
+pub use status::{StatusKind, StatusKinds};
+pub mod guid;
+pub use guid::Guid;
+pub mod domain_config;
+pub use domain_config::DomainConfig;
+pub mod logging;
+pub mod statistics;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod coherent;
+pub mod acks;
+pub mod history;
+pub mod matched;
+pub mod guard_condition;
+pub use guard_condition::GuardCondition;
+#[cfg(feature = "backend")]
+pub mod backend;
+pub mod rpc;
+pub mod raw;
+pub mod discovery;
+pub mod recording;
+pub mod mcap;
+pub mod dynamic;
+pub mod ros2;
+#[cfg(feature = "serde")]
+pub mod cdr;
+#[cfg(feature = "serde")]
+pub mod serde_topic;
+#[cfg(feature = "testing")]
+pub mod testing;
+
 //// some macros we need to use in Rust
 pub const DDS_FREE_KEY_BIT: u32 =  0x01;
 pub const DDS_FREE_CONTENTS_BIT:u32 =  0x02;
 pub const DDS_FREE_ALL_BIT:u32 =  0x04;
 
+pub const DDS_TOPIC_NO_OPTIMIZE: u32 = 1 << 0;
+pub const DDS_TOPIC_FIXED_KEY: u32 = 1 << 1;
+pub const DDS_TOPIC_CONTAINS_UNION: u32 = 1 << 2;
+pub const DDS_TOPIC_FIXED_SIZE: u32 = 1 << 4;
+
+pub const DDS_NSECS_IN_SEC: i64 = 1_000_000_000;
+pub const DDS_NSECS_IN_MSEC: i64 = 1_000_000;
+pub const DDS_NSECS_IN_USEC: i64 = 1_000;
+pub const DDS_INFINITY: dds_duration_t = i64::MAX;
+pub const DDS_NEVER: dds_time_t = i64::MAX;
+/// The domain from the Cyclone configuration
+pub const DDS_DOMAIN_DEFAULT: dds_domainid_t = 0xffff_ffff;
+
+pub const fn DDS_SECS(n: i64) -> dds_duration_t {
+    n * DDS_NSECS_IN_SEC
+}
+pub const fn DDS_MSECS(n: i64) -> dds_duration_t {
+    n * DDS_NSECS_IN_MSEC
+}
+pub const fn DDS_USECS(n: i64) -> dds_duration_t {
+    n * DDS_NSECS_IN_USEC
+}
+
 
 #[derive(Clone,PartialEq)]
 pub struct DdsEntity(dds_entity_t);
@@ -41,6 +106,30 @@ impl DdsEntity {
     pub unsafe fn entity(&self) -> dds_entity_t {
         self.0
     }
+
+    /// Delete the entity and its children, releasing any Rust state
+    /// (filters, conditions) that was attached to them.
+    pub fn delete(self) -> Result<(), DDSError> {
+        let ret = unsafe { dds_delete(self.0) };
+        entity_state::purge();
+        DDSError::check(ret).map(|_| ())
+    }
+
+    /// The direct children of this entity
+    pub fn children(&self) -> Result<Vec<DdsEntity>, DDSError> {
+        loop {
+            let n = DDSError::check(unsafe { dds_get_children(self.0, std::ptr::null_mut(), 0) })?;
+            let mut children = vec![0; n as usize];
+            let m = DDSError::check(unsafe {
+                dds_get_children(self.0, children.as_mut_ptr(), children.len() as size_t)
+            })?;
+            // retry if children were added in between
+            if m <= n {
+                children.truncate(m as usize);
+                return Ok(children.into_iter().map(DdsEntity).collect());
+            }
+        }
+    }
 }
 
 pub mod builtin_entity {
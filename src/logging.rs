@@ -0,0 +1,401 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Redirect Cyclone's log and trace output.
+//!
+//! Cyclone has two sinks: the log sink gets errors, warnings and info
+//! messages, the trace sink gets everything enabled in the log mask. The
+//! sinks are process wide. With the `log` or `tracing` feature enabled,
+//! [`forward_to_log`] / [`forward_to_tracing`] install sinks that turn each
+//! record into a `log` record or `tracing` event with target `cyclonedds`.
+
+use std::ffi::{c_void, CStr};
+use std::os::raw::c_char;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::sync::{Arc, RwLock};
+
+use bitmask::bitmask;
+use lazy_static::lazy_static;
+
+use crate::domain_config::ShmLogLevel;
+use crate::{
+    dds_log_data_t, dds_set_log_mask, dds_set_log_sink, dds_set_trace_sink, ddsi_shm_loglevel,
+    ddsi_shm_loglevel_DDSI_SHM_DEBUG, ddsi_shm_loglevel_DDSI_SHM_ERROR,
+    ddsi_shm_loglevel_DDSI_SHM_FATAL, ddsi_shm_loglevel_DDSI_SHM_INFO,
+    ddsi_shm_loglevel_DDSI_SHM_OFF, ddsi_shm_loglevel_DDSI_SHM_VERBOSE,
+    ddsi_shm_loglevel_DDSI_SHM_WARN,
+};
+
+bitmask! {
+    pub mask LogCategories : u32 where flags LogCategory {
+        DdsLcFatal = 1,
+        DdsLcError = 2,
+        DdsLcWarning = 4,
+        DdsLcInfo = 8,
+        DdsLcConfig = 16,
+        DdsLcDiscovery = 32,
+        DdsLcData = 64,
+        DdsLcTrace = 128,
+        DdsLcRadmin = 256,
+        DdsLcTiming = 512,
+        DdsLcTraffic = 1024,
+        DdsLcTopic = 2048,
+        DdsLcTcp = 4096,
+        DdsLcPlist = 8192,
+        DdsLcWhc = 16384,
+        DdsLcThrottle = 32768,
+        DdsLcRhc = 65536,
+        DdsLcContent = 131072,
+        DdsLcShm = 262144,
+        DdsLcAll = 524287,
+    }
+}
+
+impl LogCategories {
+    /// What Cyclone enables by default
+    pub fn default_mask() -> Self {
+        LogCategory::DdsLcFatal | LogCategory::DdsLcError | LogCategory::DdsLcWarning
+    }
+
+    pub fn from_raw(mask: u32) -> Self {
+        LogCategories { mask }
+    }
+}
+
+/// Coarse severity of a record, derived from its category
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    fn from_priority(priority: u32) -> Self {
+        if priority & (LogCategory::DdsLcFatal as u32 | LogCategory::DdsLcError as u32) != 0 {
+            LogLevel::Error
+        } else if priority & LogCategory::DdsLcWarning as u32 != 0 {
+            LogLevel::Warn
+        } else if priority & LogCategory::DdsLcInfo as u32 != 0 {
+            LogLevel::Info
+        } else if priority & LogCategory::DdsLcConfig as u32 != 0 {
+            LogLevel::Debug
+        } else {
+            LogLevel::Trace
+        }
+    }
+}
+
+/// A decoded `dds_log_data_t`
+#[derive(Debug, Clone, Copy)]
+pub struct LogRecord<'a> {
+    /// The category bit the message was logged under
+    pub priority: u32,
+    pub level: LogLevel,
+    /// The domain, `u32::MAX` when not tied to a domain
+    pub domain_id: u32,
+    pub file: &'a str,
+    pub line: u32,
+    pub function: &'a str,
+    /// The message without Cyclone's timestamp/thread header
+    pub message: &'a str,
+    /// The message including the header
+    pub full_message: &'a str,
+}
+
+impl<'a> LogRecord<'a> {
+    /// # Safety
+    /// `data` must point to a record handed to a log or trace sink.
+    unsafe fn from_raw(data: &'a dds_log_data_t) -> Self {
+        let cstr = |p: *const c_char| {
+            if p.is_null() {
+                ""
+            } else {
+                CStr::from_ptr(p).to_str().unwrap_or("")
+            }
+        };
+        let full = if data.message.is_null() {
+            ""
+        } else {
+            let bytes = std::slice::from_raw_parts(data.message as *const u8, data.size as usize);
+            std::str::from_utf8(bytes).unwrap_or("")
+        };
+        let full = full.trim_end_matches(['\n', '\0']);
+        let message = full.get(data.hdrsize as usize..).unwrap_or(full);
+        LogRecord {
+            priority: data.priority,
+            level: LogLevel::from_priority(data.priority),
+            domain_id: data.domid,
+            file: cstr(data.file),
+            line: data.line,
+            function: cstr(data.function),
+            message,
+            full_message: full,
+        }
+    }
+}
+
+type Sink = Arc<dyn Fn(&LogRecord) + Send + Sync>;
+
+lazy_static! {
+    static ref LOG_SINK: RwLock<Option<Sink>> = RwLock::new(None);
+    static ref TRACE_SINK: RwLock<Option<Sink>> = RwLock::new(None);
+}
+
+unsafe extern "C" fn sink_trampoline(arg: *mut c_void, data: *const dds_log_data_t) {
+    if arg.is_null() || data.is_null() {
+        return;
+    }
+    let slot = &*(arg as *const RwLock<Option<Sink>>);
+    // run the sink without holding the lock, it may log or replace itself
+    let sink = slot.read().unwrap_or_else(|e| e.into_inner()).clone();
+    if let Some(sink) = sink {
+        let record = LogRecord::from_raw(&*data);
+        let _ = catch_unwind(AssertUnwindSafe(|| sink(&record)));
+    }
+}
+
+fn install(slot: &'static RwLock<Option<Sink>>, sink: Option<Sink>, trace: bool) {
+    let install = sink.is_some();
+    *slot.write().unwrap_or_else(|e| e.into_inner()) = sink;
+    let arg = slot as *const RwLock<Option<Sink>> as *mut c_void;
+    unsafe {
+        match (install, trace) {
+            (true, false) => dds_set_log_sink(Some(sink_trampoline), arg),
+            (true, true) => dds_set_trace_sink(Some(sink_trampoline), arg),
+            (false, false) => dds_set_log_sink(None, ptr::null_mut()),
+            (false, true) => dds_set_trace_sink(None, ptr::null_mut()),
+        }
+    }
+}
+
+/// Enable the given categories for logging and tracing
+pub fn set_log_mask<M: Into<LogCategories>>(mask: M) {
+    unsafe { dds_set_log_mask(*mask.into()) }
+}
+
+/// Send log messages (fatal, error, warning, info) to a closure instead of
+/// the log file. A panicking sink drops the record.
+pub fn set_log_sink<F>(sink: F)
+where
+    F: Fn(&LogRecord) + Send + Sync + 'static,
+{
+    install(&LOG_SINK, Some(Arc::new(sink)), false)
+}
+
+/// Restore the default log output
+pub fn clear_log_sink() {
+    install(&LOG_SINK, None, false)
+}
+
+/// Send trace messages (every category enabled in the log mask) to a
+/// closure instead of the trace file.
+pub fn set_trace_sink<F>(sink: F)
+where
+    F: Fn(&LogRecord) + Send + Sync + 'static,
+{
+    install(&TRACE_SINK, Some(Arc::new(sink)), true)
+}
+
+/// Restore the default trace output
+pub fn clear_trace_sink() {
+    install(&TRACE_SINK, None, true)
+}
+
+impl ShmLogLevel {
+    pub fn to_raw(self) -> ddsi_shm_loglevel {
+        match self {
+            ShmLogLevel::Off => ddsi_shm_loglevel_DDSI_SHM_OFF,
+            ShmLogLevel::Fatal => ddsi_shm_loglevel_DDSI_SHM_FATAL,
+            ShmLogLevel::Error => ddsi_shm_loglevel_DDSI_SHM_ERROR,
+            ShmLogLevel::Warn => ddsi_shm_loglevel_DDSI_SHM_WARN,
+            ShmLogLevel::Info => ddsi_shm_loglevel_DDSI_SHM_INFO,
+            ShmLogLevel::Debug => ddsi_shm_loglevel_DDSI_SHM_DEBUG,
+            ShmLogLevel::Verbose => ddsi_shm_loglevel_DDSI_SHM_VERBOSE,
+        }
+    }
+
+    /// The iceoryx log level that matches a Cyclone log mask
+    pub fn for_categories(mask: LogCategories) -> Self {
+        if mask.contains(LogCategory::DdsLcShm) {
+            ShmLogLevel::Verbose
+        } else if mask.intersects(LogCategory::DdsLcConfig | LogCategory::DdsLcTrace) {
+            ShmLogLevel::Debug
+        } else if mask.contains(LogCategory::DdsLcInfo) {
+            ShmLogLevel::Info
+        } else if mask.contains(LogCategory::DdsLcWarning) {
+            ShmLogLevel::Warn
+        } else if mask.contains(LogCategory::DdsLcError) {
+            ShmLogLevel::Error
+        } else if mask.contains(LogCategory::DdsLcFatal) {
+            ShmLogLevel::Fatal
+        } else {
+            ShmLogLevel::Off
+        }
+    }
+}
+
+/// Set the log level of the iceoryx shared memory transport
+#[cfg(feature = "shm")]
+pub fn set_shm_log_level(level: ShmLogLevel) {
+    unsafe { crate::shm_set_loglevel(level.to_raw()) }
+}
+
+/// Set the Cyclone log mask and, with shared memory support, the matching
+/// iceoryx log level
+pub fn configure<M: Into<LogCategories>>(mask: M) {
+    let mask = mask.into();
+    set_log_mask(mask);
+    #[cfg(feature = "shm")]
+    set_shm_log_level(ShmLogLevel::for_categories(mask));
+}
+
+#[cfg(feature = "log")]
+fn emit_log(record: &LogRecord) {
+    let level = match record.level {
+        LogLevel::Error => log::Level::Error,
+        LogLevel::Warn => log::Level::Warn,
+        LogLevel::Info => log::Level::Info,
+        LogLevel::Debug => log::Level::Debug,
+        LogLevel::Trace => log::Level::Trace,
+    };
+    log::logger().log(
+        &log::Record::builder()
+            .args(format_args!("{}", record.message))
+            .level(level)
+            .target("cyclonedds")
+            .module_path_static(Some("cyclonedds"))
+            .file(Some(record.file))
+            .line(Some(record.line))
+            .build(),
+    );
+}
+
+/// Forward Cyclone's log and trace output for `mask` to the `log` crate
+#[cfg(feature = "log")]
+pub fn forward_to_log<M: Into<LogCategories>>(mask: M) {
+    configure(mask);
+    set_log_sink(emit_log);
+    set_trace_sink(|record| {
+        // log messages also reach the trace sink, don't report them twice
+        if record.level > LogLevel::Info {
+            emit_log(record)
+        }
+    });
+}
+
+#[cfg(feature = "tracing")]
+fn emit_tracing(record: &LogRecord) {
+    macro_rules! emit {
+        ($level:expr) => {
+            tracing::event!(
+                target: "cyclonedds",
+                $level,
+                domain_id = record.domain_id,
+                file = record.file,
+                line = record.line,
+                function = record.function,
+                "{}",
+                record.message
+            )
+        };
+    }
+    match record.level {
+        LogLevel::Error => emit!(tracing::Level::ERROR),
+        LogLevel::Warn => emit!(tracing::Level::WARN),
+        LogLevel::Info => emit!(tracing::Level::INFO),
+        LogLevel::Debug => emit!(tracing::Level::DEBUG),
+        LogLevel::Trace => emit!(tracing::Level::TRACE),
+    }
+}
+
+/// Forward Cyclone's log and trace output for `mask` to `tracing` events
+#[cfg(feature = "tracing")]
+pub fn forward_to_tracing<M: Into<LogCategories>>(mask: M) {
+    configure(mask);
+    set_log_sink(emit_tracing);
+    set_trace_sink(|record| {
+        if record.level > LogLevel::Info {
+            emit_tracing(record)
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn raw_record(priority: u32, message: &[u8], hdrsize: usize) -> dds_log_data_t {
+        dds_log_data_t {
+            priority,
+            domid: 0,
+            file: b"q_init.c\0".as_ptr() as *const c_char,
+            line: 42,
+            function: b"rtps_init\0".as_ptr() as *const c_char,
+            message: message.as_ptr() as *const c_char,
+            size: message.len() as _,
+            hdrsize: hdrsize as _,
+        }
+    }
+
+    #[test]
+    fn records_are_decoded() {
+        let data = raw_record(
+            LogCategory::DdsLcWarning as u32,
+            b"1.5/main: no interface\n\0",
+            10,
+        );
+        let record = unsafe { LogRecord::from_raw(&data) };
+        assert_eq!(record.level, LogLevel::Warn);
+        assert_eq!(record.message, "no interface");
+        assert_eq!(record.full_message, "1.5/main: no interface");
+        assert_eq!(record.file, "q_init.c");
+        assert_eq!(record.function, "rtps_init");
+        assert_eq!(record.line, 42);
+        assert_eq!(
+            LogLevel::from_priority(LogCategory::DdsLcDiscovery as u32),
+            LogLevel::Trace
+        );
+        assert_eq!(
+            LogLevel::from_priority(LogCategory::DdsLcFatal as u32),
+            LogLevel::Error
+        );
+    }
+
+    #[test]
+    fn sinks_run_without_holding_the_lock() {
+        static SLOT: RwLock<Option<Sink>> = RwLock::new(None);
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        *SLOT.write().unwrap() = Some(Arc::new(|_: &LogRecord| {
+            CALLS.fetch_add(1, Ordering::SeqCst);
+            // replacing the sink from inside it must not deadlock
+            *SLOT.write().unwrap() = None;
+        }));
+        let data = raw_record(LogCategory::DdsLcInfo as u32, b"hello", 0);
+        let arg = &SLOT as *const RwLock<Option<Sink>> as *mut c_void;
+        unsafe {
+            sink_trampoline(arg, &data);
+            sink_trampoline(arg, &data);
+        }
+        assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+        assert!(SLOT.read().unwrap().is_none());
+    }
+}
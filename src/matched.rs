@@ -0,0 +1,122 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Readers matched with a writer and writers matched with a reader.
+
+use std::ffi::CStr;
+use std::os::raw::c_char;
+
+use crate::{
+    dds_builtintopic_endpoint_t, dds_builtintopic_free_endpoint, dds_copy_qos,
+    dds_get_matched_publication_data, dds_get_matched_subscription_data, dds_instance_handle_t,
+    DDSError, DdsEntity, DdsQos, Guid,
+};
+
+/// An owned copy of a `dds_builtintopic_endpoint_t`
+#[derive(Debug, Clone)]
+pub struct MatchedEndpoint {
+    /// The local instance handle of the remote endpoint
+    pub instance_handle: dds_instance_handle_t,
+    pub guid: Guid,
+    pub participant_guid: Guid,
+    pub participant_instance_handle: dds_instance_handle_t,
+    pub topic_name: String,
+    pub type_name: String,
+    pub qos: DdsQos,
+}
+
+unsafe fn string(s: *const c_char) -> String {
+    if s.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(s).to_string_lossy().into_owned()
+    }
+}
+
+impl MatchedEndpoint {
+    /// Copy the contents of a builtin endpoint, such as a sample of the
+    /// DCPSPublication or DCPSSubscription builtin topics
+    ///
+    /// # Safety
+    /// `endpoint` must point to a valid `dds_builtintopic_endpoint_t`.
+    pub unsafe fn from_builtin(
+        instance_handle: dds_instance_handle_t,
+        endpoint: &dds_builtintopic_endpoint_t,
+    ) -> Self {
+        let mut qos = DdsQos::new();
+        if !endpoint.qos.is_null() {
+            dds_copy_qos(qos.as_mut_ptr(), endpoint.qos);
+        }
+        MatchedEndpoint {
+            instance_handle,
+            guid: endpoint.key.into(),
+            participant_guid: endpoint.participant_key.into(),
+            participant_instance_handle: endpoint.participant_instance_handle,
+            topic_name: string(endpoint.topic_name),
+            type_name: string(endpoint.type_name),
+            qos,
+        }
+    }
+
+    /// Take over an endpoint returned by `dds_get_matched_*_data`
+    unsafe fn from_owned(
+        instance_handle: dds_instance_handle_t,
+        endpoint: *mut dds_builtintopic_endpoint_t,
+    ) -> Option<Self> {
+        if endpoint.is_null() {
+            return None;
+        }
+        let matched = MatchedEndpoint::from_builtin(instance_handle, &*endpoint);
+        dds_builtintopic_free_endpoint(endpoint);
+        Some(matched)
+    }
+}
+
+impl DdsEntity {
+    /// The readers matched with this writer. Readers that go away while
+    /// the list is collected are left out.
+    pub fn matched_readers(&self) -> Result<Vec<MatchedEndpoint>, DDSError> {
+        Ok(self
+            .matched_subscriptions()?
+            .into_iter()
+            .filter_map(|ih| unsafe {
+                MatchedEndpoint::from_owned(ih, dds_get_matched_subscription_data(self.0, ih))
+            })
+            .collect())
+    }
+
+    /// A single reader matched with this writer
+    pub fn matched_reader(&self, ih: dds_instance_handle_t) -> Option<MatchedEndpoint> {
+        unsafe { MatchedEndpoint::from_owned(ih, dds_get_matched_subscription_data(self.0, ih)) }
+    }
+
+    /// A single writer matched with this reader, e.g. the one a sample came
+    /// from (`dds_sample_info_t::publication_handle`)
+    pub fn matched_writer(&self, ih: dds_instance_handle_t) -> Option<MatchedEndpoint> {
+        unsafe { MatchedEndpoint::from_owned(ih, dds_get_matched_publication_data(self.0, ih)) }
+    }
+
+    /// The writers matched with this reader
+    pub fn matched_writers(&self) -> Result<Vec<MatchedEndpoint>, DDSError> {
+        Ok(self
+            .matched_publications()?
+            .into_iter()
+            .filter_map(|ih| unsafe {
+                MatchedEndpoint::from_owned(ih, dds_get_matched_publication_data(self.0, ih))
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,403 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Writing DDS samples as [MCAP](https://mcap.dev).
+//!
+//! Messages are the serialized samples with their encapsulation header and
+//! message encoding `cdr`, which is what Foxglove and the ROS 2 tools read.
+//! Each channel is a topic and type name pair with its metadata (so the same
+//! topic in different partitions gives different channels); the type gets a
+//! schema record whose text is either supplied by the caller (an IDL or
+//! `.msg` file) or generated from a [`SampleLayout`] with [`omgidl_schema`],
+//! or from a discovered type with [`omgidl_schema_for_type`].
+//!
+//! Only the data section is written: no chunks, no summary and no CRCs,
+//! which readers accept (they fall back to scanning the file).
+
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fmt;
+use std::io::{self, Write};
+
+use crate::dynamic::{DynKind, DynStruct, TypeMeta};
+use crate::sample_layout::{FieldKind, SampleLayout};
+use crate::{dds_topic_descriptor_t, DdsTime};
+
+const MAGIC: &[u8; 8] = b"\x89MCAP0\r\n";
+
+const OP_HEADER: u8 = 0x01;
+const OP_FOOTER: u8 = 0x02;
+const OP_SCHEMA: u8 = 0x03;
+const OP_CHANNEL: u8 = 0x04;
+const OP_MESSAGE: u8 = 0x05;
+const OP_DATA_END: u8 = 0x0f;
+
+/// Message encoding of serialized DDS samples
+pub const MESSAGE_ENCODING_CDR: &str = "cdr";
+/// Schema encoding of IDL schemas
+pub const SCHEMA_ENCODING_OMGIDL: &str = "omgidl";
+/// Schema encoding of ROS 2 `.msg` schemas
+pub const SCHEMA_ENCODING_ROS2MSG: &str = "ros2msg";
+
+/// The schema of a channel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schema {
+    /// Fully qualified type name, e.g. `geometry::Point` for IDL or
+    /// `geometry_msgs/msg/Point` for ROS 2
+    pub name: String,
+    /// [`SCHEMA_ENCODING_OMGIDL`] or [`SCHEMA_ENCODING_ROS2MSG`]
+    pub encoding: String,
+    pub data: String,
+}
+
+/// Topic name, type name and metadata
+type ChannelKey = (String, String, Vec<(String, String)>);
+
+pub struct McapWriter<W: Write> {
+    out: W,
+    schemas: HashMap<(String, String), u16>,
+    channels: HashMap<ChannelKey, u16>,
+    sequences: HashMap<u16, u32>,
+}
+
+impl<W: Write> McapWriter<W> {
+    /// Start an MCAP file, `library` is recorded in the header
+    pub fn new(mut out: W, library: &str) -> io::Result<Self> {
+        out.write_all(MAGIC)?;
+        let mut body = Vec::new();
+        put_str(&mut body, "");
+        put_str(&mut body, library);
+        put_record(&mut out, OP_HEADER, &body)?;
+        Ok(McapWriter {
+            out,
+            schemas: HashMap::new(),
+            channels: HashMap::new(),
+            sequences: HashMap::new(),
+        })
+    }
+
+    /// The channel for a topic and type with `metadata`, writing the schema
+    /// and channel records the first time the combination is seen. `schema`
+    /// is only used then.
+    pub fn channel(
+        &mut self,
+        topic_name: &str,
+        type_name: &str,
+        schema: Option<&Schema>,
+        metadata: &[(&str, &str)],
+    ) -> io::Result<u16> {
+        let key = (
+            topic_name.to_owned(),
+            type_name.to_owned(),
+            metadata
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        );
+        if let Some(id) = self.channels.get(&key) {
+            return Ok(*id);
+        }
+        let schema_id = match schema {
+            Some(schema) => self.schema(schema)?,
+            None => 0,
+        };
+        let id = self.channels.len() as u16;
+        let mut body = Vec::new();
+        body.extend_from_slice(&id.to_le_bytes());
+        body.extend_from_slice(&schema_id.to_le_bytes());
+        put_str(&mut body, topic_name);
+        put_str(&mut body, MESSAGE_ENCODING_CDR);
+        let mut map = Vec::new();
+        put_str(&mut map, "type_name");
+        put_str(&mut map, type_name);
+        for (k, v) in metadata {
+            put_str(&mut map, k);
+            put_str(&mut map, v);
+        }
+        put_bytes(&mut body, &map);
+        put_record(&mut self.out, OP_CHANNEL, &body)?;
+        self.channels.insert(key, id);
+        Ok(id)
+    }
+
+    fn schema(&mut self, schema: &Schema) -> io::Result<u16> {
+        let key = (schema.name.clone(), schema.encoding.clone());
+        if let Some(id) = self.schemas.get(&key) {
+            return Ok(*id);
+        }
+        // 0 means "no schema"
+        let id = self.schemas.len() as u16 + 1;
+        let mut body = Vec::new();
+        body.extend_from_slice(&id.to_le_bytes());
+        put_str(&mut body, &schema.name);
+        put_str(&mut body, &schema.encoding);
+        put_bytes(&mut body, schema.data.as_bytes());
+        put_record(&mut self.out, OP_SCHEMA, &body)?;
+        self.schemas.insert(key, id);
+        Ok(id)
+    }
+
+    /// Write a serialized sample (including its encapsulation header) to a
+    /// channel returned by [`McapWriter::channel`]
+    pub fn write_message(
+        &mut self,
+        channel: u16,
+        log_time: DdsTime,
+        publish_time: DdsTime,
+        data: &[u8],
+    ) -> io::Result<()> {
+        let sequence = self.sequences.entry(channel).or_insert(0);
+        *sequence = sequence.wrapping_add(1);
+        let mut body = Vec::with_capacity(22 + data.len());
+        body.extend_from_slice(&channel.to_le_bytes());
+        body.extend_from_slice(&sequence.to_le_bytes());
+        body.extend_from_slice(&(log_time.as_nanos().max(0) as u64).to_le_bytes());
+        body.extend_from_slice(&(publish_time.as_nanos().max(0) as u64).to_le_bytes());
+        body.extend_from_slice(data);
+        put_record(&mut self.out, OP_MESSAGE, &body)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Close the data section and write the footer. A file that is not
+    /// finished can still be recovered by most readers.
+    pub fn finish(mut self) -> io::Result<W> {
+        put_record(&mut self.out, OP_DATA_END, &0u32.to_le_bytes())?;
+        put_record(&mut self.out, OP_FOOTER, &[0u8; 20])?;
+        self.out.write_all(MAGIC)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+fn put_record<W: Write>(out: &mut W, op: u8, body: &[u8]) -> io::Result<()> {
+    out.write_all(&[op])?;
+    out.write_all(&(body.len() as u64).to_le_bytes())?;
+    out.write_all(body)
+}
+
+fn put_bytes(buf: &mut Vec<u8>, b: &[u8]) {
+    buf.extend_from_slice(&(b.len() as u32).to_le_bytes());
+    buf.extend_from_slice(b);
+}
+
+fn put_str(buf: &mut Vec<u8>, s: &str) {
+    put_bytes(buf, s.as_bytes())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaError {
+    /// The member's type is not described by the layout (sequences, arrays,
+    /// unions, bitmasks and external members)
+    Unsupported(String),
+    /// The ops of a discovered type cannot be interpreted
+    Type(String),
+}
+
+impl std::error::Error for SchemaError {}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SchemaError::Unsupported(name) => {
+                write!(f, "type of member {} cannot be described", name)
+            }
+            SchemaError::Type(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+fn idl_type(kind: FieldKind) -> Option<String> {
+    let t = match kind {
+        FieldKind::Bool => "boolean",
+        FieldKind::Int8 => "int8",
+        FieldKind::UInt8 => "uint8",
+        FieldKind::Int16 => "short",
+        FieldKind::UInt16 => "unsigned short",
+        FieldKind::Int32 => "long",
+        // the enumerators are not in the ops, the wire representation is the same
+        FieldKind::UInt32 | FieldKind::Enum => "unsigned long",
+        FieldKind::Int64 => "long long",
+        FieldKind::UInt64 => "unsigned long long",
+        FieldKind::Float32 => "float",
+        FieldKind::Float64 => "double",
+        FieldKind::String => "string",
+        FieldKind::BoundedString(n) => return Some(format!("string<{}>", n.saturating_sub(1))),
+        FieldKind::Unsupported => return None,
+    };
+    Some(t.to_owned())
+}
+
+/// Emit `struct name { ... };` for the members `fields` (paths relative to
+/// this struct), with nested structs emitted before it
+fn idl_struct(
+    name: &str,
+    fields: &[(Vec<&str>, FieldKind, bool, &str)],
+    indent: &str,
+    out: &mut String,
+) -> Result<(), SchemaError> {
+    let mut members = String::new();
+    let mut i = 0;
+    while i < fields.len() {
+        let (path, kind, key, full) = &fields[i];
+        if path.len() == 1 {
+            let t = idl_type(*kind).ok_or_else(|| SchemaError::Unsupported(full.to_string()))?;
+            let key = if *key { "@key " } else { "" };
+            members.push_str(&format!("{}  {}{} {};\n", indent, key, t, path[0]));
+            i += 1;
+        } else {
+            // all consecutive members under the same prefix form one nested struct
+            let head = path[0];
+            let end = fields[i..]
+                .iter()
+                .position(|(p, ..)| p.len() == 1 || p[0] != head)
+                .map_or(fields.len(), |n| i + n);
+            let nested: Vec<_> = fields[i..end]
+                .iter()
+                .map(|(p, k, key, full)| (p[1..].to_vec(), *k, *key, *full))
+                .collect();
+            let nested_name = format!("{}_{}", name, head);
+            idl_struct(&nested_name, &nested, indent, out)?;
+            members.push_str(&format!("{}  {} {};\n", indent, nested_name, head));
+            i = end;
+        }
+    }
+    out.push_str(&format!(
+        "{}struct {} {{\n{}{}}};\n",
+        indent, name, members, indent
+    ));
+    Ok(())
+}
+
+/// An `omgidl` schema for the type of `layout`, with modules taken from the
+/// scoped type name. Members of nested structs (`position.x`) become members
+/// of generated structs named after the parent (`Type_position`).
+pub fn omgidl_schema(layout: &SampleLayout) -> Result<Schema, SchemaError> {
+    let scopes: Vec<&str> = layout.type_name().split("::").collect();
+    let (name, modules) = scopes.split_last().unwrap_or((&"", &[]));
+    let fields: Vec<_> = layout
+        .fields()
+        .iter()
+        .map(|f| {
+            (
+                f.name.split('.').collect::<Vec<_>>(),
+                f.kind,
+                f.key,
+                f.name.as_str(),
+            )
+        })
+        .collect();
+
+    let mut data = String::new();
+    for (depth, module) in modules.iter().enumerate() {
+        data.push_str(&format!("{}module {} {{\n", "  ".repeat(depth), module));
+    }
+    idl_struct(name, &fields, &"  ".repeat(modules.len()), &mut data)?;
+    for depth in (0..modules.len()).rev() {
+        data.push_str(&format!("{}}};\n", "  ".repeat(depth)));
+    }
+    Ok(Schema {
+        name: layout.type_name().to_owned(),
+        encoding: SCHEMA_ENCODING_OMGIDL.to_owned(),
+        data,
+    })
+}
+
+/// Positional names for the leaf members of `s`: `m0`, `m1.m0`, ...
+fn positional_names(s: &DynStruct, prefix: &str, names: &mut Vec<String>) {
+    for (i, m) in s.members.iter().enumerate() {
+        let name = format!("{}m{}", prefix, i);
+        match &m.kind {
+            DynKind::Struct(nested) => positional_names(nested, &format!("{}.", name), names),
+            _ => names.push(name),
+        }
+    }
+}
+
+/// An `omgidl` schema for a type found by type discovery, built from its
+/// ops with [`omgidl_schema`]. The ops carry no member names, so members
+/// are named by position (`m0`, `m1`, ...).
+pub fn omgidl_schema_for_type(meta: &TypeMeta) -> Result<Schema, SchemaError> {
+    let ty = meta
+        .dyn_type()
+        .map_err(|e| SchemaError::Type(e.to_string()))?;
+    let mut names = Vec::new();
+    positional_names(ty.root(), "", &mut names);
+    let type_name =
+        CString::new(meta.type_name.as_str()).map_err(|e| SchemaError::Type(e.to_string()))?;
+    let mut desc: dds_topic_descriptor_t = unsafe { std::mem::zeroed() };
+    desc.m_typename = type_name.as_ptr();
+    // every instruction takes at least one word
+    desc.m_nops = meta.ops.len() as u32;
+    desc.m_ops = meta.ops.as_ptr();
+    let layout = unsafe { SampleLayout::from_descriptor(&desc, &names) }
+        .map_err(|e| SchemaError::Type(e.to_string()))?;
+    omgidl_schema(&layout)
+}
+
+/// The ROS 2 name of a DDS type name: `pkg::msg::dds_::Type_` becomes
+/// `pkg/msg/Type`. Other names are returned with `::` replaced by `/`.
+pub fn ros2msg_schema_name(type_name: &str) -> String {
+    crate::ros2::ros_type_name(type_name).unwrap_or_else(|| type_name.replace("::", "/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_layout::tests::{EXT, INT32, RTS, STRING};
+    use crate::sample_layout::DDS_OP_FLAG_KEY;
+
+    #[test]
+    fn discovered_types_get_positional_schemas() {
+        // struct { @key int32; struct { int32; string; }; }
+        let meta = TypeMeta {
+            type_name: "pkg::Sample".to_owned(),
+            ops: vec![
+                INT32 | DDS_OP_FLAG_KEY,
+                0,
+                EXT,
+                8,
+                (3 << 16) | 4,
+                RTS,
+                INT32,
+                0,
+                STRING,
+                8,
+                RTS,
+            ],
+            ..TypeMeta::default()
+        };
+        let schema = omgidl_schema_for_type(&meta).unwrap();
+        assert_eq!(schema.name, "pkg::Sample");
+        assert_eq!(
+            schema.data,
+            "module pkg {\n  struct Sample_m1 {\n    long m0;\n    string m1;\n  };\n  \
+             struct Sample {\n    @key long m0;\n    Sample_m1 m1;\n  };\n};\n"
+        );
+    }
+
+    #[test]
+    fn channels_differ_by_metadata() {
+        let mut w = McapWriter::new(Vec::new(), "test").unwrap();
+        let a = w.channel("t", "T", None, &[("partitions", "a")]).unwrap();
+        let b = w.channel("t", "T", None, &[("partitions", "b")]).unwrap();
+        let again = w.channel("t", "T", None, &[("partitions", "a")]).unwrap();
+        assert_ne!(a, b);
+        assert_eq!(a, again);
+    }
+}
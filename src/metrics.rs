@@ -0,0 +1,300 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Export endpoint statistics in the Prometheus text format.
+//!
+//! [`StatisticsExporter`] walks the entity tree below a participant (or
+//! domain) at a fixed interval and keeps the rendered text of all reader and
+//! writer counters, ready to be served on a `/metrics` endpoint. Every
+//! series carries the endpoint GUID, whether it is a reader or a writer and
+//! its topic name as labels. The statistics handles of the endpoints are
+//! kept in a [`StatisticsCache`] between rounds and only refreshed.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::os::raw::c_char;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::guid::{EntityKind, Guid};
+use crate::statistics::{Statistics, StatisticsHandle};
+use crate::{dds_entity_t, dds_get_name, dds_get_topic, DdsEntity};
+
+const PREFIX: &str = "cyclonedds_";
+
+/// All readers and writers at or below `root`
+pub fn endpoints(root: &DdsEntity) -> Vec<DdsEntity> {
+    let mut found = Vec::new();
+    for entity in descendants(root) {
+        if StatisticsHandle::new(&entity).is_ok() {
+            found.push(entity);
+        }
+    }
+    found
+}
+
+/// `root` and every entity below it
+fn descendants(root: &DdsEntity) -> Vec<DdsEntity> {
+    let mut found = Vec::new();
+    let mut stack = vec![root.clone()];
+    while let Some(entity) = stack.pop() {
+        if let Ok(children) = entity.children() {
+            stack.extend(children);
+        }
+        found.push(entity);
+    }
+    found
+}
+
+fn topic_name(endpoint: &DdsEntity) -> String {
+    let mut buf = [0 as c_char; 256];
+    unsafe {
+        let topic = dds_get_topic(endpoint.0);
+        if topic < 0 || dds_get_name(topic, buf.as_mut_ptr(), buf.len() as _) < 0 {
+            return String::new();
+        }
+        std::ffi::CStr::from_ptr(buf.as_ptr())
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn labels(guid: Option<Guid>, topic: &str) -> String {
+    let kind = match guid.map(|g| g.entity_kind()) {
+        Some(EntityKind::WriterWithKey) | Some(EntityKind::WriterNoKey) => "writer",
+        Some(EntityKind::ReaderWithKey) | Some(EntityKind::ReaderNoKey) => "reader",
+        _ => "unknown",
+    };
+    let guid = guid.map(|g| g.to_string()).unwrap_or_default();
+    format!(
+        "guid=\"{}\",kind=\"{}\",topic=\"{}\"",
+        guid,
+        kind,
+        escape_label(topic)
+    )
+}
+
+fn endpoint_labels(endpoint: &DdsEntity) -> String {
+    labels(endpoint.guid().ok(), &topic_name(endpoint))
+}
+
+/// Render (labels, statistics) pairs. All counters only ever grow, so they
+/// are counters named with a `_total` suffix.
+fn render<'a, I>(rows: I) -> String
+where
+    I: IntoIterator<Item = (&'a str, &'a Statistics)>,
+{
+    // metric name -> series lines
+    let mut metrics: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (labels, stats) in rows {
+        for stat in stats.iter() {
+            metrics
+                .entry(format!("{}{}_total", PREFIX, stat.name))
+                .or_default()
+                .push(format!("{{{}}} {}", labels, stat.value.as_u64()));
+        }
+    }
+
+    let mut out = String::new();
+    for (name, series) in metrics {
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        for line in series {
+            let _ = writeln!(out, "{}{}", name, line);
+        }
+    }
+    out
+}
+
+/// Render the statistics of the given endpoints. Endpoints that were
+/// deleted in the meantime are skipped.
+pub fn prometheus_text(endpoints: &[DdsEntity]) -> String {
+    let rows: Vec<(String, Statistics)> = endpoints
+        .iter()
+        .filter_map(|endpoint| Some((endpoint_labels(endpoint), endpoint.statistics().ok()?)))
+        .collect();
+    render(rows.iter().map(|(labels, stats)| (labels.as_str(), stats)))
+}
+
+struct CachedEndpoint {
+    handle: StatisticsHandle,
+    labels: String,
+}
+
+/// Statistics handles and labels of the endpoints below an entity, kept
+/// from one scrape to the next. Entities without statistics are
+/// remembered too, so they are not probed again.
+#[derive(Default)]
+pub struct StatisticsCache {
+    entities: HashMap<dds_entity_t, Option<CachedEndpoint>>,
+}
+
+impl StatisticsCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Refresh the statistics of every endpoint at or below `root` and
+    /// render them. Handles of endpoints that are gone are released.
+    pub fn scrape(&mut self, root: &DdsEntity) -> String {
+        let mut entities = HashMap::new();
+        for entity in descendants(root) {
+            let cached = match self.entities.remove(&entity.0) {
+                Some(cached) => cached,
+                None => StatisticsHandle::new(&entity)
+                    .ok()
+                    .map(|handle| CachedEndpoint {
+                        handle,
+                        labels: endpoint_labels(&entity),
+                    }),
+            };
+            entities.insert(entity.0, cached);
+        }
+        self.entities = entities;
+
+        let mut rows = Vec::new();
+        for endpoint in self.entities.values_mut().flatten() {
+            if endpoint.handle.refresh().is_ok() {
+                rows.push((endpoint.labels.as_str(), endpoint.handle.snapshot()));
+            }
+        }
+        render(rows.iter().map(|(labels, stats)| (*labels, stats)))
+    }
+
+    /// Number of endpoints with a cached statistics handle
+    pub fn len(&self) -> usize {
+        self.entities.values().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Periodically renders the statistics of every endpoint below an entity
+pub struct StatisticsExporter {
+    text: Arc<Mutex<String>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl StatisticsExporter {
+    /// Start collecting every `interval`. The entity tree is walked again
+    /// on each round so endpoints created later are picked up.
+    pub fn start(root: DdsEntity, interval: Duration) -> Self {
+        let text = Arc::new(Mutex::new(String::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let text = text.clone();
+            let stop = stop.clone();
+            thread::Builder::new()
+                .name("cdds-metrics".to_owned())
+                .spawn(move || {
+                    let mut cache = StatisticsCache::new();
+                    while !stop.load(Ordering::Relaxed) {
+                        let rendered = cache.scrape(&root);
+                        *text.lock().unwrap_or_else(|e| e.into_inner()) = rendered;
+                        thread::park_timeout(interval);
+                    }
+                })
+                .expect("cannot spawn metrics thread")
+        };
+        StatisticsExporter {
+            text,
+            stop,
+            thread: Some(thread),
+        }
+    }
+    /// The text from the last collection round
+    pub fn render(&self) -> String {
+        self.text.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for StatisticsExporter {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::statistics::{StatValue, Statistic};
+    use crate::DdsTime;
+
+    fn stats(values: &[(&str, StatValue)]) -> Statistics {
+        let values = values
+            .iter()
+            .map(|(name, value)| Statistic {
+                name: (*name).to_owned(),
+                value: *value,
+            })
+            .collect();
+        Statistics::new(1, DdsTime::At(0), values)
+    }
+
+    #[test]
+    fn labels_name_the_endpoint() {
+        let writer: Guid = "1:2:3:102".parse().unwrap();
+        assert_eq!(
+            labels(Some(writer), "a \"b\"\\c\n"),
+            "guid=\"1:2:3:102\",kind=\"writer\",topic=\"a \\\"b\\\"\\\\c\\n\""
+        );
+        let reader: Guid = "1:2:3:107".parse().unwrap();
+        assert!(labels(Some(reader), "t").contains("kind=\"reader\""));
+        assert!(labels(None, "t").starts_with("guid=\"\",kind=\"unknown\""));
+    }
+
+    #[test]
+    fn counters_are_grouped_and_suffixed() {
+        let writer = stats(&[
+            ("rexmit_count", StatValue::U64(3)),
+            ("time_rexmit", StatValue::LengthTime(1000)),
+        ]);
+        let other = stats(&[("rexmit_count", StatValue::U32(5))]);
+        let text = render(vec![("w=\"1\"", &writer), ("w=\"2\"", &other)]);
+        assert_eq!(
+            text,
+            "# TYPE cyclonedds_rexmit_count_total counter\n\
+             cyclonedds_rexmit_count_total{w=\"1\"} 3\n\
+             cyclonedds_rexmit_count_total{w=\"2\"} 5\n\
+             # TYPE cyclonedds_time_rexmit_total counter\n\
+             cyclonedds_time_rexmit_total{w=\"1\"} 1000\n"
+        );
+        assert_eq!(render(Vec::new()), "");
+    }
+}
@@ -0,0 +1,483 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Owned `dds_qos_t` with typed setters and getters.
+//!
+//! Every policy that takes a time takes a [`DdsDuration`] (or anything that
+//! converts into one, such as `std::time::Duration`).
+
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::c_char;
+use std::ptr;
+
+use crate::*;
+
+pub struct DdsQos(*mut dds_qos_t);
+
+unsafe impl Send for DdsQos {}
+unsafe impl Sync for DdsQos {}
+
+impl DdsQos {
+    pub fn new() -> Self {
+        DdsQos(unsafe { dds_create_qos() })
+    }
+
+    /// Take ownership of a qos allocated by Cyclone
+    ///
+    /// # Safety
+    /// `qos` must come from `dds_create_qos` (or a Cyclone call that returns
+    /// an owned qos) and must not be freed by anyone else.
+    pub unsafe fn from_raw(qos: *mut dds_qos_t) -> Self {
+        DdsQos(qos)
+    }
+
+    /// The current qos of an entity
+    pub fn of(entity: &DdsEntity) -> Result<Self, DDSError> {
+        let qos = DdsQos::new();
+        DDSError::check(unsafe { dds_get_qos(entity.0, qos.0) })?;
+        Ok(qos)
+    }
+
+    /// Apply this qos to an entity. Only mutable policies can be changed
+    /// after the entity is created.
+    pub fn apply(&self, entity: &DdsEntity) -> Result<(), DDSError> {
+        DDSError::check(unsafe { dds_set_qos(entity.0, self.0) }).map(|_| ())
+    }
+
+    pub fn as_ptr(&self) -> *const dds_qos_t {
+        self.0
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut dds_qos_t {
+        self.0
+    }
+
+    pub fn into_raw(self) -> *mut dds_qos_t {
+        let qos = self.0;
+        std::mem::forget(self);
+        qos
+    }
+
+    pub fn reset(&mut self) -> &mut Self {
+        unsafe { dds_reset_qos(self.0) };
+        self
+    }
+
+    /// Copy the policies set in `other` that are not set in this qos
+    pub fn merge(&mut self, other: &DdsQos) -> &mut Self {
+        unsafe { dds_merge_qos(self.0, other.0) };
+        self
+    }
+
+    pub fn userdata(&mut self, value: &[u8]) -> &mut Self {
+        unsafe { dds_qset_userdata(self.0, value.as_ptr() as *const c_void, value.len() as _) };
+        self
+    }
+
+    pub fn topicdata(&mut self, value: &[u8]) -> &mut Self {
+        unsafe { dds_qset_topicdata(self.0, value.as_ptr() as *const c_void, value.len() as _) };
+        self
+    }
+
+    pub fn groupdata(&mut self, value: &[u8]) -> &mut Self {
+        unsafe { dds_qset_groupdata(self.0, value.as_ptr() as *const c_void, value.len() as _) };
+        self
+    }
+
+    pub fn durability(&mut self, kind: dds_durability_kind) -> &mut Self {
+        unsafe { dds_qset_durability(self.0, kind) };
+        self
+    }
+
+    pub fn history(&mut self, kind: dds_history_kind, depth: i32) -> &mut Self {
+        unsafe { dds_qset_history(self.0, kind, depth) };
+        self
+    }
+
+    pub fn resource_limits(
+        &mut self,
+        max_samples: i32,
+        max_instances: i32,
+        max_samples_per_instance: i32,
+    ) -> &mut Self {
+        unsafe {
+            dds_qset_resource_limits(self.0, max_samples, max_instances, max_samples_per_instance)
+        };
+        self
+    }
+
+    pub fn presentation(
+        &mut self,
+        access_scope: dds_presentation_access_scope_kind,
+        coherent_access: bool,
+        ordered_access: bool,
+    ) -> &mut Self {
+        unsafe { dds_qset_presentation(self.0, access_scope, coherent_access, ordered_access) };
+        self
+    }
+
+    pub fn lifespan<D: Into<DdsDuration>>(&mut self, lifespan: D) -> &mut Self {
+        unsafe { dds_qset_lifespan(self.0, lifespan.into().into()) };
+        self
+    }
+
+    pub fn deadline<D: Into<DdsDuration>>(&mut self, deadline: D) -> &mut Self {
+        unsafe { dds_qset_deadline(self.0, deadline.into().into()) };
+        self
+    }
+
+    pub fn latency_budget<D: Into<DdsDuration>>(&mut self, duration: D) -> &mut Self {
+        unsafe { dds_qset_latency_budget(self.0, duration.into().into()) };
+        self
+    }
+
+    pub fn ownership(&mut self, kind: dds_ownership_kind) -> &mut Self {
+        unsafe { dds_qset_ownership(self.0, kind) };
+        self
+    }
+
+    pub fn ownership_strength(&mut self, value: i32) -> &mut Self {
+        unsafe { dds_qset_ownership_strength(self.0, value) };
+        self
+    }
+
+    pub fn liveliness<D: Into<DdsDuration>>(
+        &mut self,
+        kind: dds_liveliness_kind,
+        lease_duration: D,
+    ) -> &mut Self {
+        unsafe { dds_qset_liveliness(self.0, kind, lease_duration.into().into()) };
+        self
+    }
+
+    pub fn time_based_filter<D: Into<DdsDuration>>(&mut self, minimum_separation: D) -> &mut Self {
+        unsafe { dds_qset_time_based_filter(self.0, minimum_separation.into().into()) };
+        self
+    }
+
+    /// Set the partitions. Names containing a NUL byte are skipped.
+    pub fn partition<S: AsRef<str>>(&mut self, names: &[S]) -> &mut Self {
+        let names: Vec<CString> = names
+            .iter()
+            .filter_map(|n| CString::new(n.as_ref()).ok())
+            .collect();
+        let mut ptrs: Vec<*const c_char> = names.iter().map(|n| n.as_ptr()).collect();
+        unsafe { dds_qset_partition(self.0, ptrs.len() as u32, ptrs.as_mut_ptr()) };
+        self
+    }
+
+    pub fn reliability<D: Into<DdsDuration>>(
+        &mut self,
+        kind: dds_reliability_kind,
+        max_blocking_time: D,
+    ) -> &mut Self {
+        unsafe { dds_qset_reliability(self.0, kind, max_blocking_time.into().into()) };
+        self
+    }
+
+    pub fn transport_priority(&mut self, value: i32) -> &mut Self {
+        unsafe { dds_qset_transport_priority(self.0, value) };
+        self
+    }
+
+    pub fn destination_order(&mut self, kind: dds_destination_order_kind) -> &mut Self {
+        unsafe { dds_qset_destination_order(self.0, kind) };
+        self
+    }
+
+    pub fn writer_data_lifecycle(&mut self, autodispose: bool) -> &mut Self {
+        unsafe { dds_qset_writer_data_lifecycle(self.0, autodispose) };
+        self
+    }
+
+    pub fn reader_data_lifecycle<D: Into<DdsDuration>, E: Into<DdsDuration>>(
+        &mut self,
+        autopurge_nowriter_samples_delay: D,
+        autopurge_disposed_samples_delay: E,
+    ) -> &mut Self {
+        unsafe {
+            dds_qset_reader_data_lifecycle(
+                self.0,
+                autopurge_nowriter_samples_delay.into().into(),
+                autopurge_disposed_samples_delay.into().into(),
+            )
+        };
+        self
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn durability_service<D: Into<DdsDuration>>(
+        &mut self,
+        service_cleanup_delay: D,
+        history_kind: dds_history_kind,
+        history_depth: i32,
+        max_samples: i32,
+        max_instances: i32,
+        max_samples_per_instance: i32,
+    ) -> &mut Self {
+        unsafe {
+            dds_qset_durability_service(
+                self.0,
+                service_cleanup_delay.into().into(),
+                history_kind,
+                history_depth,
+                max_samples,
+                max_instances,
+                max_samples_per_instance,
+            )
+        };
+        self
+    }
+
+    pub fn ignorelocal(&mut self, ignore: dds_ignorelocal_kind) -> &mut Self {
+        unsafe { dds_qset_ignorelocal(self.0, ignore) };
+        self
+    }
+
+    pub fn get_durability(&self) -> Option<dds_durability_kind> {
+        let mut kind = dds_durability_kind::DDS_DURABILITY_VOLATILE;
+        unsafe { dds_qget_durability(self.0, &mut kind) }.then_some(kind)
+    }
+
+    pub fn get_history(&self) -> Option<(dds_history_kind, i32)> {
+        let mut kind = dds_history_kind::DDS_HISTORY_KEEP_LAST;
+        let mut depth = 0;
+        unsafe { dds_qget_history(self.0, &mut kind, &mut depth) }.then_some((kind, depth))
+    }
+
+    /// (max_samples, max_instances, max_samples_per_instance)
+    pub fn get_resource_limits(&self) -> Option<(i32, i32, i32)> {
+        let (mut samples, mut instances, mut per_instance) = (0, 0, 0);
+        unsafe { dds_qget_resource_limits(self.0, &mut samples, &mut instances, &mut per_instance) }
+            .then_some((samples, instances, per_instance))
+    }
+
+    /// (access_scope, coherent_access, ordered_access)
+    pub fn get_presentation(&self) -> Option<(dds_presentation_access_scope_kind, bool, bool)> {
+        let mut scope = dds_presentation_access_scope_kind::DDS_PRESENTATION_INSTANCE;
+        let (mut coherent, mut ordered) = (false, false);
+        unsafe { dds_qget_presentation(self.0, &mut scope, &mut coherent, &mut ordered) }
+            .then_some((scope, coherent, ordered))
+    }
+
+    pub fn get_lifespan(&self) -> Option<DdsDuration> {
+        let mut d = 0;
+        unsafe { dds_qget_lifespan(self.0, &mut d) }.then(|| d.into())
+    }
+
+    pub fn get_deadline(&self) -> Option<DdsDuration> {
+        let mut d = 0;
+        unsafe { dds_qget_deadline(self.0, &mut d) }.then(|| d.into())
+    }
+
+    pub fn get_latency_budget(&self) -> Option<DdsDuration> {
+        let mut d = 0;
+        unsafe { dds_qget_latency_budget(self.0, &mut d) }.then(|| d.into())
+    }
+
+    pub fn get_ownership(&self) -> Option<dds_ownership_kind> {
+        let mut kind = dds_ownership_kind::DDS_OWNERSHIP_SHARED;
+        unsafe { dds_qget_ownership(self.0, &mut kind) }.then_some(kind)
+    }
+
+    pub fn get_ownership_strength(&self) -> Option<i32> {
+        let mut value = 0;
+        unsafe { dds_qget_ownership_strength(self.0, &mut value) }.then_some(value)
+    }
+
+    pub fn get_liveliness(&self) -> Option<(dds_liveliness_kind, DdsDuration)> {
+        let mut kind = dds_liveliness_kind::DDS_LIVELINESS_AUTOMATIC;
+        let mut lease = 0;
+        unsafe { dds_qget_liveliness(self.0, &mut kind, &mut lease) }.then(|| (kind, lease.into()))
+    }
+
+    pub fn get_time_based_filter(&self) -> Option<DdsDuration> {
+        let mut d = 0;
+        unsafe { dds_qget_time_based_filter(self.0, &mut d) }.then(|| d.into())
+    }
+
+    pub fn get_partition(&self) -> Option<Vec<String>> {
+        let mut n = 0u32;
+        let mut ps: *mut *mut c_char = ptr::null_mut();
+        if !unsafe { dds_qget_partition(self.0, &mut n, &mut ps) } {
+            return None;
+        }
+        let mut names = Vec::with_capacity(n as usize);
+        unsafe {
+            for i in 0..n as usize {
+                let name = *ps.add(i);
+                names.push(CStr::from_ptr(name).to_string_lossy().into_owned());
+                dds_free(name as *mut c_void);
+            }
+            dds_free(ps as *mut c_void);
+        }
+        Some(names)
+    }
+
+    pub fn get_reliability(&self) -> Option<(dds_reliability_kind, DdsDuration)> {
+        let mut kind = dds_reliability_kind::DDS_RELIABILITY_BEST_EFFORT;
+        let mut blocking = 0;
+        unsafe { dds_qget_reliability(self.0, &mut kind, &mut blocking) }
+            .then(|| (kind, blocking.into()))
+    }
+
+    pub fn get_transport_priority(&self) -> Option<i32> {
+        let mut value = 0;
+        unsafe { dds_qget_transport_priority(self.0, &mut value) }.then_some(value)
+    }
+
+    pub fn get_destination_order(&self) -> Option<dds_destination_order_kind> {
+        let mut kind = dds_destination_order_kind::DDS_DESTINATIONORDER_BY_RECEPTION_TIMESTAMP;
+        unsafe { dds_qget_destination_order(self.0, &mut kind) }.then_some(kind)
+    }
+
+    pub fn get_writer_data_lifecycle(&self) -> Option<bool> {
+        let mut autodispose = false;
+        unsafe { dds_qget_writer_data_lifecycle(self.0, &mut autodispose) }.then_some(autodispose)
+    }
+
+    /// (autopurge_nowriter_samples_delay, autopurge_disposed_samples_delay)
+    pub fn get_reader_data_lifecycle(&self) -> Option<(DdsDuration, DdsDuration)> {
+        let (mut nowriter, mut disposed) = (0, 0);
+        unsafe { dds_qget_reader_data_lifecycle(self.0, &mut nowriter, &mut disposed) }
+            .then(|| (nowriter.into(), disposed.into()))
+    }
+
+    pub fn get_ignorelocal(&self) -> Option<dds_ignorelocal_kind> {
+        let mut kind = dds_ignorelocal_kind::DDS_IGNORELOCAL_NONE;
+        unsafe { dds_qget_ignorelocal(self.0, &mut kind) }.then_some(kind)
+    }
+
+    /// (service_cleanup_delay, history_kind, history_depth, max_samples,
+    /// max_instances, max_samples_per_instance)
+    #[allow(clippy::type_complexity)]
+    pub fn get_durability_service(
+        &self,
+    ) -> Option<(DdsDuration, dds_history_kind, i32, i32, i32, i32)> {
+        let mut delay = 0;
+        let mut kind = dds_history_kind::DDS_HISTORY_KEEP_LAST;
+        let (mut depth, mut samples, mut instances, mut per_instance) = (0, 0, 0, 0);
+        unsafe {
+            dds_qget_durability_service(
+                self.0,
+                &mut delay,
+                &mut kind,
+                &mut depth,
+                &mut samples,
+                &mut instances,
+                &mut per_instance,
+            )
+        }
+        .then(|| (delay.into(), kind, depth, samples, instances, per_instance))
+    }
+
+    pub fn get_userdata(&self) -> Option<Vec<u8>> {
+        self.get_octets(dds_qget_userdata)
+    }
+
+    pub fn get_topicdata(&self) -> Option<Vec<u8>> {
+        self.get_octets(dds_qget_topicdata)
+    }
+
+    pub fn get_groupdata(&self) -> Option<Vec<u8>> {
+        self.get_octets(dds_qget_groupdata)
+    }
+
+    fn get_octets(
+        &self,
+        get: unsafe extern "C" fn(*const dds_qos_t, *mut *mut c_void, *mut size_t) -> bool,
+    ) -> Option<Vec<u8>> {
+        let mut value: *mut c_void = ptr::null_mut();
+        let mut sz = 0;
+        if !unsafe { get(self.0, &mut value, &mut sz) } {
+            return None;
+        }
+        let data = if value.is_null() {
+            Vec::new()
+        } else {
+            let data =
+                unsafe { std::slice::from_raw_parts(value as *const u8, sz as usize) }.to_vec();
+            unsafe { dds_free(value) };
+            data
+        };
+        Some(data)
+    }
+}
+
+impl Default for DdsQos {
+    fn default() -> Self {
+        DdsQos::new()
+    }
+}
+
+impl Clone for DdsQos {
+    fn clone(&self) -> Self {
+        let qos = DdsQos::new();
+        unsafe { dds_copy_qos(qos.0, self.0) };
+        qos
+    }
+}
+
+/// Lists the policies that are set
+impl std::fmt::Debug for DdsQos {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut s = f.debug_struct("DdsQos");
+        macro_rules! field {
+            ($name:literal, $get:ident) => {
+                if let Some(v) = self.$get() {
+                    s.field($name, &v);
+                }
+            };
+        }
+        field!("durability", get_durability);
+        field!("history", get_history);
+        field!("resource_limits", get_resource_limits);
+        field!("presentation", get_presentation);
+        field!("lifespan", get_lifespan);
+        field!("deadline", get_deadline);
+        field!("latency_budget", get_latency_budget);
+        field!("ownership", get_ownership);
+        field!("ownership_strength", get_ownership_strength);
+        field!("liveliness", get_liveliness);
+        field!("time_based_filter", get_time_based_filter);
+        field!("partition", get_partition);
+        field!("reliability", get_reliability);
+        field!("transport_priority", get_transport_priority);
+        field!("destination_order", get_destination_order);
+        field!("writer_data_lifecycle", get_writer_data_lifecycle);
+        field!("reader_data_lifecycle", get_reader_data_lifecycle);
+        field!("durability_service", get_durability_service);
+        field!("ignorelocal", get_ignorelocal);
+        field!("userdata", get_userdata);
+        field!("topicdata", get_topicdata);
+        field!("groupdata", get_groupdata);
+        s.finish()
+    }
+}
+
+impl PartialEq for DdsQos {
+    fn eq(&self, other: &Self) -> bool {
+        unsafe { dds_qos_equal(self.0, other.0) }
+    }
+}
+
+impl Drop for DdsQos {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            unsafe { dds_delete_qos(self.0) };
+        }
+    }
+}
@@ -0,0 +1,317 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Query conditions with Rust closures as the sample predicate.
+//!
+//! `dds_querycondition_filter_fn` has no user argument, so the closure cannot
+//! be passed to Cyclone directly. Instead a fixed table of trampolines is
+//! used, each bound to one slot. A slot is taken when the condition is created
+//! and given back when the condition is deleted, either explicitly or by
+//! dropping its [`QueryCondition`].
+
+use std::ffi::c_void;
+use std::marker::PhantomData;
+use std::sync::{Arc, RwLock};
+
+use lazy_static::lazy_static;
+
+use crate::topic_filter::{erase, FilterSlot, SampleFilter};
+use crate::{
+    dds_attach_t, dds_create_querycondition, dds_delete, dds_entity_t,
+    dds_querycondition_filter_fn, dds_read, dds_return_loan, dds_return_t, dds_sample_info_t,
+    dds_take, dds_waitset_attach, entity_state, DDSError, DdsEntity, StateMask,
+};
+
+/// Maximum number of query conditions with closures that can exist at the same time.
+pub const MAX_QUERY_CONDITIONS: usize = 64;
+
+const QUERY_CONDITION_KEY: &str = "query_condition";
+
+lazy_static! {
+    static ref QUERY_SLOTS: Vec<RwLock<Option<Arc<FilterSlot>>>> = (0..MAX_QUERY_CONDITIONS)
+        .map(|_| RwLock::new(None))
+        .collect();
+}
+
+unsafe extern "C" fn query_trampoline<const N: usize>(sample: *const c_void) -> bool {
+    let slot = QUERY_SLOTS[N].read().unwrap_or_else(|e| e.into_inner());
+    match slot.as_ref() {
+        Some(filter) => filter.accept(sample),
+        None => false,
+    }
+}
+
+macro_rules! trampolines {
+    ($($n:literal)*) => {
+        [$(Some(query_trampoline::<$n> as unsafe extern "C" fn(*const c_void) -> bool)),*]
+    };
+}
+
+static TRAMPOLINES: [dds_querycondition_filter_fn; MAX_QUERY_CONDITIONS] = trampolines!(
+    0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15
+    16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+    32 33 34 35 36 37 38 39 40 41 42 43 44 45 46 47
+    48 49 50 51 52 53 54 55 56 57 58 59 60 61 62 63
+);
+
+/// Gives the slot back when the condition entity is gone.
+struct SlotLease(usize);
+
+impl Drop for SlotLease {
+    fn drop(&mut self) {
+        *QUERY_SLOTS[self.0]
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
+
+fn lease_slot(filter: Arc<FilterSlot>) -> Option<SlotLease> {
+    for (index, slot) in QUERY_SLOTS.iter().enumerate() {
+        let mut slot = slot.write().unwrap_or_else(|e| e.into_inner());
+        if slot.is_none() {
+            *slot = Some(filter);
+            return Some(SlotLease(index));
+        }
+    }
+    None
+}
+
+/// A query condition whose predicate is a Rust closure over samples of type `T`.
+/// Use it wherever a reader can be used for reading and taking, or attach it
+/// to a waitset. The condition is deleted when this handle is dropped.
+pub struct QueryCondition<T> {
+    entity: DdsEntity,
+    _sample: PhantomData<fn(&T)>,
+}
+
+impl<T> QueryCondition<T> {
+    pub fn entity(&self) -> &DdsEntity {
+        &self.entity
+    }
+
+    /// Attach the condition to a waitset
+    pub fn attach(&self, waitset: &DdsEntity, x: dds_attach_t) -> Result<(), DDSError> {
+        let ret = unsafe { dds_waitset_attach(waitset.entity(), self.entity.entity(), x) };
+        DDSError::check(ret).map(|_| ())
+    }
+
+    /// Read up to `max` samples matching the condition. The callback gets `None`
+    /// for samples that carry no data (e.g. dispose notifications).
+    pub fn read_with<F>(&self, max: usize, f: F) -> Result<usize, DDSError>
+    where
+        F: FnMut(Option<&T>, &dds_sample_info_t),
+    {
+        self.loan(max, false, f)
+    }
+
+    /// Take up to `max` samples matching the condition.
+    pub fn take_with<F>(&self, max: usize, f: F) -> Result<usize, DDSError>
+    where
+        F: FnMut(Option<&T>, &dds_sample_info_t),
+    {
+        self.loan(max, true, f)
+    }
+
+    fn loan<F>(&self, max: usize, take: bool, f: F) -> Result<usize, DDSError>
+    where
+        F: FnMut(Option<&T>, &dds_sample_info_t),
+    {
+        unsafe { loan_samples(self.entity.entity(), max, take, f) }
+    }
+
+    /// Delete the condition and release the closure.
+    pub fn delete(self) -> Result<(), DDSError> {
+        let ret = self.release();
+        std::mem::forget(self);
+        DDSError::check(ret).map(|_| ())
+    }
+
+    fn release(&self) -> dds_return_t {
+        let condition = unsafe { self.entity.entity() };
+        let ret = unsafe { dds_delete(condition) };
+        // the slot is freed right away rather than at the next purge
+        entity_state::remove(condition, QUERY_CONDITION_KEY);
+        ret
+    }
+}
+
+impl<T> Drop for QueryCondition<T> {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+impl DdsEntity {
+    /// Create a query condition on this reader. Samples match when their state
+    /// is in `mask` and the closure returns true. The closure is released when
+    /// the condition (or the reader) is deleted.
+    ///
+    /// # Safety
+    /// The entity must be a reader and `T` must be the in-memory sample type
+    /// of its topic.
+    pub unsafe fn query<T, F>(
+        &self,
+        mask: StateMask,
+        filter: F,
+    ) -> Result<QueryCondition<T>, DDSError>
+    where
+        T: 'static,
+        F: Fn(&T) -> bool + Send + Sync + 'static,
+    {
+        self.query_untyped(mask, erase(filter))
+    }
+
+    pub(crate) unsafe fn query_untyped<T>(
+        &self,
+        mask: StateMask,
+        filter: SampleFilter,
+    ) -> Result<QueryCondition<T>, DDSError> {
+        entity_state::purge();
+
+        let slot = Arc::new(FilterSlot::default());
+        slot.set(Some(filter));
+        let lease = lease_slot(slot).ok_or(DDSError::OutOfResources)?;
+
+        let condition: dds_entity_t = DDSError::check(dds_create_querycondition(
+            self.entity(),
+            *mask,
+            TRAMPOLINES[lease.0],
+        ))?;
+        entity_state::get_or_insert_with(condition, QUERY_CONDITION_KEY, move || lease);
+
+        Ok(QueryCondition {
+            entity: DdsEntity::new(condition),
+            _sample: PhantomData,
+        })
+    }
+}
+
+/// Samples loaned from a reader or condition, returned when dropped so a
+/// panicking callback does not leak them.
+struct Loan {
+    entity: dds_entity_t,
+    samples: Vec<*mut c_void>,
+    count: usize,
+}
+
+impl Loan {
+    fn give_back(&mut self) -> dds_return_t {
+        let count = std::mem::replace(&mut self.count, 0);
+        if count == 0 {
+            return 0;
+        }
+        unsafe { dds_return_loan(self.entity, self.samples.as_mut_ptr(), count as i32) }
+    }
+}
+
+impl Drop for Loan {
+    fn drop(&mut self) {
+        self.give_back();
+    }
+}
+
+/// Read or take up to `max` loaned samples from a reader or condition and
+/// hand them to `f`, returning the loan afterwards, also when `f` panics.
+///
+/// # Safety
+/// `T` must be the in-memory sample type of the entity's topic.
+pub(crate) unsafe fn loan_samples<T, F>(
+    entity: dds_entity_t,
+    max: usize,
+    take: bool,
+    mut f: F,
+) -> Result<usize, DDSError>
+where
+    F: FnMut(Option<&T>, &dds_sample_info_t),
+{
+    let mut samples: Vec<*mut c_void> = vec![std::ptr::null_mut(); max];
+    let mut infos: Vec<dds_sample_info_t> = vec![std::mem::zeroed(); max];
+
+    let ret = if take {
+        dds_take(
+            entity,
+            samples.as_mut_ptr(),
+            infos.as_mut_ptr(),
+            max as _,
+            max as u32,
+        )
+    } else {
+        dds_read(
+            entity,
+            samples.as_mut_ptr(),
+            infos.as_mut_ptr(),
+            max as _,
+            max as u32,
+        )
+    };
+    let count = DDSError::check(ret)? as usize;
+    let mut loan = Loan {
+        entity,
+        samples,
+        count,
+    };
+
+    for (sample, info) in loan.samples.iter().zip(infos.iter()).take(count) {
+        let sample = if info.valid_data && !sample.is_null() {
+            Some(&*(*sample as *const T))
+        } else {
+            None
+        };
+        f(sample, info);
+    }
+
+    DDSError::check(loan.give_back())?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accepting(accept: bool) -> Arc<FilterSlot> {
+        let slot = Arc::new(FilterSlot::default());
+        slot.set(Some(erase(move |_: &i32| accept)));
+        slot
+    }
+
+    #[test]
+    fn slots_are_released_when_the_condition_state_is_removed() {
+        let sample = 7i32;
+        let sample = &sample as *const i32 as *const c_void;
+
+        let mut leases: Vec<SlotLease> = (0..MAX_QUERY_CONDITIONS)
+            .map(|_| lease_slot(accepting(true)).expect("free slot"))
+            .collect();
+        assert!(lease_slot(accepting(true)).is_none());
+
+        // park one lease the way a created condition does
+        let lease = leases.swap_remove(3);
+        let index = lease.0;
+        let trampoline = TRAMPOLINES[index].unwrap();
+        assert!(unsafe { trampoline(sample) });
+        entity_state::get_or_insert_with(-1, QUERY_CONDITION_KEY, move || lease);
+
+        entity_state::remove(-1, QUERY_CONDITION_KEY);
+        assert!(!unsafe { trampoline(sample) });
+
+        let lease = lease_slot(accepting(false)).expect("released slot");
+        assert_eq!(lease.0, index);
+        assert!(!unsafe { trampoline(sample) });
+        drop(lease);
+        drop(leases);
+        assert!(lease_slot(accepting(true)).is_some());
+    }
+}
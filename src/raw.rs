@@ -0,0 +1,586 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Topics of any type, carried as serialized CDR.
+//!
+//! A raw topic only needs the type name; samples are passed through as the
+//! bytes that go over the wire, including the 4 byte encapsulation header.
+//! This is what tools that record, replay or bridge unknown types need.
+//!
+//! A raw topic created with [`create_raw_topic`](DdsEntity::create_raw_topic)
+//! is keyless: everything read from one ends up in a single instance, and
+//! samples written to one carry no key hash (readers with the real type take
+//! the key from the payload). Given the [`DynType`] of the topic,
+//! [`create_raw_topic_with_type`](DdsEntity::create_raw_topic_with_type)
+//! makes it keyed: the key hash of every sample is computed from its
+//! payload, or taken over from the key hash that came with a dispose, so
+//! instances are kept apart as with the real type.
+
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::c_char;
+use std::ptr;
+
+use crate::dynamic::DynType;
+use crate::{
+    dds_create_topic_sertype, dds_forwardcdr, dds_free_op_t, dds_get_entity_sertype,
+    dds_sample_info_t, dds_takecdr, dds_writecdr, ddsi_keyhash, ddsi_serdata, ddsi_serdata_addref,
+    ddsi_serdata_init, ddsi_serdata_kind, ddsi_serdata_kind_SDK_DATA, ddsi_serdata_kind_SDK_KEY,
+    ddsi_serdata_ops, ddsi_serdata_removeref, ddsi_sertype, ddsi_sertype_fini, ddsi_sertype_init,
+    ddsi_sertype_ops, ddsi_sertype_v0, ddsrs_copy_fragchain, ddsrt_iovec_t, ddsrt_md5_append,
+    ddsrt_md5_finish, ddsrt_md5_init, ddsrt_md5_state_t, ddsrt_msg_iovlen_t, nn_rdata, size_t,
+    DDSError, DdsEntity, DdsQos, DdsTime, DDS_FREE_ALL_BIT, DDS_FREE_CONTENTS_BIT,
+};
+
+/// The in-memory sample of a raw topic, for the rare case a raw topic is
+/// used with `dds_write`/`dds_take`: a `malloc`ed buffer.
+#[repr(C)]
+pub struct RawBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+/// A serialized sample read from a raw topic
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawSample {
+    /// CDR payload including the encapsulation header
+    pub data: Vec<u8>,
+    pub source_timestamp: DdsTime,
+    pub publication_handle: crate::dds_instance_handle_t,
+    pub instance_state: crate::dds_instance_state_t,
+    /// False for disposes and unregisters, which carry no data
+    pub valid_data: bool,
+    /// All zeros on a keyless topic
+    pub keyhash: [u8; 16],
+}
+
+#[repr(C)]
+struct RawSerdata {
+    c: ddsi_serdata,
+    data: Vec<u8>,
+    /// As given by [`DynType::key_bytes`], empty when not known
+    key: Vec<u8>,
+    keyhash: [u8; 16],
+}
+
+#[repr(C)]
+struct RawSertype {
+    c: ddsi_sertype,
+    /// Set for keyed topics
+    ty: Option<DynType>,
+    /// Whether the key hash is the padded key rather than its MD5 hash
+    fixed_key: bool,
+}
+
+/// FNV-1a, used for the sertype hash
+pub(crate) fn hash(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5u32, |h, b| {
+        (h ^ *b as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+pub(crate) unsafe fn type_name<'a>(tp: *const ddsi_sertype) -> &'a [u8] {
+    CStr::from_ptr((*tp).type_name).to_bytes()
+}
+
+unsafe fn sertype<'a>(tp: *const ddsi_sertype) -> &'a RawSertype {
+    &*(tp as *const RawSertype)
+}
+
+/// The key hash of `key`: the key itself zero-padded if every key of the
+/// type fits, its MD5 hash otherwise
+fn keyhash(key: &[u8], fixed: bool) -> [u8; 16] {
+    let mut hash = [0u8; 16];
+    if fixed {
+        let n = key.len().min(16);
+        hash[..n].copy_from_slice(&key[..n]);
+    } else {
+        unsafe {
+            let mut state: ddsrt_md5_state_t = std::mem::zeroed();
+            ddsrt_md5_init(&mut state);
+            ddsrt_md5_append(&mut state, key.as_ptr(), key.len() as u32);
+            ddsrt_md5_finish(&mut state, hash.as_mut_ptr());
+        }
+    }
+    hash
+}
+
+/// The key of a full sample or, with `key_only`, of a serialized key. A
+/// sample that does not decode gets the empty key.
+unsafe fn key_of(tp: *const ddsi_sertype, data: &[u8], key_only: bool) -> Vec<u8> {
+    match &sertype(tp).ty {
+        Some(ty) if key_only => ty.key_bytes_of_key(data).unwrap_or_default(),
+        Some(ty) => ty.key_bytes(data).unwrap_or_default(),
+        None => Vec::new(),
+    }
+}
+
+unsafe extern "C" fn sertype_free(tp: *mut ddsi_sertype) {
+    ddsi_sertype_fini(tp);
+    drop(Box::from_raw(tp as *mut RawSertype));
+}
+
+unsafe extern "C" fn sertype_zero_samples(
+    _: *const ddsi_sertype,
+    samples: *mut c_void,
+    count: size_t,
+) {
+    ptr::write_bytes(samples as *mut RawBuffer, 0, count as usize);
+}
+
+unsafe extern "C" fn sertype_realloc_samples(
+    ptrs: *mut *mut c_void,
+    _: *const ddsi_sertype,
+    old: *mut c_void,
+    oldcount: size_t,
+    count: size_t,
+) {
+    let size = std::mem::size_of::<RawBuffer>();
+    let new = libc::realloc(old, count as usize * size) as *mut RawBuffer;
+    if count > oldcount {
+        ptr::write_bytes(new.add(oldcount as usize), 0, (count - oldcount) as usize);
+    }
+    for i in 0..count as usize {
+        *ptrs.add(i) = new.add(i) as *mut c_void;
+    }
+}
+
+unsafe extern "C" fn sertype_free_samples(
+    _: *const ddsi_sertype,
+    ptrs: *mut *mut c_void,
+    count: size_t,
+    op: dds_free_op_t,
+) {
+    if count == 0 {
+        return;
+    }
+    if op & DDS_FREE_CONTENTS_BIT != 0 {
+        for i in 0..count as usize {
+            let sample = *ptrs.add(i) as *mut RawBuffer;
+            libc::free((*sample).data as *mut c_void);
+            (*sample).data = ptr::null_mut();
+            (*sample).len = 0;
+        }
+    }
+    if op & DDS_FREE_ALL_BIT != 0 {
+        libc::free(*ptrs);
+    }
+}
+
+unsafe extern "C" fn sertype_equal(a: *const ddsi_sertype, b: *const ddsi_sertype) -> bool {
+    type_name(a) == type_name(b)
+        && sertype(a).ty.as_ref().map(DynType::root) == sertype(b).ty.as_ref().map(DynType::root)
+}
+
+unsafe extern "C" fn sertype_hash(tp: *const ddsi_sertype) -> u32 {
+    hash(type_name(tp))
+}
+
+/// The ops are never modified; `arg` is the only pointer and it is null
+struct SertypeOps(ddsi_sertype_ops);
+unsafe impl Sync for SertypeOps {}
+
+static SERTYPE_OPS: SertypeOps = SertypeOps(ddsi_sertype_ops {
+    version: Some(ddsi_sertype_v0),
+    arg: ptr::null_mut(),
+    free: Some(sertype_free),
+    zero_samples: Some(sertype_zero_samples),
+    realloc_samples: Some(sertype_realloc_samples),
+    free_samples: Some(sertype_free_samples),
+    equal: Some(sertype_equal),
+    hash: Some(sertype_hash),
+    type_id: None,
+    type_map: None,
+    type_info: None,
+    derive_sertype: None,
+    get_serialized_size: None,
+    serialize_into: None,
+});
+
+unsafe fn new_serdata(
+    tp: *const ddsi_sertype,
+    kind: ddsi_serdata_kind,
+    data: Vec<u8>,
+    key: Vec<u8>,
+) -> *mut ddsi_serdata {
+    let keyhash = keyhash(&key, sertype(tp).fixed_key);
+    with_keyhash(tp, kind, data, key, keyhash)
+}
+
+unsafe fn with_keyhash(
+    tp: *const ddsi_sertype,
+    kind: ddsi_serdata_kind,
+    data: Vec<u8>,
+    key: Vec<u8>,
+    keyhash: [u8; 16],
+) -> *mut ddsi_serdata {
+    let mut d = Box::new(RawSerdata {
+        c: std::mem::zeroed(),
+        data,
+        key,
+        keyhash,
+    });
+    ddsi_serdata_init(&mut d.c, tp, kind);
+    d.c.hash = (*tp).serdata_basehash ^ hash(&keyhash);
+    Box::into_raw(d) as *mut ddsi_serdata
+}
+
+/// A serialized sample, or a serialized key for `SDK_KEY`
+unsafe fn from_serialized(
+    tp: *const ddsi_sertype,
+    kind: ddsi_serdata_kind,
+    data: Vec<u8>,
+) -> *mut ddsi_serdata {
+    let key = key_of(tp, &data, kind == ddsi_serdata_kind_SDK_KEY);
+    new_serdata(tp, kind, data, key)
+}
+
+unsafe fn raw<'a>(d: *const ddsi_serdata) -> &'a RawSerdata {
+    &*(d as *const RawSerdata)
+}
+
+unsafe extern "C" fn serdata_eqkey(a: *const ddsi_serdata, b: *const ddsi_serdata) -> bool {
+    raw(a).keyhash == raw(b).keyhash
+}
+
+unsafe extern "C" fn serdata_get_size(d: *const ddsi_serdata) -> u32 {
+    raw(d).data.len() as u32
+}
+
+unsafe extern "C" fn serdata_from_ser(
+    tp: *const ddsi_sertype,
+    kind: ddsi_serdata_kind,
+    fragchain: *const nn_rdata,
+    size: size_t,
+) -> *mut ddsi_serdata {
+    let mut data = vec![0u8; size as usize];
+    ddsrs_copy_fragchain(fragchain, size, data.as_mut_ptr());
+    from_serialized(tp, kind, data)
+}
+
+unsafe extern "C" fn serdata_from_ser_iov(
+    tp: *const ddsi_sertype,
+    kind: ddsi_serdata_kind,
+    niov: ddsrt_msg_iovlen_t,
+    iov: *const ddsrt_iovec_t,
+    size: size_t,
+) -> *mut ddsi_serdata {
+    let mut data = Vec::with_capacity(size as usize);
+    for i in 0..niov as usize {
+        let v = &*iov.add(i);
+        data.extend_from_slice(std::slice::from_raw_parts(
+            v.iov_base as *const u8,
+            v.iov_len as usize,
+        ));
+    }
+    from_serialized(tp, kind, data)
+}
+
+unsafe extern "C" fn serdata_from_keyhash(
+    tp: *const ddsi_sertype,
+    keyhash: *const ddsi_keyhash,
+) -> *mut ddsi_serdata {
+    let keyhash = (*keyhash).value;
+    // a padded key hash is the key; an MD5 hash cannot be undone
+    let key = if sertype(tp).fixed_key {
+        keyhash.to_vec()
+    } else {
+        Vec::new()
+    };
+    with_keyhash(tp, ddsi_serdata_kind_SDK_KEY, Vec::new(), key, keyhash)
+}
+
+unsafe extern "C" fn serdata_from_sample(
+    tp: *const ddsi_sertype,
+    kind: ddsi_serdata_kind,
+    sample: *const c_void,
+) -> *mut ddsi_serdata {
+    let sample = &*(sample as *const RawBuffer);
+    let data = if sample.data.is_null() {
+        Vec::new()
+    } else {
+        std::slice::from_raw_parts(sample.data, sample.len).to_vec()
+    };
+    // a sample, also when only its key is wanted
+    let key = key_of(tp, &data, false);
+    new_serdata(tp, kind, data, key)
+}
+
+/// The part of `data` at `off` of `sz` bytes, cut short where `data` ends
+pub(crate) fn ser_range(data: &[u8], off: size_t, sz: size_t) -> &[u8] {
+    let start = (off as usize).min(data.len());
+    let end = start.saturating_add(sz as usize).min(data.len());
+    &data[start..end]
+}
+
+unsafe extern "C" fn serdata_to_ser(
+    d: *const ddsi_serdata,
+    off: size_t,
+    sz: size_t,
+    buf: *mut c_void,
+) {
+    let data = ser_range(&raw(d).data, off, sz);
+    ptr::copy_nonoverlapping(data.as_ptr(), buf as *mut u8, data.len());
+}
+
+unsafe extern "C" fn serdata_to_ser_ref(
+    d: *const ddsi_serdata,
+    off: size_t,
+    sz: size_t,
+    r: *mut ddsrt_iovec_t,
+) -> *mut ddsi_serdata {
+    let data = ser_range(&raw(d).data, off, sz);
+    (*r).iov_base = data.as_ptr() as *mut c_void;
+    (*r).iov_len = data.len() as _;
+    ddsi_serdata_addref(d)
+}
+
+unsafe extern "C" fn serdata_to_ser_unref(d: *mut ddsi_serdata, _: *const ddsrt_iovec_t) {
+    ddsi_serdata_removeref(d)
+}
+
+unsafe extern "C" fn serdata_to_sample(
+    d: *const ddsi_serdata,
+    sample: *mut c_void,
+    _: *mut *mut c_void,
+    _: *mut c_void,
+) -> bool {
+    let sample = &mut *(sample as *mut RawBuffer);
+    let data = &raw(d).data;
+    libc::free(sample.data as *mut c_void);
+    sample.data = libc::malloc(data.len().max(1)) as *mut u8;
+    if sample.data.is_null() {
+        sample.len = 0;
+        return false;
+    }
+    ptr::copy_nonoverlapping(data.as_ptr(), sample.data, data.len());
+    sample.len = data.len();
+    true
+}
+
+unsafe extern "C" fn serdata_to_untyped(d: *const ddsi_serdata) -> *mut ddsi_serdata {
+    let d = raw(d);
+    let untyped = with_keyhash(
+        d.c.type_,
+        ddsi_serdata_kind_SDK_KEY,
+        Vec::new(),
+        d.key.clone(),
+        d.keyhash,
+    );
+    (*untyped).type_ = ptr::null();
+    untyped
+}
+
+unsafe extern "C" fn serdata_untyped_to_sample(
+    _: *const ddsi_sertype,
+    _: *const ddsi_serdata,
+    sample: *mut c_void,
+    _: *mut *mut c_void,
+    _: *mut c_void,
+) -> bool {
+    let sample = &mut *(sample as *mut RawBuffer);
+    libc::free(sample.data as *mut c_void);
+    sample.data = ptr::null_mut();
+    sample.len = 0;
+    true
+}
+
+unsafe extern "C" fn serdata_free(d: *mut ddsi_serdata) {
+    drop(Box::from_raw(d as *mut RawSerdata));
+}
+
+unsafe extern "C" fn serdata_print(
+    _: *const ddsi_sertype,
+    d: *const ddsi_serdata,
+    buf: *mut c_char,
+    size: size_t,
+) -> size_t {
+    let text = format!("raw({} bytes)", raw(d).data.len());
+    let n = text.len().min((size as usize).saturating_sub(1));
+    ptr::copy_nonoverlapping(text.as_ptr() as *const c_char, buf, n);
+    *buf.add(n) = 0;
+    n as size_t
+}
+
+unsafe extern "C" fn serdata_get_keyhash(
+    d: *const ddsi_serdata,
+    buf: *mut ddsi_keyhash,
+    force_md5: bool,
+) {
+    let d = raw(d);
+    (*buf).value = if force_md5 && !d.key.is_empty() {
+        keyhash(&d.key, false)
+    } else {
+        d.keyhash
+    };
+}
+
+static SERDATA_OPS: ddsi_serdata_ops = ddsi_serdata_ops {
+    eqkey: Some(serdata_eqkey),
+    get_size: Some(serdata_get_size),
+    from_ser: Some(serdata_from_ser),
+    from_ser_iov: Some(serdata_from_ser_iov),
+    from_keyhash: Some(serdata_from_keyhash),
+    from_sample: Some(serdata_from_sample),
+    to_ser: Some(serdata_to_ser),
+    to_ser_ref: Some(serdata_to_ser_ref),
+    to_ser_unref: Some(serdata_to_ser_unref),
+    to_sample: Some(serdata_to_sample),
+    to_untyped: Some(serdata_to_untyped),
+    untyped_to_sample: Some(serdata_untyped_to_sample),
+    free: Some(serdata_free),
+    print: Some(serdata_print),
+    get_keyhash: Some(serdata_get_keyhash),
+    get_sample_size: None,
+    from_iox_buffer: None,
+};
+
+impl DdsEntity {
+    /// Create a topic `name` of type `type_name` that reads and writes
+    /// serialized samples, see [`take_raw`](Self::take_raw) and
+    /// [`write_raw`](Self::write_raw).
+    pub fn create_raw_topic(
+        &self,
+        name: &str,
+        type_name: &str,
+        qos: Option<&DdsQos>,
+    ) -> Result<DdsEntity, DDSError> {
+        self.raw_topic(name, type_name, None, qos)
+    }
+
+    /// Create a raw topic `name` of type `ty`, keyed if `ty` has a key
+    pub fn create_raw_topic_with_type(
+        &self,
+        name: &str,
+        ty: &DynType,
+        qos: Option<&DdsQos>,
+    ) -> Result<DdsEntity, DDSError> {
+        let keyed = Some(ty.clone()).filter(DynType::is_keyed);
+        self.raw_topic(name, ty.type_name(), keyed, qos)
+    }
+
+    fn raw_topic(
+        &self,
+        name: &str,
+        type_name: &str,
+        ty: Option<DynType>,
+        qos: Option<&DdsQos>,
+    ) -> Result<DdsEntity, DDSError> {
+        let name = CString::new(name).map_err(|_| DDSError::BadParameter)?;
+        let type_name = CString::new(type_name).map_err(|_| DDSError::BadParameter)?;
+        unsafe {
+            let fixed_key = match &ty {
+                Some(ty) => ty.key_fits_keyhash(),
+                None => true,
+            };
+            let keyless = ty.is_none();
+            let sertype = Box::into_raw(Box::new(RawSertype {
+                c: std::mem::zeroed(),
+                ty,
+                fixed_key,
+            }));
+            ddsi_sertype_init(
+                &mut (*sertype).c,
+                type_name.as_ptr(),
+                &SERTYPE_OPS.0,
+                &SERDATA_OPS,
+                keyless,
+            );
+            let mut tp = sertype as *mut ddsi_sertype;
+            let topic = dds_create_topic_sertype(
+                self.0,
+                name.as_ptr(),
+                &mut tp,
+                qos.map_or(ptr::null(), DdsQos::as_ptr),
+                ptr::null(),
+                ptr::null(),
+            );
+            if topic < 0 {
+                sertype_free(sertype as *mut ddsi_sertype);
+            }
+            DDSError::check(topic).map(|_| DdsEntity(topic))
+        }
+    }
+
+    /// Take up to `max` samples from a reader of a raw topic. Samples
+    /// without data (disposes, unregisters) have empty `data`.
+    pub fn take_raw(&self, max: usize) -> Result<Vec<RawSample>, DDSError> {
+        let mut buf: Vec<*mut ddsi_serdata> = vec![ptr::null_mut(); max];
+        let mut infos: Vec<dds_sample_info_t> = vec![unsafe { std::mem::zeroed() }; max];
+        let n = DDSError::check(unsafe {
+            dds_takecdr(self.0, buf.as_mut_ptr(), max as u32, infos.as_mut_ptr(), 0)
+        })? as usize;
+
+        let mut samples = Vec::with_capacity(n);
+        for (d, info) in buf.iter().zip(infos.iter()).take(n) {
+            unsafe {
+                if ptr::eq((**d).ops, &SERDATA_OPS) {
+                    samples.push(RawSample {
+                        data: if info.valid_data {
+                            raw(*d).data.clone()
+                        } else {
+                            Vec::new()
+                        },
+                        source_timestamp: info.source_timestamp.into(),
+                        publication_handle: info.publication_handle,
+                        instance_state: info.instance_state,
+                        valid_data: info.valid_data,
+                        keyhash: raw(*d).keyhash,
+                    });
+                }
+                ddsi_serdata_removeref(*d);
+            }
+        }
+        Ok(samples)
+    }
+
+    /// Publish a serialized sample on a writer of a raw topic. `data` must
+    /// start with the encapsulation header.
+    pub fn write_raw(&self, data: &[u8]) -> Result<(), DDSError> {
+        unsafe {
+            let d = self.raw_serdata(data)?;
+            // dds_writecdr takes over the reference
+            DDSError::check(dds_writecdr(self.0, d)).map(|_| ())
+        }
+    }
+
+    /// [`write_raw`](Self::write_raw) with the given source timestamp, for
+    /// replaying samples
+    pub fn write_raw_ts(&self, data: &[u8], timestamp: DdsTime) -> Result<(), DDSError> {
+        if timestamp == DdsTime::Never {
+            return Err(DDSError::BadParameter);
+        }
+        unsafe {
+            let d = self.raw_serdata(data)?;
+            (*d).timestamp.v = timestamp.as_nanos();
+            (*d).statusinfo = 0;
+            // dds_forwardcdr keeps the timestamp and takes over the reference
+            DDSError::check(dds_forwardcdr(self.0, d)).map(|_| ())
+        }
+    }
+
+    unsafe fn raw_serdata(&self, data: &[u8]) -> Result<*mut ddsi_serdata, DDSError> {
+        let mut tp: *const ddsi_sertype = ptr::null();
+        DDSError::check(dds_get_entity_sertype(self.0, &mut tp))?;
+        if !ptr::eq((*tp).serdata_ops, &SERDATA_OPS) {
+            return Err(DDSError::BadParameter);
+        }
+        Ok(from_serialized(
+            tp,
+            ddsi_serdata_kind_SDK_DATA,
+            data.to_vec(),
+        ))
+    }
+}
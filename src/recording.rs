@@ -0,0 +1,321 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! The file format of `cyclonedds-record`.
+//!
+//! A recording starts with the magic `DDSREC02`, followed by records. Each
+//! record is a one byte tag and a little endian body:
+//!
+//! * channel (1): id `u32`, topic name, type name, partition count `u32`
+//!   and that many partition names, then the type as found by discovery:
+//!   op count `u32` and that many ops `u32`, the serialized type
+//!   information and the serialized type map, all empty if the type was not
+//!   discovered. Strings and byte strings are a `u32` length and the bytes.
+//! * sample (2): channel id `u32`, log time `i64` (when it was recorded),
+//!   source timestamp `i64`, writer GUID (16 bytes), payload length `u32`
+//!   and the serialized sample including its encapsulation header.
+//!
+//! A channel record comes before the first sample on that channel.
+
+use std::io::{self, Read, Write};
+
+use crate::dynamic::TypeMeta;
+use crate::{DdsTime, Guid};
+
+const MAGIC: &[u8; 8] = b"DDSREC02";
+const TAG_CHANNEL: u8 = 1;
+const TAG_SAMPLE: u8 = 2;
+/// Longest string or payload accepted when reading, so a corrupt length
+/// cannot make the reader allocate gigabytes
+const MAX_LENGTH: usize = 1 << 28;
+
+/// A topic in a recording
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Channel {
+    pub id: u32,
+    pub topic_name: String,
+    pub type_name: String,
+    pub partitions: Vec<String>,
+    /// The serializer ops of the type, see [`TypeMeta`]
+    pub ops: Vec<u32>,
+    pub type_information: Vec<u8>,
+    pub type_mapping: Vec<u8>,
+}
+
+impl Channel {
+    /// The type of the channel, if it was discovered when recording
+    pub fn type_meta(&self) -> Option<TypeMeta> {
+        if self.ops.is_empty() {
+            return None;
+        }
+        Some(TypeMeta {
+            type_name: self.type_name.clone(),
+            ops: self.ops.clone(),
+            type_information: self.type_information.clone(),
+            type_mapping: self.type_mapping.clone(),
+        })
+    }
+}
+
+/// A serialized sample in a recording
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedSample {
+    pub channel: u32,
+    pub log_time: DdsTime,
+    pub source_timestamp: DdsTime,
+    pub writer: Guid,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Record {
+    Channel(Channel),
+    Sample(RecordedSample),
+}
+
+pub struct RecordingWriter<W: Write> {
+    out: W,
+    next_channel: u32,
+}
+
+impl<W: Write> RecordingWriter<W> {
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(MAGIC)?;
+        Ok(RecordingWriter {
+            out,
+            next_channel: 0,
+        })
+    }
+
+    /// Add a channel, returning its id. `meta` is the type if it was
+    /// discovered.
+    pub fn add_channel<S: AsRef<str>>(
+        &mut self,
+        topic_name: &str,
+        type_name: &str,
+        partitions: &[S],
+        meta: Option<&TypeMeta>,
+    ) -> io::Result<u32> {
+        let id = self.next_channel;
+        self.next_channel += 1;
+        self.out.write_all(&[TAG_CHANNEL])?;
+        self.out.write_all(&id.to_le_bytes())?;
+        self.write_str(topic_name)?;
+        self.write_str(type_name)?;
+        self.out
+            .write_all(&(partitions.len() as u32).to_le_bytes())?;
+        for p in partitions {
+            self.write_str(p.as_ref())?;
+        }
+        let empty = TypeMeta::default();
+        let meta = meta.unwrap_or(&empty);
+        self.out.write_all(&(meta.ops.len() as u32).to_le_bytes())?;
+        for op in &meta.ops {
+            self.out.write_all(&op.to_le_bytes())?;
+        }
+        self.write_bytes(&meta.type_information)?;
+        self.write_bytes(&meta.type_mapping)?;
+        Ok(id)
+    }
+
+    pub fn write_sample(&mut self, sample: &RecordedSample) -> io::Result<()> {
+        self.out.write_all(&[TAG_SAMPLE])?;
+        self.out.write_all(&sample.channel.to_le_bytes())?;
+        self.out
+            .write_all(&sample.log_time.as_nanos().to_le_bytes())?;
+        self.out
+            .write_all(&sample.source_timestamp.as_nanos().to_le_bytes())?;
+        self.out.write_all(&<[u8; 16]>::from(sample.writer))?;
+        self.out
+            .write_all(&(sample.data.len() as u32).to_le_bytes())?;
+        self.out.write_all(&sample.data)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn write_str(&mut self, s: &str) -> io::Result<()> {
+        self.write_bytes(s.as_bytes())
+    }
+
+    fn write_bytes(&mut self, b: &[u8]) -> io::Result<()> {
+        self.out.write_all(&(b.len() as u32).to_le_bytes())?;
+        self.out.write_all(b)
+    }
+}
+
+pub struct RecordingReader<R: Read> {
+    input: R,
+}
+
+fn invalid(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what.to_owned())
+}
+
+impl<R: Read> RecordingReader<R> {
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut magic = [0u8; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a DDS recording"));
+        }
+        Ok(RecordingReader { input })
+    }
+
+    /// The next record, `None` at the end of the file
+    pub fn next_record(&mut self) -> io::Result<Option<Record>> {
+        let mut tag = [0u8; 1];
+        if self.input.read(&mut tag)? == 0 {
+            return Ok(None);
+        }
+        match tag[0] {
+            TAG_CHANNEL => {
+                let id = self.read_u32()?;
+                let topic_name = self.read_str()?;
+                let type_name = self.read_str()?;
+                let count = self.read_u32()?;
+                let partitions = (0..count)
+                    .map(|_| self.read_str())
+                    .collect::<io::Result<_>>()?;
+                let count = self.read_u32()?;
+                let ops = (0..count)
+                    .map(|_| self.read_u32())
+                    .collect::<io::Result<_>>()?;
+                Ok(Some(Record::Channel(Channel {
+                    id,
+                    topic_name,
+                    type_name,
+                    partitions,
+                    ops,
+                    type_information: self.read_bytes()?,
+                    type_mapping: self.read_bytes()?,
+                })))
+            }
+            TAG_SAMPLE => {
+                let channel = self.read_u32()?;
+                let log_time = DdsTime::from(self.read_i64()?);
+                let source_timestamp = DdsTime::from(self.read_i64()?);
+                let mut guid = [0u8; 16];
+                self.input.read_exact(&mut guid)?;
+                let data = self.read_bytes()?;
+                Ok(Some(Record::Sample(RecordedSample {
+                    channel,
+                    log_time,
+                    source_timestamp,
+                    writer: Guid::from(guid),
+                    data,
+                })))
+            }
+            _ => Err(invalid("unknown record")),
+        }
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        let mut b = [0u8; 4];
+        self.input.read_exact(&mut b)?;
+        Ok(u32::from_le_bytes(b))
+    }
+
+    fn read_i64(&mut self) -> io::Result<i64> {
+        let mut b = [0u8; 8];
+        self.input.read_exact(&mut b)?;
+        Ok(i64::from_le_bytes(b))
+    }
+
+    fn read_bytes(&mut self) -> io::Result<Vec<u8>> {
+        let len = self.read_u32()? as usize;
+        if len > MAX_LENGTH {
+            return Err(invalid("length out of range"));
+        }
+        let mut b = vec![0u8; len];
+        self.input.read_exact(&mut b)?;
+        Ok(b)
+    }
+
+    fn read_str(&mut self) -> io::Result<String> {
+        String::from_utf8(self.read_bytes()?).map_err(|_| invalid("string is not UTF-8"))
+    }
+}
+
+impl<R: Read> Iterator for RecordingReader<R> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channels_keep_their_type() {
+        let meta = TypeMeta {
+            type_name: "test::T".to_owned(),
+            ops: vec![1, 2, 3],
+            type_information: vec![4, 5],
+            type_mapping: vec![6],
+        };
+        let mut w = RecordingWriter::new(Vec::new()).unwrap();
+        assert_eq!(
+            w.add_channel("a", "test::T", &["p"], Some(&meta)).unwrap(),
+            0
+        );
+        assert_eq!(w.add_channel::<&str>("b", "test::U", &[], None).unwrap(), 1);
+        let sample = RecordedSample {
+            channel: 0,
+            log_time: DdsTime::from(2),
+            source_timestamp: DdsTime::from(1),
+            writer: Guid::from([7u8; 16]),
+            data: vec![0, 1, 0, 0],
+        };
+        w.write_sample(&sample).unwrap();
+
+        let records: Vec<Record> = RecordingReader::new(&w.into_inner()[..])
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        match &records[..] {
+            [Record::Channel(a), Record::Channel(b), Record::Sample(s)] => {
+                assert_eq!(a.partitions, vec!["p".to_owned()]);
+                assert_eq!(a.type_meta(), Some(meta));
+                assert_eq!(b.type_meta(), None);
+                assert_eq!(s, &sample);
+            }
+            other => panic!("unexpected records {:?}", other),
+        }
+    }
+
+    #[test]
+    fn oversized_lengths_are_rejected() {
+        let mut file = MAGIC.to_vec();
+        file.push(TAG_SAMPLE);
+        file.extend_from_slice(&0u32.to_le_bytes());
+        file.extend_from_slice(&[0u8; 32]);
+        file.extend_from_slice(&u32::MAX.to_le_bytes());
+        let err = RecordingReader::new(&file[..])
+            .unwrap()
+            .next_record()
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,466 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Interoperability with ROS 2 nodes running on `rmw_cyclonedds`.
+//!
+//! ROS names become DDS topic names with a prefix and without the leading
+//! slash: `rt/` for topics, and for services `rq/` and `rr/`, with
+//! `Request` and `Reply` appended (the naming [`crate::rpc`] uses).
+//! Message types `pkg/msg/Type` become `pkg::msg::dds_::Type_`. The
+//! [`QosProfile`]s are the `rmw_qos_profile_*` presets.
+//!
+//! ROS tools list nodes, not participants: a node exists when it is
+//! announced on `ros_discovery_info`, which [`DiscoveryInfoWriter`] does.
+//! Participants should also carry the user data `rmw_cyclonedds` puts on
+//! its own, see [`participant_qos`].
+//!
+//! ```no_run
+//! use cyclonedds_sys::dynamic::DynType;
+//! use cyclonedds_sys::ros2::{self, DiscoveryInfoWriter, GidSize, QosProfile};
+//! use cyclonedds_sys::*;
+//!
+//! let qos = ros2::participant_qos("/");
+//! let p = unsafe { dds_create_participant(0, qos.as_ptr(), std::ptr::null()) };
+//! let participant = unsafe { DdsEntity::new(p) };
+//!
+//! // std_msgs/msg/String: struct { string data; }
+//! let name = ros2::expand_name("chatter", "/", "talker").unwrap();
+//! let type_name = ros2::dds_type_name("std_msgs/msg/String").unwrap();
+//! let ops = [
+//!     dds_stream_opcode_DDS_OP_ADR | dds_stream_typecode_primary_DDS_OP_TYPE_STR,
+//!     0,
+//!     dds_stream_opcode_DDS_OP_RTS,
+//! ];
+//! let ty = DynType::from_ops(&type_name, &ops).unwrap();
+//! let writer_qos = QosProfile::Default.qos();
+//! let topic = participant
+//!     .create_raw_topic_with_type(&ros2::topic_name(&name), &ty, Some(&writer_qos))
+//!     .unwrap();
+//! let w = unsafe {
+//!     dds_create_writer(p, topic.entity(), writer_qos.as_ptr(), std::ptr::null())
+//! };
+//! let writer = unsafe { DdsEntity::new(DDSError::check(w).unwrap()) };
+//!
+//! let mut info = DiscoveryInfoWriter::new(&participant, GidSize::Bytes24).unwrap();
+//! info.node("/", "talker").writers.push(writer.guid().unwrap());
+//! info.publish().unwrap();
+//! ```
+
+use std::fmt;
+
+use crate::{
+    dds_create_writer, dds_durability_kind, dds_history_kind, dds_reliability_kind, rpc, DDSError,
+    DdsDuration, DdsEntity, DdsQos, Guid,
+};
+
+/// A ROS name that cannot be used
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidName {
+    pub name: String,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for InvalidName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid ROS name {:?}: {}", self.name, self.reason)
+    }
+}
+
+impl std::error::Error for InvalidName {}
+
+fn invalid(name: &str, reason: &'static str) -> InvalidName {
+    InvalidName {
+        name: name.to_owned(),
+        reason,
+    }
+}
+
+/// Check the rules for a fully qualified ROS name
+fn validate(name: &str) -> Result<(), &'static str> {
+    let rest = name.strip_prefix('/').ok_or("not absolute")?;
+    if rest.is_empty() {
+        return Ok(());
+    }
+    for token in rest.split('/') {
+        if token.is_empty() {
+            return Err("empty token");
+        }
+        if token.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err("token starts with a digit");
+        }
+        if !token.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err("only letters, digits, '_' and '/' are allowed");
+        }
+        if token.contains("__") {
+            return Err("repeated '_'");
+        }
+    }
+    Ok(())
+}
+
+fn join(base: &str, name: &str) -> String {
+    if base.ends_with('/') {
+        format!("{}{}", base, name)
+    } else {
+        format!("{}/{}", base, name)
+    }
+}
+
+/// The fully qualified form of a topic or service name used by the node
+/// `node` in `namespace`: relative names are put in the namespace, and `~`
+/// stands for the node's own namespace (`namespace/node`).
+pub fn expand_name(name: &str, namespace: &str, node: &str) -> Result<String, InvalidName> {
+    let namespace = if namespace.is_empty() { "/" } else { namespace };
+    validate(namespace).map_err(|reason| invalid(namespace, reason))?;
+    let expanded = if name == "~" {
+        join(namespace, node)
+    } else if let Some(rest) = name.strip_prefix("~/") {
+        join(&join(namespace, node), rest)
+    } else if name.starts_with('/') {
+        name.to_owned()
+    } else {
+        join(namespace, name)
+    };
+    if expanded == "/" {
+        return Err(invalid(name, "the root namespace is not a topic"));
+    }
+    validate(&expanded).map_err(|reason| invalid(name, reason))?;
+    Ok(expanded)
+}
+
+/// The DDS topic of the fully qualified topic `name`: `/chatter` is
+/// `rt/chatter`
+pub fn topic_name(name: &str) -> String {
+    format!("rt/{}", name.trim_start_matches('/'))
+}
+
+/// The DDS topic carrying the requests of the fully qualified service
+/// `name`: `/add` is `rq/addRequest`
+pub fn request_topic_name(name: &str) -> String {
+    rpc::request_topic_name(name.trim_start_matches('/'))
+}
+
+/// The DDS topic carrying the replies of the fully qualified service
+/// `name`: `/add` is `rr/addReply`
+pub fn reply_topic_name(name: &str) -> String {
+    rpc::reply_topic_name(name.trim_start_matches('/'))
+}
+
+/// What a DDS topic is in ROS, with the fully qualified ROS name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RosName {
+    Topic(String),
+    ServiceRequest(String),
+    ServiceReply(String),
+}
+
+/// The ROS name of a DDS topic, `None` for topics that are not ROS ones
+pub fn ros_name(topic: &str) -> Option<RosName> {
+    if let Some(name) = topic.strip_prefix("rt/") {
+        Some(RosName::Topic(format!("/{}", name)))
+    } else if let Some(name) = topic.strip_prefix("rq/") {
+        let name = name.strip_suffix("Request")?;
+        Some(RosName::ServiceRequest(format!("/{}", name)))
+    } else if let Some(name) = topic.strip_prefix("rr/") {
+        let name = name.strip_suffix("Reply")?;
+        Some(RosName::ServiceReply(format!("/{}", name)))
+    } else {
+        None
+    }
+}
+
+/// Split `pkg/kind/Type`, with `pkg/Type` short for `pkg/<default_kind>/Type`
+fn split_type<'a>(
+    ros_type: &'a str,
+    default_kind: &'a str,
+) -> Result<(&'a str, &'a str, &'a str), InvalidName> {
+    let parts: Vec<&str> = ros_type.split('/').collect();
+    let (pkg, kind, ty) = match parts.as_slice() {
+        [pkg, ty] => (*pkg, default_kind, *ty),
+        [pkg, kind, ty] => (*pkg, *kind, *ty),
+        _ => return Err(invalid(ros_type, "expected pkg/msg/Type")),
+    };
+    if [pkg, kind, ty].iter().any(|part| part.is_empty()) {
+        return Err(invalid(ros_type, "empty part"));
+    }
+    Ok((pkg, kind, ty))
+}
+
+/// The DDS type name of the message type `pkg/msg/Type` (or `pkg/Type`):
+/// `pkg::msg::dds_::Type_`
+pub fn dds_type_name(ros_type: &str) -> Result<String, InvalidName> {
+    let (pkg, kind, ty) = split_type(ros_type, "msg")?;
+    Ok(format!("{}::{}::dds_::{}_", pkg, kind, ty))
+}
+
+/// The DDS type names of the request and the response of the service type
+/// `pkg/srv/Type`: `pkg::srv::dds_::Type_Request_` and
+/// `pkg::srv::dds_::Type_Response_`
+pub fn dds_service_type_names(ros_type: &str) -> Result<(String, String), InvalidName> {
+    let (pkg, kind, ty) = split_type(ros_type, "srv")?;
+    Ok((
+        format!("{}::{}::dds_::{}_Request_", pkg, kind, ty),
+        format!("{}::{}::dds_::{}_Response_", pkg, kind, ty),
+    ))
+}
+
+/// The ROS type of a DDS type name: `pkg::msg::dds_::Type_` is
+/// `pkg/msg/Type`. `None` for names that do not follow the ROS scheme.
+pub fn ros_type_name(type_name: &str) -> Option<String> {
+    let parts: Vec<&str> = type_name.split("::").collect();
+    match parts.as_slice() {
+        [pkg, kind, "dds_", ty] => Some(format!(
+            "{}/{}/{}",
+            pkg,
+            kind,
+            ty.strip_suffix('_').unwrap_or(ty)
+        )),
+        _ => None,
+    }
+}
+
+/// The QoS presets of ROS 2 (`rmw_qos_profile_*`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QosProfile {
+    /// Reliable, volatile, keep last 10
+    Default,
+    /// Best effort, volatile, keep last 5
+    SensorData,
+    /// Reliable, volatile, keep last 10
+    Services,
+    /// Reliable, volatile, keep last 1000
+    Parameters,
+    /// Reliable, volatile, keep last 1000
+    ParameterEvents,
+    /// Whatever Cyclone's defaults are
+    SystemDefault,
+}
+
+impl QosProfile {
+    pub fn qos(self) -> DdsQos {
+        let mut qos = DdsQos::new();
+        let (reliability, depth) = match self {
+            QosProfile::Default | QosProfile::Services => {
+                (dds_reliability_kind::DDS_RELIABILITY_RELIABLE, 10)
+            }
+            QosProfile::SensorData => (dds_reliability_kind::DDS_RELIABILITY_BEST_EFFORT, 5),
+            QosProfile::Parameters | QosProfile::ParameterEvents => {
+                (dds_reliability_kind::DDS_RELIABILITY_RELIABLE, 1000)
+            }
+            QosProfile::SystemDefault => return qos,
+        };
+        qos.reliability(reliability, DdsDuration::from_millis(100))
+            .history(dds_history_kind::DDS_HISTORY_KEEP_LAST, depth)
+            .durability(dds_durability_kind::DDS_DURABILITY_VOLATILE);
+        qos
+    }
+}
+
+impl From<QosProfile> for DdsQos {
+    fn from(profile: QosProfile) -> Self {
+        profile.qos()
+    }
+}
+
+/// The participant user data of `rmw_cyclonedds`: `enclave=<enclave>;`
+pub fn participant_userdata(enclave: &str) -> Vec<u8> {
+    format!("enclave={};", enclave).into_bytes()
+}
+
+/// A participant QoS with the user data for `enclave` (`/` unless the
+/// node uses security enclaves)
+pub fn participant_qos(enclave: &str) -> DdsQos {
+    let mut qos = DdsQos::new();
+    qos.userdata(&participant_userdata(enclave));
+    qos
+}
+
+/// The enclave in the user data of a discovered participant
+pub fn parse_enclave(userdata: &[u8]) -> Option<String> {
+    let userdata = std::str::from_utf8(userdata).ok()?;
+    userdata
+        .split(';')
+        .find_map(|pair| pair.strip_prefix("enclave="))
+        .map(str::to_owned)
+}
+
+pub const DISCOVERY_INFO_TOPIC: &str = "ros_discovery_info";
+pub const DISCOVERY_INFO_TYPE: &str = "rmw_dds_common::msg::dds_::ParticipantEntitiesInfo_";
+
+/// The size of `rmw_dds_common/msg/Gid`, which changed between releases.
+/// Cyclone GUIDs fill the first 16 bytes either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GidSize {
+    /// `char[24]`, up to Humble
+    Bytes24,
+    /// `uint8[16]`, from Iron on
+    Bytes16,
+}
+
+impl GidSize {
+    fn len(self) -> usize {
+        match self {
+            GidSize::Bytes24 => 24,
+            GidSize::Bytes16 => 16,
+        }
+    }
+}
+
+/// A node and the GUIDs of its readers and writers
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct NodeEntitiesInfo {
+    pub namespace: String,
+    pub name: String,
+    pub readers: Vec<Guid>,
+    pub writers: Vec<Guid>,
+}
+
+/// The nodes of a participant, as published on `ros_discovery_info`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParticipantEntitiesInfo {
+    pub gid: Guid,
+    pub nodes: Vec<NodeEntitiesInfo>,
+}
+
+/// Little endian XCDR1 of the final structs of `rmw_dds_common`
+struct CdrWriter {
+    out: Vec<u8>,
+    gid_size: GidSize,
+}
+
+impl CdrWriter {
+    fn align4(&mut self) {
+        // alignment is relative to the end of the encapsulation header
+        while (self.out.len() - 4) & 3 != 0 {
+            self.out.push(0);
+        }
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.align4();
+        self.out.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32 + 1);
+        self.out.extend_from_slice(value.as_bytes());
+        self.out.push(0);
+    }
+
+    fn gid(&mut self, guid: &Guid) {
+        self.out.extend_from_slice(guid.as_bytes());
+        let len = self.out.len() + self.gid_size.len() - guid.as_bytes().len();
+        self.out.resize(len, 0);
+    }
+
+    fn gids(&mut self, guids: &[Guid]) {
+        self.u32(guids.len() as u32);
+        for guid in guids {
+            self.gid(guid);
+        }
+    }
+}
+
+impl ParticipantEntitiesInfo {
+    /// The serialized sample, with the encapsulation header
+    pub fn to_cdr(&self, gid_size: GidSize) -> Vec<u8> {
+        let mut w = CdrWriter {
+            out: vec![0x00, 0x01, 0x00, 0x00],
+            gid_size,
+        };
+        w.gid(&self.gid);
+        w.u32(self.nodes.len() as u32);
+        for node in &self.nodes {
+            w.string(&node.namespace);
+            w.string(&node.name);
+            w.gids(&node.readers);
+            w.gids(&node.writers);
+        }
+        // pad to a multiple of 4, the padding goes in the options
+        let padding = (4 - (w.out.len() % 4)) % 4;
+        w.out.resize(w.out.len() + padding, 0);
+        w.out[3] = padding as u8;
+        w.out
+    }
+}
+
+/// Announces the nodes of a participant on `ros_discovery_info`. Every
+/// change to [`info`](Self::info) should be followed by a [`publish`](Self::publish).
+pub struct DiscoveryInfoWriter {
+    writer: DdsEntity,
+    gid_size: GidSize,
+    pub info: ParticipantEntitiesInfo,
+}
+
+impl DiscoveryInfoWriter {
+    pub fn new(participant: &DdsEntity, gid_size: GidSize) -> Result<Self, DDSError> {
+        let mut qos = DdsQos::new();
+        qos.reliability(
+            dds_reliability_kind::DDS_RELIABILITY_RELIABLE,
+            DdsDuration::from_millis(100),
+        )
+        .history(dds_history_kind::DDS_HISTORY_KEEP_LAST, 1)
+        .durability(dds_durability_kind::DDS_DURABILITY_TRANSIENT_LOCAL);
+        let topic =
+            participant.create_raw_topic(DISCOVERY_INFO_TOPIC, DISCOVERY_INFO_TYPE, Some(&qos))?;
+        let writer = DDSError::check(unsafe {
+            dds_create_writer(participant.0, topic.0, qos.as_ptr(), std::ptr::null())
+        })?;
+        Ok(DiscoveryInfoWriter {
+            writer: DdsEntity(writer),
+            gid_size,
+            info: ParticipantEntitiesInfo {
+                gid: participant.guid()?,
+                nodes: Vec::new(),
+            },
+        })
+    }
+
+    pub fn writer(&self) -> &DdsEntity {
+        &self.writer
+    }
+
+    /// The node `name` in `namespace`, added if it is not there yet
+    pub fn node(&mut self, namespace: &str, name: &str) -> &mut NodeEntitiesInfo {
+        let index = match self
+            .info
+            .nodes
+            .iter()
+            .position(|n| n.namespace == namespace && n.name == name)
+        {
+            Some(index) => index,
+            None => {
+                self.info.nodes.push(NodeEntitiesInfo {
+                    namespace: namespace.to_owned(),
+                    name: name.to_owned(),
+                    ..Default::default()
+                });
+                self.info.nodes.len() - 1
+            }
+        };
+        &mut self.info.nodes[index]
+    }
+
+    pub fn remove_node(&mut self, namespace: &str, name: &str) {
+        self.info
+            .nodes
+            .retain(|n| n.namespace != namespace || n.name != name);
+    }
+
+    pub fn publish(&self) -> Result<(), DDSError> {
+        self.writer.write_raw(&self.info.to_cdr(self.gid_size))
+    }
+}
@@ -0,0 +1,787 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Request/reply over a pair of topics.
+//!
+//! A service `name` uses the topics `rq/<name>Request` and `rr/<name>Reply`,
+//! as ROS 2 does. Cyclone's sample info has no sequence numbers, so the
+//! correlation data travels in the samples themselves, in the header
+//! rmw_cyclonedds puts in front of every request and reply: the request and
+//! reply types start with a [`SampleIdentity`] (`unsigned long long guid;
+//! long long seq;` in IDL) and implement [`Request`] and [`Reply`] to expose
+//! it. Types laid out like that interoperate with ROS 2 clients and services.
+//!
+//! A request is identified by the instance handle of the requester's writer
+//! and a sequence number counting the requests it sent. Repliers copy that
+//! identity into the reply, which is how the requester matches replies to
+//! requests. The GUID of the requester is taken from the sample info of a
+//! request, see [`Replier::requester_guid`]. Requests may be answered by
+//! several repliers, and a replier may answer later, from any thread.
+//!
+//! Replies are only kept for requests that are still outstanding. A request
+//! stops being outstanding once [`Requester::receive_reply`] returned a reply
+//! for it, [`Requester::receive_replies`] returned, or it was given up with
+//! [`Requester::cancel`]; later replies to it are dropped.
+
+use std::collections::HashSet;
+use std::ffi::{c_void, CString};
+use std::fmt;
+use std::mem::MaybeUninit;
+use std::ops::Deref;
+use std::ptr;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::Instant;
+
+use crate::{
+    dds_create_readcondition, dds_create_reader, dds_create_topic, dds_create_waitset,
+    dds_create_writer, dds_delete, dds_entity_t, dds_free_op_t_DDS_FREE_CONTENTS,
+    dds_get_instance_handle, dds_history_kind, dds_instance_handle_t, dds_reliability_kind,
+    dds_sample_free, dds_sample_info_t, dds_take, dds_topic_descriptor_t, dds_waitset_attach,
+    dds_waitset_wait, dds_write, DDSError, DdsDuration, DdsEntity, DdsQos, Guid, State, StateMask,
+};
+
+/// Identifies a request: the requester's writer and a sequence number. The
+/// layout is that of rmw_cyclonedds' `cdds_request_header_t`.
+///
+/// Despite its name, rmw_cyclonedds fills `guid` with the instance handle of
+/// the writer (`dds_get_instance_handle`), not its GUID, and so does this
+/// module to stay compatible. It is only compared, never resolved.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct SampleIdentity {
+    /// Instance handle of the requester's writer
+    pub guid: u64,
+    /// Counts the requests sent by that writer, starting at 1
+    pub seq: i64,
+}
+
+impl fmt::Display for SampleIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:x}#{}", self.guid, self.seq)
+    }
+}
+
+/// A request type carrying its identity
+pub trait Request {
+    fn request_id(&self) -> SampleIdentity;
+    fn set_request_id(&mut self, id: SampleIdentity);
+}
+
+/// A reply type carrying the identity of the request it answers
+pub trait Reply {
+    fn related_request_id(&self) -> SampleIdentity;
+    fn set_related_request_id(&mut self, id: SampleIdentity);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RpcError {
+    Dds(DDSError),
+    /// No reply (or request) arrived in time
+    Timeout,
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RpcError::Dds(e) => write!(f, "{}", e),
+            RpcError::Timeout => write!(f, "timeout"),
+        }
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+impl From<DDSError> for RpcError {
+    fn from(e: DDSError) -> Self {
+        RpcError::Dds(e)
+    }
+}
+
+pub fn request_topic_name(service: &str) -> String {
+    format!("rq/{}Request", service)
+}
+
+pub fn reply_topic_name(service: &str) -> String {
+    format!("rr/{}Reply", service)
+}
+
+/// RELIABLE and KEEP_ALL, used when no QoS is given
+pub fn default_qos() -> DdsQos {
+    let mut qos = DdsQos::new();
+    qos.reliability(
+        dds_reliability_kind::DDS_RELIABILITY_RELIABLE,
+        DdsDuration::from_millis(100),
+    )
+    .history(dds_history_kind::DDS_HISTORY_KEEP_ALL, 0);
+    qos
+}
+
+/// A sample taken into memory owned by Rust. The contents Cyclone allocated
+/// for it (strings, sequences) are freed on drop.
+pub struct OwnedSample<T> {
+    sample: Box<T>,
+    info: dds_sample_info_t,
+    descriptor: &'static dds_topic_descriptor_t,
+}
+
+// the sample and everything it points to belong to this value alone
+unsafe impl<T> Send for OwnedSample<T> {}
+
+impl<T> OwnedSample<T> {
+    pub fn info(&self) -> &dds_sample_info_t {
+        &self.info
+    }
+}
+
+impl<T> Deref for OwnedSample<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.sample
+    }
+}
+
+impl<T> Drop for OwnedSample<T> {
+    fn drop(&mut self) {
+        unsafe {
+            dds_sample_free(
+                &mut *self.sample as *mut T as *mut c_void,
+                self.descriptor,
+                dds_free_op_t_DDS_FREE_CONTENTS,
+            )
+        }
+    }
+}
+
+/// The entities created so far by a constructor, deleted again in reverse
+/// order unless the constructor got to the end
+#[derive(Default)]
+struct Created(Vec<dds_entity_t>);
+
+impl Created {
+    fn add(&mut self, ret: dds_entity_t) -> Result<DdsEntity, DDSError> {
+        let entity = DDSError::check(ret)?;
+        self.0.push(entity);
+        Ok(DdsEntity(entity))
+    }
+
+    fn keep(mut self) {
+        self.0.clear();
+    }
+}
+
+impl Drop for Created {
+    fn drop(&mut self) {
+        for entity in self.0.iter().rev() {
+            unsafe { dds_delete(*entity) };
+        }
+    }
+}
+
+fn create_topic(
+    created: &mut Created,
+    participant: &DdsEntity,
+    descriptor: &'static dds_topic_descriptor_t,
+    name: &str,
+) -> Result<DdsEntity, DDSError> {
+    let name = CString::new(name).map_err(|_| DDSError::BadParameter)?;
+    created.add(unsafe {
+        dds_create_topic(
+            participant.0,
+            descriptor,
+            name.as_ptr(),
+            ptr::null(),
+            ptr::null(),
+        )
+    })
+}
+
+fn create_endpoints(
+    created: &mut Created,
+    participant: &DdsEntity,
+    writer_topic: &DdsEntity,
+    reader_topic: &DdsEntity,
+    qos: Option<&DdsQos>,
+) -> Result<(DdsEntity, DdsEntity), DDSError> {
+    let default = default_qos();
+    let qos = qos.unwrap_or(&default).as_ptr();
+    let writer = created
+        .add(unsafe { dds_create_writer(participant.0, writer_topic.0, qos, ptr::null()) })?;
+    let reader = created
+        .add(unsafe { dds_create_reader(participant.0, reader_topic.0, qos, ptr::null()) })?;
+    Ok((writer, reader))
+}
+
+fn instance_handle(entity: &DdsEntity) -> Result<dds_instance_handle_t, DDSError> {
+    let mut handle = 0;
+    DDSError::check(unsafe { dds_get_instance_handle(entity.0, &mut handle) })?;
+    Ok(handle)
+}
+
+/// A reader with a waitset that triggers while it holds samples
+struct Inbox<T> {
+    reader: DdsEntity,
+    waitset: DdsEntity,
+    descriptor: &'static dds_topic_descriptor_t,
+    _sample: std::marker::PhantomData<fn() -> T>,
+}
+
+// the descriptor is only ever read, and entities may be used from any thread
+unsafe impl<T> Send for Inbox<T> {}
+unsafe impl<T> Sync for Inbox<T> {}
+
+impl<T> Inbox<T> {
+    fn new(
+        created: &mut Created,
+        participant: &DdsEntity,
+        reader: DdsEntity,
+        descriptor: &'static dds_topic_descriptor_t,
+    ) -> Result<Self, DDSError> {
+        let mask = *StateMask::from(State::DdsAnyState);
+        let condition = created.add(unsafe { dds_create_readcondition(reader.0, mask) })?;
+        let waitset = created.add(unsafe { dds_create_waitset(participant.0) })?;
+        DDSError::check(unsafe { dds_waitset_attach(waitset.0, condition.0, 0) })?;
+        Ok(Inbox {
+            reader,
+            waitset,
+            descriptor,
+            _sample: std::marker::PhantomData,
+        })
+    }
+
+    /// Take the next sample with data, if any
+    fn take(&self) -> Result<Option<OwnedSample<T>>, DDSError> {
+        loop {
+            // zeroed so that Cyclone allocates strings and sequences afresh,
+            // it is only a `T` once Cyclone filled it in
+            let mut sample: Box<MaybeUninit<T>> = Box::new(MaybeUninit::zeroed());
+            let mut info = MaybeUninit::<dds_sample_info_t>::zeroed();
+            let mut buf = sample.as_mut_ptr() as *mut c_void;
+            let n = DDSError::check(unsafe {
+                dds_take(self.reader.0, &mut buf, info.as_mut_ptr(), 1, 1)
+            })?;
+            if n == 0 {
+                return Ok(None);
+            }
+            let info = unsafe { info.assume_init() };
+            if info.valid_data {
+                let sample = unsafe { Box::from_raw(Box::into_raw(sample) as *mut T) };
+                return Ok(Some(OwnedSample {
+                    sample,
+                    info,
+                    descriptor: self.descriptor,
+                }));
+            }
+            // a dispose or unregister, only the key fields are set: skip it
+            unsafe { dds_sample_free(buf, self.descriptor, dds_free_op_t_DDS_FREE_CONTENTS) };
+        }
+    }
+
+    /// Wait for data. Returns early without an error on timeout.
+    fn wait(&self, timeout: DdsDuration) -> Result<(), DDSError> {
+        let ret = unsafe { dds_waitset_wait(self.waitset.0, ptr::null_mut(), 0, timeout.into()) };
+        DDSError::check(ret).map(|_| ())
+    }
+
+    /// Delete the waitset and the reader with its read condition
+    fn delete(&self) {
+        unsafe {
+            dds_delete(self.waitset.0);
+            dds_delete(self.reader.0);
+        }
+    }
+}
+
+fn remaining(deadline: Option<Instant>) -> Option<DdsDuration> {
+    match deadline {
+        None => Some(DdsDuration::from_nanos(crate::DDS_INFINITY)),
+        Some(d) => {
+            let now = Instant::now();
+            if now >= d {
+                None
+            } else {
+                Some((d - now).into())
+            }
+        }
+    }
+}
+
+/// Replies arrived for a requester, `S` being an owned reply sample
+struct Replies<S> {
+    received: Vec<S>,
+    /// Sequence numbers of the requests replies are kept for
+    outstanding: HashSet<i64>,
+    /// A thread is blocked on the waitset, others wait on the condvar
+    waiting: bool,
+}
+
+impl<S> Replies<S>
+where
+    S: Deref,
+    S::Target: Reply,
+{
+    fn claim(&mut self, id: SampleIdentity) -> Option<S> {
+        let index = self
+            .received
+            .iter()
+            .position(|r| r.related_request_id() == id)?;
+        Some(self.received.remove(index))
+    }
+
+    /// Stop keeping replies to `id` and drop those already received
+    fn forget(&mut self, id: SampleIdentity) {
+        self.outstanding.remove(&id.seq);
+        self.received.retain(|r| r.related_request_id() != id);
+    }
+}
+
+/// The client side of a service
+pub struct Requester<Req, Rep> {
+    writer: DdsEntity,
+    inbox: Inbox<Rep>,
+    topics: [DdsEntity; 2],
+    handle: dds_instance_handle_t,
+    sequence: AtomicI64,
+    replies: Mutex<Replies<OwnedSample<Rep>>>,
+    arrived: Condvar,
+    _request: std::marker::PhantomData<fn(&Req)>,
+}
+
+impl<Req: Request, Rep: Reply> Requester<Req, Rep> {
+    /// Create the topics of `service` and the endpoints of a requester.
+    /// `qos` applies to the writer and reader, [`default_qos`] if `None`.
+    ///
+    /// # Safety
+    /// `Req` and `Rep` must be the in-memory sample types described by
+    /// `request` and `reply`.
+    pub unsafe fn new(
+        participant: &DdsEntity,
+        service: &str,
+        request: &'static dds_topic_descriptor_t,
+        reply: &'static dds_topic_descriptor_t,
+        qos: Option<&DdsQos>,
+    ) -> Result<Self, DDSError> {
+        let mut created = Created::default();
+        let request_topic = create_topic(
+            &mut created,
+            participant,
+            request,
+            &request_topic_name(service),
+        )?;
+        let reply_topic =
+            create_topic(&mut created, participant, reply, &reply_topic_name(service))?;
+        let (writer, reader) =
+            create_endpoints(&mut created, participant, &request_topic, &reply_topic, qos)?;
+        let handle = instance_handle(&writer)?;
+        let inbox = Inbox::new(&mut created, participant, reader, reply)?;
+        created.keep();
+        Ok(Requester {
+            writer,
+            inbox,
+            topics: [request_topic, reply_topic],
+            handle,
+            sequence: AtomicI64::new(0),
+            replies: Mutex::new(Replies {
+                received: Vec::new(),
+                outstanding: HashSet::new(),
+                waiting: false,
+            }),
+            arrived: Condvar::new(),
+            _request: std::marker::PhantomData,
+        })
+    }
+
+    pub fn writer(&self) -> &DdsEntity {
+        &self.writer
+    }
+
+    pub fn reader(&self) -> &DdsEntity {
+        &self.inbox.reader
+    }
+
+    /// Send a request without waiting for the reply. The returned identity
+    /// is used to collect the reply later.
+    pub fn send_request(&self, request: &mut Req) -> Result<SampleIdentity, DDSError> {
+        let id = SampleIdentity {
+            guid: self.handle,
+            seq: self.sequence.fetch_add(1, Ordering::Relaxed) + 1,
+        };
+        request.set_request_id(id);
+        // outstanding before it is sent, a reply may be quick
+        self.lock().outstanding.insert(id.seq);
+        let ret = unsafe { dds_write(self.writer.0, request as *mut Req as *const c_void) };
+        if let Err(e) = DDSError::check(ret) {
+            self.lock().forget(id);
+            return Err(e);
+        }
+        Ok(id)
+    }
+
+    /// Send a request and wait for the first reply
+    pub fn request<D: Into<DdsDuration>>(
+        &self,
+        request: &mut Req,
+        timeout: D,
+    ) -> Result<OwnedSample<Rep>, RpcError> {
+        let id = self.send_request(request)?;
+        let reply = self.receive_reply(id, timeout);
+        if reply.is_err() {
+            self.cancel(id);
+        }
+        reply
+    }
+
+    /// A reply to `id` that has already arrived
+    pub fn try_receive_reply(
+        &self,
+        id: SampleIdentity,
+    ) -> Result<Option<OwnedSample<Rep>>, DDSError> {
+        let mut replies = self.lock();
+        self.drain(&mut replies)?;
+        let reply = replies.claim(id);
+        if reply.is_some() {
+            replies.forget(id);
+        }
+        Ok(reply)
+    }
+
+    /// Wait for a reply to `id`. Once a reply is returned, further replies
+    /// to `id` are dropped.
+    pub fn receive_reply<D: Into<DdsDuration>>(
+        &self,
+        id: SampleIdentity,
+        timeout: D,
+    ) -> Result<OwnedSample<Rep>, RpcError> {
+        let deadline = timeout.into().to_std().map(|t| Instant::now() + t);
+        let mut replies = self.lock();
+        loop {
+            if let Some(reply) = replies.claim(id) {
+                replies.forget(id);
+                return Ok(reply);
+            }
+            replies = self.wait(replies, deadline)?;
+        }
+    }
+
+    /// Collect replies to `id`, from any number of repliers, until `max`
+    /// replies arrived or `timeout` passed. Replies to `id` arriving later
+    /// are dropped.
+    pub fn receive_replies<D: Into<DdsDuration>>(
+        &self,
+        id: SampleIdentity,
+        max: usize,
+        timeout: D,
+    ) -> Result<Vec<OwnedSample<Rep>>, DDSError> {
+        let deadline = timeout.into().to_std().map(|t| Instant::now() + t);
+        let mut collected = Vec::new();
+        let mut replies = self.lock();
+        while collected.len() < max {
+            while collected.len() < max {
+                match replies.claim(id) {
+                    Some(reply) => collected.push(reply),
+                    None => break,
+                }
+            }
+            if collected.len() == max {
+                break;
+            }
+            replies = match self.wait(replies, deadline) {
+                Ok(replies) => replies,
+                Err(e) => {
+                    self.lock().forget(id);
+                    return match e {
+                        RpcError::Timeout => Ok(collected),
+                        RpcError::Dds(e) => Err(e),
+                    };
+                }
+            };
+        }
+        replies.forget(id);
+        Ok(collected)
+    }
+
+    /// Give up on `id`: replies to it that arrived or arrive later are
+    /// dropped
+    pub fn cancel(&self, id: SampleIdentity) {
+        self.lock().forget(id);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Replies<OwnedSample<Rep>>> {
+        self.replies.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Move arrived replies to outstanding requests of this requester into
+    /// `replies`, dropping all others
+    fn drain(&self, replies: &mut Replies<OwnedSample<Rep>>) -> Result<(), DDSError> {
+        while let Some(reply) = self.inbox.take()? {
+            let id = reply.related_request_id();
+            if id.guid == self.handle && replies.outstanding.contains(&id.seq) {
+                replies.received.push(reply);
+            }
+        }
+        Ok(())
+    }
+
+    /// Block until new replies may have arrived. Only one thread waits on
+    /// the waitset; the others are woken when it has drained the reader.
+    fn wait<'a>(
+        &'a self,
+        mut replies: MutexGuard<'a, Replies<OwnedSample<Rep>>>,
+        deadline: Option<Instant>,
+    ) -> Result<MutexGuard<'a, Replies<OwnedSample<Rep>>>, RpcError> {
+        let timeout = remaining(deadline).ok_or(RpcError::Timeout)?;
+        if replies.waiting {
+            let replies = match timeout.to_std() {
+                Some(t) => {
+                    self.arrived
+                        .wait_timeout(replies, t)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0
+                }
+                None => self
+                    .arrived
+                    .wait(replies)
+                    .unwrap_or_else(|e| e.into_inner()),
+            };
+            return Ok(replies);
+        }
+
+        replies.waiting = true;
+        drop(replies);
+        let waited = self.inbox.wait(timeout);
+        let mut replies = self.lock();
+        replies.waiting = false;
+        let drained = self.drain(&mut replies);
+        self.arrived.notify_all();
+        waited?;
+        drained?;
+        Ok(replies)
+    }
+}
+
+impl<Req, Rep> Drop for Requester<Req, Rep> {
+    fn drop(&mut self) {
+        unsafe { dds_delete(self.writer.0) };
+        self.inbox.delete();
+        delete_topics(&self.topics);
+    }
+}
+
+/// The service side
+pub struct Replier<Req, Rep> {
+    writer: DdsEntity,
+    inbox: Inbox<Req>,
+    topics: [DdsEntity; 2],
+    /// A thread is blocked on the waitset, others wait on the condvar
+    waiting: Mutex<bool>,
+    arrived: Condvar,
+    _reply: std::marker::PhantomData<fn(&Rep)>,
+}
+
+impl<Req: Request, Rep: Reply> Replier<Req, Rep> {
+    /// Create the topics of `service` and the endpoints of a replier.
+    /// `qos` applies to the writer and reader, [`default_qos`] if `None`.
+    ///
+    /// # Safety
+    /// `Req` and `Rep` must be the in-memory sample types described by
+    /// `request` and `reply`.
+    pub unsafe fn new(
+        participant: &DdsEntity,
+        service: &str,
+        request: &'static dds_topic_descriptor_t,
+        reply: &'static dds_topic_descriptor_t,
+        qos: Option<&DdsQos>,
+    ) -> Result<Self, DDSError> {
+        let mut created = Created::default();
+        let request_topic = create_topic(
+            &mut created,
+            participant,
+            request,
+            &request_topic_name(service),
+        )?;
+        let reply_topic =
+            create_topic(&mut created, participant, reply, &reply_topic_name(service))?;
+        let (writer, reader) =
+            create_endpoints(&mut created, participant, &reply_topic, &request_topic, qos)?;
+        let inbox = Inbox::new(&mut created, participant, reader, request)?;
+        created.keep();
+        Ok(Replier {
+            writer,
+            inbox,
+            topics: [request_topic, reply_topic],
+            waiting: Mutex::new(false),
+            arrived: Condvar::new(),
+            _reply: std::marker::PhantomData,
+        })
+    }
+
+    pub fn writer(&self) -> &DdsEntity {
+        &self.writer
+    }
+
+    pub fn reader(&self) -> &DdsEntity {
+        &self.inbox.reader
+    }
+
+    /// The GUID of the writer that sent `request`, from its sample info.
+    /// `None` once that writer is no longer matched.
+    pub fn requester_guid(&self, request: &OwnedSample<Req>) -> Option<Guid> {
+        self.inbox
+            .reader
+            .matched_writer(request.info().publication_handle)
+            .map(|writer| writer.guid)
+    }
+
+    /// A request that has already arrived
+    pub fn try_receive_request(&self) -> Result<Option<OwnedSample<Req>>, DDSError> {
+        self.inbox.take()
+    }
+
+    /// Wait for the next request. Several threads may wait at once; each
+    /// request goes to one of them.
+    pub fn receive_request<D: Into<DdsDuration>>(
+        &self,
+        timeout: D,
+    ) -> Result<OwnedSample<Req>, RpcError> {
+        let deadline = timeout.into().to_std().map(|t| Instant::now() + t);
+        loop {
+            if let Some(request) = self.inbox.take()? {
+                return Ok(request);
+            }
+            self.wait(deadline)?;
+        }
+    }
+
+    /// Answer the request `id`. This can happen at any time after the
+    /// request arrived, and more than once.
+    pub fn send_reply(&self, id: SampleIdentity, reply: &mut Rep) -> Result<(), DDSError> {
+        reply.set_related_request_id(id);
+        DDSError::check(unsafe { dds_write(self.writer.0, reply as *mut Rep as *const c_void) })
+            .map(|_| ())
+    }
+
+    /// Block until a request may have arrived. As for the requester, only
+    /// one thread waits on the waitset and wakes the others.
+    fn wait(&self, deadline: Option<Instant>) -> Result<(), RpcError> {
+        let timeout = remaining(deadline).ok_or(RpcError::Timeout)?;
+        let mut waiting = self.waiting.lock().unwrap_or_else(|e| e.into_inner());
+        if *waiting {
+            match timeout.to_std() {
+                Some(t) => drop(self.arrived.wait_timeout(waiting, t)),
+                None => drop(self.arrived.wait(waiting)),
+            }
+            return Ok(());
+        }
+
+        *waiting = true;
+        drop(waiting);
+        let waited = self.inbox.wait(timeout);
+        *self.waiting.lock().unwrap_or_else(|e| e.into_inner()) = false;
+        self.arrived.notify_all();
+        waited.map_err(RpcError::Dds)
+    }
+}
+
+impl<Req, Rep> Drop for Replier<Req, Rep> {
+    fn drop(&mut self) {
+        unsafe { dds_delete(self.writer.0) };
+        self.inbox.delete();
+        delete_topics(&self.topics);
+    }
+}
+
+/// Topics go last, Cyclone refuses to delete them while readers or writers
+/// use them
+fn delete_topics(topics: &[DdsEntity]) {
+    for topic in topics {
+        unsafe { dds_delete(topic.0) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    struct TestReply {
+        related: SampleIdentity,
+        value: i32,
+    }
+
+    impl Reply for TestReply {
+        fn related_request_id(&self) -> SampleIdentity {
+            self.related
+        }
+
+        fn set_related_request_id(&mut self, id: SampleIdentity) {
+            self.related = id;
+        }
+    }
+
+    fn id(seq: i64) -> SampleIdentity {
+        SampleIdentity { guid: 7, seq }
+    }
+
+    fn reply(seq: i64, value: i32) -> Box<TestReply> {
+        Box::new(TestReply {
+            related: id(seq),
+            value,
+        })
+    }
+
+    #[test]
+    fn remaining_time_runs_out() {
+        assert_eq!(
+            remaining(None),
+            Some(DdsDuration::from_nanos(crate::DDS_INFINITY))
+        );
+        assert_eq!(remaining(Some(Instant::now())), None);
+        let left = remaining(Some(Instant::now() + Duration::from_secs(60)))
+            .and_then(|d| d.to_std())
+            .unwrap();
+        assert!(left > Duration::from_secs(59) && left <= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn replies_are_claimed_in_arrival_order() {
+        let mut replies = Replies {
+            received: vec![reply(1, 10), reply(2, 20), reply(1, 11)],
+            outstanding: [1, 2].iter().copied().collect(),
+            waiting: false,
+        };
+        assert_eq!(replies.claim(id(1)).map(|r| r.value), Some(10));
+        assert_eq!(replies.claim(id(1)).map(|r| r.value), Some(11));
+        assert!(replies.claim(id(1)).is_none());
+        // another requester's request with the same sequence number
+        assert!(replies.claim(SampleIdentity { guid: 8, seq: 2 }).is_none());
+        assert_eq!(replies.received.len(), 1);
+    }
+
+    #[test]
+    fn forgotten_requests_drop_their_replies() {
+        let mut replies = Replies {
+            received: vec![reply(1, 10), reply(2, 20), reply(1, 11)],
+            outstanding: [1, 2].iter().copied().collect(),
+            waiting: false,
+        };
+        replies.forget(id(1));
+        assert!(!replies.outstanding.contains(&1));
+        assert!(replies.outstanding.contains(&2));
+        assert!(replies.claim(id(1)).is_none());
+        assert_eq!(replies.claim(id(2)).map(|r| r.value), Some(20));
+    }
+}
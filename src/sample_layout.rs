@@ -0,0 +1,571 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Field layout of samples described by the serializer ops of a
+//! `dds_topic_descriptor_t`.
+//!
+//! The ops describe where each member lives in the C representation of a
+//! sample but carry no member names. Names are supplied by the caller, one per
+//! leaf member in declaration order, with members of nested structs flattened
+//! (e.g. `["id", "position.x", "position.y", "speed"]`).
+
+use std::collections::BTreeSet;
+use std::ffi::{c_void, CStr};
+use std::fmt;
+use std::os::raw::c_char;
+
+use crate::*;
+
+const DDS_OP_MASK: u32 = 0xff00_0000;
+const DDS_OP_TYPE_MASK: u32 = 0x007f_0000;
+const DDS_OP_SUBTYPE_MASK: u32 = 0x0000_ff00;
+
+pub const DDS_OP_FLAG_KEY: u32 = 1 << 0;
+pub const DDS_OP_FLAG_FP: u32 = 1 << 1;
+pub const DDS_OP_FLAG_SGN: u32 = 1 << 2;
+pub const DDS_OP_FLAG_EXT: u32 = 1 << 6;
+
+pub(crate) fn op_code(insn: u32) -> u32 {
+    insn & DDS_OP_MASK
+}
+
+pub(crate) fn op_type(insn: u32) -> u32 {
+    (insn & DDS_OP_TYPE_MASK) >> 16
+}
+
+pub(crate) fn op_subtype(insn: u32) -> u32 {
+    (insn & DDS_OP_SUBTYPE_MASK) >> 8
+}
+
+pub(crate) fn op_jmp(word: u32) -> usize {
+    (word >> 16) as usize
+}
+
+pub(crate) fn op_jsr(word: u32) -> isize {
+    (word & 0xffff) as u16 as i16 as isize
+}
+
+/// Type of a leaf member of a sample
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldKind {
+    Bool,
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Int64,
+    UInt64,
+    Float32,
+    Float64,
+    Enum,
+    /// `char *`
+    String,
+    /// Inline `char[N]`, N including the terminating NUL
+    BoundedString(usize),
+    /// Sequences, arrays, unions, bitmasks and external members. These take
+    /// part in the layout but their value cannot be read.
+    Unsupported,
+}
+
+impl FieldKind {
+    pub fn is_numeric(&self) -> bool {
+        !matches!(
+            self,
+            FieldKind::String | FieldKind::BoundedString(_) | FieldKind::Unsupported
+        )
+    }
+
+    pub fn is_string(&self) -> bool {
+        matches!(self, FieldKind::String | FieldKind::BoundedString(_))
+    }
+}
+
+/// A leaf member of a sample
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub name: String,
+    pub offset: usize,
+    pub kind: FieldKind,
+    pub key: bool,
+}
+
+/// Value of a member read from (or to be compared against) a sample
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str(String),
+}
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FieldValue::Bool(v) => write!(f, "{}", v),
+            FieldValue::Int(v) => write!(f, "{}", v),
+            FieldValue::UInt(v) => write!(f, "{}", v),
+            FieldValue::Float(v) => write!(f, "{}", v),
+            FieldValue::Str(v) => write!(f, "'{}'", v),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayoutError {
+    /// The descriptor has no serializer ops
+    NoOps,
+    /// An instruction that is not understood was found at the given op index
+    UnsupportedOp(usize, u32),
+    /// The number of names does not match the number of leaf members
+    NameCount { expected: usize, got: usize },
+    /// The ops run on past the number of instructions in `m_nops`
+    Overrun(usize),
+}
+
+impl std::error::Error for LayoutError {}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LayoutError::NoOps => write!(f, "topic descriptor has no ops"),
+            LayoutError::UnsupportedOp(index, insn) => {
+                write!(f, "unsupported op {:#010x} at index {}", insn, index)
+            }
+            LayoutError::NameCount { expected, got } => write!(
+                f,
+                "type has {} leaf members but {} names were given",
+                expected, got
+            ),
+            LayoutError::Overrun(nops) => write!(
+                f,
+                "ops do not end within the {} instructions the descriptor declares",
+                nops
+            ),
+        }
+    }
+}
+
+/// Leaf members of a sample type, in declaration order
+#[derive(Debug, Clone)]
+pub struct SampleLayout {
+    type_name: String,
+    size: usize,
+    fields: Vec<Field>,
+}
+
+impl SampleLayout {
+    /// Build the layout from a topic descriptor, naming the leaf members with
+    /// `names`.
+    ///
+    /// # Safety
+    /// `desc` must point to a valid topic descriptor whose `m_ops` array holds
+    /// the `m_nops` instructions it declares.
+    pub unsafe fn from_descriptor<S: AsRef<str>>(
+        desc: *const dds_topic_descriptor_t,
+        names: &[S],
+    ) -> Result<Self, LayoutError> {
+        let desc = &*desc;
+        if desc.m_ops.is_null() || desc.m_nops == 0 {
+            return Err(LayoutError::NoOps);
+        }
+        let type_name = if desc.m_typename.is_null() {
+            String::new()
+        } else {
+            CStr::from_ptr(desc.m_typename)
+                .to_string_lossy()
+                .into_owned()
+        };
+
+        let mut leaves = Vec::new();
+        walk_struct(&mut Ops::new(desc), 0, 0, 0, &mut leaves)?;
+
+        if leaves.len() != names.len() {
+            return Err(LayoutError::NameCount {
+                expected: leaves.len(),
+                got: names.len(),
+            });
+        }
+        let fields = leaves
+            .into_iter()
+            .zip(names.iter())
+            .map(|((offset, kind, key), name)| Field {
+                name: name.as_ref().to_owned(),
+                offset,
+                kind,
+                key,
+            })
+            .collect();
+
+        Ok(SampleLayout {
+            type_name,
+            size: desc.m_size as usize,
+            fields,
+        })
+    }
+
+    /// Count the leaf members of a descriptor, i.e. the number of names
+    /// `from_descriptor` expects.
+    ///
+    /// # Safety
+    /// Same requirements as `from_descriptor`.
+    pub unsafe fn leaf_count(desc: *const dds_topic_descriptor_t) -> Result<usize, LayoutError> {
+        let desc = &*desc;
+        if desc.m_ops.is_null() || desc.m_nops == 0 {
+            return Err(LayoutError::NoOps);
+        }
+        let mut leaves = Vec::new();
+        walk_struct(&mut Ops::new(desc), 0, 0, 0, &mut leaves)?;
+        Ok(leaves.len())
+    }
+
+    pub fn type_name(&self) -> &str {
+        &self.type_name
+    }
+
+    /// Size of the C representation of a sample
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    /// Read the value of a field from a sample. Returns `None` for fields whose
+    /// kind cannot be read.
+    ///
+    /// # Safety
+    /// `sample` must point to a valid sample of the type this layout was built for.
+    pub unsafe fn read(&self, field: &Field, sample: *const c_void) -> Option<FieldValue> {
+        let p = (sample as *const u8).add(field.offset);
+        let value = match field.kind {
+            FieldKind::Bool => FieldValue::Bool(*p != 0),
+            FieldKind::Int8 => FieldValue::Int((p as *const i8).read_unaligned() as i64),
+            FieldKind::UInt8 => FieldValue::UInt(*p as u64),
+            FieldKind::Int16 => FieldValue::Int((p as *const i16).read_unaligned() as i64),
+            FieldKind::UInt16 => FieldValue::UInt((p as *const u16).read_unaligned() as u64),
+            FieldKind::Int32 => FieldValue::Int((p as *const i32).read_unaligned() as i64),
+            FieldKind::UInt32 | FieldKind::Enum => {
+                FieldValue::UInt((p as *const u32).read_unaligned() as u64)
+            }
+            FieldKind::Int64 => FieldValue::Int((p as *const i64).read_unaligned()),
+            FieldKind::UInt64 => FieldValue::UInt((p as *const u64).read_unaligned()),
+            FieldKind::Float32 => FieldValue::Float((p as *const f32).read_unaligned() as f64),
+            FieldKind::Float64 => FieldValue::Float((p as *const f64).read_unaligned()),
+            FieldKind::String => {
+                let s = (p as *const *const c_char).read_unaligned();
+                if s.is_null() {
+                    FieldValue::Str(String::new())
+                } else {
+                    FieldValue::Str(CStr::from_ptr(s).to_string_lossy().into_owned())
+                }
+            }
+            FieldKind::BoundedString(max) => {
+                let bytes = std::slice::from_raw_parts(p, max);
+                let len = bytes.iter().position(|b| *b == 0).unwrap_or(max);
+                FieldValue::Str(String::from_utf8_lossy(&bytes[..len]).into_owned())
+            }
+            FieldKind::Unsupported => return None,
+        };
+        Some(value)
+    }
+}
+
+fn primitive_kind(insn: u32, typecode: u32) -> FieldKind {
+    let signed = insn & DDS_OP_FLAG_SGN != 0;
+    let float = insn & DDS_OP_FLAG_FP != 0;
+    match typecode {
+        dds_stream_typecode_DDS_OP_VAL_BLN => FieldKind::Bool,
+        dds_stream_typecode_DDS_OP_VAL_1BY if signed => FieldKind::Int8,
+        dds_stream_typecode_DDS_OP_VAL_1BY => FieldKind::UInt8,
+        dds_stream_typecode_DDS_OP_VAL_2BY if signed => FieldKind::Int16,
+        dds_stream_typecode_DDS_OP_VAL_2BY => FieldKind::UInt16,
+        dds_stream_typecode_DDS_OP_VAL_4BY if float => FieldKind::Float32,
+        dds_stream_typecode_DDS_OP_VAL_4BY if signed => FieldKind::Int32,
+        dds_stream_typecode_DDS_OP_VAL_4BY => FieldKind::UInt32,
+        dds_stream_typecode_DDS_OP_VAL_8BY if float => FieldKind::Float64,
+        dds_stream_typecode_DDS_OP_VAL_8BY if signed => FieldKind::Int64,
+        dds_stream_typecode_DDS_OP_VAL_8BY => FieldKind::UInt64,
+        _ => FieldKind::Unsupported,
+    }
+}
+
+/// Number of op words taken by a sequence member
+unsafe fn sequence_len(insn: u32, ops: *const u32) -> usize {
+    let bound = if op_type(insn) == dds_stream_typecode_DDS_OP_VAL_BSQ {
+        1
+    } else {
+        0
+    };
+    match op_subtype(insn) {
+        dds_stream_typecode_DDS_OP_VAL_BLN
+        | dds_stream_typecode_DDS_OP_VAL_1BY
+        | dds_stream_typecode_DDS_OP_VAL_2BY
+        | dds_stream_typecode_DDS_OP_VAL_4BY
+        | dds_stream_typecode_DDS_OP_VAL_8BY
+        | dds_stream_typecode_DDS_OP_VAL_STR => 2 + bound,
+        dds_stream_typecode_DDS_OP_VAL_BST => 3 + bound,
+        dds_stream_typecode_DDS_OP_VAL_ENU => 4 + bound,
+        dds_stream_typecode_DDS_OP_VAL_BMK => 5 + bound,
+        _ => match op_jmp(*ops.add(3 + bound)) {
+            0 => 4 + bound,
+            jmp => jmp,
+        },
+    }
+}
+
+/// Number of op words taken by an array member
+unsafe fn array_len(insn: u32, ops: *const u32) -> usize {
+    match op_subtype(insn) {
+        dds_stream_typecode_DDS_OP_VAL_BLN
+        | dds_stream_typecode_DDS_OP_VAL_1BY
+        | dds_stream_typecode_DDS_OP_VAL_2BY
+        | dds_stream_typecode_DDS_OP_VAL_4BY
+        | dds_stream_typecode_DDS_OP_VAL_8BY
+        | dds_stream_typecode_DDS_OP_VAL_STR => 3,
+        dds_stream_typecode_DDS_OP_VAL_ENU => 4,
+        dds_stream_typecode_DDS_OP_VAL_BST | dds_stream_typecode_DDS_OP_VAL_BMK => 5,
+        _ => match op_jmp(*ops.add(3)) {
+            0 => 5,
+            jmp => jmp,
+        },
+    }
+}
+
+/// The ops of a descriptor and the number of instructions it declares in
+/// `m_nops`. Every instruction is counted once, however often a nested struct
+/// is referred to, so an ops stream that does not end where the descriptor
+/// says it does is rejected instead of being read past its end.
+struct Ops {
+    ops: *const u32,
+    nops: usize,
+    seen: BTreeSet<usize>,
+}
+
+impl Ops {
+    fn new(desc: &dds_topic_descriptor_t) -> Self {
+        Ops {
+            ops: desc.m_ops,
+            nops: desc.m_nops as usize,
+            seen: BTreeSet::new(),
+        }
+    }
+
+    /// The instruction at `index`
+    unsafe fn insn(&mut self, index: usize) -> Result<u32, LayoutError> {
+        if !self.seen.contains(&index) {
+            if self.seen.len() >= self.nops {
+                return Err(LayoutError::Overrun(self.nops));
+            }
+            self.seen.insert(index);
+        }
+        Ok(*self.ops.add(index))
+    }
+
+    /// An operand of the instruction at `index`
+    unsafe fn word(&self, index: usize) -> u32 {
+        *self.ops.add(index)
+    }
+}
+
+/// Collect (offset, kind, key) for every leaf member of the struct whose ops
+/// start at `start`, adding `base` to each offset. `depth` is the nesting
+/// level, which cannot exceed the number of instructions either.
+unsafe fn walk_struct(
+    ops: &mut Ops,
+    start: usize,
+    base: usize,
+    depth: usize,
+    leaves: &mut Vec<(usize, FieldKind, bool)>,
+) -> Result<(), LayoutError> {
+    if depth > ops.nops {
+        return Err(LayoutError::Overrun(ops.nops));
+    }
+    let mut index = start;
+    loop {
+        let insn = ops.insn(index)?;
+        match op_code(insn) {
+            dds_stream_opcode_DDS_OP_RTS => return Ok(()),
+            dds_stream_opcode_DDS_OP_DLC => index += 1,
+            dds_stream_opcode_DDS_OP_ADR => {
+                let offset = base + ops.word(index + 1) as usize;
+                let key = insn & DDS_OP_FLAG_KEY != 0;
+                let typecode = op_type(insn);
+                let (kind, len) = match typecode {
+                    dds_stream_typecode_DDS_OP_VAL_BLN
+                    | dds_stream_typecode_DDS_OP_VAL_1BY
+                    | dds_stream_typecode_DDS_OP_VAL_2BY
+                    | dds_stream_typecode_DDS_OP_VAL_4BY
+                    | dds_stream_typecode_DDS_OP_VAL_8BY => (primitive_kind(insn, typecode), 2),
+                    dds_stream_typecode_DDS_OP_VAL_STR => (FieldKind::String, 2),
+                    dds_stream_typecode_DDS_OP_VAL_BST => {
+                        (FieldKind::BoundedString(ops.word(index + 2) as usize), 3)
+                    }
+                    dds_stream_typecode_DDS_OP_VAL_ENU => (FieldKind::Enum, 3),
+                    dds_stream_typecode_DDS_OP_VAL_BMK => (FieldKind::Unsupported, 4),
+                    dds_stream_typecode_DDS_OP_VAL_SEQ | dds_stream_typecode_DDS_OP_VAL_BSQ => (
+                        FieldKind::Unsupported,
+                        sequence_len(insn, ops.ops.add(index)),
+                    ),
+                    dds_stream_typecode_DDS_OP_VAL_ARR => {
+                        (FieldKind::Unsupported, array_len(insn, ops.ops.add(index)))
+                    }
+                    dds_stream_typecode_DDS_OP_VAL_UNI => {
+                        let len = match op_jmp(ops.word(index + 3)) {
+                            0 => 4,
+                            jmp => jmp,
+                        };
+                        (FieldKind::Unsupported, len)
+                    }
+                    dds_stream_typecode_DDS_OP_VAL_EXT => {
+                        let jump = ops.word(index + 2);
+                        let len = match op_jmp(jump) {
+                            0 => 3,
+                            jmp => jmp,
+                        };
+                        if insn & DDS_OP_FLAG_EXT != 0 {
+                            // @external members are pointers, not inline structs
+                            (FieldKind::Unsupported, len)
+                        } else {
+                            let nested = index as isize + op_jsr(jump);
+                            if nested < 0 {
+                                return Err(LayoutError::UnsupportedOp(index, insn));
+                            }
+                            walk_struct(ops, nested as usize, offset, depth + 1, leaves)?;
+                            index += len;
+                            continue;
+                        }
+                    }
+                    _ => return Err(LayoutError::UnsupportedOp(index, insn)),
+                };
+                leaves.push((offset, kind, key));
+                index += len;
+            }
+            _ => return Err(LayoutError::UnsupportedOp(index, insn)),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) const ADR: u32 = dds_stream_opcode_DDS_OP_ADR;
+    pub(crate) const INT32: u32 =
+        ADR | dds_stream_typecode_primary_DDS_OP_TYPE_4BY | DDS_OP_FLAG_SGN;
+    pub(crate) const STRING: u32 = ADR | dds_stream_typecode_primary_DDS_OP_TYPE_STR;
+    pub(crate) const EXT: u32 = ADR | dds_stream_typecode_primary_DDS_OP_TYPE_EXT;
+    pub(crate) const RTS: u32 = dds_stream_opcode_DDS_OP_RTS;
+
+    /// A descriptor for hand-written ops, leaked like the ones idlc generates
+    pub(crate) fn descriptor(
+        size: u32,
+        ops: Vec<u32>,
+        nops: u32,
+    ) -> &'static dds_topic_descriptor_t {
+        let mut desc: dds_topic_descriptor_t = unsafe { std::mem::zeroed() };
+        desc.m_size = size;
+        desc.m_align = 8;
+        desc.m_typename = b"test::Sample\0".as_ptr() as *const _;
+        desc.m_nops = nops;
+        desc.m_ops = Box::leak(ops.into_boxed_slice()).as_ptr();
+        Box::leak(Box::new(desc))
+    }
+
+    #[test]
+    fn nested_structs_are_flattened() {
+        // struct { int32 id; struct { int32 x; string s; } inner; }
+        let ops = vec![
+            INT32 | DDS_OP_FLAG_KEY,
+            0,
+            EXT,
+            8,
+            (3 << 16) | 4,
+            RTS,
+            INT32,
+            0,
+            STRING,
+            8,
+            RTS,
+        ];
+        let desc = descriptor(24, ops, 6);
+        let layout =
+            unsafe { SampleLayout::from_descriptor(desc, &["id", "inner.x", "inner.s"]) }.unwrap();
+        let fields: Vec<_> = layout
+            .fields()
+            .iter()
+            .map(|f| (f.name.as_str(), f.offset, f.kind, f.key))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("id", 0, FieldKind::Int32, true),
+                ("inner.x", 8, FieldKind::Int32, false),
+                ("inner.s", 16, FieldKind::String, false),
+            ]
+        );
+        assert_eq!(layout.type_name(), "test::Sample");
+    }
+
+    #[test]
+    fn names_must_match_leaves() {
+        let desc = descriptor(8, vec![INT32, 0, INT32, 4, RTS], 3);
+        assert_eq!(unsafe { SampleLayout::leaf_count(desc) }, Ok(2));
+        let err = unsafe { SampleLayout::from_descriptor(desc, &["a"]) }.unwrap_err();
+        assert_eq!(
+            err,
+            LayoutError::NameCount {
+                expected: 2,
+                got: 1
+            }
+        );
+    }
+
+    #[test]
+    fn ops_must_end_within_m_nops() {
+        let desc = descriptor(12, vec![INT32, 0, INT32, 4, INT32, 8, RTS], 2);
+        assert_eq!(
+            unsafe { SampleLayout::leaf_count(desc) },
+            Err(LayoutError::Overrun(2))
+        );
+    }
+
+    #[test]
+    fn recursive_structs_are_rejected() {
+        // a member whose type jumps back to the struct containing it
+        let desc = descriptor(8, vec![EXT, 0, 3 << 16, RTS], 2);
+        assert_eq!(
+            unsafe { SampleLayout::leaf_count(desc) },
+            Err(LayoutError::Overrun(2))
+        );
+    }
+
+    #[test]
+    fn unknown_ops_are_reported() {
+        let desc = descriptor(8, vec![0x7f00_0000, RTS], 2);
+        assert_eq!(
+            unsafe { SampleLayout::leaf_count(desc) },
+            Err(LayoutError::UnsupportedOp(0, 0x7f00_0000))
+        );
+    }
+}
@@ -0,0 +1,782 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Topics of any serde type.
+//!
+//! A type that is `Serialize`, `DeserializeOwned` and [`Keyed`] can be a
+//! topic type without generated code. Samples are encoded by
+//! [`crate::cdr`], so the Rust type has to follow the IDL of the topic
+//! member for member, see there for the mapping.
+//!
+//! [`Keyed::key`] returns the key members, in IDL order, as a value that
+//! serializes the way those members do in the sample: a single member as
+//! itself, several as a tuple. A zero sized key, such as `()`, makes the
+//! topic keyless.
+//!
+//! ```no_run
+//! use cyclonedds_sys::serde_topic::Keyed;
+//! use cyclonedds_sys::*;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Position {
+//!     id: u32,
+//!     x: f64,
+//!     y: f64,
+//! }
+//!
+//! impl Keyed for Position {
+//!     type Key = u32;
+//!     fn key(&self) -> u32 {
+//!         self.id
+//!     }
+//! }
+//!
+//! let p = unsafe { dds_create_participant(0, std::ptr::null(), std::ptr::null()) };
+//! let participant = unsafe { DdsEntity::new(p) };
+//! let topic = participant
+//!     .create_serde_topic::<Position>("Position", "demo::Position", None)
+//!     .unwrap();
+//! let w = unsafe {
+//!     dds_create_writer(p, topic.entity(), std::ptr::null(), std::ptr::null())
+//! };
+//! let writer = unsafe { DdsEntity::new(DDSError::check(w).unwrap()) };
+//! writer.write_serde(&Position { id: 1, x: 0.5, y: 2.0 }).unwrap();
+//! ```
+//!
+//! Samples stay serialized inside Cyclone and are decoded by
+//! [`take_serde`](DdsEntity::take_serde). For `dds_write`, `dds_take`,
+//! `dds_read` and loans the in-memory sample is an `Option<T>`, `None` for
+//! samples without data (writing `None` fails).
+//!
+//! Key hashes are the big endian XCDR2 key, zero padded, when the largest
+//! possible key of the type fits in 16 bytes and its MD5 otherwise (XTypes
+//! 7.6.8). Keys with strings, sequences, maps, optional members or enum
+//! variants with data count as unbounded.
+//!
+//! A panic in the `Serialize`, `Deserialize`, `Keyed` or `Drop`
+//! implementation of `T` while Cyclone converts a sample is caught; the
+//! conversion fails instead.
+
+use std::any::TypeId;
+use std::ffi::{c_void, CString};
+use std::mem::ManuallyDrop;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::{fmt, mem, ptr};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::cdr::{self, CdrError};
+use crate::raw::{hash, ser_range, type_name};
+use crate::{
+    dds_create_topic_sertype, dds_dispose, dds_free_op_t, dds_get_entity_sertype,
+    dds_sample_info_t, dds_takecdr, dds_unregister_instance, dds_writecdr, ddsi_keyhash,
+    ddsi_serdata, ddsi_serdata_addref, ddsi_serdata_init, ddsi_serdata_kind,
+    ddsi_serdata_kind_SDK_DATA, ddsi_serdata_kind_SDK_KEY, ddsi_serdata_ops,
+    ddsi_serdata_removeref, ddsi_sertype, ddsi_sertype_fini, ddsi_sertype_init, ddsi_sertype_ops,
+    ddsi_sertype_v0, ddsrs_copy_fragchain, ddsrt_iovec_t, ddsrt_md5_append, ddsrt_md5_finish,
+    ddsrt_md5_init, ddsrt_md5_state_t, ddsrt_msg_iovlen_t, nn_rdata, size_t, DDSError, DdsEntity,
+    DdsQos, DDS_FREE_ALL_BIT, DDS_FREE_CONTENTS_BIT,
+};
+
+/// The key of a topic type
+pub trait Keyed {
+    /// The key members; zero sized for keyless types
+    type Key: Serialize + DeserializeOwned;
+
+    fn key(&self) -> Self::Key;
+}
+
+/// Everything a type needs to be used with [`DdsEntity::create_serde_topic`]
+pub trait TopicType: Serialize + DeserializeOwned + Keyed + Send + 'static {}
+
+impl<T: Serialize + DeserializeOwned + Keyed + Send + 'static> TopicType for T {}
+
+#[derive(Debug)]
+pub enum SerdeTopicError {
+    Dds(DDSError),
+    Cdr(CdrError),
+}
+
+impl fmt::Display for SerdeTopicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SerdeTopicError::Dds(e) => write!(f, "{}", e),
+            SerdeTopicError::Cdr(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SerdeTopicError {}
+
+impl From<DDSError> for SerdeTopicError {
+    fn from(e: DDSError) -> Self {
+        SerdeTopicError::Dds(e)
+    }
+}
+
+impl From<CdrError> for SerdeTopicError {
+    fn from(e: CdrError) -> Self {
+        SerdeTopicError::Cdr(e)
+    }
+}
+
+/// A sample taken from a reader of a serde topic
+pub struct SerdeSample<T> {
+    /// `None` for samples without data (disposes, unregisters)
+    pub value: Option<T>,
+    pub info: dds_sample_info_t,
+}
+
+fn keyless<T: Keyed>() -> bool {
+    mem::size_of::<T::Key>() == 0
+}
+
+/// The key of a sample as big endian XCDR2, empty for keyless types
+#[derive(Clone)]
+struct Key {
+    bytes: Vec<u8>,
+    /// Whether every key of the type fits in a key hash
+    fixed: bool,
+}
+
+const NO_KEY: Key = Key {
+    bytes: Vec::new(),
+    fixed: true,
+};
+
+fn key_bytes<T: Keyed>(key: &T::Key) -> Result<Key, CdrError> {
+    if keyless::<T>() {
+        return Ok(NO_KEY);
+    }
+    let (bytes, bounded) = cdr::to_key_bytes(key)?;
+    Ok(Key {
+        fixed: bounded && bytes.len() <= 16,
+        bytes,
+    })
+}
+
+/// Run code of `T` for Cyclone, which cannot be unwound through
+fn guarded<R>(f: impl FnOnce() -> Result<R, CdrError>) -> Result<R, CdrError> {
+    panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|_| Err(CdrError::Message("panicked".to_owned())))
+}
+
+/// A serialized sample and its key
+type Encoded = (Vec<u8>, Key);
+
+/// What the sertype does differently for each `T`
+struct TypeOps {
+    type_id: TypeId,
+    /// Size of the in-memory sample, an `Option<T>`
+    sample_size: usize,
+    /// The serialized sample (only the key when `key_only` is set) and the
+    /// key of an `Option<T>`
+    from_sample: unsafe fn(*const c_void, bool) -> Result<Encoded, CdrError>,
+    /// The key of a serialized sample (or key, when `key_only` is set)
+    key_of: fn(&[u8], bool) -> Result<Key, CdrError>,
+    /// Decode into an in-memory sample
+    to_sample: unsafe fn(&[u8], *mut c_void) -> bool,
+    /// Initialize uninitialized memory as an empty sample
+    zero: unsafe fn(*mut c_void),
+    /// Drop what an in-memory sample holds, leaving it empty
+    clear: unsafe fn(*mut c_void),
+}
+
+unsafe fn from_sample<T: TopicType>(
+    sample: *const c_void,
+    key_only: bool,
+) -> Result<Encoded, CdrError> {
+    let sample = match &*(sample as *const Option<T>) {
+        Some(sample) => sample,
+        None => return Err(CdrError::Message("no sample".to_owned())),
+    };
+    guarded(|| {
+        let key = sample.key();
+        let data = if key_only {
+            cdr::to_vec(&key)?
+        } else {
+            cdr::to_vec(sample)?
+        };
+        Ok((data, key_bytes::<T>(&key)?))
+    })
+}
+
+fn key_of<T: TopicType>(data: &[u8], key_only: bool) -> Result<Key, CdrError> {
+    if keyless::<T>() {
+        return Ok(NO_KEY);
+    }
+    guarded(|| {
+        let key = if key_only {
+            cdr::from_slice::<T::Key>(data)?
+        } else {
+            cdr::from_slice::<T>(data)?.key()
+        };
+        key_bytes::<T>(&key)
+    })
+}
+
+unsafe fn to_sample<T: TopicType>(data: &[u8], sample: *mut c_void) -> bool {
+    let value = match guarded(|| cdr::from_slice::<T>(data)) {
+        Ok(value) => value,
+        Err(_) => return false,
+    };
+    clear::<T>(sample);
+    ptr::write(sample as *mut Option<T>, Some(value));
+    true
+}
+
+unsafe fn zero<T>(sample: *mut c_void) {
+    ptr::write(sample as *mut Option<T>, None);
+}
+
+/// Drop the sample, which is `None` afterwards even if dropping panics
+unsafe fn clear<T>(sample: *mut c_void) {
+    let old = ptr::replace(sample as *mut Option<T>, None);
+    let _ = panic::catch_unwind(AssertUnwindSafe(move || drop(old)));
+}
+
+impl TypeOps {
+    fn of<T: TopicType>() -> Self {
+        TypeOps {
+            type_id: TypeId::of::<T>(),
+            sample_size: mem::size_of::<Option<T>>(),
+            from_sample: from_sample::<T>,
+            key_of: key_of::<T>,
+            to_sample: to_sample::<T>,
+            zero: zero::<T>,
+            clear: clear::<T>,
+        }
+    }
+}
+
+#[repr(C)]
+struct SerdeSertype {
+    c: ddsi_sertype,
+    ops: TypeOps,
+}
+
+#[repr(C)]
+struct SerdeSerdata {
+    c: ddsi_serdata,
+    /// Serialized sample with the encapsulation header, or the serialized
+    /// key for key-only serdata
+    data: Vec<u8>,
+    key: Key,
+}
+
+unsafe fn type_ops<'a>(tp: *const ddsi_sertype) -> &'a TypeOps {
+    &(*(tp as *const SerdeSertype)).ops
+}
+
+unsafe extern "C" fn sertype_free(tp: *mut ddsi_sertype) {
+    ddsi_sertype_fini(tp);
+    drop(Box::from_raw(tp as *mut SerdeSertype));
+}
+
+unsafe extern "C" fn sertype_zero_samples(
+    tp: *const ddsi_sertype,
+    samples: *mut c_void,
+    count: size_t,
+) {
+    let ops = type_ops(tp);
+    for i in 0..count as usize {
+        (ops.zero)((samples as *mut u8).add(i * ops.sample_size) as *mut c_void);
+    }
+}
+
+unsafe extern "C" fn sertype_realloc_samples(
+    ptrs: *mut *mut c_void,
+    tp: *const ddsi_sertype,
+    old: *mut c_void,
+    oldcount: size_t,
+    count: size_t,
+) {
+    let size = type_ops(tp).sample_size;
+    let new = libc::realloc(old, (count as usize * size).max(1)) as *mut u8;
+    if count > oldcount {
+        sertype_zero_samples(
+            tp,
+            new.add(oldcount as usize * size) as *mut c_void,
+            count - oldcount,
+        );
+    }
+    for i in 0..count as usize {
+        *ptrs.add(i) = new.add(i * size) as *mut c_void;
+    }
+}
+
+unsafe extern "C" fn sertype_free_samples(
+    tp: *const ddsi_sertype,
+    ptrs: *mut *mut c_void,
+    count: size_t,
+    op: dds_free_op_t,
+) {
+    if count == 0 {
+        return;
+    }
+    if op & DDS_FREE_CONTENTS_BIT != 0 {
+        let ops = type_ops(tp);
+        for i in 0..count as usize {
+            (ops.clear)(*ptrs.add(i));
+        }
+    }
+    if op & DDS_FREE_ALL_BIT != 0 {
+        libc::free(*ptrs);
+    }
+}
+
+unsafe extern "C" fn sertype_equal(a: *const ddsi_sertype, b: *const ddsi_sertype) -> bool {
+    ptr::eq((*a).ops, (*b).ops)
+        && type_name(a) == type_name(b)
+        && type_ops(a).type_id == type_ops(b).type_id
+}
+
+unsafe extern "C" fn sertype_hash(tp: *const ddsi_sertype) -> u32 {
+    hash(type_name(tp))
+}
+
+/// The ops are never modified; `arg` is the only pointer and it is null
+struct SertypeOps(ddsi_sertype_ops);
+unsafe impl Sync for SertypeOps {}
+
+static SERTYPE_OPS: SertypeOps = SertypeOps(ddsi_sertype_ops {
+    version: Some(ddsi_sertype_v0),
+    arg: ptr::null_mut(),
+    free: Some(sertype_free),
+    zero_samples: Some(sertype_zero_samples),
+    realloc_samples: Some(sertype_realloc_samples),
+    free_samples: Some(sertype_free_samples),
+    equal: Some(sertype_equal),
+    hash: Some(sertype_hash),
+    type_id: None,
+    type_map: None,
+    type_info: None,
+    derive_sertype: None,
+    get_serialized_size: None,
+    serialize_into: None,
+});
+
+unsafe fn new_serdata(
+    tp: *const ddsi_sertype,
+    kind: ddsi_serdata_kind,
+    data: Vec<u8>,
+    key: Key,
+) -> *mut ddsi_serdata {
+    let mut d = Box::new(SerdeSerdata {
+        c: mem::zeroed(),
+        data,
+        key,
+    });
+    ddsi_serdata_init(&mut d.c, tp, kind);
+    d.c.hash = (*tp).serdata_basehash ^ hash(&d.key.bytes);
+    Box::into_raw(d) as *mut ddsi_serdata
+}
+
+/// Serdata from received bytes, null if they do not decode
+unsafe fn serdata_from_bytes(
+    tp: *const ddsi_sertype,
+    kind: ddsi_serdata_kind,
+    data: Vec<u8>,
+) -> *mut ddsi_serdata {
+    match (type_ops(tp).key_of)(&data, kind == ddsi_serdata_kind_SDK_KEY) {
+        Ok(key) => new_serdata(tp, kind, data, key),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+unsafe fn serde<'a>(d: *const ddsi_serdata) -> &'a SerdeSerdata {
+    &*(d as *const SerdeSerdata)
+}
+
+unsafe extern "C" fn serdata_eqkey(a: *const ddsi_serdata, b: *const ddsi_serdata) -> bool {
+    serde(a).key.bytes == serde(b).key.bytes
+}
+
+unsafe extern "C" fn serdata_get_size(d: *const ddsi_serdata) -> u32 {
+    serde(d).data.len() as u32
+}
+
+unsafe extern "C" fn serdata_from_ser(
+    tp: *const ddsi_sertype,
+    kind: ddsi_serdata_kind,
+    fragchain: *const nn_rdata,
+    size: size_t,
+) -> *mut ddsi_serdata {
+    let mut data = vec![0u8; size as usize];
+    ddsrs_copy_fragchain(fragchain, size, data.as_mut_ptr());
+    serdata_from_bytes(tp, kind, data)
+}
+
+unsafe extern "C" fn serdata_from_ser_iov(
+    tp: *const ddsi_sertype,
+    kind: ddsi_serdata_kind,
+    niov: ddsrt_msg_iovlen_t,
+    iov: *const ddsrt_iovec_t,
+    size: size_t,
+) -> *mut ddsi_serdata {
+    let mut data = Vec::with_capacity(size as usize);
+    for i in 0..niov as usize {
+        let v = &*iov.add(i);
+        data.extend_from_slice(std::slice::from_raw_parts(
+            v.iov_base as *const u8,
+            v.iov_len as usize,
+        ));
+    }
+    serdata_from_bytes(tp, kind, data)
+}
+
+/// A key hash does not give the key back in general
+unsafe extern "C" fn serdata_from_keyhash(
+    _: *const ddsi_sertype,
+    _: *const ddsi_keyhash,
+) -> *mut ddsi_serdata {
+    ptr::null_mut()
+}
+
+unsafe extern "C" fn serdata_from_sample(
+    tp: *const ddsi_sertype,
+    kind: ddsi_serdata_kind,
+    sample: *const c_void,
+) -> *mut ddsi_serdata {
+    match (type_ops(tp).from_sample)(sample, kind == ddsi_serdata_kind_SDK_KEY) {
+        Ok((data, key)) => new_serdata(tp, kind, data, key),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+unsafe extern "C" fn serdata_to_ser(
+    d: *const ddsi_serdata,
+    off: size_t,
+    sz: size_t,
+    buf: *mut c_void,
+) {
+    let data = ser_range(&serde(d).data, off, sz);
+    ptr::copy_nonoverlapping(data.as_ptr(), buf as *mut u8, data.len());
+}
+
+unsafe extern "C" fn serdata_to_ser_ref(
+    d: *const ddsi_serdata,
+    off: size_t,
+    sz: size_t,
+    r: *mut ddsrt_iovec_t,
+) -> *mut ddsi_serdata {
+    let data = ser_range(&serde(d).data, off, sz);
+    (*r).iov_base = data.as_ptr() as *mut c_void;
+    (*r).iov_len = data.len() as _;
+    ddsi_serdata_addref(d)
+}
+
+unsafe extern "C" fn serdata_to_ser_unref(d: *mut ddsi_serdata, _: *const ddsrt_iovec_t) {
+    ddsi_serdata_removeref(d)
+}
+
+unsafe extern "C" fn serdata_to_sample(
+    d: *const ddsi_serdata,
+    sample: *mut c_void,
+    _: *mut *mut c_void,
+    _: *mut c_void,
+) -> bool {
+    let ops = type_ops((*d).type_);
+    if (*d).kind == ddsi_serdata_kind_SDK_KEY {
+        (ops.clear)(sample);
+        true
+    } else {
+        (ops.to_sample)(&serde(d).data, sample)
+    }
+}
+
+unsafe extern "C" fn serdata_to_untyped(d: *const ddsi_serdata) -> *mut ddsi_serdata {
+    let untyped = new_serdata(
+        (*d).type_,
+        ddsi_serdata_kind_SDK_KEY,
+        Vec::new(),
+        serde(d).key.clone(),
+    );
+    (*untyped).type_ = ptr::null();
+    untyped
+}
+
+unsafe extern "C" fn serdata_untyped_to_sample(
+    tp: *const ddsi_sertype,
+    _: *const ddsi_serdata,
+    sample: *mut c_void,
+    _: *mut *mut c_void,
+    _: *mut c_void,
+) -> bool {
+    (type_ops(tp).clear)(sample);
+    true
+}
+
+unsafe extern "C" fn serdata_free(d: *mut ddsi_serdata) {
+    drop(Box::from_raw(d as *mut SerdeSerdata));
+}
+
+unsafe extern "C" fn serdata_print(
+    _: *const ddsi_sertype,
+    d: *const ddsi_serdata,
+    buf: *mut c_char,
+    size: size_t,
+) -> size_t {
+    let text = format!("serde({} bytes)", serde(d).data.len());
+    let n = text.len().min((size as usize).saturating_sub(1));
+    ptr::copy_nonoverlapping(text.as_ptr() as *const c_char, buf, n);
+    *buf.add(n) = 0;
+    n as size_t
+}
+
+unsafe extern "C" fn serdata_get_keyhash(
+    d: *const ddsi_serdata,
+    buf: *mut ddsi_keyhash,
+    force_md5: bool,
+) {
+    let key = &serde(d).key;
+    if key.fixed && !force_md5 {
+        let mut value = [0u8; 16];
+        value[..key.bytes.len()].copy_from_slice(&key.bytes);
+        (*buf).value = value;
+    } else {
+        let key = &key.bytes;
+        let mut state: ddsrt_md5_state_t = mem::zeroed();
+        ddsrt_md5_init(&mut state);
+        ddsrt_md5_append(&mut state, key.as_ptr(), key.len() as u32);
+        ddsrt_md5_finish(&mut state, (*buf).value.as_mut_ptr());
+    }
+}
+
+static SERDATA_OPS: ddsi_serdata_ops = ddsi_serdata_ops {
+    eqkey: Some(serdata_eqkey),
+    get_size: Some(serdata_get_size),
+    from_ser: Some(serdata_from_ser),
+    from_ser_iov: Some(serdata_from_ser_iov),
+    from_keyhash: Some(serdata_from_keyhash),
+    from_sample: Some(serdata_from_sample),
+    to_ser: Some(serdata_to_ser),
+    to_ser_ref: Some(serdata_to_ser_ref),
+    to_ser_unref: Some(serdata_to_ser_unref),
+    to_sample: Some(serdata_to_sample),
+    to_untyped: Some(serdata_to_untyped),
+    untyped_to_sample: Some(serdata_untyped_to_sample),
+    free: Some(serdata_free),
+    print: Some(serdata_print),
+    get_keyhash: Some(serdata_get_keyhash),
+    get_sample_size: None,
+    from_iox_buffer: None,
+};
+
+/// `value` as the in-memory sample Cyclone passes to `from_sample`: a
+/// bitwise copy that only gets read and is never dropped
+fn as_sample<T>(value: &T) -> ManuallyDrop<Option<T>> {
+    ManuallyDrop::new(Some(unsafe { ptr::read(value) }))
+}
+
+/// The sertype of `entity`, if it is the serde sertype of `T`
+fn sertype_of<T: TopicType>(entity: &DdsEntity) -> Result<*const ddsi_sertype, DDSError> {
+    let mut tp: *const ddsi_sertype = ptr::null();
+    DDSError::check(unsafe { dds_get_entity_sertype(entity.0, &mut tp) })?;
+    unsafe {
+        if !ptr::eq((*tp).ops, &SERTYPE_OPS.0) || type_ops(tp).type_id != TypeId::of::<T>() {
+            return Err(DDSError::BadParameter);
+        }
+    }
+    Ok(tp)
+}
+
+impl DdsEntity {
+    /// Create a topic `name` of type `type_name` whose samples are `T`s,
+    /// see [`write_serde`](Self::write_serde) and
+    /// [`take_serde`](Self::take_serde).
+    pub fn create_serde_topic<T: TopicType>(
+        &self,
+        name: &str,
+        type_name: &str,
+        qos: Option<&DdsQos>,
+    ) -> Result<DdsEntity, DDSError> {
+        // in-memory samples live in memory from malloc
+        if mem::align_of::<Option<T>>() > mem::align_of::<libc::max_align_t>() {
+            return Err(DDSError::Unsupported);
+        }
+        let name = CString::new(name).map_err(|_| DDSError::BadParameter)?;
+        let type_name = CString::new(type_name).map_err(|_| DDSError::BadParameter)?;
+        unsafe {
+            let sertype = Box::into_raw(Box::new(SerdeSertype {
+                c: mem::zeroed(),
+                ops: TypeOps::of::<T>(),
+            }));
+            ddsi_sertype_init(
+                &mut (*sertype).c,
+                type_name.as_ptr(),
+                &SERTYPE_OPS.0,
+                &SERDATA_OPS,
+                keyless::<T>(),
+            );
+            let mut tp = sertype as *mut ddsi_sertype;
+            let topic = dds_create_topic_sertype(
+                self.0,
+                name.as_ptr(),
+                &mut tp,
+                qos.map_or(ptr::null(), DdsQos::as_ptr),
+                ptr::null(),
+                ptr::null(),
+            );
+            if topic < 0 {
+                sertype_free(sertype as *mut ddsi_sertype);
+            }
+            DDSError::check(topic).map(|_| DdsEntity(topic))
+        }
+    }
+
+    /// Publish `value` on a writer of a serde topic of type `T`
+    pub fn write_serde<T: TopicType>(&self, value: &T) -> Result<(), SerdeTopicError> {
+        let tp = sertype_of::<T>(self)?;
+        let data = cdr::to_vec(value)?;
+        let key = key_bytes::<T>(&value.key())?;
+        unsafe {
+            let d = new_serdata(tp, ddsi_serdata_kind_SDK_DATA, data, key);
+            // dds_writecdr takes over the reference
+            DDSError::check(dds_writecdr(self.0, d))?;
+        }
+        Ok(())
+    }
+
+    /// Dispose the instance of `value` on a writer of a serde topic
+    pub fn dispose_serde<T: TopicType>(&self, value: &T) -> Result<(), DDSError> {
+        sertype_of::<T>(self)?;
+        let sample = as_sample(value);
+        DDSError::check(unsafe { dds_dispose(self.0, &*sample as *const _ as *const c_void) })
+            .map(|_| ())
+    }
+
+    /// Unregister the instance of `value` on a writer of a serde topic
+    pub fn unregister_serde<T: TopicType>(&self, value: &T) -> Result<(), DDSError> {
+        sertype_of::<T>(self)?;
+        let sample = as_sample(value);
+        DDSError::check(unsafe {
+            dds_unregister_instance(self.0, &*sample as *const _ as *const c_void)
+        })
+        .map(|_| ())
+    }
+
+    /// Take up to `max` samples from a reader of a serde topic of type `T`.
+    /// Samples that do not decode as a `T` are left out.
+    pub fn take_serde<T: TopicType>(&self, max: usize) -> Result<Vec<SerdeSample<T>>, DDSError> {
+        sertype_of::<T>(self)?;
+        let mut buf: Vec<*mut ddsi_serdata> = vec![ptr::null_mut(); max];
+        let mut infos: Vec<dds_sample_info_t> = vec![unsafe { mem::zeroed() }; max];
+        let n = DDSError::check(unsafe {
+            dds_takecdr(self.0, buf.as_mut_ptr(), max as u32, infos.as_mut_ptr(), 0)
+        })? as usize;
+
+        let mut samples = Vec::with_capacity(n);
+        for (d, info) in buf.iter().zip(infos.iter()).take(n) {
+            unsafe {
+                if ptr::eq((**d).ops, &SERDATA_OPS) {
+                    let value = if info.valid_data {
+                        cdr::from_slice::<T>(&serde(*d).data).ok()
+                    } else {
+                        None
+                    };
+                    if value.is_some() || !info.valid_data {
+                        samples.push(SerdeSample { value, info: *info });
+                    }
+                }
+                ddsi_serdata_removeref(*d);
+            }
+        }
+        Ok(samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize, Serializer};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Position {
+        id: u32,
+        x: f64,
+    }
+
+    impl Keyed for Position {
+        type Key = u32;
+        fn key(&self) -> u32 {
+            self.id
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Named {
+        name: String,
+    }
+
+    impl Keyed for Named {
+        type Key = String;
+        fn key(&self) -> String {
+            self.name.clone()
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct Panics;
+
+    impl Serialize for Panics {
+        fn serialize<S: Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+            panic!("cannot serialize")
+        }
+    }
+
+    impl Keyed for Panics {
+        type Key = ();
+        fn key(&self) {}
+    }
+
+    #[test]
+    fn samples_are_options() {
+        let position = Position { id: 7, x: 1.0 };
+        let sample = Some(position);
+        let (data, key) =
+            unsafe { from_sample::<Position>(&sample as *const _ as *const c_void, false) }
+                .unwrap();
+        assert_eq!(cdr::from_slice::<Position>(&data).ok(), sample);
+        assert_eq!(key.bytes, vec![0, 0, 0, 7]);
+        let none: Option<Position> = None;
+        assert!(
+            unsafe { from_sample::<Position>(&none as *const _ as *const c_void, false) }.is_err()
+        );
+    }
+
+    #[test]
+    fn key_hashes_go_by_the_largest_key() {
+        assert!(key_bytes::<Position>(&1).unwrap().fixed);
+        // a short string still gets hashed, other strings might not fit
+        let key =
+            key_of::<Named>(&cdr::to_vec(&Named { name: "a".into() }).unwrap(), false).unwrap();
+        assert_eq!(key.bytes, vec![0, 0, 0, 2, b'a', 0]);
+        assert!(!key.fixed);
+    }
+
+    #[test]
+    fn panics_do_not_reach_cyclone() {
+        let sample = Some(Panics);
+        assert_eq!(
+            unsafe { from_sample::<Panics>(&sample as *const _ as *const c_void, false) }.map(drop),
+            Err(CdrError::Message("panicked".to_owned()))
+        );
+    }
+}
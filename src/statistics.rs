@@ -0,0 +1,206 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Per endpoint statistics (`dds_statistics.h`).
+//!
+//! Writers report retransmit and throttling counters, readers the number of
+//! discarded bytes. The set of counters depends on the Cyclone version, so
+//! the named accessors return `None` when a counter is not provided.
+
+use std::ffi::{CStr, CString};
+
+use crate::{
+    dds_create_statistics, dds_delete_statistics, dds_entity_t, dds_lookup_statistic,
+    dds_refresh_statistics, dds_stat_keyvalue, dds_stat_kind_DDS_STAT_KIND_LENGTHTIME,
+    dds_stat_kind_DDS_STAT_KIND_UINT32, dds_stat_kind_DDS_STAT_KIND_UINT64, dds_statistics,
+    DDSError, DdsEntity, DdsTime,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatValue {
+    U32(u32),
+    U64(u64),
+    /// Integral of a length over time, in length * nanoseconds
+    LengthTime(u64),
+}
+
+impl StatValue {
+    pub fn as_u64(&self) -> u64 {
+        match *self {
+            StatValue::U32(v) => v as u64,
+            StatValue::U64(v) | StatValue::LengthTime(v) => v,
+        }
+    }
+
+    unsafe fn from_raw(kv: &dds_stat_keyvalue) -> Option<Self> {
+        match kv.kind {
+            dds_stat_kind_DDS_STAT_KIND_UINT32 => Some(StatValue::U32(kv.u.u32_)),
+            dds_stat_kind_DDS_STAT_KIND_UINT64 => Some(StatValue::U64(kv.u.u64_)),
+            dds_stat_kind_DDS_STAT_KIND_LENGTHTIME => Some(StatValue::LengthTime(kv.u.lengthtime)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Statistic {
+    pub name: String,
+    pub value: StatValue,
+}
+
+/// A copy of an entity's counters at one point in time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Statistics {
+    entity: dds_entity_t,
+    time: DdsTime,
+    values: Vec<Statistic>,
+}
+
+impl Statistics {
+    pub(crate) fn new(entity: dds_entity_t, time: DdsTime, values: Vec<Statistic>) -> Self {
+        Statistics {
+            entity,
+            time,
+            values,
+        }
+    }
+
+    pub fn entity(&self) -> DdsEntity {
+        DdsEntity(self.entity)
+    }
+
+    /// When the counters were last refreshed
+    pub fn time(&self) -> DdsTime {
+        self.time
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Statistic> {
+        self.values.iter()
+    }
+
+    pub fn get(&self, name: &str) -> Option<StatValue> {
+        self.values.iter().find(|s| s.name == name).map(|s| s.value)
+    }
+
+    fn counter(&self, name: &str) -> Option<u64> {
+        self.get(name).map(|v| v.as_u64())
+    }
+
+    /// Writer: number of retransmitted samples
+    pub fn rexmit_count(&self) -> Option<u64> {
+        self.counter("rexmit_count")
+    }
+
+    /// Writer: number of retransmitted bytes
+    pub fn rexmit_bytes(&self) -> Option<u64> {
+        self.counter("rexmit_bytes")
+    }
+
+    /// Writer: samples that could not be retransmitted because they were
+    /// no longer available
+    pub fn rexmit_lost(&self) -> Option<u64> {
+        self.counter("rexmit_lost")
+    }
+
+    /// Writer: nanoseconds spent retransmitting
+    pub fn time_rexmit(&self) -> Option<u64> {
+        self.counter("time_rexmit")
+    }
+
+    /// Writer: number of times the writer was throttled
+    pub fn throttle_count(&self) -> Option<u64> {
+        self.counter("throttle_count")
+    }
+
+    /// Writer: nanoseconds spent throttled
+    pub fn time_throttle(&self) -> Option<u64> {
+        self.counter("time_throttle")
+    }
+
+    /// Reader: bytes received but dropped
+    pub fn discarded_bytes(&self) -> Option<u64> {
+        self.counter("discarded_bytes")
+    }
+}
+
+/// An owned `dds_statistics` that can be refreshed repeatedly
+pub struct StatisticsHandle(*mut dds_statistics);
+
+unsafe impl Send for StatisticsHandle {}
+
+impl StatisticsHandle {
+    /// Fails with `BadParameter` if the entity has no statistics
+    pub fn new(entity: &DdsEntity) -> Result<Self, DDSError> {
+        let stats = unsafe { dds_create_statistics(entity.0) };
+        if stats.is_null() {
+            Err(DDSError::BadParameter)
+        } else {
+            Ok(StatisticsHandle(stats))
+        }
+    }
+
+    pub fn refresh(&mut self) -> Result<(), DDSError> {
+        DDSError::check(unsafe { dds_refresh_statistics(self.0) }).map(|_| ())
+    }
+
+    fn entries(&self) -> &[dds_stat_keyvalue] {
+        unsafe { (*self.0).kv.as_slice((*self.0).count as usize) }
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<StatValue> {
+        let name = CString::new(name).ok()?;
+        unsafe {
+            let kv = dds_lookup_statistic(self.0, name.as_ptr());
+            if kv.is_null() {
+                None
+            } else {
+                StatValue::from_raw(&*kv)
+            }
+        }
+    }
+
+    /// Copy the values as of the last refresh
+    pub fn snapshot(&self) -> Statistics {
+        let values = self
+            .entries()
+            .iter()
+            .filter_map(|kv| unsafe {
+                let value = StatValue::from_raw(kv)?;
+                if kv.name.is_null() {
+                    return None;
+                }
+                let name = CStr::from_ptr(kv.name).to_string_lossy().into_owned();
+                Some(Statistic { name, value })
+            })
+            .collect();
+        unsafe { Statistics::new((*self.0).entity, (*self.0).time.into(), values) }
+    }
+}
+
+impl Drop for StatisticsHandle {
+    fn drop(&mut self) {
+        unsafe { dds_delete_statistics(self.0) }
+    }
+}
+
+impl DdsEntity {
+    /// Current statistics of a reader or writer
+    pub fn statistics(&self) -> Result<Statistics, DDSError> {
+        let mut handle = StatisticsHandle::new(self)?;
+        handle.refresh()?;
+        Ok(handle.snapshot())
+    }
+}
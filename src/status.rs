@@ -0,0 +1,495 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Typed communication statuses.
+//!
+//! Status bits are `1 << dds_status_id`. The typed getters go through the
+//! `dds_get_*_status` calls, which reset the change flag of the status and
+//! the `*_change` counters just like reading the status in DDS does.
+
+use bitmask::bitmask;
+
+use crate::*;
+
+bitmask! {
+    pub mask StatusKinds : u32 where flags StatusKind {
+        DdsInconsistentTopicStatus = 1 << 0,
+        DdsOfferedDeadlineMissedStatus = 1 << 1,
+        DdsRequestedDeadlineMissedStatus = 1 << 2,
+        DdsOfferedIncompatibleQosStatus = 1 << 3,
+        DdsRequestedIncompatibleQosStatus = 1 << 4,
+        DdsSampleLostStatus = 1 << 5,
+        DdsSampleRejectedStatus = 1 << 6,
+        DdsDataOnReadersStatus = 1 << 7,
+        DdsDataAvailableStatus = 1 << 8,
+        DdsLivelinessLostStatus = 1 << 9,
+        DdsLivelinessChangedStatus = 1 << 10,
+        DdsPublicationMatchedStatus = 1 << 11,
+        DdsSubscriptionMatchedStatus = 1 << 12,
+    }
+}
+
+impl StatusKinds {
+    pub fn from_raw(mask: u32) -> Self {
+        StatusKinds { mask }
+    }
+}
+
+/// The QoS policy named in an incompatible qos status
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QosPolicyId {
+    Invalid,
+    UserData,
+    Durability,
+    Presentation,
+    Deadline,
+    LatencyBudget,
+    Ownership,
+    OwnershipStrength,
+    Liveliness,
+    TimeBasedFilter,
+    Partition,
+    Reliability,
+    DestinationOrder,
+    History,
+    ResourceLimits,
+    EntityFactory,
+    WriterDataLifecycle,
+    ReaderDataLifecycle,
+    TopicData,
+    GroupData,
+    TransportPriority,
+    Lifespan,
+    DurabilityService,
+    Property,
+    TypeConsistencyEnforcement,
+    DataRepresentation,
+    Unknown(u32),
+}
+
+impl From<u32> for QosPolicyId {
+    fn from(id: u32) -> Self {
+        match id {
+            0 => QosPolicyId::Invalid,
+            1 => QosPolicyId::UserData,
+            2 => QosPolicyId::Durability,
+            3 => QosPolicyId::Presentation,
+            4 => QosPolicyId::Deadline,
+            5 => QosPolicyId::LatencyBudget,
+            6 => QosPolicyId::Ownership,
+            7 => QosPolicyId::OwnershipStrength,
+            8 => QosPolicyId::Liveliness,
+            9 => QosPolicyId::TimeBasedFilter,
+            10 => QosPolicyId::Partition,
+            11 => QosPolicyId::Reliability,
+            12 => QosPolicyId::DestinationOrder,
+            13 => QosPolicyId::History,
+            14 => QosPolicyId::ResourceLimits,
+            15 => QosPolicyId::EntityFactory,
+            16 => QosPolicyId::WriterDataLifecycle,
+            17 => QosPolicyId::ReaderDataLifecycle,
+            18 => QosPolicyId::TopicData,
+            19 => QosPolicyId::GroupData,
+            20 => QosPolicyId::TransportPriority,
+            21 => QosPolicyId::Lifespan,
+            22 => QosPolicyId::DurabilityService,
+            23 => QosPolicyId::Property,
+            24 => QosPolicyId::TypeConsistencyEnforcement,
+            25 => QosPolicyId::DataRepresentation,
+            other => QosPolicyId::Unknown(other),
+        }
+    }
+}
+
+/// Why the last sample was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SampleRejectedReason {
+    NotRejected,
+    ByInstancesLimit,
+    BySamplesLimit,
+    BySamplesPerInstanceLimit,
+    Unknown(u32),
+}
+
+impl From<dds_sample_rejected_status_kind> for SampleRejectedReason {
+    fn from(kind: dds_sample_rejected_status_kind) -> Self {
+        match kind {
+            dds_sample_rejected_status_kind_DDS_NOT_REJECTED => SampleRejectedReason::NotRejected,
+            dds_sample_rejected_status_kind_DDS_REJECTED_BY_INSTANCES_LIMIT => {
+                SampleRejectedReason::ByInstancesLimit
+            }
+            dds_sample_rejected_status_kind_DDS_REJECTED_BY_SAMPLES_LIMIT => {
+                SampleRejectedReason::BySamplesLimit
+            }
+            dds_sample_rejected_status_kind_DDS_REJECTED_BY_SAMPLES_PER_INSTANCE_LIMIT => {
+                SampleRejectedReason::BySamplesPerInstanceLimit
+            }
+            other => SampleRejectedReason::Unknown(other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct InconsistentTopic {
+    pub total_count: u32,
+    pub total_count_change: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OfferedDeadlineMissed {
+    pub total_count: u32,
+    pub total_count_change: i32,
+    pub last_instance_handle: dds_instance_handle_t,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OfferedIncompatibleQos {
+    pub total_count: u32,
+    pub total_count_change: i32,
+    pub last_policy_id: QosPolicyId,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PublicationMatched {
+    pub total_count: u32,
+    pub total_count_change: i32,
+    pub current_count: u32,
+    pub current_count_change: i32,
+    pub last_subscription_handle: dds_instance_handle_t,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LivelinessLost {
+    pub total_count: u32,
+    pub total_count_change: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SubscriptionMatched {
+    pub total_count: u32,
+    pub total_count_change: i32,
+    pub current_count: u32,
+    pub current_count_change: i32,
+    pub last_publication_handle: dds_instance_handle_t,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LivelinessChanged {
+    pub alive_count: u32,
+    pub not_alive_count: u32,
+    pub alive_count_change: i32,
+    pub not_alive_count_change: i32,
+    pub last_publication_handle: dds_instance_handle_t,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleRejected {
+    pub total_count: u32,
+    pub total_count_change: i32,
+    pub last_reason: SampleRejectedReason,
+    pub last_instance_handle: dds_instance_handle_t,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SampleLost {
+    pub total_count: u32,
+    pub total_count_change: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RequestedDeadlineMissed {
+    pub total_count: u32,
+    pub total_count_change: i32,
+    pub last_instance_handle: dds_instance_handle_t,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestedIncompatibleQos {
+    pub total_count: u32,
+    pub total_count_change: i32,
+    pub last_policy_id: QosPolicyId,
+}
+
+impl From<dds_inconsistent_topic_status_t> for InconsistentTopic {
+    fn from(s: dds_inconsistent_topic_status_t) -> Self {
+        InconsistentTopic {
+            total_count: s.total_count,
+            total_count_change: s.total_count_change,
+        }
+    }
+}
+
+impl From<dds_offered_deadline_missed_status_t> for OfferedDeadlineMissed {
+    fn from(s: dds_offered_deadline_missed_status_t) -> Self {
+        OfferedDeadlineMissed {
+            total_count: s.total_count,
+            total_count_change: s.total_count_change,
+            last_instance_handle: s.last_instance_handle,
+        }
+    }
+}
+
+impl From<dds_offered_incompatible_qos_status_t> for OfferedIncompatibleQos {
+    fn from(s: dds_offered_incompatible_qos_status_t) -> Self {
+        OfferedIncompatibleQos {
+            total_count: s.total_count,
+            total_count_change: s.total_count_change,
+            last_policy_id: s.last_policy_id.into(),
+        }
+    }
+}
+
+impl From<dds_publication_matched_status_t> for PublicationMatched {
+    fn from(s: dds_publication_matched_status_t) -> Self {
+        PublicationMatched {
+            total_count: s.total_count,
+            total_count_change: s.total_count_change,
+            current_count: s.current_count,
+            current_count_change: s.current_count_change,
+            last_subscription_handle: s.last_subscription_handle,
+        }
+    }
+}
+
+impl From<dds_liveliness_lost_status_t> for LivelinessLost {
+    fn from(s: dds_liveliness_lost_status_t) -> Self {
+        LivelinessLost {
+            total_count: s.total_count,
+            total_count_change: s.total_count_change,
+        }
+    }
+}
+
+impl From<dds_subscription_matched_status_t> for SubscriptionMatched {
+    fn from(s: dds_subscription_matched_status_t) -> Self {
+        SubscriptionMatched {
+            total_count: s.total_count,
+            total_count_change: s.total_count_change,
+            current_count: s.current_count,
+            current_count_change: s.current_count_change,
+            last_publication_handle: s.last_publication_handle,
+        }
+    }
+}
+
+impl From<dds_liveliness_changed_status_t> for LivelinessChanged {
+    fn from(s: dds_liveliness_changed_status_t) -> Self {
+        LivelinessChanged {
+            alive_count: s.alive_count,
+            not_alive_count: s.not_alive_count,
+            alive_count_change: s.alive_count_change,
+            not_alive_count_change: s.not_alive_count_change,
+            last_publication_handle: s.last_publication_handle,
+        }
+    }
+}
+
+impl From<dds_sample_rejected_status_t> for SampleRejected {
+    fn from(s: dds_sample_rejected_status_t) -> Self {
+        SampleRejected {
+            total_count: s.total_count,
+            total_count_change: s.total_count_change,
+            last_reason: s.last_reason.into(),
+            last_instance_handle: s.last_instance_handle,
+        }
+    }
+}
+
+impl From<dds_sample_lost_status_t> for SampleLost {
+    fn from(s: dds_sample_lost_status_t) -> Self {
+        SampleLost {
+            total_count: s.total_count,
+            total_count_change: s.total_count_change,
+        }
+    }
+}
+
+impl From<dds_requested_deadline_missed_status_t> for RequestedDeadlineMissed {
+    fn from(s: dds_requested_deadline_missed_status_t) -> Self {
+        RequestedDeadlineMissed {
+            total_count: s.total_count,
+            total_count_change: s.total_count_change,
+            last_instance_handle: s.last_instance_handle,
+        }
+    }
+}
+
+impl From<dds_requested_incompatible_qos_status_t> for RequestedIncompatibleQos {
+    fn from(s: dds_requested_incompatible_qos_status_t) -> Self {
+        RequestedIncompatibleQos {
+            total_count: s.total_count,
+            total_count_change: s.total_count_change,
+            last_policy_id: s.last_policy_id.into(),
+        }
+    }
+}
+
+macro_rules! status_getters {
+    ($($(#[$doc:meta])* $name:ident -> $ty:ident = $ffi:ident;)+) => {
+        impl DdsEntity {
+            $(
+                $(#[$doc])*
+                pub fn $name(&self) -> Result<$ty, DDSError> {
+                    let mut status = Default::default();
+                    DDSError::check(unsafe { $ffi(self.0, &mut status) })?;
+                    Ok(status.into())
+                }
+            )+
+        }
+    };
+}
+
+status_getters! {
+    /// Topic status, resets the change counters
+    inconsistent_topic_status -> InconsistentTopic = dds_get_inconsistent_topic_status;
+    /// Writer status, resets the change counters
+    publication_matched_status -> PublicationMatched = dds_get_publication_matched_status;
+    /// Writer status, resets the change counters
+    liveliness_lost_status -> LivelinessLost = dds_get_liveliness_lost_status;
+    /// Writer status, resets the change counters
+    offered_deadline_missed_status -> OfferedDeadlineMissed =
+        dds_get_offered_deadline_missed_status;
+    /// Writer status, resets the change counters
+    offered_incompatible_qos_status -> OfferedIncompatibleQos =
+        dds_get_offered_incompatible_qos_status;
+    /// Reader status, resets the change counters
+    subscription_matched_status -> SubscriptionMatched = dds_get_subscription_matched_status;
+    /// Reader status, resets the change counters
+    liveliness_changed_status -> LivelinessChanged = dds_get_liveliness_changed_status;
+    /// Reader status, resets the change counters
+    sample_rejected_status -> SampleRejected = dds_get_sample_rejected_status;
+    /// Reader status, resets the change counters
+    sample_lost_status -> SampleLost = dds_get_sample_lost_status;
+    /// Reader status, resets the change counters
+    requested_deadline_missed_status -> RequestedDeadlineMissed =
+        dds_get_requested_deadline_missed_status;
+    /// Reader status, resets the change counters
+    requested_incompatible_qos_status -> RequestedIncompatibleQos =
+        dds_get_requested_incompatible_qos_status;
+}
+
+impl DdsEntity {
+    /// The statuses that changed since they were last read or taken
+    pub fn status_changes(&self) -> Result<StatusKinds, DDSError> {
+        let mut status = 0;
+        DDSError::check(unsafe { dds_get_status_changes(self.0, &mut status) })?;
+        Ok(StatusKinds::from_raw(status))
+    }
+
+    /// The triggered statuses in `mask`, leaving them set
+    pub fn read_status<M: Into<StatusKinds>>(&self, mask: M) -> Result<StatusKinds, DDSError> {
+        let mut status = 0;
+        DDSError::check(unsafe { dds_read_status(self.0, &mut status, *mask.into()) })?;
+        Ok(StatusKinds::from_raw(status))
+    }
+
+    /// The triggered statuses in `mask`, resetting them
+    pub fn take_status<M: Into<StatusKinds>>(&self, mask: M) -> Result<StatusKinds, DDSError> {
+        let mut status = 0;
+        DDSError::check(unsafe { dds_take_status(self.0, &mut status, *mask.into()) })?;
+        Ok(StatusKinds::from_raw(status))
+    }
+
+    /// The statuses enabled for listeners and waitsets
+    pub fn status_mask(&self) -> Result<StatusKinds, DDSError> {
+        let mut mask = 0;
+        DDSError::check(unsafe { dds_get_status_mask(self.0, &mut mask) })?;
+        Ok(StatusKinds::from_raw(mask))
+    }
+
+    pub fn set_status_mask<M: Into<StatusKinds>>(&self, mask: M) -> Result<(), DDSError> {
+        DDSError::check(unsafe { dds_set_status_mask(self.0, *mask.into()) }).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_bits_follow_the_status_ids() {
+        let kinds = [
+            (
+                StatusKind::DdsInconsistentTopicStatus,
+                dds_status_id_DDS_INCONSISTENT_TOPIC_STATUS_ID,
+            ),
+            (
+                StatusKind::DdsRequestedIncompatibleQosStatus,
+                dds_status_id_DDS_REQUESTED_INCOMPATIBLE_QOS_STATUS_ID,
+            ),
+            (
+                StatusKind::DdsDataAvailableStatus,
+                dds_status_id_DDS_DATA_AVAILABLE_STATUS_ID,
+            ),
+            (
+                StatusKind::DdsSubscriptionMatchedStatus,
+                dds_status_id_DDS_SUBSCRIPTION_MATCHED_STATUS_ID,
+            ),
+        ];
+        for (kind, id) in kinds.iter() {
+            assert_eq!(*StatusKinds::from(*kind), 1 << *id);
+        }
+        assert_eq!(*StatusKinds::all(), (1 << 13) - 1);
+    }
+
+    #[test]
+    fn raw_masks_round_trip() {
+        let raw = (1 << dds_status_id_DDS_DATA_AVAILABLE_STATUS_ID)
+            | (1 << dds_status_id_DDS_SUBSCRIPTION_MATCHED_STATUS_ID);
+        let kinds = StatusKinds::from_raw(raw);
+        assert_eq!(*kinds, raw);
+        assert!(kinds.contains(StatusKind::DdsDataAvailableStatus));
+        assert!(kinds.contains(StatusKind::DdsSubscriptionMatchedStatus));
+        assert!(!kinds.contains(StatusKind::DdsSampleLostStatus));
+        assert!(StatusKinds::from_raw(0).is_none());
+    }
+
+    #[test]
+    fn policy_ids_are_decoded() {
+        assert_eq!(QosPolicyId::from(0), QosPolicyId::Invalid);
+        assert_eq!(QosPolicyId::from(2), QosPolicyId::Durability);
+        assert_eq!(QosPolicyId::from(11), QosPolicyId::Reliability);
+        assert_eq!(QosPolicyId::from(25), QosPolicyId::DataRepresentation);
+        assert_eq!(QosPolicyId::from(26), QosPolicyId::Unknown(26));
+    }
+
+    #[test]
+    fn rejected_reasons_are_decoded() {
+        assert_eq!(
+            SampleRejectedReason::from(dds_sample_rejected_status_kind_DDS_NOT_REJECTED),
+            SampleRejectedReason::NotRejected
+        );
+        assert_eq!(
+            SampleRejectedReason::from(
+                dds_sample_rejected_status_kind_DDS_REJECTED_BY_SAMPLES_PER_INSTANCE_LIMIT
+            ),
+            SampleRejectedReason::BySamplesPerInstanceLimit
+        );
+        assert_eq!(
+            SampleRejectedReason::from(17),
+            SampleRejectedReason::Unknown(17)
+        );
+
+        let status = SampleRejected::from(dds_sample_rejected_status_t {
+            total_count: 3,
+            total_count_change: 1,
+            last_reason: dds_sample_rejected_status_kind_DDS_REJECTED_BY_SAMPLES_LIMIT,
+            last_instance_handle: 42,
+        });
+        assert_eq!(status.last_reason, SampleRejectedReason::BySamplesLimit);
+        assert_eq!((status.total_count, status.last_instance_handle), (3, 42));
+    }
+}
@@ -0,0 +1,285 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Isolated domains for tests.
+//!
+//! Every [`TestDomain`] talks over loopback only, with multicast disabled
+//! and discovery going through unicast to 127.0.0.1. It gets a domain id
+//! whose RTPS unicast ports are not bound by anyone else at the time, and a
+//! discovery tag of its own, so tests running in parallel (or in other
+//! processes) do not see each other's traffic even if they end up on the
+//! same ports.
+//!
+//! ```no_run
+//! use cyclonedds_sys::testing::TestDomain;
+//! use cyclonedds_sys::DdsDuration;
+//!
+//! let domain = TestDomain::new().unwrap();
+//! let participant = domain.participant().unwrap();
+//! // create a topic, writer and reader on `participant`, then
+//! // domain.wait_for_match(&writer, &reader, DdsDuration::from_secs(5)).unwrap();
+//! ```
+
+use std::collections::HashSet;
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use lazy_static::lazy_static;
+
+use crate::domain_config::{AllowMulticast, ParticipantIndex};
+use crate::{
+    dds_create_participant, dds_domainid_t, DDSError, DdsDuration, DdsEntity, DomainConfig,
+};
+
+/// Domain ids handed out to test domains. Domain 0 and the highest ids are
+/// left alone; ids above 232 give port numbers beyond 65535.
+const FIRST_DOMAIN: u32 = 1;
+const DOMAIN_COUNT: u32 = 200;
+
+/// Participants per test domain that can find each other
+const MAX_PARTICIPANTS: u32 = 20;
+
+/// Cyclone's default RTPS port mapping
+const PORT_BASE: u32 = 7400;
+const DOMAIN_GAIN: u32 = 250;
+const PARTICIPANT_GAIN: u32 = 2;
+const UNICAST_META_OFFSET: u32 = 10;
+const UNICAST_DATA_OFFSET: u32 = 11;
+
+static NEXT_DOMAIN: AtomicU32 = AtomicU32::new(0);
+
+lazy_static! {
+    /// Domain ids of the test domains alive in this process
+    static ref DOMAINS_IN_USE: Mutex<HashSet<dds_domainid_t>> = Mutex::new(HashSet::new());
+}
+
+fn domains_in_use() -> std::sync::MutexGuard<'static, HashSet<dds_domainid_t>> {
+    DOMAINS_IN_USE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The unicast discovery and data ports participants of `domain` bind
+fn unicast_ports(domain: dds_domainid_t) -> impl Iterator<Item = u16> {
+    let base = PORT_BASE + DOMAIN_GAIN * domain;
+    (0..MAX_PARTICIPANTS).flat_map(move |index| {
+        let offset = base + PARTICIPANT_GAIN * index;
+        vec![
+            (offset + UNICAST_META_OFFSET) as u16,
+            (offset + UNICAST_DATA_OFFSET) as u16,
+        ]
+    })
+}
+
+/// Whether nobody on this host has bound the ports of `domain`
+fn ports_are_free(domain: dds_domainid_t) -> bool {
+    unicast_ports(domain).all(|port| UdpSocket::bind(("127.0.0.1", port)).is_ok())
+}
+
+/// Reserve a domain id not used by another test domain of this process,
+/// preferring one whose ports are free. Processes start looking at
+/// different ids, spread apart by pid.
+fn reserve_domain_id() -> Result<dds_domainid_t, DDSError> {
+    let n = NEXT_DOMAIN.fetch_add(1, Ordering::Relaxed);
+    let start = std::process::id().wrapping_mul(31).wrapping_add(n);
+    let mut in_use = domains_in_use();
+    let candidates: Vec<dds_domainid_t> = (0..DOMAIN_COUNT)
+        .map(|i| FIRST_DOMAIN + start.wrapping_add(i) % DOMAIN_COUNT)
+        .filter(|id| !in_use.contains(id))
+        .collect();
+    let id = candidates
+        .iter()
+        .copied()
+        .find(|id| ports_are_free(*id))
+        .or_else(|| candidates.first().copied())
+        .ok_or(DDSError::OutOfResources)?;
+    in_use.insert(id);
+    Ok(id)
+}
+
+/// A discovery tag no other test domain uses
+fn unique_tag() -> String {
+    static NEXT_TAG: AtomicU32 = AtomicU32::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|t| t.as_nanos())
+        .unwrap_or(0);
+    format!(
+        "test-{}-{}-{}",
+        std::process::id(),
+        NEXT_TAG.fetch_add(1, Ordering::Relaxed),
+        nanos
+    )
+}
+
+/// The configuration used for test domains
+pub fn loopback_config(domain_id: dds_domainid_t) -> DomainConfig {
+    let mut config = DomainConfig::new();
+    config
+        .domain_id(Some(domain_id))
+        .network_interface_address("127.0.0.1")
+        .allow_multicast(AllowMulticast::False)
+        .peer("127.0.0.1")
+        .participant_index(ParticipantIndex::Auto)
+        .max_auto_participant_index(MAX_PARTICIPANTS)
+        .spdp_interval(DdsDuration::from_millis(100));
+    config
+}
+
+/// A domain that exists for the duration of a test. Dropping it deletes the
+/// domain and every entity created in it.
+pub struct TestDomain {
+    id: dds_domainid_t,
+    tag: String,
+    domain: DdsEntity,
+}
+
+impl TestDomain {
+    pub fn new() -> Result<Self, DDSError> {
+        Self::with_config(|_| {})
+    }
+
+    /// Create a test domain after adjusting the loopback configuration
+    pub fn with_config<F>(f: F) -> Result<Self, DDSError>
+    where
+        F: FnOnce(&mut DomainConfig),
+    {
+        let id = reserve_domain_id()?;
+        let tag = unique_tag();
+        let mut config = loopback_config(id);
+        config.discovery_tag(&tag);
+        f(&mut config);
+        match config.create_domain(id) {
+            Ok(domain) => Ok(TestDomain { id, tag, domain }),
+            Err(e) => {
+                domains_in_use().remove(&id);
+                Err(e)
+            }
+        }
+    }
+
+    pub fn id(&self) -> dds_domainid_t {
+        self.id
+    }
+
+    /// The discovery tag that keeps this domain apart from others on the
+    /// same ports
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    pub fn domain(&self) -> &DdsEntity {
+        &self.domain
+    }
+
+    /// Create a participant in this domain
+    pub fn participant(&self) -> Result<DdsEntity, DDSError> {
+        let participant = DDSError::check(unsafe {
+            dds_create_participant(self.id, std::ptr::null(), std::ptr::null())
+        })?;
+        Ok(DdsEntity(participant))
+    }
+
+    /// Wait until `writer` and `reader` have both seen at least one match.
+    /// Fails with `DDSError::Timeout` if that takes longer than `timeout`.
+    pub fn wait_for_match<D: Into<DdsDuration>>(
+        &self,
+        writer: &DdsEntity,
+        reader: &DdsEntity,
+        timeout: D,
+    ) -> Result<(), DDSError> {
+        wait_for_match(writer, reader, timeout)
+    }
+}
+
+impl Drop for TestDomain {
+    fn drop(&mut self) {
+        let _ = self.domain.clone().delete();
+        domains_in_use().remove(&self.id);
+    }
+}
+
+/// Poll the matched statuses of `writer` and `reader` until both report a
+/// current match
+pub fn wait_for_match<D: Into<DdsDuration>>(
+    writer: &DdsEntity,
+    reader: &DdsEntity,
+    timeout: D,
+) -> Result<(), DDSError> {
+    let deadline = timeout.into().to_std().map(|t| Instant::now() + t);
+    loop {
+        let published = writer.publication_matched_status()?.current_count > 0;
+        let subscribed = reader.subscription_matched_status()?.current_count > 0;
+        if published && subscribed {
+            return Ok(());
+        }
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            return Err(DDSError::Timeout);
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ports_follow_the_default_mapping() {
+        let ports: Vec<u16> = unicast_ports(1).collect();
+        assert_eq!(ports.len(), 2 * MAX_PARTICIPANTS as usize);
+        assert_eq!(&ports[..4], &[7660, 7661, 7662, 7663]);
+        assert!(unicast_ports(FIRST_DOMAIN + DOMAIN_COUNT - 1).all(|p| p > 7400));
+    }
+
+    #[test]
+    fn bound_ports_are_not_free() {
+        let domain = (FIRST_DOMAIN..FIRST_DOMAIN + DOMAIN_COUNT)
+            .find(|d| ports_are_free(*d))
+            .expect("a domain with free ports");
+        let port = unicast_ports(domain).nth(5).unwrap();
+        let socket = UdpSocket::bind(("127.0.0.1", port)).unwrap();
+        assert!(!ports_are_free(domain));
+        drop(socket);
+    }
+
+    #[test]
+    fn reserved_domain_ids_are_unique_until_released() {
+        let ids: Vec<dds_domainid_t> = (0..5).map(|_| reserve_domain_id().unwrap()).collect();
+        let unique: HashSet<_> = ids.iter().collect();
+        assert_eq!(unique.len(), ids.len());
+        assert!(ids
+            .iter()
+            .all(|id| (FIRST_DOMAIN..FIRST_DOMAIN + DOMAIN_COUNT).contains(id)));
+        let mut in_use = domains_in_use();
+        for id in &ids {
+            assert!(in_use.remove(id));
+        }
+    }
+
+    #[test]
+    fn tags_are_unique() {
+        let a = unique_tag();
+        let b = unique_tag();
+        assert_ne!(a, b);
+        assert!(a.starts_with(&format!("test-{}-", std::process::id())));
+
+        let mut config = loopback_config(3);
+        config.discovery_tag(&a);
+        assert_eq!(config.get_discovery_tag(), Some(a.as_str()));
+        assert_eq!(config.get_domain_id().unwrap(), Some(3));
+    }
+}
@@ -0,0 +1,243 @@
+/*
+    Copyright 2020 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Content filters on topics implemented as Rust closures.
+//!
+//! The closure is kept in a slot that lives as long as the topic. Cyclone
+//! only ever sees a pointer to the slot, so replacing or removing the filter
+//! never frees memory a concurrently running filter call might still use.
+//!
+//! Filters are installed through [`Topic`], which fixes the sample type the
+//! closure is called with when the handle is created.
+//!
+//! The slot is released when the topic is deleted through [`Topic::delete`]
+//! or [`DdsEntity::delete`]. A topic deleted with a plain `dds_delete` keeps its
+//! slot until the next time entity state is swept, which happens whenever a
+//! filter is installed or an entity is deleted through this crate.
+
+use std::ffi::c_void;
+use std::marker::PhantomData;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::sync::{Arc, RwLock};
+
+use crate::{
+    dds_set_topic_filter_extended, dds_topic_filter, dds_topic_filter_function_union,
+    dds_topic_filter_mode_DDS_TOPIC_FILTER_NONE, dds_topic_filter_mode_DDS_TOPIC_FILTER_SAMPLE_ARG,
+    entity_state, DDSError, DdsEntity,
+};
+
+const TOPIC_FILTER_KEY: &str = "topic_filter";
+
+pub(crate) type SampleFilter = Box<dyn Fn(*const c_void) -> bool + Send + Sync>;
+
+/// Holds the filter closure of a single topic.
+#[derive(Default)]
+pub(crate) struct FilterSlot {
+    filter: RwLock<Option<SampleFilter>>,
+}
+
+impl FilterSlot {
+    pub(crate) fn set(&self, filter: Option<SampleFilter>) {
+        *self.filter.write().unwrap_or_else(|e| e.into_inner()) = filter;
+    }
+
+    /// Run the filter against a sample. A missing filter accepts everything,
+    /// a panicking filter rejects the sample.
+    pub(crate) fn accept(&self, sample: *const c_void) -> bool {
+        let filter = self.filter.read().unwrap_or_else(|e| e.into_inner());
+        match filter.as_ref() {
+            Some(f) => catch_unwind(AssertUnwindSafe(|| f(sample))).unwrap_or(false),
+            None => true,
+        }
+    }
+}
+
+unsafe extern "C" fn filter_trampoline(sample: *const c_void, arg: *mut c_void) -> bool {
+    if arg.is_null() {
+        return true;
+    }
+    let slot = &*(arg as *const FilterSlot);
+    slot.accept(sample)
+}
+
+/// Wrap a typed closure so that it can be called with the untyped sample
+/// pointer Cyclone hands to filters.
+pub(crate) fn erase<T, F>(filter: F) -> SampleFilter
+where
+    T: 'static,
+    F: Fn(&T) -> bool + Send + Sync + 'static,
+{
+    Box::new(move |sample: *const c_void| {
+        if sample.is_null() {
+            false
+        } else {
+            filter(unsafe { &*(sample as *const T) })
+        }
+    })
+}
+
+/// A topic whose in-memory samples are of type `T`. Like [`DdsEntity`] the
+/// handle does not own the topic.
+pub struct Topic<T> {
+    entity: DdsEntity,
+    _sample: PhantomData<fn(&T)>,
+}
+
+impl<T> Clone for Topic<T> {
+    fn clone(&self) -> Self {
+        Topic {
+            entity: self.entity.clone(),
+            _sample: PhantomData,
+        }
+    }
+}
+
+impl<T: 'static> Topic<T> {
+    /// # Safety
+    /// The entity must be a topic and `T` must be the in-memory sample type
+    /// of that topic.
+    pub unsafe fn new(topic: DdsEntity) -> Self {
+        Topic {
+            entity: topic,
+            _sample: PhantomData,
+        }
+    }
+
+    pub fn entity(&self) -> &DdsEntity {
+        &self.entity
+    }
+
+    /// Install a content filter. Samples for which the closure returns false
+    /// are dropped before they reach any reader of the topic. Calling this
+    /// again replaces the previous filter.
+    ///
+    /// The closure is dropped when the topic is deleted through this crate;
+    /// deleting it with `dds_delete` leaves the closure alive until entity
+    /// state is next swept.
+    pub fn set_filter<F>(&self, filter: F) -> Result<(), DDSError>
+    where
+        F: Fn(&T) -> bool + Send + Sync + 'static,
+    {
+        unsafe { self.entity.set_topic_filter_untyped(Some(erase(filter))) }
+    }
+
+    /// Remove the content filter.
+    pub fn clear_filter(&self) -> Result<(), DDSError> {
+        self.entity.clear_topic_filter()
+    }
+
+    /// Delete the topic and release its filter.
+    pub fn delete(self) -> Result<(), DDSError> {
+        self.entity.delete()
+    }
+}
+
+impl DdsEntity {
+    /// Remove the content filter from this topic.
+    pub fn clear_topic_filter(&self) -> Result<(), DDSError> {
+        unsafe { self.set_topic_filter_untyped(None) }
+    }
+
+    pub(crate) unsafe fn set_topic_filter_untyped(
+        &self,
+        filter: Option<SampleFilter>,
+    ) -> Result<(), DDSError> {
+        entity_state::purge();
+
+        let slot: Arc<FilterSlot> =
+            entity_state::get_or_insert_with(self.entity(), TOPIC_FILTER_KEY, FilterSlot::default);
+        let install = filter.is_some();
+        slot.set(filter);
+
+        let filter = if install {
+            dds_topic_filter {
+                mode: dds_topic_filter_mode_DDS_TOPIC_FILTER_SAMPLE_ARG,
+                f: dds_topic_filter_function_union {
+                    sample_arg: Some(filter_trampoline),
+                },
+                arg: Arc::as_ptr(&slot) as *mut c_void,
+            }
+        } else {
+            dds_topic_filter {
+                mode: dds_topic_filter_mode_DDS_TOPIC_FILTER_NONE,
+                f: dds_topic_filter_function_union { sample: None },
+                arg: ptr::null_mut(),
+            }
+        };
+        DDSError::check(dds_set_topic_filter_extended(self.entity(), &filter)).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn missing_filter_accepts_everything() {
+        let slot = FilterSlot::default();
+        let sample = 1i32;
+        assert!(slot.accept(&sample as *const i32 as *const c_void));
+        assert!(unsafe { filter_trampoline(ptr::null(), ptr::null_mut()) });
+    }
+
+    #[test]
+    fn filters_can_be_replaced_and_removed() {
+        let slot = FilterSlot::default();
+        let arg = &slot as *const FilterSlot as *mut c_void;
+        let (even, odd) = (2i32, 3i32);
+        let even = &even as *const i32 as *const c_void;
+        let odd = &odd as *const i32 as *const c_void;
+
+        slot.set(Some(erase(|v: &i32| v % 2 == 0)));
+        assert!(unsafe { filter_trampoline(even, arg) });
+        assert!(!unsafe { filter_trampoline(odd, arg) });
+
+        slot.set(Some(erase(|v: &i32| v % 2 == 1)));
+        assert!(!slot.accept(even));
+        assert!(slot.accept(odd));
+
+        slot.set(None);
+        assert!(slot.accept(even) && slot.accept(odd));
+    }
+
+    #[test]
+    fn panicking_filters_reject() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        let slot = FilterSlot::default();
+        slot.set(Some(erase(move |v: &i32| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            if *v < 0 {
+                panic!("negative");
+            }
+            true
+        })));
+        let (good, bad) = (1i32, -1i32);
+        assert!(slot.accept(&good as *const i32 as *const c_void));
+        assert!(!slot.accept(&bad as *const i32 as *const c_void));
+        // the lock is not poisoned for later samples
+        assert!(slot.accept(&good as *const i32 as *const c_void));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn null_samples_are_rejected() {
+        let filter = erase(|_: &i32| true);
+        assert!(!filter(ptr::null()));
+    }
+}
@@ -0,0 +1,93 @@
+//! End to end checks of the test domain harness, using serde topics.
+
+use std::time::{Duration, Instant};
+
+use cyclonedds_sys::serde_topic::Keyed;
+use cyclonedds_sys::testing::TestDomain;
+use cyclonedds_sys::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Counter {
+    id: u32,
+    count: u64,
+}
+
+impl Keyed for Counter {
+    type Key = u32;
+    fn key(&self) -> u32 {
+        self.id
+    }
+}
+
+fn endpoints(domain: &TestDomain) -> (DdsEntity, DdsEntity) {
+    let participant = domain.participant().unwrap();
+    let topic = participant
+        .create_serde_topic::<Counter>("Counter", "test::Counter", None)
+        .unwrap();
+    unsafe {
+        let writer = dds_create_writer(
+            participant.entity(),
+            topic.entity(),
+            std::ptr::null(),
+            std::ptr::null(),
+        );
+        let reader = dds_create_reader(
+            participant.entity(),
+            topic.entity(),
+            std::ptr::null(),
+            std::ptr::null(),
+        );
+        (
+            DdsEntity::new(DDSError::check(writer).unwrap()),
+            DdsEntity::new(DDSError::check(reader).unwrap()),
+        )
+    }
+}
+
+fn take_within(reader: &DdsEntity, timeout: Duration) -> Vec<Counter> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let samples: Vec<Counter> = reader
+            .take_serde::<Counter>(16)
+            .unwrap()
+            .into_iter()
+            .filter_map(|s| s.value)
+            .collect();
+        if !samples.is_empty() || Instant::now() >= deadline {
+            return samples;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn samples_flow_within_a_test_domain() {
+    let domain = TestDomain::new().unwrap();
+    let (writer, reader) = endpoints(&domain);
+    domain
+        .wait_for_match(&writer, &reader, Duration::from_secs(10))
+        .unwrap();
+
+    let sample = Counter { id: 1, count: 42 };
+    writer.write_serde(&sample).unwrap();
+    assert_eq!(take_within(&reader, Duration::from_secs(5)), vec![sample]);
+}
+
+#[test]
+fn test_domains_do_not_see_each_other() {
+    let first = TestDomain::new().unwrap();
+    let second = TestDomain::new().unwrap();
+    assert_ne!(first.id(), second.id());
+    assert_ne!(first.tag(), second.tag());
+
+    let (writer, _) = endpoints(&first);
+    let (_, reader) = endpoints(&second);
+    assert_eq!(
+        testing::wait_for_match(&writer, &reader, Duration::from_millis(500)),
+        Err(DDSError::Timeout)
+    );
+
+    writer.write_serde(&Counter { id: 2, count: 1 }).unwrap();
+    assert!(take_within(&reader, Duration::from_millis(200)).is_empty());
+}
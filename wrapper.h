@@ -14,6 +14,7 @@ void _dummy(dds_status_id_t status);
 // cyclone inline functions needed by bindings reimplemented here
 struct ddsi_serdata *ddsi_serdata_addref (const struct ddsi_serdata *serdata_const);
 void ddsi_serdata_removeref (struct ddsi_serdata *serdata);
+void ddsrs_copy_fragchain (const struct nn_rdata *fragchain, size_t size, unsigned char *dst);
 
 const int BUILTIN_TOPIC_DCPSPARTICIPANT = DDS_BUILTIN_TOPIC_DCPSPARTICIPANT;
 const int BUILTIN_TOPIC_DCPSTOPIC = DDS_BUILTIN_TOPIC_DCPSTOPIC;